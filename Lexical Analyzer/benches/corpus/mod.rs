@@ -0,0 +1,41 @@
+// Deterministic MCPP inputs of a given size for the benchmarks and the
+// gen_corpus example. The same size always gives the same text, so numbers
+// from different runs measure the same work.
+
+// (name, approximate token count) of the standard inputs.
+pub const SIZES: &[(&str, usize)] = &[("1k", 1_000), ("100k", 100_000), ("1m", 1_000_000)];
+
+// A program of functions made of a fixed mix of declarations, loops,
+// conditions, calls, strings and comments, stopping once it has about
+// `tokens` tokens. Every function has a distinct name, so the symbol table
+// grows with the input.
+pub fn generate(tokens: usize) -> String {
+    let mut out = String::from("#include <iostream>\n#define LIMIT 100\n\n");
+    let mut count = 6;
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut function = 0;
+    while count < tokens {
+        // xorshift, so the mix varies from function to function
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let n = function;
+        out.push_str(&format!("// function {}\n", n));
+        out.push_str(&format!("int compute_{}(int a, float b) {{\n", n));
+        out.push_str(&format!("    int total_{} = a * {};\n", n, state % 1000));
+        out.push_str(&format!("    float ratio = b / {}.5;\n", state % 10));
+        out.push_str("    for (int i = 0; i < LIMIT; i = i + 1) {\n");
+        out.push_str(&format!("        if (i % {} == 0 && ratio >= 1.0) {{\n", state % 7 + 2));
+        out.push_str(&format!("            total_{} = total_{} + i;\n", n, n));
+        out.push_str("        }\n");
+        out.push_str("    }\n");
+        out.push_str(&format!("    string label = \"result {}\\n\";\n", n));
+        out.push_str("    char mark = 'x';\n");
+        out.push_str(&format!("    cout << label << total_{};\n", n));
+        out.push_str(&format!("    return total_{};\n", n));
+        out.push_str("}\n\n");
+        count += 85;
+        function += 1;
+    }
+    out
+}
@@ -0,0 +1,173 @@
+// Lexer benchmarks, run with `cargo bench --bench lexer`. Without an
+// external harness, each benchmark runs until it has taken about a second
+// (at least three times) and reports the median. With `--features logos`
+// the logos backend is timed as well.
+//
+//   cargo bench --bench lexer -- [FILTER] [--save-baseline FILE] [--baseline FILE] [--threshold PERCENT]
+//
+// `--save-baseline` writes the medians as JSON; `--baseline` compares
+// against such a file and exits with status 1 if any benchmark is slower
+// than it by more than the threshold (default 25%). The `interning` lines,
+// not timed, give the bytes of identifier and keyword text that sharing
+// lexemes saves on each input. Every backend matches only at the current
+// position, so each grows linearly with the input and is timed on all
+// three sizes.
+
+mod corpus;
+
+use mcpp_lexer::diagnostics::DiagnosticFilter;
+use mcpp_lexer::lexer::{Backend, Lexer, SymbolTableBuilder};
+use mcpp_lexer::position::PositionBase;
+use serde_json::{Map, Value};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use std::{env, fs, io, process};
+
+type Routine<'a> = Box<dyn FnMut() + 'a>;
+
+struct Args {
+    filter: Option<String>,
+    save: Option<String>,
+    baseline: Option<String>,
+    threshold: f64,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        filter: None,
+        save: None,
+        baseline: None,
+        threshold: 25.0,
+    };
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // Passed by `cargo bench`
+            "--bench" => {}
+            "--save-baseline" => args.save = iter.next(),
+            "--baseline" => args.baseline = iter.next(),
+            "--threshold" => {
+                args.threshold = iter.next().and_then(|t| t.parse().ok()).unwrap_or_else(|| {
+                    eprintln!("--threshold requires a percentage");
+                    process::exit(2);
+                })
+            }
+            _ => args.filter = Some(arg),
+        }
+    }
+    args
+}
+
+// Median time of `routine`, run until a second has passed.
+fn measure<F: FnMut()>(mut routine: F) -> Duration {
+    let mut samples = Vec::new();
+    let started = Instant::now();
+    while samples.len() < 3 || started.elapsed() < Duration::from_secs(1) {
+        let start = Instant::now();
+        routine();
+        samples.push(start.elapsed());
+    }
+    samples.sort();
+    samples[samples.len() / 2]
+}
+
+fn main() {
+    let args = parse_args();
+    let mut results = Map::new();
+    for (size, tokens) in corpus::SIZES {
+        let source = corpus::generate(*tokens);
+        let mut lexer = Lexer::new(source.clone());
+        lexer.set_backend(Backend::Scanner);
+        lexer.tokenize().expect("generated corpus lexes");
+        let lexed = lexer.get_tokens().clone();
+        
+        // Memory rather than time, so not part of a baseline
+        let interning = format!("interning/{}", size);
+        if args.filter.as_ref().is_none_or(|f| interning.contains(f.as_str())) {
+            let stats = lexer.intern_stats();
+            println!(
+                "{:<20} {:>9} lexemes {:>7} unique {:>10} bytes saved",
+                interning, stats.occurrences, stats.unique, stats.bytes_saved
+            );
+        }
+        
+        let mut benches: Vec<(&str, Routine)> = vec![
+            ("tokenize_scanner", Box::new(|| {
+                let mut lexer = Lexer::new(source.clone());
+                lexer.set_backend(Backend::Scanner);
+                black_box(lexer.tokenize()).unwrap();
+            })),
+            ("iterate_scanner", Box::new(|| {
+                let mut lexer = Lexer::new(source.clone());
+                lexer.set_backend(Backend::Scanner);
+                black_box(lexer.filter_map(Result::ok).count());
+            })),
+            ("write_json", Box::new(|| {
+                lexer.write_json(io::sink(), false, &DiagnosticFilter::default(), PositionBase::One, false).unwrap();
+            })),
+            ("symbol_table", Box::new(|| {
+                let mut builder = SymbolTableBuilder::new();
+                for token in &lexed {
+                    builder.observe(token);
+                }
+                black_box(builder.into_symbol_table());
+            })),
+        ];
+        #[cfg(feature = "logos")]
+        benches.push(("tokenize_logos", Box::new(|| {
+            let mut lexer = Lexer::new(source.clone());
+            lexer.set_backend(Backend::Logos);
+            black_box(lexer.tokenize()).unwrap();
+        })));
+        benches.push(("tokenize", Box::new(|| {
+            let mut lexer = Lexer::new(source.clone());
+            black_box(lexer.tokenize()).unwrap();
+        })));
+        benches.push(("iterate", Box::new(|| {
+            let lexer = Lexer::new(source.clone());
+            black_box(lexer.filter_map(Result::ok).count());
+        })));
+        for (bench, routine) in benches {
+            let name = format!("{}/{}", bench, size);
+            if args.filter.as_ref().is_some_and(|f| !name.contains(f.as_str())) {
+                continue;
+            }
+            let median = measure(routine);
+            let rate = lexed.len() as f64 / median.as_secs_f64();
+            println!("{:<20} {:>12.3} ms {:>14.0} tokens/s", name, median.as_secs_f64() * 1000.0, rate);
+            results.insert(name, Value::from(median.as_nanos() as u64));
+        }
+    }
+    
+    if let Some(path) = &args.save {
+        let json = serde_json::to_string_pretty(&results).unwrap();
+        fs::write(path, json + "\n").unwrap_or_else(|e| {
+            eprintln!("Could not write '{}': {}", path, e);
+            process::exit(2);
+        });
+        println!("\nBaseline saved to: {}", path);
+    }
+    if let Some(path) = &args.baseline {
+        let baseline: Map<String, Value> = fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_else(|| {
+                eprintln!("Could not read baseline '{}'", path);
+                process::exit(2);
+            });
+        let mut regressed = false;
+        println!("\n=== BASELINE {} ===", path);
+        for (name, nanos) in &results {
+            let (Some(now), Some(before)) = (nanos.as_u64(), baseline.get(name).and_then(Value::as_u64)) else {
+                continue;
+            };
+            let change = (now as f64 / before as f64 - 1.0) * 100.0;
+            let flag = if change > args.threshold { "  REGRESSION" } else { "" };
+            regressed |= change > args.threshold;
+            println!("{:<20} {:>+8.1}%{}", name, change, flag);
+        }
+        if regressed {
+            process::exit(1);
+        }
+    }
+}
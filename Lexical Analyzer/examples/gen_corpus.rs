@@ -0,0 +1,30 @@
+// Writes the benchmark inputs as .mcpp files, for running the command-line
+// lexer or other tools on the same text:
+//
+//   cargo run --example gen_corpus -- [DIRECTORY]
+//
+// The directory defaults to `target/corpus`.
+
+#[path = "../benches/corpus/mod.rs"]
+mod corpus;
+
+use std::path::Path;
+use std::{env, fs, process};
+
+fn main() {
+    let directory = env::args().nth(1).unwrap_or_else(|| "target/corpus".to_string());
+    if let Err(e) = fs::create_dir_all(&directory) {
+        eprintln!("Could not create '{}': {}", directory, e);
+        process::exit(1);
+    }
+    for (size, tokens) in corpus::SIZES {
+        let path = Path::new(&directory).join(format!("corpus_{}.mcpp", size));
+        match fs::write(&path, corpus::generate(*tokens)) {
+            Ok(_) => println!("Wrote {}", path.display()),
+            Err(e) => {
+                eprintln!("Could not write '{}': {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/* Generated by mcpp-lexer --emit-bison-defs. Do not edit. */
+#ifndef TOKENS_H
+#define TOKENS_H
+
+enum mcpp_token {
+    YYEOF = 0,
+    YYerror = 256,
+    T_INT = 258,
+    T_FLOAT = 259,
+    T_CHAR = 260,
+    T_BOOL = 261,
+    T_STRING = 262,
+    T_IF = 263,
+    T_ELSE = 264,
+    T_WHILE = 265,
+    T_FOR = 266,
+    T_RETURN = 267,
+    T_INCLUDE = 268,
+    T_DEFINE = 269,
+    T_PLUS = 270,
+    T_MINUS = 271,
+    T_MULTIPLY = 272,
+    T_DIVIDE = 273,
+    T_MODULO = 274,
+    T_ASSIGN = 275,
+    T_EQUAL = 276,
+    T_NOT_EQUAL = 277,
+    T_LESS_THAN = 278,
+    T_GREATER_THAN = 279,
+    T_LESS_EQUAL = 280,
+    T_GREATER_EQUAL = 281,
+    T_LOGICAL_AND = 282,
+    T_LOGICAL_OR = 283,
+    T_INCREMENT = 284,
+    T_DECREMENT = 285,
+    T_SEMICOLON = 286,
+    T_COMMA = 287,
+    T_LEFT_PAREN = 288,
+    T_RIGHT_PAREN = 289,
+    T_LEFT_BRACE = 290,
+    T_RIGHT_BRACE = 291,
+    T_LEFT_BRACKET = 292,
+    T_RIGHT_BRACKET = 293,
+    T_INTEGER_LITERAL = 294,
+    T_FLOAT_LITERAL = 295,
+    T_CHAR_LITERAL = 296,
+    T_STRING_LITERAL = 297,
+    T_BOOL_LITERAL = 298,
+    T_IDENTIFIER = 299,
+    T_COMMENT = 300,
+    T_AMPERSAND = 301,
+    T_DIRECTIVE = 302,
+    T_GOTO = 303,
+    T_COLON = 304,
+    T_CLASS = 305,
+    T_PUBLIC = 306,
+    T_PRIVATE = 307,
+    T_PROTECTED = 308,
+    T_TYPEDEF = 309,
+    T_USING = 310,
+    T_NEW = 311,
+    T_DELETE = 312,
+    T_ELLIPSIS = 313,
+    T_ATTRIBUTE = 314,
+    T_AUTO = 315,
+    T_LOGICAL_NOT = 316,
+    T_VOID = 317,
+};
+
+#endif
@@ -0,0 +1,61 @@
+/* Generated by mcpp-lexer --emit-bison-defs. Do not edit. */
+%token T_INT 258
+%token T_FLOAT 259
+%token T_CHAR 260
+%token T_BOOL 261
+%token T_STRING 262
+%token T_IF 263
+%token T_ELSE 264
+%token T_WHILE 265
+%token T_FOR 266
+%token T_RETURN 267
+%token T_INCLUDE 268
+%token T_DEFINE 269
+%token T_PLUS 270
+%token T_MINUS 271
+%token T_MULTIPLY 272
+%token T_DIVIDE 273
+%token T_MODULO 274
+%token T_ASSIGN 275
+%token T_EQUAL 276
+%token T_NOT_EQUAL 277
+%token T_LESS_THAN 278
+%token T_GREATER_THAN 279
+%token T_LESS_EQUAL 280
+%token T_GREATER_EQUAL 281
+%token T_LOGICAL_AND 282
+%token T_LOGICAL_OR 283
+%token T_INCREMENT 284
+%token T_DECREMENT 285
+%token T_SEMICOLON 286
+%token T_COMMA 287
+%token T_LEFT_PAREN 288
+%token T_RIGHT_PAREN 289
+%token T_LEFT_BRACE 290
+%token T_RIGHT_BRACE 291
+%token T_LEFT_BRACKET 292
+%token T_RIGHT_BRACKET 293
+%token T_INTEGER_LITERAL 294
+%token T_FLOAT_LITERAL 295
+%token T_CHAR_LITERAL 296
+%token T_STRING_LITERAL 297
+%token T_BOOL_LITERAL 298
+%token T_IDENTIFIER 299
+%token T_COMMENT 300
+%token T_AMPERSAND 301
+%token T_DIRECTIVE 302
+%token T_GOTO 303
+%token T_COLON 304
+%token T_CLASS 305
+%token T_PUBLIC 306
+%token T_PRIVATE 307
+%token T_PROTECTED 308
+%token T_TYPEDEF 309
+%token T_USING 310
+%token T_NEW 311
+%token T_DELETE 312
+%token T_ELLIPSIS 313
+%token T_ATTRIBUTE 314
+%token T_AUTO 315
+%token T_LOGICAL_NOT 316
+%token T_VOID 317
@@ -0,0 +1,168 @@
+use crate::lexer::{Delimiter, Keyword, Operator, Token, TokenType};
+use std::fmt::Write;
+
+// Where a `new` or `delete` appears, and whether it is the array form
+// (`new int[10]`, `delete[] p`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Site {
+    pub line: usize,
+    pub column: usize,
+    pub array: bool,
+}
+
+// The `new` and `delete` sites of one pointer, paired by name: a `new` is
+// the pointer's when it is assigned straight to it (`p = new int;`, `int* p =
+// new int;`) and a `delete` when the pointer follows it. A `new` used any
+// other way, as in `return new int;`, has no name. Control flow is not
+// followed, so a `delete` in one branch pairs with every `new` of the name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocations {
+    pub name: Option<String>,
+    pub allocated: Vec<Site>,
+    pub freed: Vec<Site>,
+}
+
+impl Allocations {
+    // Allocated somewhere in the file but never deleted.
+    pub fn is_unmatched(&self) -> bool {
+        self.name.is_some() && !self.allocated.is_empty() && self.freed.is_empty()
+    }
+}
+
+// One entry per name in order of first appearance, then the unnamed `new`s,
+// if any.
+pub fn allocations(tokens: &[Token]) -> Vec<Allocations> {
+    let tokens: Vec<&Token> = tokens.iter().filter(|t| t.token_type != TokenType::Comment).collect();
+    let is = |index: usize, token_type: TokenType| tokens.get(index).is_some_and(|t| t.token_type == token_type);
+    let mut named: Vec<Allocations> = Vec::new();
+    let mut unnamed = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let site = |array| Site { line: token.line, column: token.column, array };
+        match token.token_type {
+            TokenType::Keyword(Keyword::New) => {
+                let site = site(is(i + 2, TokenType::Delimiter(Delimiter::LeftBracket)));
+                let name = i
+                    .checked_sub(2)
+                    .filter(|_| is(i - 1, TokenType::Operator(Operator::Assign)) && is(i - 2, TokenType::Identifier))
+                    .map(|target| tokens[target].lexeme.as_str());
+                match name {
+                    Some(name) => entry(&mut named, name).allocated.push(site),
+                    None => unnamed.push(site),
+                }
+            }
+            TokenType::Keyword(Keyword::Delete) => {
+                let array = is(i + 1, TokenType::Delimiter(Delimiter::LeftBracket))
+                    && is(i + 2, TokenType::Delimiter(Delimiter::RightBracket));
+                let target = if array { i + 3 } else { i + 1 };
+                if is(target, TokenType::Identifier) {
+                    entry(&mut named, &tokens[target].lexeme).freed.push(site(array));
+                }
+            }
+            _ => {}
+        }
+    }
+    if !unnamed.is_empty() {
+        named.push(Allocations {
+            name: None,
+            allocated: unnamed,
+            freed: Vec::new(),
+        });
+    }
+    named
+}
+
+fn entry<'a>(entries: &'a mut Vec<Allocations>, name: &str) -> &'a mut Allocations {
+    let index = match entries.iter().position(|e| e.name.as_deref() == Some(name)) {
+        Some(index) => index,
+        None => {
+            entries.push(Allocations {
+                name: Some(name.to_string()),
+                allocated: Vec::new(),
+                freed: Vec::new(),
+            });
+            entries.len() - 1
+        }
+    };
+    &mut entries[index]
+}
+
+pub fn allocation_table(entries: &[Allocations]) -> String {
+    let lines = |sites: &[Site]| {
+        if sites.is_empty() {
+            return "-".to_string();
+        }
+        sites
+            .iter()
+            .map(|s| if s.array { format!("{}[]", s.line) } else { s.line.to_string() })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut out = String::from("=== ALLOCATIONS ===\n");
+    writeln!(out, "{:<20} {:<20} {:<20}", "Pointer", "new (lines)", "delete (lines)").unwrap();
+    writeln!(out, "{}", "-".repeat(70)).unwrap();
+    for entry in entries {
+        let name = entry.name.as_deref().unwrap_or("(unnamed)");
+        let freed = if entry.is_unmatched() { "- (never deleted)".to_string() } else { lines(&entry.freed) };
+        writeln!(out, "{:<20} {:<20} {:<20}", name, lines(&entry.allocated), freed).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn lexed(source: &str) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        lexer
+    }
+
+    fn unmatched_warnings(lexer: &Lexer) -> Vec<(String, usize, usize)> {
+        lexer
+            .get_diagnostics()
+            .iter()
+            .filter(|d| d.code == "W0017")
+            .map(|d| (d.message.clone(), d.span.line, d.span.column))
+            .collect()
+    }
+
+    #[test]
+    fn matched_pairs_are_quiet() {
+        let lexer = lexed("int main() {\n    int *p = new int[10];\n    delete[] p;\n    return 0;\n}\n");
+        let site = |line, column, array| Site { line, column, array };
+        assert_eq!(
+            lexer.allocations(),
+            [Allocations {
+                name: Some("p".to_string()),
+                allocated: vec![site(2, 14, true)],
+                freed: vec![site(3, 5, true)],
+            }]
+        );
+        assert!(unmatched_warnings(&lexer).is_empty());
+    }
+
+    #[test]
+    fn unmatched_new_is_warned_about() {
+        let lexer = lexed("int main() {\n    int *p = new int;\n    int *q = new int;\n    delete p;\n    return 0;\n}\n");
+        assert_eq!(unmatched_warnings(&lexer), [("'q' is allocated with new but never deleted".to_string(), 3, 14)]);
+        let table = allocation_table(&lexer.allocations());
+        assert!(table.contains("\np                    2                    4                   \n"), "{}", table);
+        assert!(table.contains("\nq                    3                    - (never deleted)   \n"), "{}", table);
+    }
+
+    #[test]
+    fn new_in_an_expression_has_no_name() {
+        let lexer = lexed("int f() { return g(new int, 1); }\nfloat *p;\nint h() { p = new float; total = 1; return 0; }\n");
+        let entries = lexer.allocations();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].name, None);
+        assert_eq!(entries[1].allocated, [Site { line: 1, column: 20, array: false }]);
+        // Only named pointers are warned about, and the type after `new`
+        // declares nothing
+        assert_eq!(unmatched_warnings(&lexer).len(), 1);
+        let total = lexer.get_symbol_table().get_symbols().iter().find(|s| s.name == "total").unwrap();
+        assert_eq!(total.data_type, "unknown");
+    }
+}
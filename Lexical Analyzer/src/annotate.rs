@@ -0,0 +1,228 @@
+use crate::lexer::{LineTokens, Token, TokenType};
+use crate::visual::{self, VisualColumns};
+use std::fmt::Write;
+use std::io::IsTerminal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+pub const COLOR_CHOICE_NAMES: &[&str] = &["auto", "always", "never"];
+
+impl ColorChoice {
+    pub fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        }
+    }
+
+    // `auto` colors only a terminal, and never when NO_COLOR is set.
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// ANSI color for each token category.
+fn category_color(token_type: &TokenType) -> &'static str {
+    match token_type {
+        TokenType::Keyword(_) => "\x1b[34m",
+        TokenType::Operator(_) => "\x1b[33m",
+        TokenType::Delimiter(_) => "\x1b[37m",
+        TokenType::Literal(_) => "\x1b[32m",
+        TokenType::Identifier => "\x1b[36m",
+        TokenType::Comment => "\x1b[90m",
+        TokenType::Attribute => "\x1b[35m",
+        TokenType::Error => "\x1b[31m",
+        TokenType::EOF => "\x1b[2m",
+    }
+}
+
+const GUTTER_COLOR: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotateOptions {
+    pub color: bool,
+    // Total width of a printed line, gutter included.
+    pub width: usize,
+    pub tab_width: usize,
+}
+
+impl Default for AnnotateOptions {
+    fn default() -> Self {
+        AnnotateOptions {
+            color: false,
+            width: 100,
+            tab_width: visual::DEFAULT_TAB_WIDTH,
+        }
+    }
+}
+
+// The source with line numbers, each line followed by the tokens starting
+// on it in compiler format. A token running onto later lines shows only
+// its first line and where it ends. Lines too long for the width wrap with
+// an empty gutter, so the line numbers stay aligned. Tabs are expanded to
+// `tab_width` stops and widths counted in terminal cells, so a line of wide
+// characters wraps where it reaches the edge.
+pub fn annotate(lines: &[LineTokens], options: &AnnotateOptions) -> String {
+    let number_width = lines.len().max(1).to_string().len();
+    let text_width = options.width.saturating_sub(number_width + 3).max(20);
+    let paint = |text: &str, color: &str| {
+        if options.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+    let blank_gutter = paint(&format!("{:>width$} |", "", width = number_width), GUTTER_COLOR);
+    let visual = VisualColumns::new(options.tab_width);
+
+    let mut out = String::new();
+    for line in lines {
+        let number_gutter = paint(&format!("{:>width$} |", line.line, width = number_width), GUTTER_COLOR);
+        for (i, piece) in wrap(&visual.expand_tabs(line.text), text_width).into_iter().enumerate() {
+            let gutter = if i == 0 { &number_gutter } else { &blank_gutter };
+            writeln!(out, "{} {}", gutter, piece).unwrap();
+        }
+        for token in line.tokens {
+            let color = category_color(&token.token_type);
+            for (i, piece) in wrap(&visual.expand_tabs(&token_text(token)), text_width - 4).into_iter().enumerate() {
+                let indent = if i == 0 { "    " } else { "      " };
+                writeln!(out, "{} {}{}", blank_gutter, indent, paint(piece, color)).unwrap();
+            }
+        }
+    }
+    out
+}
+
+fn token_text(token: &Token) -> String {
+    match token.lexeme.split_once('\n') {
+        Some((first, _)) => format!(
+            "<{}, {}, {}, {}> ...continues to line {}",
+            token.token_type,
+            first.strip_suffix('\r').unwrap_or(first),
+            token.line,
+            token.column,
+            token.end_line
+        ),
+        None => token.to_compiler_format(),
+    }
+}
+
+// Splits `text`, holding no tabs, into pieces of at most `width` cells,
+// breaking after the last space that fits where there is one. A combining
+// mark stays with the character before it.
+fn wrap(text: &str, width: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.chars().map(visual::char_width).sum::<usize>() > width {
+        let mut cells = 0;
+        let limit = rest
+            .char_indices()
+            .find(|&(_, ch)| {
+                cells += visual::char_width(ch);
+                cells > width
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let end = match rest[..limit].rfind(' ') {
+            Some(space) if space > 0 => space + 1,
+            _ => limit,
+        };
+        pieces.push(rest[..end].trim_end());
+        rest = &rest[end..];
+    }
+    pieces.push(rest);
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions};
+
+    fn lexed(source: &str) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            keep_comments: true,
+            ..LexerOptions::default()
+        });
+        let _ = lexer.tokenize();
+        lexer
+    }
+
+    #[test]
+    fn merged_view_without_color() {
+        let lexer = lexed("/* two\n   lines */ int x = 1;\nint long_name = first_value + second_value + third_value;\n");
+        let options = AnnotateOptions {
+            width: 40,
+            ..AnnotateOptions::default()
+        };
+        assert_eq!(
+            annotate(&lexer.tokens_by_line(), &options),
+            "1 | /* two\n\
+             \x20 |     <Comment, /* two, 1, 1>\n\
+             \x20 |       ...continues to line 2\n\
+             2 |    lines */ int x = 1;\n\
+             \x20 |     <Int, int, 2, 13>\n\
+             \x20 |     <Identifier, x, 2, 17>\n\
+             \x20 |     <Assign, =, 2, 19>\n\
+             \x20 |     <IntegerLiteral, 1, 2, 21>\n\
+             \x20 |     <Semicolon, ;, 2, 22>\n\
+             3 | int long_name = first_value +\n\
+             \x20 | second_value + third_value;\n\
+             \x20 |     <Int, int, 3, 1>\n\
+             \x20 |     <Identifier, long_name, 3, 5>\n\
+             \x20 |     <Assign, =, 3, 15>\n\
+             \x20 |     <Identifier, first_value, 3, 17>\n\
+             \x20 |     <Plus, +, 3, 29>\n\
+             \x20 |     <Identifier, second_value, 3,\n\
+             \x20 |       31>\n\
+             \x20 |     <Plus, +, 3, 44>\n\
+             \x20 |     <Identifier, third_value, 3, 46>\n\
+             \x20 |     <Semicolon, ;, 3, 57>\n\
+             4 | \n\
+             \x20 |     <EOF, EOF, 4, 1>\n"
+        );
+    }
+
+    #[test]
+    fn gutters_stay_aligned_past_nine_lines() {
+        let lexer = lexed(&"x;\n".repeat(10));
+        let view = annotate(&lexer.tokens_by_line(), &AnnotateOptions::default());
+        assert!(view.starts_with(" 1 | x;\n   |     <Identifier, x, 1, 1>\n"));
+        assert!(view.contains("\n10 | x;\n   |     <Identifier, x, 10, 1>\n"));
+        assert!(view.lines().all(|l| l.find('|') == Some(3)));
+    }
+
+    #[test]
+    fn colors_follow_the_category() {
+        let lexer = lexed("int x;\n");
+        let options = AnnotateOptions {
+            color: true,
+            ..AnnotateOptions::default()
+        };
+        let view = annotate(&lexer.tokens_by_line(), &options);
+        assert!(view.starts_with("\x1b[2m1 |\x1b[0m int x;\n\x1b[2m  |\x1b[0m     \x1b[34m<Int, int, 1, 1>\x1b[0m\n"));
+        assert!(view.contains("\x1b[36m<Identifier, x, 1, 5>\x1b[0m"));
+        assert!(view.contains("\x1b[37m<Semicolon, ;, 1, 6>\x1b[0m"));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::cache::content_hash;
+use crate::diagnostics::{DiagnosticFilter, Severity};
+use crate::document::{DocumentRef, SCHEMA_VERSION};
+use crate::lexer::{LexStats, Lexer};
+use crate::position::PositionBase;
+use serde::Serialize;
+use std::io::{self, Write};
+
+// Totals over every file of a batch, written after the last entry. Warnings
+// are those the diagnostic filter shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BatchSummary {
+    pub files: usize,
+    pub succeeded: usize,
+    pub tokens: usize,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+// Size of one file and, unless its tokens came from the cache, how long it
+// took to lex.
+#[derive(Serialize)]
+struct EntryStats {
+    tokens: usize,
+    bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<f64>,
+}
+
+// A file's single-file document with the file's name, source hash and
+// stats beside its fields, so each entry also reads as a `JsonDocument`.
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    filename: &'a str,
+    content_hash: String,
+    stats: EntryStats,
+    #[serde(flatten)]
+    document: DocumentRef<'a>,
+}
+
+// Writes one JSON document for many files: `schema_version`, a `files`
+// array with an entry per file, written as each is added so only one is
+// in memory at a time, and the `summary` written by `finish`.
+pub struct BatchWriter<W: Write> {
+    writer: W,
+    pretty: bool,
+    summary: BatchSummary,
+}
+
+impl<W: Write> BatchWriter<W> {
+    pub fn new(mut writer: W, pretty: bool) -> io::Result<Self> {
+        if pretty {
+            write!(writer, "{{\n  \"schema_version\": {},\n  \"files\": [", SCHEMA_VERSION)?;
+        } else {
+            write!(writer, "{{\"schema_version\":{},\"files\":[", SCHEMA_VERSION)?;
+        }
+        Ok(BatchWriter {
+            writer,
+            pretty,
+            summary: BatchSummary::default(),
+        })
+    }
+
+    // Adds the file `lexer` has just lexed; `stats` is None for tokens
+    // loaded from the cache.
+    pub fn add(&mut self, lexer: &Lexer, filename: &str, stats: Option<&LexStats>, filter: &DiagnosticFilter, base: PositionBase, by_scope: bool) -> io::Result<()> {
+        let entry = EntryRef {
+            filename,
+            content_hash: format!("{:016x}", content_hash(&[lexer.source().as_bytes()])),
+            stats: EntryStats {
+                tokens: lexer.get_tokens().len(),
+                bytes: lexer.source().len(),
+                elapsed_ms: stats.map(|s| s.elapsed.as_secs_f64() * 1000.0),
+            },
+            document: lexer.document(filter, base, by_scope),
+        };
+        if self.summary.files > 0 {
+            self.writer.write_all(b",")?;
+        }
+        if self.pretty {
+            self.writer.write_all(b"\n    ")?;
+        }
+        self.write_value(&entry, "    ")?;
+
+        let diagnostics = filter.apply(lexer.get_diagnostics());
+        self.summary.files += 1;
+        self.summary.succeeded += usize::from(!lexer.has_errors());
+        self.summary.tokens += lexer.get_tokens().len();
+        self.summary.errors += diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+        self.summary.warnings += diagnostics.iter().filter(|d| d.severity == Severity::Warning).count();
+        Ok(())
+    }
+
+    pub fn summary(&self) -> BatchSummary {
+        self.summary
+    }
+
+    // Closes the document and returns the writer, flushed.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.pretty {
+            if self.summary.files > 0 {
+                self.writer.write_all(b"\n  ")?;
+            }
+            self.writer.write_all(b"],\n  \"summary\": ")?;
+            let summary = self.summary;
+            self.write_value(&summary, "  ")?;
+            self.writer.write_all(b"\n}\n")?;
+        } else {
+            self.writer.write_all(b"],\"summary\":")?;
+            serde_json::to_writer(&mut self.writer, &self.summary)?;
+            self.writer.write_all(b"}")?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    // Writes `value` where the writer stands. Pretty-printed, its later lines
+    // are indented to its depth in the batch; JSON strings never hold a raw
+    // newline, so indenting every line is safe.
+    fn write_value<T: Serialize>(&mut self, value: &T, indent: &str) -> io::Result<()> {
+        if !self.pretty {
+            serde_json::to_writer(&mut self.writer, value)?;
+            return Ok(());
+        }
+        let text = serde_json::to_string_pretty(value)?;
+        let mut lines = text.lines();
+        self.writer.write_all(lines.next().unwrap_or_default().as_bytes())?;
+        for line in lines {
+            write!(self.writer, "\n{}{}", indent, line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::parse_document;
+    use serde_json::Value;
+
+    const SOURCES: &[(&str, &str)] = &[("a.mcpp", "int a = 1;\n"), ("b.mcpp", "int b = 2 @ 3;\n"), ("c.mcpp", "int c;")];
+
+    fn batch(pretty: bool) -> (Vec<u8>, BatchSummary) {
+        let mut writer = BatchWriter::new(Vec::new(), pretty).unwrap();
+        for (name, source) in SOURCES {
+            let mut lexer = Lexer::new(source.to_string());
+            let (_, stats) = lexer.tokenize_timed();
+            writer.add(&lexer, name, Some(&stats), &DiagnosticFilter::default(), PositionBase::One, false).unwrap();
+        }
+        let summary = writer.summary();
+        (writer.finish().unwrap(), summary)
+    }
+
+    #[test]
+    fn every_file_is_an_entry_with_a_summary() {
+        for pretty in [true, false] {
+            let (bytes, summary) = batch(pretty);
+            let document: Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(document["schema_version"], SCHEMA_VERSION);
+            let files = document["files"].as_array().unwrap();
+            let names: Vec<&str> = files.iter().map(|f| f["filename"].as_str().unwrap()).collect();
+            assert_eq!(names, ["a.mcpp", "b.mcpp", "c.mcpp"]);
+            assert_eq!(files[0]["content_hash"], format!("{:016x}", content_hash(&[b"int a = 1;\n"])));
+            assert_eq!((files[0]["stats"]["tokens"].as_u64(), files[0]["stats"]["bytes"].as_u64()), (Some(6), Some(11)));
+            assert!(files[0]["stats"]["elapsed_ms"].is_f64());
+            // b stops at its error, c only warns
+            assert_eq!(summary, BatchSummary { files: 3, succeeded: 2, tokens: 6 + 4 + 4, errors: 1, warnings: 1 });
+            assert_eq!(document["summary"], serde_json::to_value(summary).unwrap());
+        }
+    }
+
+    #[test]
+    fn entries_read_as_single_file_documents() {
+        let (bytes, _) = batch(true);
+        let document: Value = serde_json::from_slice(&bytes).unwrap();
+        for (entry, (_, source)) in document["files"].as_array().unwrap().iter().zip(SOURCES) {
+            let single = parse_document(entry.to_string().as_bytes()).unwrap();
+            let mut lexer = Lexer::new(source.to_string());
+            let _ = lexer.tokenize();
+            assert_eq!(&single.tokens, lexer.get_tokens());
+            assert_eq!(single.success, !lexer.has_errors());
+        }
+    }
+
+    #[test]
+    fn an_empty_batch_is_still_a_document() {
+        let writer = BatchWriter::new(Vec::new(), true).unwrap();
+        let document: Value = serde_json::from_slice(&writer.finish().unwrap()).unwrap();
+        assert_eq!(document["files"], Value::Array(Vec::new()));
+        assert_eq!(document["summary"]["files"], 0);
+    }
+}
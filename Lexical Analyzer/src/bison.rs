@@ -0,0 +1,150 @@
+use std::borrow::Cow;
+use crate::lexer::{Token, TokenType};
+use std::fmt::Write;
+
+// Bison reserves 0 for end of input and 256/257 for its error and undefined
+// tokens, so user token numbers start at 258.
+pub const FIRST_TOKEN_CODE: u32 = 258;
+
+// Error tokens from error recovery are passed on as Bison's own error token,
+// which starts the parser's error recovery.
+pub const ERROR_TOKEN_CODE: u32 = 256;
+
+// Bison-style name for a token type, e.g. IntegerLiteral -> T_INTEGER_LITERAL.
+pub fn token_name(token_type: &TokenType) -> String {
+    match token_type {
+        TokenType::EOF => return "YYEOF".to_string(),
+        TokenType::Error => return "YYerror".to_string(),
+        _ => {}
+    }
+    let mut name = String::from("T_");
+    for (i, ch) in token_type.name().chars().enumerate() {
+        if ch.is_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.push(ch.to_ascii_uppercase());
+    }
+    name
+}
+
+pub fn token_code(token_type: &TokenType) -> u32 {
+    match token_type {
+        TokenType::EOF => return 0,
+        TokenType::Error => return ERROR_TOKEN_CODE,
+        _ => {}
+    }
+    let index = declared_types().position(|t| t == token_type).unwrap();
+    FIRST_TOKEN_CODE + index as u32
+}
+
+fn declared_types() -> impl Iterator<Item = &'static TokenType> {
+    TokenType::all().iter().filter(|t| !matches!(t, TokenType::EOF | TokenType::Error))
+}
+
+// `%token` block to paste into (or %include from) a grammar file.
+pub fn token_declarations() -> String {
+    let mut out = String::new();
+    writeln!(out, "/* Generated by mcpp-lexer --emit-bison-defs. Do not edit. */").unwrap();
+    for token_type in declared_types() {
+        writeln!(out, "%token {} {}", token_name(token_type), token_code(token_type)).unwrap();
+    }
+    out
+}
+
+// C header with the same numbering for code that does not include the
+// Bison-generated parser header.
+pub fn token_header(guard: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "/* Generated by mcpp-lexer --emit-bison-defs. Do not edit. */").unwrap();
+    writeln!(out, "#ifndef {}", guard).unwrap();
+    writeln!(out, "#define {}\n", guard).unwrap();
+    writeln!(out, "enum mcpp_token {{").unwrap();
+    writeln!(out, "    YYEOF = 0,").unwrap();
+    writeln!(out, "    YYerror = {},", ERROR_TOKEN_CODE).unwrap();
+    for token_type in declared_types() {
+        writeln!(out, "    {} = {},", token_name(token_type), token_code(token_type)).unwrap();
+    }
+    writeln!(out, "}};\n").unwrap();
+    writeln!(out, "#endif").unwrap();
+    out
+}
+
+// One token per line: `code<TAB>line<TAB>column<TAB>lexeme`, with the lexeme
+// C-escaped so a yylex() shim can read it back with a simple scanf loop.
+// Merged and raw string literals are written as the ordinary string literal
+// they stand for.
+pub fn token_stream(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        writeln!(out, "{}\t{}\t{}\t{}",
+            token_code(&token.token_type),
+            token.line,
+            token.column,
+            escape_c(&string_text(token))
+        ).unwrap();
+    }
+    out
+}
+
+fn string_text(token: &Token) -> Cow<'_, str> {
+    match &token.value {
+        Some(value) if token.lexeme.starts_with('R') => {
+            Cow::Owned(format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        }
+        Some(value) => Cow::Owned(format!("\"{}\"", value)),
+        None => Cow::Borrowed(&token.lexeme),
+    }
+}
+
+fn escape_c(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use std::collections::HashSet;
+
+    // The committed grammar/tokens.y and tokens.h must be what the current
+    // TokenType generates; after changing the token types, regenerate them
+    // with `mcpp-lexer --emit-bison-defs grammar/tokens.y`.
+    #[test]
+    fn committed_definitions_match_the_token_types() {
+        assert_eq!(token_declarations(), include_str!("../grammar/tokens.y"), "grammar/tokens.y is stale");
+        assert_eq!(token_header("TOKENS_H"), include_str!("../grammar/tokens.h"), "grammar/tokens.h is stale");
+    }
+
+    #[test]
+    fn every_token_type_has_its_own_code_and_name() {
+        let codes: HashSet<u32> = TokenType::all().iter().map(token_code).collect();
+        let names: HashSet<String> = TokenType::all().iter().map(token_name).collect();
+        assert_eq!(codes.len(), TokenType::all().len());
+        assert_eq!(names.len(), TokenType::all().len());
+        assert_eq!(token_code(&TokenType::EOF), 0);
+        assert_eq!(token_code(&TokenType::Error), ERROR_TOKEN_CODE);
+        assert!(declared_types().all(|t| token_code(t) >= FIRST_TOKEN_CODE));
+        assert_eq!(token_name(&TokenType::Literal(crate::lexer::LiteralKind::Integer)), "T_INTEGER_LITERAL");
+    }
+
+    #[test]
+    fn stream_rows_escape_lexemes() {
+        let mut lexer = Lexer::new("string s = \"a\\tb\";\n".to_string());
+        lexer.tokenize().unwrap();
+        let stream = token_stream(lexer.get_tokens());
+        let rows: Vec<Vec<&str>> = stream.lines().map(|row| row.split('\t').collect()).collect();
+        assert_eq!(rows.len(), lexer.get_tokens().len());
+        assert_eq!(rows[3], [token_code(&lexer.get_tokens()[3].token_type).to_string().as_str(), "1", "12", "\"a\\\\tb\""]);
+        assert_eq!(rows.last().unwrap()[0], "0");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::lexer::Token;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Entries written by a different lexer version are never trusted.
+pub const CACHE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    version: String,
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    tokens_file: String,
+    tokens_hash: String,
+}
+
+// Token streams of previously lexed inputs, keyed by a hash of the source
+// text and of everything else that affects the output.
+pub struct Cache {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl Cache {
+    // Opens (creating if needed) a cache directory. A missing, unreadable or
+    // outdated manifest starts an empty cache instead of failing.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Cache> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let manifest = fs::read(dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Manifest>(&bytes).ok())
+            .filter(|manifest| manifest.version == CACHE_VERSION)
+            .unwrap_or_else(|| Manifest {
+                version: CACHE_VERSION.to_string(),
+                entries: BTreeMap::new(),
+            });
+        Ok(Cache { dir, manifest })
+    }
+
+    // `fingerprint` describes the options the token stream depends on.
+    pub fn key(source: &str, fingerprint: &str) -> String {
+        let hash = content_hash(&[CACHE_VERSION.as_bytes(), fingerprint.as_bytes(), source.as_bytes()]);
+        format!("{:016x}", hash)
+    }
+
+    // Returns the cached tokens for `path` if they were stored under `key`
+    // and the stored file is intact.
+    pub fn lookup(&self, path: &str, key: &str) -> Option<Vec<Token>> {
+        let entry = self.manifest.entries.get(path).filter(|entry| entry.key == key)?;
+        let bytes = fs::read(self.dir.join(&entry.tokens_file)).ok()?;
+        if format!("{:016x}", content_hash(&[&bytes])) != entry.tokens_hash {
+            return None;
+        }
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    pub fn store(&mut self, path: &str, key: &str, tokens: &[Token]) -> io::Result<()> {
+        let bytes = serde_json::to_vec(tokens)?;
+        let tokens_file = format!("{}.json", key);
+        write_atomic(&self.dir.join(&tokens_file), &bytes)?;
+
+        let entry = CacheEntry {
+            key: key.to_string(),
+            tokens_file,
+            tokens_hash: format!("{:016x}", content_hash(&[&bytes])),
+        };
+        if let Some(old) = self.manifest.entries.insert(path.to_string(), entry) {
+            let still_used = self.manifest.entries.values().any(|e| e.tokens_file == old.tokens_file);
+            if !still_used {
+                let _ = fs::remove_file(self.dir.join(&old.tokens_file));
+            }
+        }
+
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        write_atomic(&self.dir.join(MANIFEST_FILE), &manifest)
+    }
+}
+
+// Writes through a temporary file so an interrupted run never leaves a
+// half-written entry behind.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(&temp, path)
+}
+
+// 64-bit FNV-1a over the concatenated parts, each followed by a separator so
+// that ("ab", "c") and ("a", "bc") hash differently. Unlike DefaultHasher its
+// output is fixed, so cache keys stay valid across toolchains.
+pub fn content_hash(parts: &[&[u8]]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = OFFSET;
+    for part in parts {
+        for byte in part.iter().chain(std::iter::once(&0xff)) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mcpp-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn stored_tokens_are_found_under_the_same_key() {
+        let dir = temp_dir("hit");
+        let tokens = lex("int x = 1;\n").unwrap();
+        let key = Cache::key("int x = 1;\n", "default");
+        let mut cache = Cache::open(&dir).unwrap();
+        assert_eq!(cache.lookup("a.mcpp", &key), None);
+        cache.store("a.mcpp", &key, &tokens).unwrap();
+        // A fresh handle reads the manifest back
+        let cache = Cache::open(&dir).unwrap();
+        assert_eq!(cache.lookup("a.mcpp", &key), Some(tokens));
+        assert_eq!(cache.lookup("b.mcpp", &key), None);
+        assert_eq!(cache.lookup("a.mcpp", &Cache::key("int x = 2;\n", "default")), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keys_depend_on_source_and_options() {
+        let key = Cache::key("int x;", "default");
+        assert_eq!(key, Cache::key("int x;", "default"));
+        assert_ne!(key, Cache::key("int y;", "default"));
+        assert_ne!(key, Cache::key("int x;", "keep_comments"));
+        assert_eq!(key.len(), 16);
+        assert_ne!(content_hash(&[b"ab", b"c"]), content_hash(&[b"a", b"bc"]));
+    }
+
+    #[test]
+    fn corruption_and_version_changes_are_misses() {
+        let dir = temp_dir("corrupt");
+        let tokens = lex("int x = 1;\n").unwrap();
+        let key = Cache::key("int x = 1;\n", "default");
+        let mut cache = Cache::open(&dir).unwrap();
+        cache.store("a.mcpp", &key, &tokens).unwrap();
+        fs::write(dir.join(format!("{}.json", key)), b"[]").unwrap();
+        assert_eq!(Cache::open(&dir).unwrap().lookup("a.mcpp", &key), None);
+
+        cache.store("a.mcpp", &key, &tokens).unwrap();
+        let manifest = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), manifest.replace(CACHE_VERSION, "0.0.0-other")).unwrap();
+        assert_eq!(Cache::open(&dir).unwrap().lookup("a.mcpp", &key), None);
+
+        fs::write(dir.join(MANIFEST_FILE), b"{not json").unwrap();
+        assert_eq!(Cache::open(&dir).unwrap().lookup("a.mcpp", &key), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
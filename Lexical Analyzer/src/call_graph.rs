@@ -0,0 +1,173 @@
+use crate::lexer::SymbolTable;
+use std::fmt::Write;
+
+// Which functions call which, from the call sites in a symbol table. Calls
+// outside every function body have no caller and are left out. A callee
+// never declared in the input (usually from an `#include`) is external.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CallGraph {
+    functions: Vec<String>,
+    external: Vec<String>,
+    edges: Vec<(String, String)>,
+}
+
+impl CallGraph {
+    pub fn build(table: &SymbolTable) -> Self {
+        let mut graph = CallGraph::default();
+        for symbol in table.get_symbols().iter().filter(|s| s.symbol_type == "function") {
+            if !graph.functions.contains(&symbol.name) {
+                graph.functions.push(symbol.name.clone());
+            }
+        }
+        for site in table.call_sites() {
+            let Some(caller) = &site.caller else {
+                continue;
+            };
+            if !graph.functions.contains(&site.callee) && !graph.external.contains(&site.callee) {
+                graph.external.push(site.callee.clone());
+            }
+            let edge = (caller.clone(), site.callee.clone());
+            if !graph.edges.contains(&edge) {
+                graph.edges.push(edge);
+            }
+        }
+        graph
+    }
+
+    // Functions declared in the input, in declaration order.
+    pub fn functions(&self) -> &[String] {
+        &self.functions
+    }
+
+    pub fn external(&self) -> &[String] {
+        &self.external
+    }
+
+    pub fn is_external(&self, name: &str) -> bool {
+        self.external.iter().any(|e| e == name)
+    }
+
+    // (caller, callee) pairs in order of first call. A recursive function
+    // has an edge to itself.
+    pub fn edges(&self) -> &[(String, String)] {
+        &self.edges
+    }
+
+    pub fn callees(&self, caller: &str) -> Vec<&str> {
+        self.edges
+            .iter()
+            .filter(|(from, _)| from == caller)
+            .map(|(_, to)| to.as_str())
+            .collect()
+    }
+
+    // One line per declared function listing what it calls.
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("=== CALL GRAPH ===\n");
+        for function in &self.functions {
+            let callees: Vec<String> = self
+                .callees(function)
+                .into_iter()
+                .map(|callee| {
+                    if self.is_external(callee) {
+                        format!("{} (external)", callee)
+                    } else {
+                        callee.to_string()
+                    }
+                })
+                .collect();
+            if callees.is_empty() {
+                writeln!(out, "{}", function).unwrap();
+            } else {
+                writeln!(out, "{} -> {}", function, callees.join(", ")).unwrap();
+            }
+        }
+        out
+    }
+
+    // Graphviz source; external functions are drawn dashed.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph calls {\n");
+        for function in &self.functions {
+            writeln!(out, "    \"{}\";", function).unwrap();
+        }
+        for function in &self.external {
+            writeln!(out, "    \"{}\" [style=dashed];", function).unwrap();
+        }
+        for (caller, callee) in &self.edges {
+            writeln!(out, "    \"{}\" -> \"{}\";", caller, callee).unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    const PROGRAM: &str = "int fact(int n) { if (n < 2) { return 1; } return n * fact(n - 1); }\n\
+                           int square(int x) { return x * x; }\n\
+                           int main() { int a = fact(square(2)); printf(a); return helper(a); }\n\
+                           int helper(int v) { return square(v); }\n\
+                           int g = fact(3);\n";
+
+    fn graph(source: &str) -> (CallGraph, Lexer) {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        (CallGraph::build(lexer.get_symbol_table()), lexer)
+    }
+
+    #[test]
+    fn calls_are_told_apart_from_declarations() {
+        let (_, lexer) = graph(PROGRAM);
+        let sites: Vec<(&str, Option<&str>, usize)> = lexer
+            .get_symbol_table()
+            .call_sites()
+            .iter()
+            .map(|s| (s.callee.as_str(), s.caller.as_deref(), s.line))
+            .collect();
+        assert_eq!(
+            sites,
+            [
+                ("fact", Some("fact"), 1),
+                ("fact", Some("main"), 3),
+                ("square", Some("main"), 3),
+                ("printf", Some("main"), 3),
+                ("helper", Some("main"), 3),
+                ("square", Some("helper"), 4),
+                // A global initializer has no caller
+                ("fact", None, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn edges_cover_recursion_and_external_calls() {
+        let (graph, _) = graph(PROGRAM);
+        assert_eq!(graph.functions(), ["fact", "square", "main", "helper"]);
+        assert_eq!(graph.external(), ["printf"]);
+        let edges: Vec<(&str, &str)> = graph.edges().iter().map(|(a, b)| (a.as_str(), b.as_str())).collect();
+        assert_eq!(
+            edges,
+            [("fact", "fact"), ("main", "fact"), ("main", "square"), ("main", "printf"), ("main", "helper"), ("helper", "square")]
+        );
+        assert!(graph.callees("square").is_empty());
+        assert!(graph.is_external("printf") && !graph.is_external("helper"));
+    }
+
+    #[test]
+    fn text_and_dot_renderings() {
+        let (graph, _) = graph(PROGRAM);
+        assert_eq!(
+            graph.to_text(),
+            "=== CALL GRAPH ===\nfact -> fact\nsquare\nmain -> fact, square, printf (external), helper\nhelper -> square\n"
+        );
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph calls {\n    \"fact\";\n"));
+        assert!(dot.contains("    \"printf\" [style=dashed];\n"));
+        assert!(dot.contains("    \"helper\" -> \"square\";\n}\n"));
+        assert_eq!(dot.matches(" -> ").count(), 6);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::lexer::{Delimiter, Keyword, LiteralKind, Operator, Token, TokenType};
+use crate::position::PositionBase;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// One branch of a conditional: the lines after its `#if`, `#ifdef`,
+// `#ifndef`, `#elif` or `#else` up to the next directive of the same chain.
+// `included` is false only where the branch is known not to be taken, or
+// an enclosing one is not; a branch whose condition could not be evaluated
+// is included, as is every later one that is not known to be false.
+// Written to the JSON document as `conditionals`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConditionalBranch {
+    pub directive: String,
+    pub line: usize,
+    pub column: usize,
+    pub first_line: usize,
+    pub last_line: usize,
+    pub included: bool,
+}
+
+impl ConditionalBranch {
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        self.line = from.rebase(self.line, to);
+        self.column = from.rebase(self.column, to);
+        self.first_line = from.rebase(self.first_line, to);
+        self.last_line = from.rebase(self.last_line, to);
+    }
+
+    // Whether `line` is one of the branch's own lines and the branch is not
+    // compiled.
+    pub fn excludes(&self, line: usize) -> bool {
+        !self.included && (self.first_line..=self.last_line).contains(&line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionalProblemKind {
+    // The condition, with why it could not be evaluated
+    Unevaluable(String),
+    // An `#if` still open at the end of the file
+    Unterminated,
+    // An `#elif`, `#else` or `#endif` with no `#if` to belong to
+    Unmatched,
+    // An `#elif` or `#else` after the `#else` of the chain opened at the line
+    AfterElse(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionalProblem {
+    pub kind: ConditionalProblemKind,
+    pub directive: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConditionalReport {
+    pub branches: Vec<ConditionalBranch>,
+    pub problems: Vec<ConditionalProblem>,
+}
+
+// An `#if` chain being read: the branch open now, whether an earlier branch
+// is known to have been taken, and whether `#else` has been seen.
+struct Chain {
+    directive: String,
+    line: usize,
+    column: usize,
+    enclosing: bool,
+    taken: bool,
+    seen_else: bool,
+    branch: usize,
+}
+
+// Follows the conditional directives in `tokens` and works out which
+// branches are compiled. Conditions may use integer literals, macros that
+// an earlier `#define` in an included branch gives an integer value,
+// `defined(X)` or `defined X`, arithmetic, comparisons, `&` and the logical
+// operators, with C's precedence. The lexer does not follow `#include`, so
+// a name with no `#define` in the file makes a condition unevaluable
+// instead of 0.
+pub fn conditionals(tokens: &[Token]) -> ConditionalReport {
+    let last_line = tokens.iter().rev().find(|t| t.token_type != TokenType::EOF).map_or(1, |t| t.end_line);
+    let mut report = ConditionalReport::default();
+    let mut macros: HashMap<&str, Option<i64>> = HashMap::new();
+    let mut chains: Vec<Chain> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let arguments = tokens[i + 1..].iter().take_while(|t| t.directive && t.line == token.line).filter(|t| t.token_type != TokenType::Comment);
+        let arguments: Vec<&Token> = arguments.collect();
+        let directive = match token.token_type {
+            TokenType::Keyword(Keyword::Define | Keyword::Directive) => token.lexeme.as_str(),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        i += 1 + arguments.len();
+        let active = chains.last().is_none_or(|chain| report.branches[chain.branch].included);
+        let problem = |kind| ConditionalProblem {
+            kind,
+            directive: directive.to_string(),
+            line: token.line,
+            column: token.column,
+        };
+
+        match directive {
+            "#define" if active => {
+                if let Some(name) = arguments.first().filter(|t| t.token_type == TokenType::Identifier) {
+                    let function_like = arguments
+                        .get(1)
+                        .is_some_and(|t| t.token_type == TokenType::Delimiter(Delimiter::LeftParen) && t.line == name.line && t.column == name.end_column + 1);
+                    let value = match &arguments[1..] {
+                        [value] if !function_like => integer_value(value),
+                        _ => None,
+                    };
+                    macros.insert(name.lexeme.as_str(), value);
+                }
+            }
+            "#undef" if active => {
+                if let Some(name) = arguments.first() {
+                    macros.remove(name.lexeme.as_str());
+                }
+            }
+            "#if" | "#ifdef" | "#ifndef" => {
+                let value = match directive {
+                    "#if" => evaluate(&arguments, &macros),
+                    _ => match arguments.first() {
+                        Some(name) if name.token_type == TokenType::Identifier => {
+                            Ok(i64::from(macros.contains_key(name.lexeme.as_str()) == (directive == "#ifdef")))
+                        }
+                        _ => Err("a macro name is needed".to_string()),
+                    },
+                };
+                if let Err(reason) = &value {
+                    report.problems.push(problem(ConditionalProblemKind::Unevaluable(reason.clone())));
+                }
+                let included = active && !matches!(value, Ok(0));
+                report.branches.push(branch(directive, token, included));
+                chains.push(Chain {
+                    directive: directive.to_string(),
+                    line: token.line,
+                    column: token.column,
+                    enclosing: active,
+                    taken: value.is_ok_and(|v| v != 0),
+                    seen_else: false,
+                    branch: report.branches.len() - 1,
+                });
+            }
+            "#elif" | "#else" => {
+                let Some(chain) = chains.last_mut() else {
+                    report.problems.push(problem(ConditionalProblemKind::Unmatched));
+                    continue;
+                };
+                if chain.seen_else {
+                    report.problems.push(problem(ConditionalProblemKind::AfterElse(chain.line)));
+                    continue;
+                }
+                report.branches[chain.branch].last_line = token.line - 1;
+                let value = if directive == "#elif" { evaluate(&arguments, &macros) } else { Ok(1) };
+                if let Err(reason) = &value {
+                    report.problems.push(problem(ConditionalProblemKind::Unevaluable(reason.clone())));
+                }
+                let included = chain.enclosing && !chain.taken && !matches!(value, Ok(0));
+                chain.taken |= value.is_ok_and(|v| v != 0);
+                chain.seen_else = directive == "#else";
+                report.branches.push(branch(directive, token, included));
+                chain.branch = report.branches.len() - 1;
+            }
+            "#endif" => match chains.pop() {
+                Some(chain) => report.branches[chain.branch].last_line = token.line - 1,
+                None => report.problems.push(problem(ConditionalProblemKind::Unmatched)),
+            },
+            _ => {}
+        }
+    }
+
+    for chain in chains.into_iter().rev() {
+        let open = &mut report.branches[chain.branch];
+        open.last_line = last_line.max(open.line);
+        report.problems.push(ConditionalProblem {
+            kind: ConditionalProblemKind::Unterminated,
+            directive: chain.directive,
+            line: chain.line,
+            column: chain.column,
+        });
+    }
+    report.problems.sort_by_key(|p| (p.line, p.column));
+    report
+}
+
+fn branch(directive: &str, token: &Token, included: bool) -> ConditionalBranch {
+    ConditionalBranch {
+        directive: directive.to_string(),
+        line: token.line,
+        column: token.column,
+        first_line: token.line + 1,
+        last_line: token.line,
+        included,
+    }
+}
+
+// The value of an integer literal; MCPP's are plain decimal digits.
+fn integer_value(token: &Token) -> Option<i64> {
+    if token.token_type != TokenType::Literal(LiteralKind::Integer) {
+        return None;
+    }
+    token.lexeme.parse().ok()
+}
+
+// The value of a condition, or why it has none.
+fn evaluate(tokens: &[&Token], macros: &HashMap<&str, Option<i64>>) -> Result<i64, String> {
+    if tokens.is_empty() {
+        return Err("the condition is empty".to_string());
+    }
+    let mut parser = Parser { tokens, position: 0, macros };
+    let value = parser.expression(0)?;
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(format!("unexpected '{}'", token.lexeme)),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [&'a Token],
+    position: usize,
+    macros: &'a HashMap<&'a str, Option<i64>>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&TokenType> {
+        self.tokens.get(self.position).map(|t| &t.token_type)
+    }
+
+    fn expect_right_paren(&mut self) -> Result<(), String> {
+        if self.peek() != Some(&TokenType::Delimiter(Delimiter::RightParen)) {
+            return Err("a ')' is missing".to_string());
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    // Binary operators binding tighter than `min`, by the operator's
+    // precedence level.
+    fn expression(&mut self, min: u8) -> Result<i64, String> {
+        let mut left = self.unary()?;
+        while let Some(TokenType::Operator(operator)) = self.peek() {
+            let operator = *operator;
+            let Some(level) = binary_level(operator).filter(|level| *level > min) else {
+                break;
+            };
+            self.position += 1;
+            let right = self.expression(level)?;
+            left = apply(operator, left, right)?;
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let Some(token) = self.tokens.get(self.position) else {
+            return Err("the condition ends early".to_string());
+        };
+        self.position += 1;
+        match &token.token_type {
+            TokenType::Operator(Operator::LogicalNot) => Ok(i64::from(self.unary()? == 0)),
+            TokenType::Operator(Operator::Minus) => self.unary()?.checked_neg().ok_or_else(overflow),
+            TokenType::Operator(Operator::Plus) => self.unary(),
+            TokenType::Delimiter(Delimiter::LeftParen) => {
+                let value = self.expression(0)?;
+                self.expect_right_paren()?;
+                Ok(value)
+            }
+            TokenType::Literal(LiteralKind::Integer) => integer_value(token).ok_or_else(|| format!("'{}' does not fit in 64 bits", token.lexeme)),
+            TokenType::Identifier if token.lexeme == "defined" => {
+                let parenthesized = self.peek() == Some(&TokenType::Delimiter(Delimiter::LeftParen));
+                self.position += usize::from(parenthesized);
+                let name = match self.tokens.get(self.position) {
+                    Some(name) if name.token_type == TokenType::Identifier => name,
+                    _ => return Err("'defined' needs a macro name".to_string()),
+                };
+                self.position += 1;
+                if parenthesized {
+                    self.expect_right_paren()?;
+                }
+                Ok(i64::from(self.macros.contains_key(name.lexeme.as_str())))
+            }
+            TokenType::Identifier => match self.macros.get(token.lexeme.as_str()) {
+                Some(Some(value)) => Ok(*value),
+                Some(None) => Err(format!("'{}' is not defined as an integer", token.lexeme)),
+                None => Err(format!("'{}' is not defined in this file", token.lexeme)),
+            },
+            _ => Err(format!("unexpected '{}'", token.lexeme)),
+        }
+    }
+}
+
+fn binary_level(operator: Operator) -> Option<u8> {
+    match operator {
+        Operator::LogicalOr => Some(1),
+        Operator::LogicalAnd => Some(2),
+        Operator::Ampersand => Some(3),
+        Operator::Equal | Operator::NotEqual => Some(4),
+        Operator::LessThan | Operator::GreaterThan | Operator::LessEqual | Operator::GreaterEqual => Some(5),
+        Operator::Plus | Operator::Minus => Some(6),
+        Operator::Multiply | Operator::Divide | Operator::Modulo => Some(7),
+        _ => None,
+    }
+}
+
+fn overflow() -> String {
+    "the value overflows 64 bits".to_string()
+}
+
+fn apply(operator: Operator, left: i64, right: i64) -> Result<i64, String> {
+    let value = match operator {
+        Operator::LogicalOr => Some(i64::from(left != 0 || right != 0)),
+        Operator::LogicalAnd => Some(i64::from(left != 0 && right != 0)),
+        Operator::Ampersand => Some(left & right),
+        Operator::Equal => Some(i64::from(left == right)),
+        Operator::NotEqual => Some(i64::from(left != right)),
+        Operator::LessThan => Some(i64::from(left < right)),
+        Operator::GreaterThan => Some(i64::from(left > right)),
+        Operator::LessEqual => Some(i64::from(left <= right)),
+        Operator::GreaterEqual => Some(i64::from(left >= right)),
+        Operator::Plus => left.checked_add(right),
+        Operator::Minus => left.checked_sub(right),
+        Operator::Multiply => left.checked_mul(right),
+        Operator::Divide | Operator::Modulo if right == 0 => return Err("division by zero".to_string()),
+        Operator::Divide => left.checked_div(right),
+        Operator::Modulo => left.checked_rem(right),
+        _ => None,
+    };
+    value.ok_or_else(overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // Nested chains taking their first, a later and no branch, with the
+    // line each branch is on.
+    const NESTED: &str = "\
+#define VERSION 3
+#define FEATURE
+#if VERSION >= 2
+int a;
+#if defined(FEATURE) && !defined(LEGACY)
+int b;
+#elif VERSION == 3
+int c;
+#else
+int d;
+#endif
+#elif 1
+int e;
+#else
+int f;
+#endif
+#if VERSION < 2
+int g;
+#if 1
+int h;
+#endif
+#elif VERSION * 2 == 6
+int i;
+#else
+int j;
+#endif
+";
+
+    fn report(source: &str) -> ConditionalReport {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        lexer.conditionals()
+    }
+
+    fn branches(report: &ConditionalReport) -> Vec<(&str, usize, usize, usize, bool)> {
+        report.branches.iter().map(|b| (b.directive.as_str(), b.line, b.first_line, b.last_line, b.included)).collect()
+    }
+
+    #[test]
+    fn nested_chains_take_one_branch_each() {
+        let report = report(NESTED);
+        assert_eq!(
+            branches(&report),
+            [
+                ("#if", 3, 4, 11, true),
+                ("#if", 5, 6, 6, true),
+                ("#elif", 7, 8, 8, false),
+                ("#else", 9, 10, 10, false),
+                ("#elif", 12, 13, 13, false),
+                ("#else", 14, 15, 15, false),
+                ("#if", 17, 18, 21, false),
+                // True, but inside a branch that is not compiled
+                ("#if", 19, 20, 20, false),
+                ("#elif", 22, 23, 23, true),
+                ("#else", 24, 25, 25, false),
+            ]
+        );
+        assert!(report.problems.is_empty());
+    }
+
+    #[test]
+    fn else_is_taken_when_nothing_before_it_is() {
+        let report = report("#define N 0\n#if N\nint a;\n#elif N + 1 > 1\nint b;\n#else\nint c;\n#endif\n");
+        let included: Vec<_> = report.branches.iter().map(|b| b.included).collect();
+        assert_eq!(included, [false, false, true]);
+    }
+
+    #[test]
+    fn unevaluable_conditions_keep_their_branch() {
+        let report = report("#if UNKNOWN > 1\nint a;\n#elif 0\nint b;\n#else\nint c;\n#endif\n");
+        let included: Vec<_> = report.branches.iter().map(|b| b.included).collect();
+        assert_eq!(included, [true, false, true]);
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].kind, ConditionalProblemKind::Unevaluable("'UNKNOWN' is not defined in this file".to_string()));
+    }
+
+    #[test]
+    fn chains_open_at_the_end_run_to_the_last_line() {
+        let report = report("#if 1\nint a;\n#if 0\nint b;\n#else\nint c;\n");
+        assert_eq!(branches(&report), [("#if", 1, 2, 6, true), ("#if", 3, 4, 4, false), ("#else", 5, 6, 6, true)]);
+        let problems: Vec<_> = report.problems.iter().map(|p| (&p.kind, p.directive.as_str(), p.line)).collect();
+        assert_eq!(problems, [(&ConditionalProblemKind::Unterminated, "#if", 1), (&ConditionalProblemKind::Unterminated, "#if", 3)]);
+    }
+
+    #[test]
+    fn stray_directives_are_unmatched() {
+        let report = report("#endif\n#if 1\n#else\n#else\n#endif\n#elif 1\n");
+        let kinds: Vec<_> = report.problems.iter().map(|p| (&p.kind, p.line)).collect();
+        assert_eq!(
+            kinds,
+            [(&ConditionalProblemKind::Unmatched, 1), (&ConditionalProblemKind::AfterElse(2), 4), (&ConditionalProblemKind::Unmatched, 6)]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+
+// The project config file, looked for next to the input and in each
+// directory above it.
+pub const CONFIG_FILE_NAME: &str = ".mcpprc";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigValue {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<ConfigValue>),
+}
+
+impl ConfigValue {
+    // The value as a command-line argument would spell it; None for an
+    // array.
+    pub fn as_arg(&self) -> Option<String> {
+        match self {
+            ConfigValue::String(text) => Some(text.clone()),
+            ConfigValue::Integer(number) => Some(number.to_string()),
+            ConfigValue::Bool(flag) => Some(flag.to_string()),
+            ConfigValue::Array(_) => None,
+        }
+    }
+}
+
+// One `key = value` line. A key under a `[table]` header is named
+// `table.key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: ConfigValue,
+    pub line: usize,
+}
+
+// The nearest config file in `dir` or a directory above it.
+pub fn find_config(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    dir.ancestors().map(|d| d.join(CONFIG_FILE_NAME)).find(|path| path.is_file())
+}
+
+// Reads the part of TOML a flat list of settings needs: bare keys, `[table]`
+// headers, basic and literal strings, integers, booleans and one-line
+// arrays of those, with `#` comments.
+pub fn parse_config(text: &str) -> Result<Vec<ConfigEntry>, String> {
+    let mut entries = Vec::new();
+    let mut table = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let (name, rest) = header.split_once(']').ok_or_else(|| format!("Line {}: unclosed table header", line_number))?;
+            if !is_bare_key(name.trim()) || !is_comment_or_empty(rest) {
+                return Err(format!("Line {}: expected `[table]`, found '{}'", line_number, line));
+            }
+            table = name.trim().to_string();
+            continue;
+        }
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Line {}: expected `key = value`, found '{}'", line_number, line))?;
+        let key = key.trim();
+        if !is_bare_key(key) {
+            return Err(format!("Line {}: '{}' is not a bare key", line_number, key));
+        }
+        let mut cursor = Cursor { rest: rest.trim_start(), line: line_number };
+        let value = cursor.value()?;
+        if !is_comment_or_empty(cursor.rest) {
+            return Err(format!("Line {}: unexpected '{}' after the value", line_number, cursor.rest.trim()));
+        }
+        let key = if table.is_empty() { key.to_string() } else { format!("{}.{}", table, key) };
+        if entries.iter().any(|e: &ConfigEntry| e.key == key) {
+            return Err(format!("Line {}: '{}' is set twice", line_number, key));
+        }
+        entries.push(ConfigEntry { key, value, line: line_number });
+    }
+    Ok(entries)
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_comment_or_empty(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+    line: usize,
+}
+
+impl Cursor<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("Line {}: {}", self.line, message)
+    }
+
+    fn value(&mut self) -> Result<ConfigValue, String> {
+        let value = match self.rest.chars().next() {
+            Some('"') => ConfigValue::String(self.basic_string()?),
+            Some('\'') => {
+                let end = self.rest[1..].find('\'').ok_or_else(|| self.error("unterminated string"))?;
+                let text = self.rest[1..end + 1].to_string();
+                self.rest = &self.rest[end + 2..];
+                ConfigValue::String(text)
+            }
+            Some('[') => {
+                self.rest = self.rest[1..].trim_start();
+                let mut items = Vec::new();
+                while !self.rest.starts_with(']') {
+                    items.push(self.value()?);
+                    self.rest = self.rest.trim_start();
+                    match self.rest.strip_prefix(',') {
+                        Some(rest) => self.rest = rest.trim_start(),
+                        None if self.rest.starts_with(']') => {}
+                        None => return Err(self.error("expected ',' or ']' in array")),
+                    }
+                }
+                self.rest = &self.rest[1..];
+                ConfigValue::Array(items)
+            }
+            _ => {
+                let end = self.rest.find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#').unwrap_or(self.rest.len());
+                let word = &self.rest[..end];
+                let value = match word {
+                    "true" => ConfigValue::Bool(true),
+                    "false" => ConfigValue::Bool(false),
+                    _ => ConfigValue::Integer(
+                        word.replace('_', "")
+                            .parse()
+                            .map_err(|_| self.error(&format!("'{}' is not a string, integer, boolean or array", word)))?,
+                    ),
+                };
+                self.rest = &self.rest[end..];
+                value
+            }
+        };
+        Ok(value)
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        let mut text = String::new();
+        let mut chars = self.rest.char_indices().skip(1);
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(text);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('u') => {
+                        let digits: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let ch = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32);
+                        text.push(ch.ok_or_else(|| self.error(&format!("invalid escape '\\u{}'", digits)))?);
+                    }
+                    Some(other) => return Err(self.error(&format!("unknown escape '\\{}'", other))),
+                    None => break,
+                },
+                _ => text.push(ch),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mcpp-config-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn the_nearest_config_is_found() {
+        let root = temp_dir("find");
+        let inner = root.join("course").join("week1");
+        fs::create_dir_all(&inner).unwrap();
+        assert_eq!(find_config(&inner), None);
+        fs::write(root.join(CONFIG_FILE_NAME), "keep-comments = true\n").unwrap();
+        assert_eq!(find_config(&inner), Some(root.canonicalize().unwrap().join(CONFIG_FILE_NAME)));
+        fs::write(root.join("course").join(CONFIG_FILE_NAME), "").unwrap();
+        assert_eq!(find_config(&inner), Some(root.join("course").canonicalize().unwrap().join(CONFIG_FILE_NAME)));
+        // A directory of that name is not a config file
+        fs::create_dir(inner.join(CONFIG_FILE_NAME)).unwrap();
+        assert_eq!(find_config(&inner), find_config(&root.join("course")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn settings_parse_with_their_tables_and_lines() {
+        let text = "# shared\nprofile = \"strict\"\nmax-nesting = 1_0 # deep\n\n[extra]\nlint = ['const-candidates', \"x\\u0041\"]\non = false\n";
+        let entries = parse_config(text).unwrap();
+        let entry = |key: &str, value: ConfigValue, line: usize| ConfigEntry { key: key.to_string(), value, line };
+        assert_eq!(
+            entries,
+            [
+                entry("profile", ConfigValue::String("strict".to_string()), 2),
+                entry("max-nesting", ConfigValue::Integer(10), 3),
+                entry(
+                    "extra.lint",
+                    ConfigValue::Array(vec![ConfigValue::String("const-candidates".to_string()), ConfigValue::String("xA".to_string())]),
+                    6
+                ),
+                entry("extra.on", ConfigValue::Bool(false), 7),
+            ]
+        );
+        assert_eq!(entries[1].value.as_arg().as_deref(), Some("10"));
+        assert_eq!(entries[2].value.as_arg(), None);
+    }
+
+    #[test]
+    fn malformed_lines_name_their_line() {
+        for (text, error) in [
+            ("a = 1\na = 2\n", "Line 2: 'a' is set twice"),
+            ("[table\n", "Line 1: unclosed table header"),
+            ("just words\n", "Line 1: expected `key = value`, found 'just words'"),
+            ("a = \"open\n", "Line 1: unterminated string"),
+            ("a = [1 2]\n", "Line 1: expected ',' or ']' in array"),
+            ("a = maybe\n", "Line 1: 'maybe' is not a string, integer, boolean or array"),
+            ("a = 1 2\n", "Line 1: unexpected '2' after the value"),
+        ] {
+            assert_eq!(parse_config(text).unwrap_err(), error, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// Unicode characters that commonly end up in source pasted from PDFs, word
+// processors or slides, with the ASCII character that was meant.
+pub const CONFUSABLES: &[(char, char, &str)] = &[
+    ('\u{2018}', '\'', "left single quotation mark"),
+    ('\u{2019}', '\'', "right single quotation mark"),
+    ('\u{201A}', '\'', "single low-9 quotation mark"),
+    ('\u{2032}', '\'', "prime"),
+    ('\u{201C}', '"', "left double quotation mark"),
+    ('\u{201D}', '"', "right double quotation mark"),
+    ('\u{201E}', '"', "double low-9 quotation mark"),
+    ('\u{2033}', '"', "double prime"),
+    ('\u{2010}', '-', "hyphen"),
+    ('\u{2011}', '-', "non-breaking hyphen"),
+    ('\u{2013}', '-', "en dash"),
+    ('\u{2014}', '-', "em dash"),
+    ('\u{2212}', '-', "minus sign"),
+    ('\u{00A0}', ' ', "no-break space"),
+    ('\u{2007}', ' ', "figure space"),
+    ('\u{202F}', ' ', "narrow no-break space"),
+    ('\u{FF08}', '(', "fullwidth left parenthesis"),
+    ('\u{FF09}', ')', "fullwidth right parenthesis"),
+    ('\u{FF3B}', '[', "fullwidth left square bracket"),
+    ('\u{FF3D}', ']', "fullwidth right square bracket"),
+    ('\u{FF5B}', '{', "fullwidth left curly bracket"),
+    ('\u{FF5D}', '}', "fullwidth right curly bracket"),
+    ('\u{FF1B}', ';', "fullwidth semicolon"),
+    ('\u{FF0C}', ',', "fullwidth comma"),
+    ('\u{FF1D}', '=', "fullwidth equals sign"),
+    ('\u{FF0B}', '+', "fullwidth plus sign"),
+    ('\u{00D7}', '*', "multiplication sign"),
+    ('\u{2215}', '/', "division slash"),
+];
+
+// The intended ASCII character and the Unicode name of `ch`, if it is a
+// known look-alike.
+pub fn lookup(ch: char) -> Option<(char, &'static str)> {
+    CONFUSABLES
+        .iter()
+        .find(|(confusable, _, _)| *confusable == ch)
+        .map(|(_, ascii, name)| (*ascii, *name))
+}
+
+// No-break spaces look exactly like a space but are not MCPP whitespace
+// unless the lexer is told to be lenient about them.
+pub fn is_non_breaking_space(ch: char) -> bool {
+    matches!(ch, '\u{00A0}' | '\u{2007}' | '\u{202F}')
+}
+
+// Cyrillic and Greek letters that render like ASCII letters. An identifier
+// using one of them looks identical to, but is a different name from, its
+// ASCII spelling.
+pub const HOMOGLYPHS: &[(char, char, &str)] = &[
+    ('\u{0430}', 'a', "cyrillic small letter a"),
+    ('\u{0435}', 'e', "cyrillic small letter ie"),
+    ('\u{043E}', 'o', "cyrillic small letter o"),
+    ('\u{0440}', 'p', "cyrillic small letter er"),
+    ('\u{0441}', 'c', "cyrillic small letter es"),
+    ('\u{0443}', 'y', "cyrillic small letter u"),
+    ('\u{0445}', 'x', "cyrillic small letter ha"),
+    ('\u{0455}', 's', "cyrillic small letter dze"),
+    ('\u{0456}', 'i', "cyrillic small letter byelorussian-ukrainian i"),
+    ('\u{0458}', 'j', "cyrillic small letter je"),
+    ('\u{0501}', 'd', "cyrillic small letter komi de"),
+    ('\u{04BB}', 'h', "cyrillic small letter shha"),
+    ('\u{051B}', 'q', "cyrillic small letter qa"),
+    ('\u{051D}', 'w', "cyrillic small letter we"),
+    ('\u{0410}', 'A', "cyrillic capital letter a"),
+    ('\u{0412}', 'B', "cyrillic capital letter ve"),
+    ('\u{0415}', 'E', "cyrillic capital letter ie"),
+    ('\u{041A}', 'K', "cyrillic capital letter ka"),
+    ('\u{041C}', 'M', "cyrillic capital letter em"),
+    ('\u{041D}', 'H', "cyrillic capital letter en"),
+    ('\u{041E}', 'O', "cyrillic capital letter o"),
+    ('\u{0420}', 'P', "cyrillic capital letter er"),
+    ('\u{0421}', 'C', "cyrillic capital letter es"),
+    ('\u{0422}', 'T', "cyrillic capital letter te"),
+    ('\u{0425}', 'X', "cyrillic capital letter ha"),
+    ('\u{0405}', 'S', "cyrillic capital letter dze"),
+    ('\u{0406}', 'I', "cyrillic capital letter byelorussian-ukrainian i"),
+    ('\u{0408}', 'J', "cyrillic capital letter je"),
+    ('\u{0423}', 'Y', "cyrillic capital letter u"),
+    ('\u{03BF}', 'o', "greek small letter omicron"),
+    ('\u{03BD}', 'v', "greek small letter nu"),
+    ('\u{0391}', 'A', "greek capital letter alpha"),
+    ('\u{0392}', 'B', "greek capital letter beta"),
+    ('\u{0395}', 'E', "greek capital letter epsilon"),
+    ('\u{0396}', 'Z', "greek capital letter zeta"),
+    ('\u{0397}', 'H', "greek capital letter eta"),
+    ('\u{0399}', 'I', "greek capital letter iota"),
+    ('\u{039A}', 'K', "greek capital letter kappa"),
+    ('\u{039C}', 'M', "greek capital letter mu"),
+    ('\u{039D}', 'N', "greek capital letter nu"),
+    ('\u{039F}', 'O', "greek capital letter omicron"),
+    ('\u{03A1}', 'P', "greek capital letter rho"),
+    ('\u{03A4}', 'T', "greek capital letter tau"),
+    ('\u{03A5}', 'Y', "greek capital letter upsilon"),
+    ('\u{03A7}', 'X', "greek capital letter chi"),
+];
+
+pub fn homoglyph(ch: char) -> Option<(char, &'static str)> {
+    HOMOGLYPHS
+        .iter()
+        .find(|(homoglyph, _, _)| *homoglyph == ch)
+        .map(|(_, ascii, name)| (*ascii, *name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions, TokenType};
+
+    fn first_error(source: &str) -> crate::lexer::LexError {
+        Lexer::new(source.to_string()).tokenize().unwrap_err()
+    }
+
+    #[test]
+    fn every_confusable_suggests_its_ascii_character() {
+        for (ch, ascii, name) in CONFUSABLES {
+            let error = first_error(&format!("a {} b", ch));
+            assert_eq!(error.code, "E0002", "{}", name);
+            assert_eq!(
+                error.message,
+                format!("Invalid character '{}' (U+{:04X}, {}); did you mean '{}'?", ch, *ch as u32, name, ascii)
+            );
+            assert_eq!((error.line, error.column, error.length), (1, 3, 1));
+        }
+    }
+
+    #[test]
+    fn other_characters_get_no_suggestion() {
+        let error = first_error("a @ b");
+        assert_eq!(error.code, "E0001");
+        assert!(!error.message.contains("did you mean"));
+        assert_eq!(lookup('@'), None);
+    }
+
+    #[test]
+    fn lenient_whitespace_accepts_only_no_break_spaces() {
+        let options = LexerOptions {
+            lenient_whitespace: true,
+            ..LexerOptions::default()
+        };
+        for space in ['\u{00A0}', '\u{2007}', '\u{202F}'] {
+            assert!(is_non_breaking_space(space));
+            let mut lexer = Lexer::new(format!("int{}x = 1;", space));
+            lexer.set_options(options.clone());
+            lexer.tokenize().unwrap();
+            assert_eq!(lexer.get_tokens()[1].token_type, TokenType::Identifier);
+            assert_eq!(lexer.get_tokens()[1].column, 5);
+        }
+        let mut lexer = Lexer::new("a – b".to_string());
+        lexer.set_options(options);
+        assert_eq!(lexer.tokenize().unwrap_err().code, "E0002");
+    }
+}
@@ -0,0 +1,433 @@
+// Hand-built DFAs for a few MCPP token classes, used to show in class how the
+// regex table's decisions look as automata. They are written out by hand
+// rather than derived from the regex crate so that every state is nameable.
+
+use crate::lexer::{LiteralKind, Operator, TokenType};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CharSet {
+    Char(char),
+    OneOf(&'static str),
+    Digit,
+    IdentStart,
+    IdentContinue,
+    NoneOf(&'static str),
+}
+
+impl CharSet {
+    pub fn matches(&self, ch: char) -> bool {
+        match self {
+            CharSet::Char(c) => ch == *c,
+            CharSet::OneOf(set) => set.contains(ch),
+            CharSet::Digit => ch.is_ascii_digit(),
+            CharSet::IdentStart => ch.is_ascii_alphabetic() || ch == '_',
+            CharSet::IdentContinue => ch.is_ascii_alphanumeric() || ch == '_',
+            CharSet::NoneOf(set) => !set.contains(ch),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            CharSet::Char(c) => format!("'{}'", c.escape_default()),
+            CharSet::OneOf(set) => format!("[{}]", set.escape_default()),
+            CharSet::Digit => "[0-9]".to_string(),
+            CharSet::IdentStart => "[a-zA-Z_]".to_string(),
+            CharSet::IdentContinue => "[a-zA-Z0-9_]".to_string(),
+            CharSet::NoneOf(set) => format!("[^{}]", set.escape_default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    pub name: String,
+    pub accepts: Option<TokenType>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub from: usize,
+    pub to: usize,
+    pub on: CharSet,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dfa {
+    pub name: &'static str,
+    pub states: Vec<State>,
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceOutcome {
+    // Longest accepted prefix length (in characters) and its token type.
+    Accepted(usize, TokenType),
+    Rejected,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub states: Vec<usize>,
+    pub consumed: usize,
+    pub outcome: TraceOutcome,
+}
+
+pub const DFA_NAMES: &[&str] = &["identifiers", "numbers", "comments", "operators"];
+
+impl Dfa {
+    // The start state is always state 0.
+    pub const START: usize = 0;
+
+    fn new(name: &'static str, states: &[(&str, Option<TokenType>)]) -> Self {
+        Dfa {
+            name,
+            states: states
+                .iter()
+                .map(|(name, accepts)| State {
+                    name: name.to_string(),
+                    accepts: accepts.clone(),
+                })
+                .collect(),
+            transitions: Vec::new(),
+        }
+    }
+
+    fn on(mut self, from: usize, on: CharSet, to: usize) -> Self {
+        self.transitions.push(Transition { from, to, on });
+        self
+    }
+
+    pub fn by_name(name: &str) -> Option<Dfa> {
+        match name {
+            "identifiers" => Some(Dfa::identifiers()),
+            "numbers" => Some(Dfa::numbers()),
+            "comments" => Some(Dfa::comments()),
+            "operators" => Some(Dfa::operators()),
+            _ => None,
+        }
+    }
+
+    pub fn all() -> Vec<Dfa> {
+        DFA_NAMES.iter().filter_map(|name| Dfa::by_name(name)).collect()
+    }
+
+    pub fn identifiers() -> Dfa {
+        Dfa::new("identifiers", &[("q0", None), ("q1", Some(TokenType::Identifier))])
+            .on(0, CharSet::IdentStart, 1)
+            .on(1, CharSet::IdentContinue, 1)
+    }
+
+    pub fn numbers() -> Dfa {
+        Dfa::new("numbers", &[
+            ("q0", None),
+            ("q1", Some(TokenType::Literal(LiteralKind::Integer))),
+            ("q2", None),
+            ("q3", Some(TokenType::Literal(LiteralKind::Float))),
+            ("q4", None),
+            ("q5", None),
+            ("q6", Some(TokenType::Literal(LiteralKind::Float))),
+        ])
+        .on(0, CharSet::Digit, 1)
+        .on(1, CharSet::Digit, 1)
+        .on(1, CharSet::Char('.'), 2)
+        .on(2, CharSet::Digit, 3)
+        .on(3, CharSet::Digit, 3)
+        .on(3, CharSet::OneOf("eE"), 4)
+        .on(4, CharSet::OneOf("+-"), 5)
+        .on(4, CharSet::Digit, 6)
+        .on(5, CharSet::Digit, 6)
+        .on(6, CharSet::Digit, 6)
+    }
+
+    pub fn comments() -> Dfa {
+        Dfa::new("comments", &[
+            ("q0", None),
+            ("q1", Some(TokenType::Operator(Operator::Divide))),
+            ("q2", Some(TokenType::Comment)),
+            ("q3", None),
+            ("q4", None),
+            ("q5", Some(TokenType::Comment)),
+        ])
+        .on(0, CharSet::Char('/'), 1)
+        .on(1, CharSet::Char('/'), 2)
+        .on(2, CharSet::NoneOf("\n"), 2)
+        .on(1, CharSet::Char('*'), 3)
+        .on(3, CharSet::NoneOf("*"), 3)
+        .on(3, CharSet::Char('*'), 4)
+        .on(4, CharSet::Char('*'), 4)
+        .on(4, CharSet::Char('/'), 5)
+        .on(4, CharSet::NoneOf("*/"), 3)
+    }
+
+    pub fn operators() -> Dfa {
+        Dfa::new("operators", &[
+            ("q0", None),
+            ("q1", Some(TokenType::Operator(Operator::Assign))),
+            ("q2", Some(TokenType::Operator(Operator::Equal))),
+            ("q3", Some(TokenType::Operator(Operator::LogicalNot))),
+            ("q4", Some(TokenType::Operator(Operator::NotEqual))),
+            ("q5", Some(TokenType::Operator(Operator::LessThan))),
+            ("q6", Some(TokenType::Operator(Operator::LessEqual))),
+            ("q7", Some(TokenType::Operator(Operator::GreaterThan))),
+            ("q8", Some(TokenType::Operator(Operator::GreaterEqual))),
+            ("q9", Some(TokenType::Operator(Operator::Ampersand))),
+            ("q10", Some(TokenType::Operator(Operator::LogicalAnd))),
+            ("q11", None),
+            ("q12", Some(TokenType::Operator(Operator::LogicalOr))),
+            ("q13", Some(TokenType::Operator(Operator::Plus))),
+            ("q14", Some(TokenType::Operator(Operator::Increment))),
+            ("q15", Some(TokenType::Operator(Operator::Minus))),
+            ("q16", Some(TokenType::Operator(Operator::Decrement))),
+        ])
+        .on(0, CharSet::Char('='), 1)
+        .on(1, CharSet::Char('='), 2)
+        .on(0, CharSet::Char('!'), 3)
+        .on(3, CharSet::Char('='), 4)
+        .on(0, CharSet::Char('<'), 5)
+        .on(5, CharSet::Char('='), 6)
+        .on(0, CharSet::Char('>'), 7)
+        .on(7, CharSet::Char('='), 8)
+        .on(0, CharSet::Char('&'), 9)
+        .on(9, CharSet::Char('&'), 10)
+        .on(0, CharSet::Char('|'), 11)
+        .on(11, CharSet::Char('|'), 12)
+        .on(0, CharSet::Char('+'), 13)
+        .on(13, CharSet::Char('+'), 14)
+        .on(0, CharSet::Char('-'), 15)
+        .on(15, CharSet::Char('-'), 16)
+    }
+
+    pub fn step(&self, state: usize, ch: char) -> Option<usize> {
+        self.transitions
+            .iter()
+            .find(|t| t.from == state && t.on.matches(ch))
+            .map(|t| t.to)
+    }
+
+    // Runs the automaton over `input` and reports the longest accepted
+    // prefix, the same maximal-munch rule the lexer applies.
+    pub fn trace(&self, input: &str) -> Trace {
+        let mut state = Dfa::START;
+        let mut states = vec![state];
+        let mut last_accept = self.states[state].accepts.clone().map(|t| (0, t));
+        for ch in input.chars() {
+            match self.step(state, ch) {
+                Some(next) => {
+                    state = next;
+                    states.push(state);
+                    if let Some(token_type) = &self.states[state].accepts {
+                        last_accept = Some((states.len() - 1, token_type.clone()));
+                    }
+                }
+                None => break,
+            }
+        }
+        Trace {
+            consumed: states.len() - 1,
+            states,
+            outcome: match last_accept {
+                Some((len, token_type)) => TraceOutcome::Accepted(len, token_type),
+                None => TraceOutcome::Rejected,
+            },
+        }
+    }
+
+    fn labels(&self) -> Vec<String> {
+        let mut labels: Vec<String> = Vec::new();
+        for transition in &self.transitions {
+            let label = transition.on.label();
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        labels
+    }
+
+    pub fn transition_table(&self) -> String {
+        let labels = self.labels();
+        let width = labels.iter().map(|l| l.len()).max().unwrap_or(0).max(4);
+        let mut out = String::new();
+        writeln!(out, "DFA: {}", self.name).unwrap();
+        write!(out, "{:<8}", "State").unwrap();
+        for label in &labels {
+            write!(out, " {:<width$}", label, width = width).unwrap();
+        }
+        writeln!(out, " Accepts").unwrap();
+        writeln!(out, "{}", "-".repeat(8 + (width + 1) * labels.len() + 8)).unwrap();
+        for (index, state) in self.states.iter().enumerate() {
+            let marker = match (index == Dfa::START, state.accepts.is_some()) {
+                (true, _) => "->",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            write!(out, "{} {:<5}", marker, state.name).unwrap();
+            for label in &labels {
+                let target = self
+                    .transitions
+                    .iter()
+                    .find(|t| t.from == index && t.on.label() == *label)
+                    .map(|t| self.states[t.to].name.clone())
+                    .unwrap_or_else(|| "-".to_string());
+                write!(out, " {:<width$}", target, width = width).unwrap();
+            }
+            let accepts = state.accepts.as_ref().map(|t| t.to_string()).unwrap_or_default();
+            writeln!(out, " {}", accepts).unwrap();
+        }
+        out
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph {} {{", self.name).unwrap();
+        writeln!(out, "    rankdir=LR;").unwrap();
+        writeln!(out, "    node [shape=circle];").unwrap();
+        writeln!(out, "    __start [shape=point];").unwrap();
+        for state in &self.states {
+            match &state.accepts {
+                Some(token_type) => writeln!(out, "    {} [shape=doublecircle, label=\"{}\\n{}\"];", state.name, state.name, token_type).unwrap(),
+                None => writeln!(out, "    {};", state.name).unwrap(),
+            }
+        }
+        writeln!(out, "    __start -> {};", self.states[Dfa::START].name).unwrap();
+        for transition in &self.transitions {
+            writeln!(out, "    {} -> {} [label=\"{}\"];",
+                self.states[transition.from].name,
+                self.states[transition.to].name,
+                dot_escape(&transition.on.label())
+            ).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    pub fn format_trace(&self, input: &str, trace: &Trace) -> String {
+        let chars: Vec<char> = input.chars().collect();
+        let mut out = self.states[trace.states[0]].name.clone();
+        for (i, state) in trace.states.iter().enumerate().skip(1) {
+            write!(out, " --'{}'--> {}", chars[i - 1].escape_default(), self.states[*state].name).unwrap();
+        }
+        match &trace.outcome {
+            TraceOutcome::Accepted(len, token_type) => {
+                let lexeme: String = chars[..*len].iter().collect();
+                write!(out, "\naccepted {:?} as {}", lexeme, token_type).unwrap();
+            }
+            TraceOutcome::Rejected => write!(out, "\nrejected: no accepting state reached").unwrap(),
+        }
+        if trace.consumed < chars.len() {
+            write!(out, " (stopped before '{}' at offset {})", chars[trace.consumed].escape_default(), trace.consumed).unwrap();
+        }
+        out
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepted(dfa: &Dfa, input: &str) -> TraceOutcome {
+        dfa.trace(input).outcome
+    }
+
+    // Node declarations (the start point included), edges and accepting
+    // states in the DOT output.
+    fn dot_counts(dfa: &Dfa) -> (usize, usize, usize) {
+        let dot = dfa.to_dot();
+        let statements: Vec<&str> = dot.lines().filter(|l| l.starts_with("    ") && !l.contains("rankdir") && !l.contains("node [")).collect();
+        let edges = statements.iter().filter(|l| l.contains(" -> ")).count();
+        (statements.len() - edges, edges, dot.matches("doublecircle").count())
+    }
+
+    #[test]
+    fn transitions_follow_the_character_classes() {
+        let identifiers = Dfa::identifiers();
+        assert_eq!(identifiers.step(0, 'a'), Some(1));
+        assert_eq!(identifiers.step(0, '_'), Some(1));
+        assert_eq!(identifiers.step(0, '7'), None);
+        assert_eq!(identifiers.step(1, '7'), Some(1));
+        let numbers = Dfa::numbers();
+        assert_eq!(numbers.step(1, '.'), Some(2));
+        assert_eq!(numbers.step(2, 'e'), None);
+        assert_eq!(numbers.step(3, 'E'), Some(4));
+        assert_eq!(numbers.step(4, '-'), Some(5));
+        assert_eq!(numbers.step(4, '2'), Some(6));
+        assert_eq!(numbers.step(5, '+'), None);
+        let comments = Dfa::comments();
+        assert_eq!(comments.step(2, '\n'), None);
+        assert_eq!(comments.step(4, '*'), Some(4));
+        assert_eq!(comments.step(4, '/'), Some(5));
+        assert_eq!(comments.step(4, 'x'), Some(3));
+        let operators = Dfa::operators();
+        assert_eq!(operators.step(0, '|'), Some(11));
+        assert_eq!(operators.step(11, '|'), Some(12));
+        assert_eq!(operators.step(11, '&'), None);
+        assert_eq!(operators.step(2, '='), None);
+    }
+
+    #[test]
+    fn traces_take_the_longest_accepted_prefix() {
+        let trace = Dfa::identifiers().trace("count1+");
+        assert_eq!((trace.states, trace.consumed), (vec![0, 1, 1, 1, 1, 1, 1], 6));
+        assert_eq!(trace.outcome, TraceOutcome::Accepted(6, TokenType::Identifier));
+        assert_eq!(accepted(&Dfa::identifiers(), "1x"), TraceOutcome::Rejected);
+        let float = TokenType::Literal(LiteralKind::Float);
+        assert_eq!(accepted(&Dfa::numbers(), "12.5e+3;"), TraceOutcome::Accepted(7, float.clone()));
+        assert_eq!(accepted(&Dfa::numbers(), "3.0e"), TraceOutcome::Accepted(3, float));
+        let backed_off = Dfa::numbers().trace("12.");
+        assert_eq!((backed_off.consumed, backed_off.outcome), (3, TraceOutcome::Accepted(2, TokenType::Literal(LiteralKind::Integer))));
+        assert_eq!(accepted(&Dfa::comments(), "/* a **/x"), TraceOutcome::Accepted(8, TokenType::Comment));
+        assert_eq!(accepted(&Dfa::comments(), "// end\nx"), TraceOutcome::Accepted(6, TokenType::Comment));
+        assert_eq!(accepted(&Dfa::comments(), "/* never"), TraceOutcome::Accepted(1, TokenType::Operator(Operator::Divide)));
+        assert_eq!(accepted(&Dfa::operators(), "<=>"), TraceOutcome::Accepted(2, TokenType::Operator(Operator::LessEqual)));
+        assert_eq!(accepted(&Dfa::operators(), "+++"), TraceOutcome::Accepted(2, TokenType::Operator(Operator::Increment)));
+        assert_eq!(accepted(&Dfa::operators(), "|x"), TraceOutcome::Rejected);
+        assert_eq!(accepted(&Dfa::operators(), ""), TraceOutcome::Rejected);
+    }
+
+    #[test]
+    fn traces_print_each_step() {
+        let numbers = Dfa::numbers();
+        assert_eq!(
+            numbers.format_trace("12.", &numbers.trace("12.")),
+            "q0 --'1'--> q1 --'2'--> q1 --'.'--> q2\naccepted \"12\" as IntegerLiteral"
+        );
+        let operators = Dfa::operators();
+        assert_eq!(
+            operators.format_trace("|x", &operators.trace("|x")),
+            "q0 --'|'--> q11\nrejected: no accepting state reached (stopped before 'x' at offset 1)"
+        );
+    }
+
+    #[test]
+    fn dot_output_has_a_node_per_state_and_an_edge_per_transition() {
+        assert_eq!(dot_counts(&Dfa::identifiers()), (3, 3, 1));
+        assert_eq!(dot_counts(&Dfa::numbers()), (8, 11, 3));
+        assert_eq!(dot_counts(&Dfa::comments()), (7, 10, 3));
+        assert_eq!(dot_counts(&Dfa::operators()), (18, 17, 15));
+        let dot = Dfa::comments().to_dot();
+        assert!(dot.starts_with("digraph comments {\n    rankdir=LR;\n"));
+        assert!(dot.contains("    __start -> q0;\n"));
+        assert!(dot.contains("    q4 -> q3 [label=\"[^*/]\"];\n"));
+        assert!(dot.contains("    q2 -> q2 [label=\"[^\\\\n]\"];\n"));
+        assert!(dot.contains("    q5 [shape=doublecircle, label=\"q5\\nComment\"];\n"));
+    }
+
+    #[test]
+    fn transition_tables_mark_start_and_accepting_states() {
+        assert_eq!(
+            Dfa::identifiers().transition_table(),
+            "DFA: identifiers\n\
+             State    [a-zA-Z_]    [a-zA-Z0-9_] Accepts\n\
+             ------------------------------------------\n\
+             -> q0    q1           -            \n\
+             \x20* q1    -            q1           Identifier\n"
+        );
+        let names: Vec<&str> = Dfa::all().iter().map(|dfa| dfa.name).collect();
+        assert_eq!(names, DFA_NAMES);
+        assert!(Dfa::by_name("strings").is_none());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::lexer::LexError;
+use crate::position::{ColumnUnit, LineOrigin, PositionBase};
+use crate::visual::VisualColumns;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Write;
+
+// Ordered from least to most severe, so `--warn-level` can be a threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+pub const SEVERITY_NAMES: &[&str] = &["note", "warning", "error"];
+
+impl Severity {
+    pub fn from_name(name: &str) -> Option<Severity> {
+        match name {
+            "note" => Some(Severity::Note),
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Characters of source text a message quotes before it cuts the rest off.
+pub const QUOTE_LIMIT: usize = 60;
+
+// Columns of a long line an excerpt shows, starting this many before the
+// span.
+pub const EXCERPT_WIDTH: usize = 120;
+const EXCERPT_LEAD: usize = 40;
+
+// `text` as a message quotes it: whole if short, otherwise its first
+// `QUOTE_LIMIT` characters and how many more there are, so a 500 KB string
+// literal makes a one-line message. Tokens keep the full lexeme.
+pub fn quote(text: &str) -> String {
+    match text.char_indices().nth(QUOTE_LIMIT) {
+        Some((index, _)) => format!("{}… ({} more)", &text[..index], text[index..].chars().count()),
+        None => text.to_string(),
+    }
+}
+
+// Where a diagnostic points: 1-based line and column, length in the
+// configured column unit, and the line and column of its last character,
+// which for a string continued with a backslash is on a later line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SpanRecord")]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    // A span on one line. An empty one, like the end of a file, ends where
+    // it starts.
+    pub fn new(line: usize, column: usize, length: usize) -> Self {
+        Span {
+            line,
+            column,
+            length,
+            end_line: line,
+            end_column: column + length.saturating_sub(1),
+        }
+    }
+
+    // The span of `text` starting at `line` and `column`, which may run over
+    // several lines.
+    pub fn over(line: usize, column: usize, text: &str, unit: ColumnUnit) -> Self {
+        let mut span = Span::new(line, column, unit.measure(text));
+        let (mut next_line, mut next_column) = (line, column);
+        for ch in text.chars() {
+            (span.end_line, span.end_column) = (next_line, next_column);
+            if ch == '\n' {
+                next_line += 1;
+                next_column = 1;
+            } else {
+                next_column += unit.width(ch);
+            }
+        }
+        span
+    }
+}
+
+// Span as read from JSON. Files written before end positions existed lack
+// them, so they are taken to end on the starting line.
+#[derive(Deserialize)]
+struct SpanRecord {
+    line: usize,
+    column: usize,
+    length: usize,
+    end_line: Option<usize>,
+    end_column: Option<usize>,
+}
+
+impl From<SpanRecord> for Span {
+    fn from(record: SpanRecord) -> Self {
+        let mut span = Span::new(record.line, record.column, record.length);
+        if let (Some(end_line), Some(end_column)) = (record.end_line, record.end_column) {
+            span.end_line = end_line;
+            span.end_column = end_column;
+        }
+        span
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: String,
+    pub message: String,
+    // Physical position in the source, whatever `#line` says
+    pub span: Span,
+    // Where a `#line` directive says the span's first line comes from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<LineOrigin>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, message: String, span: Span) -> Self {
+        Diagnostic {
+            severity,
+            code: code.to_string(),
+            message,
+            span,
+            origin: None,
+        }
+    }
+
+    pub fn warning(code: &str, message: String, span: Span) -> Self {
+        Self::new(Severity::Warning, code, message, span)
+    }
+
+    pub fn note(code: &str, message: String, span: Span) -> Self {
+        Self::new(Severity::Note, code, message, span)
+    }
+    
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        self.span.line = from.rebase(self.span.line, to);
+        self.span.column = from.rebase(self.span.column, to);
+        self.span.end_line = from.rebase(self.span.end_line, to);
+        self.span.end_column = from.rebase(self.span.end_column, to);
+    }
+    
+    // The diagnostic's line of `source` with its span underlined, `^` under
+    // the first cell and `~` under the rest. A span running onto later
+    // lines is underlined to the end of its first and says where it ends. A
+    // line longer than `EXCERPT_WIDTH` bytes is cut to that many columns
+    // around the span, with `...` where text was left out. Tabs are expanded
+    // to `tab_width` stops and wide characters underlined across both of
+    // their cells, as `visual::VisualColumns` counts them.
+    pub fn excerpt(&self, source: &str, unit: ColumnUnit, tab_width: usize) -> Option<String> {
+        let visual = VisualColumns::new(tab_width);
+        let text = source.split('\n').nth(self.span.line.checked_sub(1)?)?;
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let end = self.span.column + self.span.length;
+        let from = if text.len() > EXCERPT_WIDTH { self.span.column.saturating_sub(EXCERPT_LEAD).max(1) } else { 1 };
+        let (mut shown, mut padding, mut underline) = (String::new(), String::new(), String::new());
+        let (mut column, mut cell) = (1, 0);
+        for ch in text.chars() {
+            if column >= from + EXCERPT_WIDTH {
+                shown.push_str("...");
+                break;
+            }
+            let cells = visual.width_at(ch, cell);
+            if column >= from {
+                if ch == '\t' {
+                    shown.extend(std::iter::repeat_n(' ', cells));
+                } else {
+                    shown.push(ch);
+                }
+                if column < self.span.column {
+                    padding.extend(std::iter::repeat_n(' ', cells));
+                } else if column < end {
+                    // A span starting at a combining mark points at the
+                    // character the mark is drawn on
+                    let marked = cells == 0 && underline.is_empty() && padding.pop().is_some();
+                    for _ in 0..cells.max(usize::from(marked)) {
+                        underline.push(if underline.is_empty() { '^' } else { '~' });
+                    }
+                }
+            }
+            cell += cells;
+            column += unit.width(ch);
+        }
+        if from > 1 {
+            shown.insert_str(0, "...");
+            padding.insert_str(0, "   ");
+        }
+        if underline.is_empty() {
+            underline.push('^');
+        }
+        if self.span.end_line > self.span.line {
+            write!(underline, " ...continues to line {}", self.span.end_line).unwrap();
+        }
+        let width = self.span.line.to_string().len();
+        Some(format!("{:>width$} | {}\n{:>width$} | {}{}", self.span.line, shown, "", padding, underline, width = width))
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(error: &LexError) -> Self {
+        Diagnostic::new(
+            Severity::Error,
+            &error.code,
+            error.message.clone(),
+            Span::new(error.line, error.column, error.length),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Note => "Lexical Note",
+            Severity::Warning => "Lexical Warning",
+            Severity::Error => "Lexical Error",
+        };
+        let line = self.origin.as_ref().map_or(self.span.line, |origin| origin.line);
+        write!(f, "{}: {} at line {}, column {}", label, self.message, line, self.span.column)?;
+        if let Some(file) = self.origin.as_ref().and_then(|origin| origin.file.as_ref()) {
+            write!(f, " of {}", file)?;
+        }
+        Ok(())
+    }
+}
+
+// Which diagnostics get reported. Errors always are; notes and warnings can
+// be hidden by severity or, per code, with `--allow`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticFilter {
+    pub min_severity: Severity,
+    pub allowed: Vec<String>,
+}
+
+impl Default for DiagnosticFilter {
+    fn default() -> Self {
+        DiagnosticFilter {
+            min_severity: Severity::Note,
+            allowed: Vec::new(),
+        }
+    }
+}
+
+impl DiagnosticFilter {
+    pub fn shows(&self, diagnostic: &Diagnostic) -> bool {
+        diagnostic.severity == Severity::Error
+            || (diagnostic.severity >= self.min_severity && !self.allowed.contains(&diagnostic.code))
+    }
+
+    pub fn apply<'a>(&self, diagnostics: &'a [Diagnostic]) -> Vec<&'a Diagnostic> {
+        diagnostics.iter().filter(|d| self.shows(d)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<Diagnostic> {
+        vec![
+            Diagnostic::note("N0001", "endless".to_string(), Span::new(1, 1, 3)),
+            Diagnostic::warning("W0011", "no newline".to_string(), Span::new(2, 5, 0)),
+            Diagnostic::warning("W0012", "trailing".to_string(), Span::new(3, 2, 2)),
+            Diagnostic::new(Severity::Error, "E0001", "invalid".to_string(), Span::new(4, 1, 1)),
+        ]
+    }
+
+    fn shown(filter: &DiagnosticFilter) -> Vec<String> {
+        filter.apply(&sample()).iter().map(|d| d.code.clone()).collect()
+    }
+
+    #[test]
+    fn filter_by_severity_threshold() {
+        assert_eq!(shown(&DiagnosticFilter::default()), ["N0001", "W0011", "W0012", "E0001"]);
+        let warnings = DiagnosticFilter {
+            min_severity: Severity::Warning,
+            ..DiagnosticFilter::default()
+        };
+        assert_eq!(shown(&warnings), ["W0011", "W0012", "E0001"]);
+        let errors = DiagnosticFilter {
+            min_severity: Severity::Error,
+            ..DiagnosticFilter::default()
+        };
+        assert_eq!(shown(&errors), ["E0001"]);
+    }
+
+    #[test]
+    fn allowed_codes_are_suppressed_but_errors_never_are() {
+        let filter = DiagnosticFilter {
+            min_severity: Severity::Note,
+            allowed: vec!["W0012".to_string(), "N0001".to_string(), "E0001".to_string()],
+        };
+        assert_eq!(shown(&filter), ["W0011", "E0001"]);
+    }
+
+    #[test]
+    fn severities_order_and_round_trip_their_names() {
+        assert!(Severity::Note < Severity::Warning && Severity::Warning < Severity::Error);
+        for name in SEVERITY_NAMES {
+            assert_eq!(Severity::from_name(name).unwrap().name(), *name);
+        }
+        assert_eq!(Severity::from_name("fatal"), None);
+    }
+
+    #[test]
+    fn diagnostics_serialize_and_display() {
+        let diagnostic = &sample()[1];
+        let json = serde_json::to_value(diagnostic).unwrap();
+        assert_eq!(json["severity"], "warning");
+        assert_eq!(json["code"], "W0011");
+        assert_eq!(json["span"]["column"], 5);
+        assert!(json.get("origin").is_none());
+        assert_eq!(&serde_json::from_value::<Diagnostic>(json).unwrap(), diagnostic);
+        assert_eq!(diagnostic.to_string(), "Lexical Warning: no newline at line 2, column 5");
+        // Spans saved without end positions end on their line
+        let old: Span = serde_json::from_str(r#"{"line": 3, "column": 4, "length": 2}"#).unwrap();
+        assert_eq!(old, Span::new(3, 4, 2));
+    }
+
+    fn excerpts(source: &str) -> Vec<String> {
+        let mut lexer = crate::lexer::Lexer::new(source.to_string());
+        lexer.set_options(crate::lexer::LexerOptions {
+            error_recovery: true,
+            ..crate::lexer::LexerOptions::default()
+        });
+        let _ = lexer.tokenize();
+        lexer.get_diagnostics().iter().map(|d| d.excerpt(source, ColumnUnit::Chars, 4).unwrap()).collect()
+    }
+
+    #[test]
+    fn underlines_cover_the_span() {
+        // One character
+        assert_eq!(excerpts("int a = 1 @ 2;\n"), ["1 | int a = 1 @ 2;\n  |           ^"]);
+        // The whole token
+        assert_eq!(excerpts("char c = 'abc';\n"), ["1 | char c = 'abc';\n  |          ^~~~~"]);
+        // To the end of the first line, then where it ends
+        let source = "string s = \"open \\\nstill open\nint z;\n";
+        assert_eq!(excerpts(source), ["1 | string s = \"open \\\n  |            ^~~~~~~ ...continues to line 2"]);
+    }
+
+    #[test]
+    fn multi_line_spans_serialize_their_end() {
+        let span = Span::over(1, 12, "\"open \\\nstill open", ColumnUnit::Chars);
+        assert_eq!((span.length, span.end_line, span.end_column), (18, 2, 10));
+        let json = serde_json::to_value(span).unwrap();
+        assert_eq!((json["end_line"].as_u64(), json["end_column"].as_u64()), (Some(2), Some(10)));
+        assert_eq!(serde_json::from_value::<Span>(json).unwrap(), span);
+        assert_eq!(Span::new(2, 5, 0).end_column, 5);
+    }
+
+    #[test]
+    fn quotes_stop_at_the_limit() {
+        let exact = "x".repeat(QUOTE_LIMIT);
+        assert_eq!(quote(&exact), exact);
+        assert_eq!(quote(&"é".repeat(QUOTE_LIMIT + 5)), format!("{}… (5 more)", "é".repeat(QUOTE_LIMIT)));
+        assert_eq!(quote("'a'"), "'a'");
+    }
+
+    #[test]
+    fn long_lines_are_windowed_around_the_span() {
+        // A megabyte line with the error a little past its middle
+        let half = "a".repeat(1 << 19);
+        let source = format!("int {} = 1 @ 2; int {};\n", half, half);
+        let excerpt = &excerpts(&source)[0];
+        let (shown, underline) = excerpt.split_once('\n').unwrap();
+        assert_eq!(shown, format!("1 | ...{} = 1 @ 2; int {}...", "a".repeat(35), "a".repeat(EXCERPT_WIDTH - 49)));
+        assert_eq!(underline, format!("  |    {}^", " ".repeat(EXCERPT_LEAD)));
+        // Short lines are shown whole
+        assert_eq!(excerpts("@\n"), ["1 | @\n  | ^"]);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::conditional::ConditionalBranch;
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{FileSummary, ScopeNode, Symbol, SymbolTable, SymbolsByScope, Token};
+use crate::lifetime::Lifetime;
+use crate::metrics::FunctionMetrics;
+use crate::nesting::NestingReport;
+use crate::position::{ColumnUnit, PositionBase};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+
+// Bumped whenever a field changes meaning or disappears; new fields may be
+// added without a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// The JSON written for each input file. `position_base` says whether lines
+// and columns count from 0 or 1, and `column_unit` what a column counts;
+// documents without them count characters from 1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonDocument {
+    pub schema_version: u32,
+    #[serde(default = "one_based")]
+    pub position_base: u32,
+    #[serde(default)]
+    pub column_unit: ColumnUnit,
+    pub success: bool,
+    pub tokens: Vec<Token>,
+    #[serde(default)]
+    pub symbols: Vec<Symbol>,
+    // The same symbols nested by scope; absent from older documents.
+    #[serde(default)]
+    pub scope_tree: Option<ScopeNode>,
+    #[serde(default)]
+    pub metrics: Vec<FunctionMetrics>,
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    // Line count, longest line and trailing-whitespace lines; all zero in
+    // older documents.
+    #[serde(default)]
+    pub summary: FileSummary,
+    // Deepest nesting of each kind of bracket; none in older documents.
+    #[serde(default)]
+    pub nesting: NestingReport,
+    // How long each variable and constant is in use; absent from older
+    // documents.
+    #[serde(default)]
+    pub lifetimes: Vec<Lifetime>,
+    // Written only when asked for with `--symbols-by-scope`.
+    #[serde(default)]
+    pub symbols_by_scope: Option<SymbolsByScope>,
+    // Each branch of the conditional directives; left out when there are
+    // none.
+    #[serde(default)]
+    pub conditionals: Vec<ConditionalBranch>,
+}
+
+fn one_based() -> u32 {
+    PositionBase::One.offset()
+}
+
+impl JsonDocument {
+    // Converts every position to `to`.
+    pub fn rebase(&mut self, to: PositionBase) {
+        let Some(from) = PositionBase::from_offset(self.position_base) else {
+            return;
+        };
+        self.tokens.iter_mut().for_each(|t| t.rebase(from, to));
+        self.symbols.iter_mut().for_each(|s| s.rebase(from, to));
+        if let Some(tree) = self.scope_tree.as_mut() {
+            tree.rebase(from, to);
+        }
+        self.metrics.iter_mut().for_each(|m| m.rebase(from, to));
+        self.diagnostics.iter_mut().for_each(|d| d.rebase(from, to));
+        self.nesting.rebase(from, to);
+        self.lifetimes.iter_mut().for_each(|l| l.rebase(from, to));
+        if let Some(groups) = self.symbols_by_scope.as_mut() {
+            groups.rebase(from, to);
+        }
+        self.conditionals.iter_mut().for_each(|b| b.rebase(from, to));
+        self.position_base = to.offset();
+    }
+}
+
+// Same shape as JsonDocument, borrowing from the lexer so writing large
+// outputs does not copy the token stream. Only a 0-based document is built
+// from converted copies; the scope tree is always built for the occasion.
+#[derive(Serialize)]
+pub(crate) struct DocumentRef<'a> {
+    pub schema_version: u32,
+    pub position_base: u32,
+    pub column_unit: ColumnUnit,
+    pub success: bool,
+    pub tokens: Cow<'a, [Token]>,
+    pub symbols: Cow<'a, [Symbol]>,
+    pub scope_tree: ScopeNode,
+    pub metrics: Vec<FunctionMetrics>,
+    pub diagnostics: Vec<Cow<'a, Diagnostic>>,
+    pub summary: FileSummary,
+    pub nesting: NestingReport,
+    pub lifetimes: Vec<Lifetime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbols_by_scope: Option<SymbolsByScope>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conditionals: Vec<ConditionalBranch>,
+}
+
+impl<'a> DocumentRef<'a> {
+    pub fn new(
+        success: bool,
+        tokens: &'a [Token],
+        table: &'a SymbolTable,
+        metrics: Vec<FunctionMetrics>,
+        diagnostics: Vec<&'a Diagnostic>,
+        base: PositionBase,
+        column_unit: ColumnUnit,
+    ) -> Self {
+        let mut document = DocumentRef {
+            schema_version: SCHEMA_VERSION,
+            position_base: base.offset(),
+            column_unit,
+            success,
+            tokens: Cow::Borrowed(tokens),
+            symbols: Cow::Borrowed(table.get_symbols()),
+            scope_tree: table.scope_tree(),
+            metrics,
+            diagnostics: diagnostics.into_iter().map(Cow::Borrowed).collect(),
+            summary: FileSummary::default(),
+            nesting: NestingReport::default(),
+            lifetimes: Vec::new(),
+            symbols_by_scope: None,
+            conditionals: Vec::new(),
+        };
+        if base != PositionBase::One {
+            document.tokens.to_mut().iter_mut().for_each(|t| t.rebase(PositionBase::One, base));
+            document.symbols.to_mut().iter_mut().for_each(|s| s.rebase(PositionBase::One, base));
+            document.scope_tree.rebase(PositionBase::One, base);
+            document.metrics.iter_mut().for_each(|m| m.rebase(PositionBase::One, base));
+            document.diagnostics.iter_mut().for_each(|d| d.to_mut().rebase(PositionBase::One, base));
+        }
+        document
+    }
+}
+
+// Reads a JSON document, or the bare token array written by versions
+// before the document format, which is returned with no symbols or
+// diagnostics. Positions come back 1-based whatever base the file used.
+pub fn parse_document(bytes: &[u8]) -> serde_json::Result<JsonDocument> {
+    let value: Value = serde_json::from_slice(bytes)?;
+    if value.is_array() {
+        let tokens: Vec<Token> = serde_json::from_value(value)?;
+        return Ok(JsonDocument {
+            schema_version: 0,
+            position_base: one_based(),
+            column_unit: ColumnUnit::Chars,
+            success: true,
+            tokens,
+            symbols: Vec::new(),
+            scope_tree: None,
+            metrics: Vec::new(),
+            diagnostics: Vec::new(),
+            summary: FileSummary::default(),
+            nesting: NestingReport::default(),
+            lifetimes: Vec::new(),
+            symbols_by_scope: None,
+            conditionals: Vec::new(),
+        });
+    }
+    let mut document: JsonDocument = serde_json::from_value(value)?;
+    document.rebase(PositionBase::One);
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::DiagnosticFilter;
+    use crate::lexer::Lexer;
+
+    fn written(source: &str, base: PositionBase) -> Value {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        let mut buffer = Vec::new();
+        lexer.write_json(&mut buffer, false, &DiagnosticFilter::default(), base, false).unwrap();
+        serde_json::from_slice(&buffer).unwrap()
+    }
+
+    #[test]
+    fn one_file_under_both_bases() {
+        let source = "int x = 1;\nreturn x;";
+        let one = written(source, PositionBase::One);
+        let zero = written(source, PositionBase::Zero);
+        assert_eq!((one["position_base"].as_u64(), zero["position_base"].as_u64()), (Some(1), Some(0)));
+        // `return` on the second line
+        let position = |document: &Value| (document["tokens"][5]["line"].as_u64(), document["tokens"][5]["column"].as_u64());
+        assert_eq!(one["tokens"][5]["lexeme"], "return");
+        assert_eq!(position(&one), (Some(2), Some(1)));
+        assert_eq!(position(&zero), (Some(1), Some(0)));
+        assert_eq!(zero["symbols"][0]["line"], 0);
+        // The missing final newline is reported after the last character
+        let warning = |document: &Value| document["diagnostics"][0]["span"]["column"].as_u64();
+        assert_eq!((warning(&one), warning(&zero)), (Some(10), Some(9)));
+    }
+
+    #[test]
+    fn parsed_documents_come_back_one_based() {
+        let source = "int x = 1;\nreturn x;\n";
+        let one = parse_document(written(source, PositionBase::One).to_string().as_bytes()).unwrap();
+        let zero = parse_document(written(source, PositionBase::Zero).to_string().as_bytes()).unwrap();
+        assert_eq!(zero.position_base, 1);
+        assert_eq!(zero, one);
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        assert_eq!(lexer.get_tokens()[5].position(PositionBase::Zero), (1, 0));
+        assert_eq!(&one.tokens, lexer.get_tokens());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::lexer::{Token, TokenType};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+// Tokens in one shingle.
+pub const SHINGLE_SIZE: usize = 5;
+
+// Similarity percentage a pair of blocks needs when no threshold is given.
+pub const DEFAULT_DUPLICATION_THRESHOLD: f64 = 70.0;
+
+// Fewest code tokens a reported block may have.
+pub const MIN_DUPLICATE_TOKENS: usize = 30;
+
+// A shingle found more often than this is taken for a common idiom rather
+// than copied code, and keeps repetitive files from taking quadratic time.
+const MAX_SHINGLE_REPEATS: usize = 16;
+
+// Two blocks of one token stream that look copied from each other. `first`
+// and `second` index the stream, `first` coming earlier, and the lines are
+// those the blocks start and end on. `similarity` is the percentage of the
+// shingles in either block found in both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    pub first: Range<usize>,
+    pub second: Range<usize>,
+    pub first_lines: (usize, usize),
+    pub second_lines: (usize, usize),
+    pub similarity: f64,
+}
+
+// What a token counts as when comparing code: its type, so that renamed
+// variables and changed literal values still match.
+fn shape(token: &Token) -> &'static str {
+    token.token_type.name()
+}
+
+// Pairs of blocks in `tokens` whose shingles, the runs of `SHINGLE_SIZE`
+// token shapes, are at least `threshold` percent the same, in the order of
+// their first block. Comments are skipped, and a block has at least
+// `MIN_DUPLICATE_TOKENS` tokens and does not start with a `;`, `}`, `)` or
+// `,` ending the code before it. A block is grown over every
+// shingle it shares with the other at the same offset, bridging an edit
+// that breaks fewer than a shingle's worth of them, and two blocks that
+// would overlap, as in code that simply repeats itself, are not reported.
+pub fn duplicates(tokens: &[Token], threshold: f64) -> Vec<Duplicate> {
+    let code: Vec<usize> = (0..tokens.len())
+        .filter(|&i| !matches!(tokens[i].token_type, TokenType::Comment | TokenType::EOF))
+        .collect();
+    if code.len() < SHINGLE_SIZE * 2 {
+        return Vec::new();
+    }
+    let shapes: Vec<&str> = code.iter().map(|&i| shape(&tokens[i])).collect();
+    let shingles: Vec<&[&str]> = shapes.windows(SHINGLE_SIZE).collect();
+    let mut positions: HashMap<&[&str], Vec<usize>> = HashMap::new();
+    for (i, shingle) in shingles.iter().enumerate() {
+        positions.entry(shingle).or_default().push(i);
+    }
+
+    // Matching shingles at least a shingle apart, by their distance
+    let mut diagonals: HashMap<usize, Vec<usize>> = HashMap::new();
+    for found in positions.values().filter(|found| found.len() > 1 && found.len() <= MAX_SHINGLE_REPEATS) {
+        for (n, &a) in found.iter().enumerate() {
+            for &b in found[n + 1..].iter().filter(|&&b| b >= a + SHINGLE_SIZE) {
+                diagonals.entry(b - a).or_default().push(a);
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    for (distance, mut starts) in diagonals {
+        starts.sort_unstable();
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for start in starts {
+            match runs.last_mut() {
+                Some(run) if start - run.end <= SHINGLE_SIZE + 1 => run.end = start,
+                _ => runs.push(start..start),
+            }
+        }
+        for run in runs {
+            // Not starting with what closes the code before the block
+            let closing = shapes[run.start..].iter().take_while(|shape| matches!(**shape, "Semicolon" | "RightBrace" | "RightParen" | "Comma"));
+            let start = run.start + closing.count().min(run.len());
+            let first = start..run.end + SHINGLE_SIZE;
+            let second = start + distance..run.end + distance + SHINGLE_SIZE;
+            if first.len() < MIN_DUPLICATE_TOKENS || first.end > second.start {
+                continue;
+            }
+            let similarity = similarity(&shingles, &first, &second);
+            if similarity >= threshold {
+                found.push(duplicate(tokens, &code, first, second, similarity));
+            }
+        }
+    }
+    found.sort_by_key(|d| (d.first.start, d.second.start));
+    found
+}
+
+// Percentage of the distinct shingles of either range of shapes that both
+// hold, to one decimal.
+fn similarity(shingles: &[&[&str]], first: &Range<usize>, second: &Range<usize>) -> f64 {
+    let set = |range: &Range<usize>| -> HashSet<&[&str]> { shingles[range.start..range.end + 1 - SHINGLE_SIZE].iter().copied().collect() };
+    let (a, b) = (set(first), set(second));
+    let union = a.union(&b).count();
+    let shared = a.intersection(&b).count();
+    (shared as f64 * 1000.0 / union as f64).round() / 10.0
+}
+
+// A pair of ranges of code tokens as ranges of the whole stream.
+fn duplicate(tokens: &[Token], code: &[usize], first: Range<usize>, second: Range<usize>, similarity: f64) -> Duplicate {
+    let span = |range: Range<usize>| code[range.start]..code[range.end - 1] + 1;
+    let lines = |range: &Range<usize>| (tokens[range.start].line, tokens[range.end - 1].end_line);
+    let (first, second) = (span(first), span(second));
+    Duplicate {
+        first_lines: lines(&first),
+        second_lines: lines(&second),
+        first,
+        second,
+        similarity,
+    }
+}
+
+pub fn duplication_table(duplicates: &[Duplicate]) -> String {
+    let mut out = String::from("=== DUPLICATED CODE ===\n");
+    if duplicates.is_empty() {
+        out.push_str("No duplicated blocks found\n");
+    }
+    for duplicate in duplicates {
+        writeln!(
+            out,
+            "Lines {}-{} and {}-{}: {:.1}% similar ({} tokens)",
+            duplicate.first_lines.0,
+            duplicate.first_lines.1,
+            duplicate.second_lines.0,
+            duplicate.second_lines.1,
+            duplicate.similarity,
+            duplicate.first.len()
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // 43 tokens over 7 lines.
+    const BLOCK: &str = "\
+int total = 0;
+for (int i = 0; i < 10; i++) {
+    total = total + i * 2;
+    if (total > 50) {
+        total = total - 1;
+    }
+}
+";
+
+    // The block with every name and value changed.
+    const RENAMED: &str = "\
+int sum = 0;
+for (int k = 0; k < 20; k++) {
+    sum = sum + k * 2;
+    if (sum > 75) {
+        sum = sum - 1;
+    }
+}
+";
+
+    // 19 tokens over 5 lines sharing no shingle with the block.
+    const UNRELATED: &str = "string name = \"x\";\nwhile (name != \"y\") {\n    name = \"y\";\n}\nreturn;\n";
+
+    fn found(source: &str) -> Vec<Duplicate> {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        lexer.duplicates(DEFAULT_DUPLICATION_THRESHOLD)
+    }
+
+    #[test]
+    fn an_exact_copy_is_found_with_its_lines() {
+        let duplicates = found(&format!("{}{}{}", BLOCK, UNRELATED, BLOCK));
+        assert_eq!(
+            duplicates,
+            [Duplicate {
+                first: 0..43,
+                second: 62..105,
+                first_lines: (1, 7),
+                second_lines: (13, 19),
+                similarity: 100.0,
+            }]
+        );
+        assert_eq!(
+            duplication_table(&duplicates),
+            "=== DUPLICATED CODE ===\nLines 1-7 and 13-19: 100.0% similar (43 tokens)\n"
+        );
+    }
+
+    #[test]
+    fn renamed_variables_and_changed_values_still_match() {
+        let duplicates = found(&format!("{}{}{}", BLOCK, UNRELATED, RENAMED));
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((duplicates[0].first_lines, duplicates[0].second_lines, duplicates[0].similarity), ((1, 7), (13, 19), 100.0));
+    }
+
+    #[test]
+    fn unrelated_code_is_not_reported() {
+        assert_eq!(found(&format!("{}{}", BLOCK, UNRELATED)), []);
+        assert_eq!(found(&UNRELATED.repeat(2)), [], "blocks under MIN_DUPLICATE_TOKENS");
+        assert_eq!(duplication_table(&[]), "=== DUPLICATED CODE ===\nNo duplicated blocks found\n");
+    }
+}
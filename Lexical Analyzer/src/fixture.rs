@@ -0,0 +1,135 @@
+use crate::lexer::{Lexer, Token, TokenType};
+use std::fmt::Write;
+
+// Tokens listed before the rest are only counted, so a fixture for a huge
+// file stays small enough to paste.
+pub const FIXTURE_TOKEN_LIMIT: usize = 500;
+
+// A Rust test for `tests/` that lexes `source` with the default options and
+// checks each token's type, lexeme, line and column against what the lexer
+// produces now. `name` becomes the test's name once made an identifier.
+// Streams longer than `FIXTURE_TOKEN_LIMIT` are checked only that far.
+pub fn rust_fixture(name: &str, source: &str) -> String {
+    let mut lexer = Lexer::new(source.to_string());
+    let _ = lexer.tokenize();
+    let tokens = lexer.get_tokens();
+    let checked = &tokens[..tokens.len().min(FIXTURE_TOKEN_LIMIT)];
+    let name = identifier(name);
+
+    let expected: Vec<String> = checked.iter().map(tuple).collect();
+    // Importing only the enums the list names keeps the test warning-free
+    let mut imports: Vec<&str> = ["Delimiter", "Keyword", "LiteralKind", "Operator"]
+        .into_iter()
+        .filter(|e| expected.iter().any(|t| t.contains(&format!("({}::", e))))
+        .collect();
+    imports.extend(["Lexer", "TokenType"]);
+    imports.sort();
+
+    let mut out = String::new();
+    writeln!(out, "use mcpp_lexer::lexer::{{{}}};", imports.join(", ")).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "const {}_SOURCE: &str = {};", name.to_ascii_uppercase(), string_literal(source)).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "#[test]").unwrap();
+    writeln!(out, "fn {}_tokens() {{", name).unwrap();
+    writeln!(out, "    let mut lexer = Lexer::new({}_SOURCE.to_string());", name.to_ascii_uppercase()).unwrap();
+    writeln!(out, "    let _ = lexer.tokenize();").unwrap();
+    if checked.len() < tokens.len() {
+        writeln!(out, "    // Only the first {} of {} tokens are checked", checked.len(), tokens.len()).unwrap();
+    }
+    writeln!(out, "    let actual: Vec<(TokenType, &str, usize, usize)> = lexer").unwrap();
+    writeln!(out, "        .get_tokens()").unwrap();
+    writeln!(out, "        .iter()").unwrap();
+    if checked.len() < tokens.len() {
+        writeln!(out, "        .take({})", checked.len()).unwrap();
+    }
+    writeln!(out, "        .map(|t| (t.token_type.clone(), t.lexeme.as_str(), t.line, t.column))").unwrap();
+    writeln!(out, "        .collect();").unwrap();
+    writeln!(out, "    let expected: Vec<(TokenType, &str, usize, usize)> = vec![").unwrap();
+    for tuple in &expected {
+        writeln!(out, "        {},", tuple).unwrap();
+    }
+    writeln!(out, "    ];").unwrap();
+    writeln!(out, "    assert_eq!(actual, expected);").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn tuple(token: &Token) -> String {
+    format!("({}, {:?}, {}, {})", type_expr(&token.token_type), token.lexeme, token.line, token.column)
+}
+
+// The Rust path of a token type, as in `TokenType::Keyword(Keyword::Int)`.
+fn type_expr(token_type: &TokenType) -> String {
+    let debug = format!("{:?}", token_type);
+    match debug.split_once('(') {
+        Some((outer, inner)) => {
+            let inner_enum = if outer == "Literal" { "LiteralKind" } else { outer };
+            format!("TokenType::{}({}::{}", outer, inner_enum, inner)
+        }
+        None => format!("TokenType::{}", debug),
+    }
+}
+
+// A raw string with one more `#` than the longest run after a quote in the
+// text. Rust rejects a bare carriage return in a raw string, so text with
+// one is written as an escaped literal instead.
+fn string_literal(text: &str) -> String {
+    if text.contains('\r') {
+        return format!("{:?}", text);
+    }
+    let mut longest = 0;
+    for (i, _) in text.match_indices('"') {
+        let run = text[i + 1..].chars().take_while(|&c| c == '#').count();
+        longest = longest.max(run);
+    }
+    let hashes = "#".repeat(longest + 1);
+    format!("r{}\"{}\"{}", hashes, text, hashes)
+}
+
+// `name` with everything but letters, digits and underscores replaced, and
+// an underscore in front if it would start with a digit.
+fn identifier(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTED_HASHES: &str = "string s = \"a\\\"#b\";\nstring t = R\"(\"##)\";\n";
+
+    // tests/fixture.rs compiles and runs this file, so the generated code
+    // is checked by the compiler as well as here
+    #[test]
+    fn generated_fixture_is_the_committed_one() {
+        assert_eq!(rust_fixture("quoted hashes", QUOTED_HASHES), include_str!("../tests/golden/quoted_hashes_fixture.rs"));
+    }
+
+    #[test]
+    fn raw_strings_get_one_more_hash_than_needed() {
+        assert_eq!(string_literal("int x;"), "r#\"int x;\"#");
+        assert_eq!(string_literal("\"#\" and \"##"), "r###\"\"#\" and \"##\"###");
+        // No raw string may hold a carriage return
+        assert_eq!(string_literal("a;\r\n"), "\"a;\\r\\n\"");
+        assert_eq!(identifier("2 bad-names"), "_2_bad_names");
+        assert_eq!(identifier(""), "_");
+    }
+
+    #[test]
+    fn long_streams_are_cut_with_a_note() {
+        let source = "x;\n".repeat(FIXTURE_TOKEN_LIMIT);
+        let fixture = rust_fixture("long", &source);
+        assert!(fixture.contains(&format!("    // Only the first {} of {} tokens are checked\n", FIXTURE_TOKEN_LIMIT, 2 * FIXTURE_TOKEN_LIMIT + 1)));
+        assert!(fixture.contains(&format!("        .take({})\n", FIXTURE_TOKEN_LIMIT)));
+        assert_eq!(fixture.matches("(TokenType::").count(), FIXTURE_TOKEN_LIMIT);
+        assert!(fixture.starts_with("use mcpp_lexer::lexer::{Delimiter, Lexer, TokenType};\n"));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::bison;
+use crate::lexer::{PatternSet, TokenType};
+use std::fmt::Write;
+
+// Renders a Flex specification with one rule per entry of the pattern table,
+// in table order. Flex prefers the longest match and only falls back to rule
+// order on ties, which gives the same results as the ordered table here
+// because every keyword pattern is guarded by word boundaries.
+pub fn flex_specification(patterns: &PatternSet, header: &str) -> Result<String, String> {
+    let mut rules = String::new();
+    for (token_type, regex) in patterns.patterns() {
+        let translated = translate_regex(regex.as_str()).map_err(|e| {
+            format!("Cannot translate the {} pattern `{}` to Flex: {}", token_type, regex.as_str(), e)
+        })?;
+        let action = if *token_type == TokenType::Comment {
+            "{ /* comment */ }".to_string()
+        } else {
+            format!("{{ return {}; }}", bison::token_name(token_type))
+        };
+        writeln!(rules, "{:<40} {}", translated, action).unwrap();
+    }
+
+    let mut out = String::new();
+    writeln!(out, "%{{").unwrap();
+    writeln!(out, "/* Generated by mcpp-lexer --emit-flex from the lexer's pattern table. */").unwrap();
+    writeln!(out, "#include <stdio.h>").unwrap();
+    writeln!(out, "#include \"{}\"", header).unwrap();
+    writeln!(out, "%}}\n").unwrap();
+    writeln!(out, "%option noyywrap").unwrap();
+    writeln!(out, "%option yylineno\n").unwrap();
+    writeln!(out, "%%\n").unwrap();
+    out.push_str(&rules);
+    writeln!(out, "{:<40} {{ /* whitespace */ }}", "[ \\t\\r\\n\\f\\v]+").unwrap();
+    writeln!(out, "{:<40} {{ fprintf(stderr, \"Lexical Error: Invalid character '%s' at line %d\\n\", yytext, yylineno); return -1; }}", ".").unwrap();
+    writeln!(out, "\n%%").unwrap();
+    Ok(out)
+}
+
+// Translates the subset of Rust regex syntax used by the pattern table into
+// Flex syntax. Word boundaries are only accepted at either end of a pattern,
+// where Flex's longest-match rule makes them redundant, and a lazy `.*?` is
+// only accepted when followed by a one- or two-character literal terminator.
+pub fn translate_regex(pattern: &str) -> Result<String, String> {
+    let mut chars: Vec<char> = pattern.chars().collect();
+    let mut dot_matches_newline = false;
+    if pattern.starts_with("(?s)") {
+        dot_matches_newline = true;
+        chars.drain(..4);
+    } else if pattern.starts_with("(?") && !pattern.starts_with("(?:") {
+        return Err("inline flags other than (?s) are not supported".to_string());
+    }
+
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        match ch {
+            '\\' => {
+                let escaped = *chars.get(i + 1).ok_or("trailing backslash")?;
+                match escaped {
+                    'd' => out.push_str("[0-9]"),
+                    'w' => out.push_str("[a-zA-Z0-9_]"),
+                    's' => out.push_str("[ \\t\\r\\n\\f\\v]"),
+                    'b' => {
+                        if i != 0 && i + 2 != chars.len() {
+                            return Err("word boundary in the middle of a pattern".to_string());
+                        }
+                    }
+                    'n' | 't' | 'r' => {
+                        out.push('\\');
+                        out.push(escaped);
+                    }
+                    c if c.is_ascii_alphanumeric() => return Err(format!("escape sequence \\{}", c)),
+                    c => out.push_str(&flex_literal(c)),
+                }
+                i += 2;
+            }
+            '[' => {
+                let end = class_end(&chars, i).ok_or("unterminated character class")?;
+                out.push_str(&translate_class(&chars[i..=end])?);
+                i = end + 1;
+            }
+            '.' => {
+                if chars.get(i + 1) == Some(&'*') && chars.get(i + 2) == Some(&'?') {
+                    let (terminator, next) = literal_run(&chars, i + 3)?;
+                    out.push_str(&lazy_until(&terminator, dot_matches_newline)?);
+                    i = next;
+                } else {
+                    out.push_str(if dot_matches_newline { "(.|\\n)" } else { "." });
+                    i += 1;
+                }
+            }
+            '*' | '+' | '?' => {
+                if chars.get(i + 1) == Some(&'?') {
+                    return Err("non-greedy quantifier".to_string());
+                }
+                out.push(ch);
+                i += 1;
+            }
+            '{' => {
+                let end = chars[i..].iter().position(|&c| c == '}').ok_or("unterminated repetition")? + i;
+                out.extend(&chars[i..=end]);
+                i = end + 1;
+            }
+            '(' => {
+                if chars.get(i + 1) == Some(&'?') {
+                    if chars.get(i + 2) != Some(&':') {
+                        return Err("group flags or look-around".to_string());
+                    }
+                    i += 3;
+                } else {
+                    i += 1;
+                }
+                out.push('(');
+            }
+            ')' | '|' | '^' | '$' => {
+                out.push(ch);
+                i += 1;
+            }
+            c => {
+                out.push_str(&flex_literal(c));
+                i += 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn flex_literal(c: char) -> String {
+    if c.is_ascii_alphanumeric() || c == '_' {
+        c.to_string()
+    } else {
+        format!("\\{}", c)
+    }
+}
+
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if chars.get(i) == Some(&'^') {
+        i += 1;
+    }
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            ']' => return Some(i),
+            '[' => return None,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn translate_class(class: &[char]) -> Result<String, String> {
+    let mut out = String::from("[");
+    let mut i = 1;
+    while i < class.len() - 1 {
+        if class[i] == '\\' {
+            match class[i + 1] {
+                'd' => out.push_str("0-9"),
+                'w' => out.push_str("a-zA-Z0-9_"),
+                'n' | 't' | 'r' | '\\' | ']' | '^' | '-' => {
+                    out.push('\\');
+                    out.push(class[i + 1]);
+                }
+                c if c.is_ascii_alphanumeric() => return Err(format!("escape sequence \\{} in a class", c)),
+                c => out.push(c),
+            }
+            i += 2;
+        } else {
+            out.push(class[i]);
+            i += 1;
+        }
+    }
+    out.push(']');
+    Ok(out)
+}
+
+// Reads literal characters starting at `start`, returning them and the index
+// of the first character that is not part of the run.
+fn literal_run(chars: &[char], start: usize) -> Result<(Vec<char>, usize), String> {
+    let mut run = Vec::new();
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                let c = *chars.get(i + 1).ok_or("trailing backslash")?;
+                if c.is_ascii_alphanumeric() {
+                    break;
+                }
+                run.push(c);
+                i += 2;
+            }
+            '[' | '(' | ')' | '|' | '.' | '*' | '+' | '?' | '{' | '^' | '$' => break,
+            c => {
+                run.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok((run, i))
+}
+
+// Flex has no lazy quantifiers, so "anything up to the first occurrence of
+// the terminator" is spelled out with negated classes.
+fn lazy_until(terminator: &[char], dot_matches_newline: bool) -> Result<String, String> {
+    let newline = if dot_matches_newline { "" } else { "\\n" };
+    let class_char = |c: char| match c {
+        ']' | '\\' | '^' | '-' => format!("\\{}", c),
+        _ => c.to_string(),
+    };
+    match terminator {
+        [a] => Ok(format!("[^{}{}]*{}", class_char(*a), newline, flex_literal(*a))),
+        [a, b] if a != b => Ok(format!(
+            "([^{a}{nl}]|{la}+[^{a}{b}{nl}])*{la}+{lb}",
+            a = class_char(*a),
+            b = class_char(*b),
+            nl = newline,
+            la = flex_literal(*a),
+            lb = flex_literal(*b),
+        )),
+        _ => Err("lazy `.*?` must be followed by a one- or two-character literal terminator".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_rule_per_pattern_in_table_order() {
+        let patterns = PatternSet::shared();
+        let spec = flex_specification(&patterns, "tokens.h").unwrap();
+        assert!(spec.contains("#include \"tokens.h\""));
+        let rules: Vec<&str> = spec.split("%%").nth(1).unwrap().lines().filter(|line| !line.is_empty()).collect();
+        // The pattern rules, then whitespace and the catch-all
+        assert_eq!(rules.len(), patterns.patterns().len() + 2);
+        for ((token_type, regex), rule) in patterns.patterns().iter().zip(&rules) {
+            let (pattern, action) = rule.split_once(" {").unwrap();
+            assert_eq!(pattern.trim_end(), translate_regex(regex.as_str()).unwrap());
+            if *token_type == TokenType::Comment {
+                assert!(action.contains("comment"));
+            } else {
+                assert_eq!(action, format!(" return {}; }}", bison::token_name(token_type)));
+            }
+        }
+        assert!(rules[rules.len() - 1].starts_with(". "));
+    }
+
+    #[test]
+    fn translates_the_dialect_differences() {
+        assert_eq!(translate_regex(r"\bwhile\b").unwrap(), "while");
+        assert_eq!(translate_regex(r"\d+\.\d+").unwrap(), "[0-9]+\\.[0-9]+");
+        assert_eq!(translate_regex(r"(?:a|b)").unwrap(), "(a|b)");
+        assert_eq!(translate_regex(r#"".*?""#).unwrap(), "\\\"[^\"\\n]*\\\"");
+        assert_eq!(translate_regex(r"(?s)/\*.*?\*/").unwrap(), "\\/\\*([^*]|\\*+[^*/])*\\*+\\/");
+        assert_eq!(translate_regex(r"[\w$]").unwrap(), "[a-zA-Z0-9_$]");
+    }
+
+    #[test]
+    fn refuses_untranslatable_constructs() {
+        for pattern in [r"(?i)int", r"a\bb", r"a+?", r"(?=a)", r"\p{L}", r"[a", r"x.*?[ab]"] {
+            assert!(translate_regex(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::lexer::{Delimiter, Keyword, SymbolTableBuilder, Token, TokenType};
+use std::ops::Range;
+
+// Where one function definition lies in the token stream: `tokens` indexes
+// the stream from the first token of its signature, such as the return
+// type, through the `}` closing its body, and `line` and `end_line` are the
+// lines those two tokens are on. A body still open at EOF ends at the last
+// token and has `closed` false.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSpan {
+    pub name: String,
+    pub tokens: Range<usize>,
+    pub line: usize,
+    pub end_line: usize,
+    pub closed: bool,
+}
+
+// Every function whose body opens in `tokens`, in source order, found the
+// way the symbol table finds them. A signature starts after the `;`, `{`,
+// `}` or `:` before it, so comments and directives before it are left out;
+// prototypes have no body and are not listed.
+pub fn function_spans(tokens: &[Token]) -> Vec<FunctionSpan> {
+    let mut builder = SymbolTableBuilder::new();
+    let mut spans: Vec<FunctionSpan> = Vec::new();
+    let mut statement_start = None;
+    for (i, token) in tokens.iter().enumerate() {
+        if token.token_type == TokenType::EOF {
+            break;
+        }
+        let was_open = builder.current_function().is_some();
+        builder.observe(token);
+        let directive = token.directive || matches!(token.token_type, TokenType::Keyword(Keyword::Include | Keyword::Define | Keyword::Directive));
+        let code = token.token_type != TokenType::Comment && !directive;
+        if code && statement_start.is_none() {
+            statement_start = Some(i);
+        }
+        if !was_open {
+            if let Some(name) = builder.current_function() {
+                let start = statement_start.unwrap_or(i);
+                spans.push(FunctionSpan {
+                    name: name.to_string(),
+                    tokens: start..i + 1,
+                    line: tokens[start].line,
+                    end_line: token.end_line,
+                    closed: false,
+                });
+            }
+        }
+        if was_open || builder.current_function().is_some() {
+            let span = spans.last_mut().unwrap();
+            span.tokens.end = i + 1;
+            span.end_line = token.end_line;
+            span.closed = builder.current_function().is_none();
+        }
+        if code
+            && matches!(
+                token.token_type,
+                TokenType::Delimiter(Delimiter::Semicolon | Delimiter::LeftBrace | Delimiter::RightBrace | Delimiter::Colon)
+            )
+        {
+            statement_start = None;
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions};
+
+    const TWO_FUNCTIONS: &str = "\
+// helper
+int add(int a, int b);
+int add(int a, int b) {
+    return a + b;
+}
+/* main */
+int main() {
+    if (add(1, 2) > 2) { return 1; }
+    return 0;
+}
+";
+
+    fn lexed(source: &str) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            keep_comments: true,
+            ..LexerOptions::default()
+        });
+        let _ = lexer.tokenize();
+        lexer
+    }
+
+    fn lexemes(tokens: &[Token]) -> String {
+        tokens.iter().map(|t| t.lexeme.as_ref()).collect::<Vec<&str>>().join(" ")
+    }
+
+    #[test]
+    fn slices_run_from_the_signature_through_the_closing_brace() {
+        let lexer = lexed(TWO_FUNCTIONS);
+        let spans: Vec<_> = lexer.functions().into_iter().map(|s| (s.name, s.tokens, s.line, s.end_line, s.closed)).collect();
+        // The comments and the prototype before each are left out
+        assert_eq!(spans, [("add".to_string(), 11..27, 3, 5, true), ("main".to_string(), 28..53, 7, 10, true)]);
+        assert_eq!(lexemes(lexer.function_tokens("add").unwrap()), "int add ( int a , int b ) { return a + b ; }");
+        let main = lexer.function_tokens("main").unwrap();
+        assert_eq!(lexemes(&main[..4]), "int main ( )");
+        assert_eq!((main.last().unwrap().lexeme.as_ref(), main.last().unwrap().line), ("}", 10));
+        assert_eq!(lexer.function_tokens("missing"), None);
+    }
+
+    #[test]
+    fn same_named_functions_are_all_returned() {
+        let lexer = lexed("void f() { }\nvoid f(int x) { x = 1; }\nvoid g() {\n  int y;\n");
+        let all: Vec<String> = lexer.all_function_tokens("f").into_iter().map(lexemes).collect();
+        assert_eq!(all, ["void f ( ) { }", "void f ( int x ) { x = 1 ; }"]);
+        assert_eq!(lexemes(lexer.function_tokens("f").unwrap()), all[0]);
+
+        // Still open at the end of the file
+        let open = &lexer.functions()[2];
+        assert_eq!((open.name.as_str(), open.line, open.end_line, open.closed), ("g", 3, 4, false));
+        assert_eq!(lexemes(lexer.function_tokens("g").unwrap()), "void g ( ) { int y ;");
+    }
+}
@@ -0,0 +1,359 @@
+use crate::lexer::Lexer;
+
+// SplitMix64: small, fast and the same on every platform, which is all a
+// reproducible test corpus needs.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+// Every token type but Error appears in this fixed opening, so even a small
+// program covers them all; the functions that follow add the bulk.
+const PRELUDE: &str = r#"#include <iostream>
+#define LIMIT 100
+#pragma once
+using Number = int;
+typedef float Real;
+class Counter {
+public:
+    int count = 0;
+protected:
+    bool ready = true;
+private:
+    char tag = '\'';
+};
+[[nodiscard]] int total(int first, ...);
+void reset();
+"#;
+
+const STRINGS: &[&str] = &[
+    r#""plain text""#,
+    r#""tab\tand newline\n""#,
+    r#""say \"hi\"""#,
+    r#""back\\slash""#,
+    r#""bell\a form\f query\?""#,
+    r#""""#,
+];
+
+const CHARS: &[&str] = &["'a'", "'Z'", "'0'", r"'\n'", r"'\t'", r"'\\'", r"'\''", r"'\0'"];
+
+const COMMENTS: &[&str] = &[
+    "// running total",
+    "// TODO: check the bounds",
+    "/* keeps the loop short */",
+    "/* see the header for the contract */",
+];
+
+struct Generator {
+    rng: Rng,
+    out: String,
+    next_name: usize,
+    // Names of the int variables visible at each open block, innermost last
+    scopes: Vec<Vec<String>>,
+    // The label the current function's `goto`s jump to, once one is needed
+    label: Option<String>,
+}
+
+// A syntactically plausible MCPP program of roughly `size` tokens, the same
+// for the same seed: the prelude, then int functions of declarations,
+// assignments, loops, conditions, comments and allocations, then `main`.
+// Every name is declared once and initialized, and every `new` is deleted,
+// so the program lexes without errors or warnings.
+pub fn generate_program(seed: u64, size: usize) -> String {
+    let mut generator = Generator {
+        rng: Rng(seed),
+        out: String::from(PRELUDE),
+        next_name: 0,
+        scopes: Vec::new(),
+        label: None,
+    };
+    let mut tokens = count_tokens(PRELUDE);
+    while tokens < size {
+        let start = generator.out.len();
+        let name = generator.name("f");
+        generator.function(&name);
+        tokens += count_tokens(&generator.out[start..]);
+    }
+    generator.function("main");
+    generator.out
+}
+
+fn count_tokens(text: &str) -> usize {
+    let mut lexer = Lexer::new(text.to_string());
+    let _ = lexer.tokenize();
+    lexer.get_tokens().len().saturating_sub(1)
+}
+
+impl Generator {
+    fn name(&mut self, prefix: &str) -> String {
+        self.next_name += 1;
+        format!("{}{}", prefix, self.next_name)
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.scopes.len() {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn declare_int(&mut self, name: &str) {
+        self.scopes.last_mut().unwrap().push(name.to_string());
+    }
+
+    fn function(&mut self, name: &str) {
+        let params: Vec<String> = if name == "main" {
+            Vec::new()
+        } else {
+            (0..1 + self.rng.below(3)).map(|_| self.name("a")).collect()
+        };
+        let list: Vec<String> = params.iter().map(|p| format!("int {}", p)).collect();
+        self.line(&format!("int {}({}) {{", name, list.join(", ")));
+        self.scopes.push(params);
+        self.label = None;
+        for _ in 0..3 + self.rng.below(6) {
+            self.statement(2);
+        }
+        if let Some(label) = self.label.take() {
+            self.line(&format!("{}:", label));
+        }
+        let result = if name == "main" { "0".to_string() } else { self.int_expr(2) };
+        self.line(&format!("return {};", result));
+        self.scopes.pop();
+        self.line("}");
+    }
+
+    // Opens a block after `header` with `names` in scope and fills it; the
+    // caller may add to it before closing it.
+    fn open(&mut self, header: &str, depth: usize, names: Vec<String>) {
+        self.line(&format!("{} {{", header));
+        self.scopes.push(names);
+        for _ in 0..1 + self.rng.below(3) {
+            self.statement(depth);
+        }
+    }
+
+    fn close(&mut self, footer: &str) {
+        self.scopes.pop();
+        self.line(footer);
+    }
+
+    fn statement(&mut self, depth: usize) {
+        let choice = if depth == 0 { self.rng.below(5) } else { self.rng.below(10) };
+        match choice {
+            0 | 1 => self.declaration(),
+            2 => self.assignment(),
+            3 => {
+                let comment = *self.rng.pick(COMMENTS);
+                self.line(comment);
+            }
+            4 => self.allocation(),
+            5 | 6 => {
+                let condition = self.condition();
+                self.open(&format!("if ({})", condition), depth - 1, Vec::new());
+                if self.rng.chance(40) {
+                    self.close("} else {");
+                    self.scopes.push(Vec::new());
+                    self.statement(depth - 1);
+                }
+                self.close("}");
+            }
+            7 => {
+                let counter = self.name("i");
+                let limit = self.int_operand();
+                let step = if self.rng.chance(70) { format!("{}++", counter) } else { format!("{}--", counter) };
+                let compare = if step.ends_with("++") { "<" } else { ">=" };
+                let start = if compare == "<" { "0" } else { "10" };
+                let header = format!("for (int {} = {}; {} {} {}; {})", counter, start, counter, compare, limit, step);
+                self.open(&header, depth - 1, vec![counter]);
+                self.close("}");
+            }
+            8 => {
+                let counter = self.name("n");
+                let start = self.rng.below(10);
+                self.line(&format!("int {} = {};", counter, start));
+                self.declare_int(&counter);
+                let header = format!("while ({} > 0 && !{})", counter, self.bool_literal());
+                self.open(&header, depth - 1, Vec::new());
+                self.line(&format!("{}--;", counter));
+                self.close("}");
+            }
+            _ => {
+                let label = match &self.label {
+                    Some(label) => label.clone(),
+                    None => {
+                        let label = self.name("done");
+                        self.label = Some(label.clone());
+                        label
+                    }
+                };
+                let condition = self.condition();
+                self.line(&format!("if ({}) {{", condition));
+                self.scopes.push(Vec::new());
+                self.line(&format!("goto {};", label));
+                self.close("}");
+            }
+        }
+    }
+
+    fn declaration(&mut self) {
+        let name = self.name("v");
+        let (kind, value) = match self.rng.below(6) {
+            0 | 1 => ("int", self.int_expr(2)),
+            2 => ("float", self.float_literal()),
+            3 => ("char", self.rng.pick(CHARS).to_string()),
+            4 => ("bool", self.bool_literal().to_string()),
+            _ if self.rng.chance(50) => ("string", self.rng.pick(STRINGS).to_string()),
+            _ => ("auto", self.float_literal()),
+        };
+        self.line(&format!("{} {} = {};", kind, name, value));
+        if kind == "int" {
+            self.declare_int(&name);
+        }
+    }
+
+    fn assignment(&mut self) {
+        let Some(target) = self.visible_int() else {
+            return self.declaration();
+        };
+        let value = self.int_expr(2);
+        self.line(&format!("{} = {};", target, value));
+    }
+
+    fn allocation(&mut self) {
+        let pointer = self.name("p");
+        if self.rng.chance(50) {
+            let length = 1 + self.rng.below(16);
+            self.line(&format!("int* {} = new int[{}];", pointer, length));
+            self.line(&format!("delete[] {};", pointer));
+        } else {
+            self.line(&format!("int* {} = new int;", pointer));
+            self.line(&format!("delete {};", pointer));
+        }
+    }
+
+    fn visible_int(&mut self) -> Option<String> {
+        let visible: Vec<&String> = self.scopes.iter().flatten().collect();
+        if visible.is_empty() {
+            return None;
+        }
+        Some(visible[self.rng.below(visible.len())].clone())
+    }
+
+    fn int_operand(&mut self) -> String {
+        match self.visible_int() {
+            Some(name) if self.rng.chance(60) => name,
+            _ => self.rng.below(1000).to_string(),
+        }
+    }
+
+    fn int_expr(&mut self, depth: usize) -> String {
+        if depth == 0 || self.rng.chance(40) {
+            return self.int_operand();
+        }
+        let left = self.int_expr(depth - 1);
+        let op = *self.rng.pick(&["+", "-", "*", "/", "%", "&"]);
+        // Dividing only by a nonzero literal keeps the program meaningful
+        let right = if op == "/" || op == "%" {
+            (1 + self.rng.below(9)).to_string()
+        } else {
+            self.int_expr(depth - 1)
+        };
+        if self.rng.chance(30) {
+            format!("({} {} {})", left, op, right)
+        } else {
+            format!("{} {} {}", left, op, right)
+        }
+    }
+
+    fn condition(&mut self) -> String {
+        let left = self.int_operand();
+        let op = *self.rng.pick(&["==", "!=", "<", ">", "<=", ">="]);
+        let right = self.int_operand();
+        let comparison = format!("{} {} {}", left, op, right);
+        match self.rng.below(4) {
+            0 => format!("!({})", comparison),
+            1 => format!("{} && {}", comparison, self.bool_literal()),
+            2 => {
+                let other = self.int_operand();
+                format!("{} || {} > 0", comparison, other)
+            }
+            _ => comparison,
+        }
+    }
+
+    fn bool_literal(&mut self) -> &'static str {
+        if self.rng.chance(50) {
+            "true"
+        } else {
+            "false"
+        }
+    }
+
+    fn float_literal(&mut self) -> String {
+        let (whole, fraction) = (self.rng.below(100), self.rng.below(100));
+        if self.rng.chance(20) {
+            format!("{}.{}e{}", whole, fraction, self.rng.below(5))
+        } else {
+            format!("{}.{}", whole, fraction)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions, TokenType};
+    use std::collections::HashSet;
+
+    fn lexed(source: &str) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            keep_comments: true,
+            ..LexerOptions::default()
+        });
+        lexer.tokenize().unwrap();
+        lexer
+    }
+
+    #[test]
+    fn programs_lex_cleanly_for_every_seed() {
+        for seed in [0, 1, 7, 42, 1234, u64::MAX] {
+            let program = generate_program(seed, 5000);
+            let lexer = lexed(&program);
+            assert!(lexer.get_diagnostics().is_empty(), "seed {}: {:?}", seed, lexer.get_diagnostics());
+            let seen: HashSet<&TokenType> = lexer.get_tokens().iter().map(|t| &t.token_type).collect();
+            let missing: Vec<&TokenType> = TokenType::all().iter().filter(|t| **t != TokenType::Error && !seen.contains(t)).collect();
+            assert!(missing.is_empty(), "seed {} never produced {:?}", seed, missing);
+            // Roughly the size asked for
+            let count = lexer.get_tokens().len();
+            assert!((4000..7000).contains(&count), "seed {} gave {} tokens", seed, count);
+        }
+    }
+
+    #[test]
+    fn the_seed_decides_the_program() {
+        assert_eq!(generate_program(42, 2000), generate_program(42, 2000));
+        assert_ne!(generate_program(42, 2000), generate_program(43, 2000));
+    }
+}
@@ -0,0 +1,186 @@
+// Gzip (RFC 1952) support for compressed token dumps, through `flate2`.
+// Output is streamed through the encoder as it is written, and reading
+// accepts every member of a multi-member file, as `gzip` itself does.
+
+use flate2::read::MultiGzDecoder;
+use flate2::Compression;
+use std::io::{self, Read, Write};
+
+pub const DEFAULT_LEVEL: u32 = 6;
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] == 0x1F && data[1] == 0x8B
+}
+
+// A gzip stream written to `inner` at `level`, 0 (stored) to 9; higher
+// levels are taken as 9.
+pub struct GzEncoder<W: Write> {
+    inner: flate2::write::GzEncoder<W>,
+}
+
+impl<W: Write> GzEncoder<W> {
+    pub fn new(inner: W, level: u32) -> Self {
+        GzEncoder {
+            inner: flate2::write::GzEncoder::new(inner, Compression::new(level.min(9))),
+        }
+    }
+
+    // Compresses whatever is buffered, writes the trailer and hands back the
+    // inner writer. Dropping the encoder without calling this truncates the output.
+    pub fn finish(self) -> io::Result<W> {
+        let mut inner = self.inner.finish()?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Decompresses every gzip member in `data` and verifies their checksums.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if !is_gzip(data) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a gzip stream"));
+    }
+    let mut output = Vec::new();
+    MultiGzDecoder::new(data).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::token_stream::TokenStream;
+
+    fn compress(data: &[u8], level: u32) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), level);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // More than one block of text that repeats, then bytes that do not.
+    fn sample() -> Vec<u8> {
+        let mut data = b"{\"token_type\": \"Identifier\", \"lexeme\": \"x\"},\n".repeat(2000);
+        let mut seed: u32 = 7;
+        for _ in 0..5000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            data.push((seed >> 16) as u8);
+        }
+        data
+    }
+
+    #[test]
+    fn every_level_round_trips() {
+        let data = sample();
+        for level in 0..=9 {
+            let compressed = compress(&data, level);
+            assert!(is_gzip(&compressed));
+            assert_eq!(decompress(&compressed).unwrap(), data, "level {}", level);
+        }
+        assert!(compress(&data, 9).len() < data.len() / 4);
+        assert_eq!(decompress(&compress(b"", DEFAULT_LEVEL)).unwrap(), b"");
+    }
+
+    #[test]
+    fn reads_other_tools_output() {
+        // `gzip` output for "hello, world\n" (fixed Huffman code) and for a
+        // pangram repeated three times (dynamic code)
+        let fixed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x28, 0xcf,
+            0x2f, 0xca, 0x49, 0xe1, 0x02, 0x00, 0x53, 0x74, 0x24, 0xf4, 0x0d, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(decompress(&fixed).unwrap(), b"hello, world\n");
+        let dynamic = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xd5, 0x8c, 0x49, 0x01, 0x80, 0x20, 0x10, 0x45, 0xef,
+            0xa6, 0xf8, 0x09, 0x2c, 0x60, 0x0d, 0x0a, 0xa0, 0x82, 0xa0, 0xc0, 0xb0, 0x08, 0x88, 0xe9, 0x9d, 0x1a, 0x9e, 0xdf,
+            0x22, 0x8c, 0x42, 0xaa, 0x76, 0xbb, 0xb0, 0x66, 0xea, 0x01, 0x9a, 0x1e, 0x9c, 0xd5, 0xc7, 0x02, 0x6a, 0x2a, 0xe3,
+            0x66, 0xec, 0xe4, 0x3b, 0xb0, 0xd3, 0xb1, 0x20, 0x4a, 0xf6, 0xfc, 0xc0, 0xca, 0x52, 0xb7, 0xb7, 0x81, 0xb6, 0x4d,
+            0x31, 0x7a, 0x55, 0x80, 0xb3, 0xa9, 0x52, 0xe6, 0xf6, 0x28, 0xf3, 0x24, 0x7e, 0x74, 0xfd, 0x00, 0x25, 0xc9, 0x7f,
+            0xd3, 0x02, 0x01, 0x00, 0x00,
+        ];
+        let text = b"The quick brown fox jumps over the lazy dog; pack my box with five dozen liquor jugs.\n".repeat(3);
+        assert_eq!(decompress(&dynamic).unwrap(), text);
+    }
+
+    #[test]
+    fn corrupt_input_is_an_error() {
+        let mut compressed = compress(b"int main() { return 0; }\n", DEFAULT_LEVEL);
+        let last = compressed.len() - 5;
+        compressed[last] ^= 0xFF;
+        assert!(decompress(&compressed).is_err());
+        assert!(decompress(&compressed[..12]).is_err());
+        assert!(!is_gzip(b"{\"tokens\": []}"));
+    }
+
+    #[test]
+    fn compressed_json_reads_back_as_the_same_tokens() {
+        let mut lexer = Lexer::new("int main() {\n    string s = \"a\\tb\";\n    return 0;\n}\n".to_string());
+        lexer.tokenize().unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), DEFAULT_LEVEL);
+        lexer.write_json(&mut encoder, false, &Default::default(), Default::default(), false).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let stream = TokenStream::from_json_slice(&decompress(&compressed).unwrap()).unwrap();
+        assert_eq!(stream.tokens(), lexer.get_tokens().as_slice());
+    }
+
+    #[test]
+    fn every_truncation_is_an_error() {
+        let data = b"int main() {\n    return 0;\n}\n".repeat(20);
+        let compressed = compress(&data, DEFAULT_LEVEL);
+        for end in 0..compressed.len() {
+            assert!(decompress(&compressed[..end]).is_err(), "cut at {} of {}", end, compressed.len());
+        }
+    }
+
+    #[test]
+    fn flipped_bytes_never_read_back_as_other_text() {
+        let data = b"string s = \"abc\"; int x = 42;\n".repeat(10);
+        let compressed = compress(&data, DEFAULT_LEVEL);
+        // The text flag, timestamp, extra flags and OS byte of the header,
+        // and the padding bits after the last block, may change freely
+        let last_block_byte = compressed.len() - 9;
+        for index in 0..compressed.len() {
+            for mask in [0x01, 0x80, 0xFF] {
+                let mut corrupt = compressed.clone();
+                corrupt[index] ^= mask;
+                if let Ok(output) = decompress(&corrupt) {
+                    assert_eq!(output, data, "byte {} ^ {:#x}", index, mask);
+                    assert!((3..10).contains(&index) || index == last_block_byte, "byte {} ^ {:#x} went unnoticed", index, mask);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_bytes_after_the_magic_are_refused() {
+        let mut seed: u32 = 99;
+        for length in [2, 10, 18, 64, 300] {
+            for _ in 0..50 {
+                let mut data = vec![0x1F, 0x8B];
+                while data.len() < length {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    data.push((seed >> 16) as u8);
+                }
+                assert!(decompress(&data).is_err());
+            }
+        }
+        let mut trailing = compress(b"x", DEFAULT_LEVEL);
+        trailing.extend_from_slice(b"garbage");
+        assert!(decompress(&trailing).is_err());
+    }
+
+    #[test]
+    fn members_are_concatenated() {
+        let mut joined = compress(b"int a;\n", 1);
+        joined.extend(compress(b"int b;\n", 9));
+        assert_eq!(decompress(&joined).unwrap(), b"int a;\nint b;\n");
+    }
+}
@@ -0,0 +1,242 @@
+use crate::lexer::{Token, TokenType};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Identifiers shown when no count is asked for.
+pub const DEFAULT_TOP_IDENTIFIERS: usize = 10;
+
+// Labels longer than this are cut short in the terminal chart.
+const LABEL_WIDTH: usize = 20;
+
+// Token counts per category and the most frequent identifiers, each most
+// frequent first and alphabetical among ties. EOF is not counted.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TokenHistogram {
+    pub categories: Vec<(String, usize)>,
+    pub identifiers: Vec<(String, usize)>,
+}
+
+pub fn histogram(tokens: &[Token], top: usize) -> TokenHistogram {
+    let mut categories: HashMap<&str, usize> = HashMap::new();
+    let mut identifiers: HashMap<&str, usize> = HashMap::new();
+    for token in tokens.iter().filter(|t| t.token_type != TokenType::EOF) {
+        *categories.entry(token.token_type.category()).or_insert(0) += 1;
+        if token.token_type == TokenType::Identifier {
+            *identifiers.entry(&token.lexeme).or_insert(0) += 1;
+        }
+    }
+    let mut identifiers = ranked(identifiers);
+    identifiers.truncate(top);
+    TokenHistogram {
+        categories: ranked(categories),
+        identifiers,
+    }
+}
+
+fn ranked(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.into_iter().map(|(name, count)| (name.to_string(), count)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+// How many of `room` cells a bar for `count` gets when `max` fills them all.
+// Every nonzero count gets at least one.
+pub fn bar_length(count: usize, max: usize, room: usize) -> usize {
+    if count == 0 || max == 0 {
+        return 0;
+    }
+    ((count * room + max / 2) / max).clamp(1, room.max(1))
+}
+
+// Both charts as `#` bars, the longest filling what `width` columns leave
+// after the label and count.
+pub fn histogram_chart(histogram: &TokenHistogram, width: usize) -> String {
+    let mut out = String::from("=== TOKEN HISTOGRAM ===\n");
+    chart_section(&mut out, "Tokens by category", &histogram.categories, width);
+    writeln!(out).unwrap();
+    chart_section(&mut out, "Most frequent identifiers", &histogram.identifiers, width);
+    out
+}
+
+fn chart_section(out: &mut String, title: &str, rows: &[(String, usize)], width: usize) {
+    writeln!(out, "{}", title).unwrap();
+    if rows.is_empty() {
+        writeln!(out, "  (none)").unwrap();
+        return;
+    }
+    let max = rows[0].1;
+    let count_width = max.to_string().len();
+    // Two spaces of indent, the label, a space, the count and a space
+    let room = width.saturating_sub(LABEL_WIDTH + count_width + 4).max(1);
+    for (label, count) in rows {
+        writeln!(
+            out,
+            "  {:<label_width$} {:>count_width$} {}",
+            shorten(label),
+            count,
+            "#".repeat(bar_length(*count, max, room)),
+            label_width = LABEL_WIDTH
+        )
+        .unwrap();
+    }
+}
+
+fn shorten(label: &str) -> String {
+    if label.chars().count() <= LABEL_WIDTH {
+        return label.to_string();
+    }
+    let kept: String = label.chars().take(LABEL_WIDTH - 1).collect();
+    format!("{}…", kept)
+}
+
+// Geometry of the SVG chart, in pixels.
+const SVG_WIDTH: usize = 640;
+const SVG_LABEL_WIDTH: usize = 160;
+const SVG_BAR_ROOM: usize = 400;
+const SVG_ROW_HEIGHT: usize = 22;
+const SVG_TITLE_HEIGHT: usize = 30;
+
+// A standalone SVG of both charts as horizontal bars, labels on the left
+// and counts after each bar. A chart with nothing to show says so.
+pub fn histogram_svg(histogram: &TokenHistogram) -> String {
+    let sections = [("Tokens by category", &histogram.categories), ("Most frequent identifiers", &histogram.identifiers)];
+    let height: usize = sections.iter().map(|(_, rows)| SVG_TITLE_HEIGHT + SVG_ROW_HEIGHT * rows.len().max(1)).sum::<usize>() + 10;
+
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="sans-serif" font-size="13">"#,
+        SVG_WIDTH, height
+    )
+    .unwrap();
+    writeln!(out, r#"  <rect width="100%" height="100%" fill="white"/>"#).unwrap();
+    let mut y = 0;
+    for (title, rows) in sections {
+        y += SVG_TITLE_HEIGHT;
+        writeln!(out, r#"  <text x="10" y="{}" font-weight="bold" font-size="15">{}</text>"#, y - 10, title).unwrap();
+        if rows.is_empty() {
+            writeln!(out, r##"  <text x="10" y="{}" fill="#666">None</text>"##, y + 15).unwrap();
+            y += SVG_ROW_HEIGHT;
+            continue;
+        }
+        let max = rows[0].1;
+        for (label, count) in rows.iter() {
+            let length = bar_length(*count, max, SVG_BAR_ROOM);
+            writeln!(out, r#"  <text x="{}" y="{}" text-anchor="end">{}</text>"#, SVG_LABEL_WIDTH - 8, y + 15, escape_xml(&shorten(label))).unwrap();
+            writeln!(out, r##"  <rect x="{}" y="{}" width="{}" height="{}" fill="#4a7ab5"/>"##, SVG_LABEL_WIDTH, y + 3, length, SVG_ROW_HEIGHT - 6).unwrap();
+            writeln!(out, r#"  <text x="{}" y="{}">{}</text>"#, SVG_LABEL_WIDTH + length + 6, y + 15, count).unwrap();
+            y += SVG_ROW_HEIGHT;
+        }
+    }
+    writeln!(out, "</svg>").unwrap();
+    out
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn counted(source: &str, top: usize) -> TokenHistogram {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        histogram(lexer.get_tokens(), top)
+    }
+
+    const SOURCE: &str = "int total = 0;\nint count = 0;\ntotal = total + count;\ntotal = total * 2;\n";
+
+    // Checks that every tag closes in order and that text holds no bare `<`
+    // or `&`, which is as much of XML as the chart uses.
+    fn assert_well_formed(xml: &str) {
+        let body = xml.strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n").expect("declaration");
+        let mut open: Vec<&str> = Vec::new();
+        let mut rest = body;
+        while let Some(start) = rest.find('<') {
+            let text = &rest[..start];
+            for (index, _) in text.match_indices('&') {
+                let entity = &text[index..text[index..].find(';').map_or(text.len(), |end| index + end + 1)];
+                assert!(["&amp;", "&lt;", "&gt;", "&quot;", "&apos;"].contains(&entity), "bare & in {:?}", text);
+            }
+            let end = start + rest[start..].find('>').expect("unclosed tag");
+            let tag = &rest[start + 1..end];
+            assert!(!tag.contains('<'), "< inside {:?}", tag);
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name));
+            } else if !tag.ends_with('/') {
+                open.push(tag.split_whitespace().next().unwrap());
+            }
+            rest = &rest[end + 1..];
+        }
+        assert!(open.is_empty(), "unclosed {:?}", open);
+        assert_eq!(rest.trim(), "");
+    }
+
+    #[test]
+    fn counts_rank_most_frequent_first() {
+        let counted = counted(SOURCE, 1);
+        let categories: Vec<(&str, usize)> = counted.categories.iter().map(|(name, count)| (name.as_str(), *count)).collect();
+        assert_eq!(categories, [("identifier", 7), ("operator", 6), ("delimiter", 4), ("literal", 3), ("keyword", 2)]);
+        assert_eq!(counted.identifiers, [("total".to_string(), 5)]);
+    }
+
+    #[test]
+    fn bars_scale_to_a_fixed_width() {
+        assert_eq!(bar_length(7, 7, 25), 25);
+        assert_eq!(bar_length(6, 7, 25), 21);
+        assert_eq!(bar_length(1, 1000, 25), 1);
+        assert_eq!(bar_length(0, 7, 25), 0);
+        assert_eq!(bar_length(3, 0, 25), 0);
+        // 50 columns leave 25 for bars after the label and a one-digit count
+        let chart = histogram_chart(&counted(SOURCE, 3), 50);
+        let bars: Vec<usize> = chart.lines().filter(|l| l.starts_with("  ")).map(|l| l.matches('#').count()).collect();
+        assert_eq!(bars, [25, 21, 14, 11, 7, 25, 10]);
+        assert!(chart.lines().all(|line| line.chars().count() <= 50));
+    }
+
+    #[test]
+    fn an_empty_stream_charts_as_none() {
+        let empty = counted("", 10);
+        assert_eq!(empty, TokenHistogram::default());
+        assert_eq!(
+            histogram_chart(&empty, 80),
+            "=== TOKEN HISTOGRAM ===\nTokens by category\n  (none)\n\nMost frequent identifiers\n  (none)\n"
+        );
+        let svg = histogram_svg(&empty);
+        assert_well_formed(&svg);
+        assert_eq!(svg.matches(">None</text>").count(), 2);
+    }
+
+    #[test]
+    fn svg_is_well_formed_and_escapes_names() {
+        let svg = histogram_svg(&counted(SOURCE, 10));
+        assert_well_formed(&svg);
+        // The longest bar fills the room and the rest scale from it
+        assert!(svg.contains(r##"<rect x="160" y="33" width="400" height="16" fill="#4a7ab5"/>"##), "{}", svg);
+        assert!(svg.contains(r##"<rect x="160" y="55" width="343" height="16" fill="#4a7ab5"/>"##), "{}", svg);
+
+        let odd = TokenHistogram {
+            categories: Vec::new(),
+            identifiers: vec![("a<b&\"c'>".to_string(), 2)],
+        };
+        let svg = histogram_svg(&odd);
+        assert_well_formed(&svg);
+        assert!(svg.contains(">a&lt;b&amp;&quot;c&apos;&gt;</text>"));
+        assert_eq!(escape_xml("plain"), "plain");
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+// A token's text. Identifiers and keywords the lexer finds share one
+// allocation per distinct spelling, taken from its `Interner`; any other
+// lexeme owns its own. It reads as a `&str` wherever one is expected, and
+// compares, orders, hashes and serializes as its text, so two lexemes are
+// equal however they were made.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lexeme(Arc<str>);
+
+impl Lexeme {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // Whether both share one allocation, as interned copies of a spelling do.
+    pub fn shares_text(&self, other: &Lexeme) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Deref for Lexeme {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Lexeme {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Lexeme {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Lexeme {
+    fn from(text: String) -> Self {
+        Lexeme(text.into())
+    }
+}
+
+impl From<&str> for Lexeme {
+    fn from(text: &str) -> Self {
+        Lexeme(text.into())
+    }
+}
+
+impl From<Lexeme> for String {
+    fn from(lexeme: Lexeme) -> Self {
+        lexeme.0.to_string()
+    }
+}
+
+impl PartialEq<str> for Lexeme {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Lexeme {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Lexeme {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<Lexeme> for str {
+    fn eq(&self, other: &Lexeme) -> bool {
+        self == &*other.0
+    }
+}
+
+impl PartialEq<Lexeme> for &str {
+    fn eq(&self, other: &Lexeme) -> bool {
+        *self == &*other.0
+    }
+}
+
+impl PartialEq<Lexeme> for String {
+    fn eq(&self, other: &Lexeme) -> bool {
+        **self == *other.0
+    }
+}
+
+impl fmt::Display for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&self.0)
+    }
+}
+
+impl fmt::Debug for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for Lexeme {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Lexeme {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Lexeme::from)
+    }
+}
+
+// The distinct identifier and keyword spellings a lexer has handed out.
+// Entries are never removed, so one whose tokens were rolled back stays
+// until the lexer is reset.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    strings: HashSet<Lexeme>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The shared copy of `text`, made on first use.
+    pub fn intern(&mut self, text: &str) -> Lexeme {
+        if let Some(lexeme) = self.strings.get(text) {
+            return lexeme.clone();
+        }
+        let lexeme = Lexeme::from(text);
+        self.strings.insert(lexeme.clone());
+        lexeme
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.strings.clear();
+    }
+}
+
+// What interning saved over a token stream: `occurrences` interned lexemes
+// spelled `unique` ways, and the bytes of text the repeats would have taken
+// had each owned its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InternStats {
+    pub unique: usize,
+    pub occurrences: usize,
+    pub bytes_saved: usize,
+}
+
+impl InternStats {
+    pub fn of<'a>(lexemes: impl IntoIterator<Item = &'a Lexeme>) -> Self {
+        let mut seen = HashSet::new();
+        let mut stats = InternStats::default();
+        for lexeme in lexemes {
+            stats.occurrences += 1;
+            if seen.insert(lexeme.as_str()) {
+                stats.unique += 1;
+            } else {
+                stats.bytes_saved += lexeme.len();
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hashed<T: Hash + ?Sized>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn each_spelling_is_allocated_once() {
+        let mut interner = Interner::new();
+        assert!(interner.is_empty());
+        let first = interner.intern("count");
+        let second = interner.intern("count");
+        let other = interner.intern("total");
+        assert!(first.shares_text(&second));
+        assert!(!first.shares_text(&other));
+        assert_eq!(interner.len(), 2);
+        interner.clear();
+        assert!(interner.is_empty());
+        let after = interner.intern("count");
+        assert_eq!(after, first);
+        assert!(!after.shares_text(&first));
+    }
+
+    #[test]
+    fn lexemes_behave_as_their_text() {
+        let interned = Interner::new().intern("alpha");
+        let owned = Lexeme::from(String::from("alpha"));
+        assert_eq!(interned, owned);
+        assert!(!interned.shares_text(&owned));
+        assert_eq!(interned, "alpha");
+        assert_eq!("alpha", interned);
+        assert_eq!(interned, String::from("alpha"));
+        assert_eq!(hashed(&interned), hashed("alpha"));
+        let mut sorted = vec![Lexeme::from("beta"), interned.clone(), Lexeme::from("Alpha")];
+        sorted.sort();
+        assert_eq!(sorted, ["Alpha", "alpha", "beta"]);
+        assert_eq!(Lexeme::from("b").cmp(&Lexeme::from("a")), "b".cmp("a"));
+        assert_eq!(format!("[{:>7}] {:?}", interned, interned), "[  alpha] \"alpha\"");
+        assert_eq!(String::from(interned.clone()), "alpha");
+        let set: HashSet<Lexeme> = [interned].into_iter().collect();
+        assert!(set.contains("alpha"));
+    }
+
+    #[test]
+    fn lexemes_serialize_as_plain_strings() {
+        let lexeme = Interner::new().intern("x\"y");
+        let json = serde_json::to_string(&lexeme).unwrap();
+        assert_eq!(json, "\"x\\\"y\"");
+        assert_eq!(json, serde_json::to_string("x\"y").unwrap());
+        let back: Lexeme = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, lexeme);
+    }
+
+    #[test]
+    fn statistics_count_repeats_by_length() {
+        let lexemes: Vec<Lexeme> = ["int", "total", "total", "i", "total", "int"].into_iter().map(Lexeme::from).collect();
+        assert_eq!(InternStats::of(&lexemes), InternStats { unique: 3, occurrences: 6, bytes_saved: 13 });
+        assert_eq!(InternStats::of(&[]), InternStats::default());
+    }
+}
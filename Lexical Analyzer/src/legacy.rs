@@ -0,0 +1,263 @@
+use crate::lexer::{Token, TokenType};
+use std::fmt;
+
+// The mapping shipped with the lexer, from `data/legacy_types.txt`.
+const BUILTIN_MAP: &str = include_str!("../data/legacy_types.txt");
+
+// One line of the earlier C++ lexer's output, `TYPE lexeme line col`. The
+// lexeme is everything between the type and the position, so it may hold
+// spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyToken {
+    pub type_name: String,
+    pub lexeme: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Reads a legacy token list, skipping blank lines.
+pub fn parse_legacy(text: &str) -> Result<Vec<LegacyToken>, String> {
+    let mut tokens = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let malformed = || format!("Line {}: expected `TYPE lexeme line column`, found '{}'", index + 1, line);
+        let (type_name, rest) = line.trim_start().split_once(' ').ok_or_else(malformed)?;
+        let (rest, column) = rest.trim_end().rsplit_once(' ').ok_or_else(malformed)?;
+        let (lexeme, row) = rest.trim_end().rsplit_once(' ').ok_or_else(malformed)?;
+        let (Ok(row), Ok(column)) = (row.parse(), column.parse()) else {
+            return Err(malformed());
+        };
+        tokens.push(LegacyToken {
+            type_name: type_name.to_string(),
+            lexeme: lexeme.trim().to_string(),
+            line: row,
+            column,
+        });
+    }
+    Ok(tokens)
+}
+
+// What a legacy type name accepts: one token type, or any in a category.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegacyType {
+    Exact(TokenType),
+    Category(String),
+}
+
+impl LegacyType {
+    pub fn accepts(&self, token_type: &TokenType) -> bool {
+        match self {
+            LegacyType::Exact(expected) => expected == token_type,
+            LegacyType::Category(category) => token_type.category() == category,
+        }
+    }
+}
+
+const CATEGORIES: &[&str] = &["keyword", "operator", "delimiter", "literal", "identifier", "comment"];
+
+// Legacy type names, uppercased, and what each stands for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyMap {
+    entries: Vec<(String, LegacyType)>,
+}
+
+impl LegacyMap {
+    pub fn builtin() -> LegacyMap {
+        LegacyMap::parse(BUILTIN_MAP).expect("data/legacy_types.txt is well formed")
+    }
+
+    // One `LEGACY_NAME mcpp-type-or-category` pair per line; `#` starts a
+    // comment.
+    pub fn parse(text: &str) -> Result<LegacyMap, String> {
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(target), None) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(format!("Line {}: expected `LEGACY_NAME TYPE`, found '{}'", index + 1, line));
+            };
+            let legacy_type = match TokenType::from_name(target) {
+                Some(token_type) => LegacyType::Exact(token_type),
+                None if CATEGORIES.contains(&target) => LegacyType::Category(target.to_string()),
+                None => return Err(format!("Line {}: '{}' is not a token type or category", index + 1, target)),
+            };
+            entries.push((name.to_ascii_uppercase(), legacy_type));
+        }
+        Ok(LegacyMap { entries })
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&LegacyType> {
+        let name = name.to_ascii_uppercase();
+        self.entries.iter().find(|(known, _)| *known == name).map(|(_, legacy_type)| legacy_type)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    // Both lexers found a token here but disagree on its type or lexeme.
+    Differs { legacy: LegacyToken, actual: Token },
+    // The legacy type name is not in the map, so the token was not compared.
+    UnknownType(LegacyToken),
+    // A token only the legacy lexer reported.
+    Missing(LegacyToken),
+    // A token only this lexer produced.
+    Extra(Token),
+}
+
+impl Discrepancy {
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            Discrepancy::Differs { legacy, .. } | Discrepancy::UnknownType(legacy) | Discrepancy::Missing(legacy) => {
+                (legacy.line, legacy.column)
+            }
+            Discrepancy::Extra(token) => (token.line, token.column),
+        }
+    }
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (line, column) = self.position();
+        write!(f, "line {}, column {}: ", line, column)?;
+        match self {
+            Discrepancy::Differs { legacy, actual } => write!(
+                f,
+                "legacy {} '{}', mcpp {} '{}'",
+                legacy.type_name, legacy.lexeme, actual.token_type, actual.lexeme
+            ),
+            Discrepancy::UnknownType(legacy) => write!(
+                f,
+                "unknown legacy type '{}' for '{}'; add it to data/legacy_types.txt",
+                legacy.type_name, legacy.lexeme
+            ),
+            Discrepancy::Missing(legacy) => write!(f, "legacy {} '{}' has no mcpp token", legacy.type_name, legacy.lexeme),
+            Discrepancy::Extra(token) => write!(f, "mcpp {} '{}' has no legacy token", token.token_type, token.lexeme),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LegacyReport {
+    pub agreements: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+// Pairs the two lists by start position, since either lexer may split or
+// drop tokens the other keeps, and compares each pair's type and lexeme.
+// EOF is left out; comments only count if the legacy list has them.
+pub fn compare_legacy(legacy: &[LegacyToken], tokens: &[Token], map: &LegacyMap) -> LegacyReport {
+    let keep_comments = legacy
+        .iter()
+        .any(|t| map.lookup(&t.type_name).is_some_and(|m| m.accepts(&TokenType::Comment)));
+    let tokens: Vec<&Token> = tokens
+        .iter()
+        .filter(|t| t.token_type != TokenType::EOF && (keep_comments || t.token_type != TokenType::Comment))
+        .collect();
+    let mut legacy: Vec<&LegacyToken> = legacy.iter().collect();
+    legacy.sort_by_key(|t| (t.line, t.column));
+
+    let mut report = LegacyReport::default();
+    let (mut i, mut j) = (0, 0);
+    while i < legacy.len() || j < tokens.len() {
+        let old = legacy.get(i).map(|t| (t.line, t.column));
+        let new = tokens.get(j).map(|t| (t.line, t.column));
+        match (old, new) {
+            (Some(old), Some(new)) if old == new => {
+                let (expected, actual) = (legacy[i], tokens[j]);
+                match map.lookup(&expected.type_name) {
+                    None => report.discrepancies.push(Discrepancy::UnknownType(expected.clone())),
+                    Some(m) if m.accepts(&actual.token_type) && expected.lexeme == actual.lexeme => report.agreements += 1,
+                    Some(_) => report.discrepancies.push(Discrepancy::Differs {
+                        legacy: expected.clone(),
+                        actual: actual.clone(),
+                    }),
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(old), Some(new)) if old < new => {
+                report.discrepancies.push(Discrepancy::Missing(legacy[i].clone()));
+                i += 1;
+            }
+            (Some(_), None) => {
+                report.discrepancies.push(Discrepancy::Missing(legacy[i].clone()));
+                i += 1;
+            }
+            _ => {
+                report.discrepancies.push(Discrepancy::Extra(tokens[j].clone()));
+                j += 1;
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        lexer.get_tokens().clone()
+    }
+
+    #[test]
+    fn the_fixture_pair_has_two_discrepancies() {
+        let legacy = parse_legacy(include_str!("../examples/example1_legacy.txt")).unwrap();
+        let report = compare_legacy(&legacy, &tokens(include_str!("../examples/example1.mcpp")), &LegacyMap::builtin());
+        assert_eq!(report.agreements, 48);
+        let found: Vec<String> = report.discrepancies.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                "line 4, column 5: legacy KEYWORD 'main', mcpp Identifier 'main'",
+                "line 8, column 16: legacy INT_LITERAL '3.14159', mcpp FloatLiteral '3.14159'",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_names_missing_and_extra_tokens_are_reported() {
+        let legacy = parse_legacy("INT int 1 1\nWIDGET x 1 5\nSEMICOLON ; 1 9\n\nSEMICOLON ; 2 1\n").unwrap();
+        let report = compare_legacy(&legacy, &tokens("int x = 1;\n"), &LegacyMap::builtin());
+        assert_eq!(report.agreements, 1);
+        let found: Vec<String> = report.discrepancies.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                "line 1, column 5: unknown legacy type 'WIDGET' for 'x'; add it to data/legacy_types.txt",
+                "line 1, column 7: mcpp Assign '=' has no legacy token",
+                // Paired by position, so this is a difference
+                "line 1, column 9: legacy SEMICOLON ';', mcpp IntegerLiteral '1'",
+                "line 1, column 10: mcpp Semicolon ';' has no legacy token",
+                "line 2, column 1: legacy SEMICOLON ';' has no mcpp token",
+            ]
+        );
+    }
+
+    #[test]
+    fn lexemes_may_hold_spaces_and_malformed_lines_are_refused() {
+        let legacy = parse_legacy("STRING_LITERAL \"a b\" 3 7\r\n").unwrap();
+        assert_eq!(legacy, [LegacyToken { type_name: "STRING_LITERAL".to_string(), lexeme: "\"a b\"".to_string(), line: 3, column: 7 }]);
+        assert_eq!(parse_legacy("INT int 1\n").unwrap_err(), "Line 1: expected `TYPE lexeme line column`, found 'INT int 1'");
+        assert!(parse_legacy("INT int one 1\n").is_err());
+    }
+
+    #[test]
+    fn maps_take_types_or_categories() {
+        let map = LegacyMap::parse("# extra names\nword keyword\nNUM IntegerLiteral  # exact\n").unwrap();
+        assert_eq!(map.lookup("WORD"), Some(&LegacyType::Category("keyword".to_string())));
+        assert!(map.lookup("num").unwrap().accepts(&TokenType::Literal(crate::lexer::LiteralKind::Integer)));
+        assert_eq!(map.lookup("other"), None);
+        assert_eq!(LegacyMap::parse("NUM Number\n").unwrap_err(), "Line 1: 'Number' is not a token type or category");
+        assert!(LegacyMap::parse("NUM\n").is_err());
+    }
+}
@@ -1,25 +1,76 @@
+use crate::allocation::{self, Allocations};
+use crate::cache::content_hash;
+use crate::confusables;
+use crate::diagnostics::{self, Diagnostic, DiagnosticFilter, Severity, Span};
+use crate::document::DocumentRef;
+use crate::duplication::{self, Duplicate};
+use crate::functions::{self, FunctionSpan};
+use crate::intern::{InternStats, Interner, Lexeme};
+use crate::lifetime::{self, Lifetime};
+use crate::line_index::LineIndex;
+use crate::loops::{self, ForLoop};
+use crate::conditional::{self, ConditionalProblemKind, ConditionalReport};
+use crate::metrics::{self, FunctionMetrics};
+use crate::naming::{self, NamingRules};
+use crate::nesting::{self, NestingReport};
+use crate::nfc;
+use crate::token_tree::{self, DelimiterProblemKind, TokenTree};
+use crate::position::{ColumnUnit, LineMap, LineOrigin, PositionBase};
+use crate::scanner;
+use crate::spelling;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum TokenType {
-    // Keywords
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Keyword {
+    // Data types
     Int,
     Float,
     Char,
     Bool,
     String,
+    Void,
+    // Typed by its initializer
+    Auto,
+    
+    // Control flow
     If,
     Else,
     While,
     For,
     Return,
+    Goto,
+    
+    // Classes
+    Class,
+    Public,
+    Private,
+    Protected,
+    
+    // Type aliases
+    Typedef,
+    Using,
+    
+    // Dynamic allocation
+    New,
+    Delete,
     
     // Preprocessor
     Include,
     Define,
-    
-    // Operators
+    // Any other `#name` starting a line, such as `#pragma`
+    Directive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operator {
     Plus,           // +
     Minus,          // -
     Multiply,       // *
@@ -34,10 +85,14 @@ pub enum TokenType {
     GreaterEqual,   // >=
     LogicalAnd,     // &&
     LogicalOr,      // ||
-    Increment,       // ++
+    Increment,      // ++
     Decrement,      // --
-    
-    // Delimiters
+    Ampersand,      // & (reference declarator; address-of and bitwise and)
+    LogicalNot,     // ! (or `not` with word operators)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Delimiter {
     Semicolon,      // ;
     Comma,          // ,
     LeftParen,      // (
@@ -46,496 +101,6866 @@ pub enum TokenType {
     RightBrace,     // }
     LeftBracket,    // [
     RightBracket,   // ]
+    Colon,          // : (after a label)
+    Ellipsis,       // ... (variadic parameters)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LiteralKind {
+    Integer,
+    Float,
+    Char,
+    String,
+    Bool,
+}
+
+// Serialized (and displayed) with the flat names used before the
+// sub-enums existed, e.g. "Int", "Plus", "IntegerLiteral".
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TokenType {
+    Keyword(Keyword),
+    Operator(Operator),
+    Delimiter(Delimiter),
+    Literal(LiteralKind),
+    Identifier,
+    Comment,
+    // `[[...]]`, brackets and all, as in `[[nodiscard]]`.
+    Attribute,
+    // A character no pattern matched, kept in the stream by error recovery.
+    Error,
+    #[allow(clippy::upper_case_acronyms)]
+    EOF,
+}
+
+impl TokenType {
+    // Every token type in the order of the original flat enum.
+    pub fn all() -> &'static [TokenType] {
+        ALL_TOKEN_TYPES
+    }
+    
+    // Keyword spellings with their types, `#include` and `#define` included.
+    pub fn keywords() -> &'static [(&'static str, TokenType)] {
+        KEYWORDS
+    }
+    
+    pub fn operators() -> &'static [(&'static str, TokenType)] {
+        OPERATORS
+    }
+    
+    pub fn delimiters() -> &'static [(&'static str, TokenType)] {
+        DELIMITERS
+    }
+    
+    // The keyword spelled `lexeme`, if any, whatever the options.
+    pub fn keyword(lexeme: &str) -> Option<TokenType> {
+        KEYWORDS.iter().find(|(spelling, _)| *spelling == lexeme).map(|(_, token_type)| token_type.clone())
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenType::Keyword(Keyword::Int) => "Int",
+            TokenType::Keyword(Keyword::Float) => "Float",
+            TokenType::Keyword(Keyword::Char) => "Char",
+            TokenType::Keyword(Keyword::Bool) => "Bool",
+            TokenType::Keyword(Keyword::String) => "String",
+            TokenType::Keyword(Keyword::Void) => "Void",
+            TokenType::Keyword(Keyword::Auto) => "Auto",
+            TokenType::Keyword(Keyword::If) => "If",
+            TokenType::Keyword(Keyword::Else) => "Else",
+            TokenType::Keyword(Keyword::While) => "While",
+            TokenType::Keyword(Keyword::For) => "For",
+            TokenType::Keyword(Keyword::Return) => "Return",
+            TokenType::Keyword(Keyword::Goto) => "Goto",
+            TokenType::Keyword(Keyword::Class) => "Class",
+            TokenType::Keyword(Keyword::Public) => "Public",
+            TokenType::Keyword(Keyword::Private) => "Private",
+            TokenType::Keyword(Keyword::Protected) => "Protected",
+            TokenType::Keyword(Keyword::Typedef) => "Typedef",
+            TokenType::Keyword(Keyword::Using) => "Using",
+            TokenType::Keyword(Keyword::New) => "New",
+            TokenType::Keyword(Keyword::Delete) => "Delete",
+            TokenType::Keyword(Keyword::Include) => "Include",
+            TokenType::Keyword(Keyword::Directive) => "Directive",
+            TokenType::Keyword(Keyword::Define) => "Define",
+            TokenType::Operator(Operator::Plus) => "Plus",
+            TokenType::Operator(Operator::Minus) => "Minus",
+            TokenType::Operator(Operator::Multiply) => "Multiply",
+            TokenType::Operator(Operator::Divide) => "Divide",
+            TokenType::Operator(Operator::Modulo) => "Modulo",
+            TokenType::Operator(Operator::Assign) => "Assign",
+            TokenType::Operator(Operator::Equal) => "Equal",
+            TokenType::Operator(Operator::NotEqual) => "NotEqual",
+            TokenType::Operator(Operator::LessThan) => "LessThan",
+            TokenType::Operator(Operator::GreaterThan) => "GreaterThan",
+            TokenType::Operator(Operator::LessEqual) => "LessEqual",
+            TokenType::Operator(Operator::GreaterEqual) => "GreaterEqual",
+            TokenType::Operator(Operator::LogicalAnd) => "LogicalAnd",
+            TokenType::Operator(Operator::Ampersand) => "Ampersand",
+            TokenType::Operator(Operator::LogicalOr) => "LogicalOr",
+            TokenType::Operator(Operator::Increment) => "Increment",
+            TokenType::Operator(Operator::Decrement) => "Decrement",
+            TokenType::Operator(Operator::LogicalNot) => "LogicalNot",
+            TokenType::Delimiter(Delimiter::Semicolon) => "Semicolon",
+            TokenType::Delimiter(Delimiter::Comma) => "Comma",
+            TokenType::Delimiter(Delimiter::LeftParen) => "LeftParen",
+            TokenType::Delimiter(Delimiter::RightParen) => "RightParen",
+            TokenType::Delimiter(Delimiter::LeftBrace) => "LeftBrace",
+            TokenType::Delimiter(Delimiter::RightBrace) => "RightBrace",
+            TokenType::Delimiter(Delimiter::LeftBracket) => "LeftBracket",
+            TokenType::Delimiter(Delimiter::RightBracket) => "RightBracket",
+            TokenType::Delimiter(Delimiter::Colon) => "Colon",
+            TokenType::Delimiter(Delimiter::Ellipsis) => "Ellipsis",
+            TokenType::Literal(LiteralKind::Integer) => "IntegerLiteral",
+            TokenType::Literal(LiteralKind::Float) => "FloatLiteral",
+            TokenType::Literal(LiteralKind::Char) => "CharLiteral",
+            TokenType::Literal(LiteralKind::String) => "StringLiteral",
+            TokenType::Literal(LiteralKind::Bool) => "BoolLiteral",
+            TokenType::Identifier => "Identifier",
+            TokenType::Comment => "Comment",
+            TokenType::Attribute => "Attribute",
+            TokenType::Error => "Error",
+            TokenType::EOF => "EOF",
+        }
+    }
+    
+    pub fn from_name(name: &str) -> Option<TokenType> {
+        ALL_TOKEN_TYPES.iter().find(|t| t.name() == name).cloned()
+    }
+    
+    pub fn category(&self) -> &'static str {
+        match self {
+            TokenType::Keyword(_) => "keyword",
+            TokenType::Operator(_) => "operator",
+            TokenType::Delimiter(_) => "delimiter",
+            TokenType::Literal(_) => "literal",
+            TokenType::Identifier => "identifier",
+            TokenType::Comment => "comment",
+            TokenType::Attribute => "attribute",
+            TokenType::Error => "error",
+            TokenType::EOF => "eof",
+        }
+    }
+    
+    pub fn is_keyword(&self) -> bool {
+        matches!(self, TokenType::Keyword(_))
+    }
+    
+    pub fn is_operator(&self) -> bool {
+        matches!(self, TokenType::Operator(_))
+    }
+    
+    pub fn is_delimiter(&self) -> bool {
+        matches!(self, TokenType::Delimiter(_))
+    }
+    
+    pub fn is_literal(&self) -> bool {
+        matches!(self, TokenType::Literal(_))
+    }
+}
+
+const ALL_TOKEN_TYPES: &[TokenType] = &[
+    TokenType::Keyword(Keyword::Int),
+    TokenType::Keyword(Keyword::Float),
+    TokenType::Keyword(Keyword::Char),
+    TokenType::Keyword(Keyword::Bool),
+    TokenType::Keyword(Keyword::String),
+    TokenType::Keyword(Keyword::If),
+    TokenType::Keyword(Keyword::Else),
+    TokenType::Keyword(Keyword::While),
+    TokenType::Keyword(Keyword::For),
+    TokenType::Keyword(Keyword::Return),
+    TokenType::Keyword(Keyword::Include),
+    TokenType::Keyword(Keyword::Define),
+    TokenType::Operator(Operator::Plus),
+    TokenType::Operator(Operator::Minus),
+    TokenType::Operator(Operator::Multiply),
+    TokenType::Operator(Operator::Divide),
+    TokenType::Operator(Operator::Modulo),
+    TokenType::Operator(Operator::Assign),
+    TokenType::Operator(Operator::Equal),
+    TokenType::Operator(Operator::NotEqual),
+    TokenType::Operator(Operator::LessThan),
+    TokenType::Operator(Operator::GreaterThan),
+    TokenType::Operator(Operator::LessEqual),
+    TokenType::Operator(Operator::GreaterEqual),
+    TokenType::Operator(Operator::LogicalAnd),
+    TokenType::Operator(Operator::LogicalOr),
+    TokenType::Operator(Operator::Increment),
+    TokenType::Operator(Operator::Decrement),
+    TokenType::Delimiter(Delimiter::Semicolon),
+    TokenType::Delimiter(Delimiter::Comma),
+    TokenType::Delimiter(Delimiter::LeftParen),
+    TokenType::Delimiter(Delimiter::RightParen),
+    TokenType::Delimiter(Delimiter::LeftBrace),
+    TokenType::Delimiter(Delimiter::RightBrace),
+    TokenType::Delimiter(Delimiter::LeftBracket),
+    TokenType::Delimiter(Delimiter::RightBracket),
+    TokenType::Literal(LiteralKind::Integer),
+    TokenType::Literal(LiteralKind::Float),
+    TokenType::Literal(LiteralKind::Char),
+    TokenType::Literal(LiteralKind::String),
+    TokenType::Literal(LiteralKind::Bool),
+    TokenType::Identifier,
+    TokenType::Comment,
+    // Types added since are listed here, so existing Bison codes keep their
+    // numbers
+    TokenType::Operator(Operator::Ampersand),
+    TokenType::Error,
+    TokenType::Keyword(Keyword::Directive),
+    TokenType::Keyword(Keyword::Goto),
+    TokenType::Delimiter(Delimiter::Colon),
+    TokenType::Keyword(Keyword::Class),
+    TokenType::Keyword(Keyword::Public),
+    TokenType::Keyword(Keyword::Private),
+    TokenType::Keyword(Keyword::Protected),
+    TokenType::Keyword(Keyword::Typedef),
+    TokenType::Keyword(Keyword::Using),
+    TokenType::Keyword(Keyword::New),
+    TokenType::Keyword(Keyword::Delete),
+    TokenType::Delimiter(Delimiter::Ellipsis),
+    TokenType::Attribute,
+    TokenType::Keyword(Keyword::Auto),
+    TokenType::Operator(Operator::LogicalNot),
+    TokenType::Keyword(Keyword::Void),
+    TokenType::EOF,
+];
+
+// How every keyword is spelled: the one table `check_keyword`, the scanner
+// and the regex patterns all read. `and`, `or` and `not` are missing because
+// they spell operators, and only with `word_operators`.
+const KEYWORDS: &[(&str, TokenType)] = &[
+    ("#include", TokenType::Keyword(Keyword::Include)),
+    ("#define", TokenType::Keyword(Keyword::Define)),
+    ("int", TokenType::Keyword(Keyword::Int)),
+    ("float", TokenType::Keyword(Keyword::Float)),
+    ("char", TokenType::Keyword(Keyword::Char)),
+    ("bool", TokenType::Keyword(Keyword::Bool)),
+    ("string", TokenType::Keyword(Keyword::String)),
+    ("void", TokenType::Keyword(Keyword::Void)),
+    ("auto", TokenType::Keyword(Keyword::Auto)),
+    ("if", TokenType::Keyword(Keyword::If)),
+    ("else", TokenType::Keyword(Keyword::Else)),
+    ("while", TokenType::Keyword(Keyword::While)),
+    ("for", TokenType::Keyword(Keyword::For)),
+    ("return", TokenType::Keyword(Keyword::Return)),
+    ("goto", TokenType::Keyword(Keyword::Goto)),
+    ("class", TokenType::Keyword(Keyword::Class)),
+    ("public", TokenType::Keyword(Keyword::Public)),
+    ("private", TokenType::Keyword(Keyword::Private)),
+    ("protected", TokenType::Keyword(Keyword::Protected)),
+    ("typedef", TokenType::Keyword(Keyword::Typedef)),
+    ("using", TokenType::Keyword(Keyword::Using)),
+    ("new", TokenType::Keyword(Keyword::New)),
+    ("delete", TokenType::Keyword(Keyword::Delete)),
+];
+
+// Operators and delimiters with their lexemes, in the order the regex
+// patterns try them: a lexeme comes before any shorter one it starts with.
+const OPERATORS: &[(&str, TokenType)] = &[
+    ("&&", TokenType::Operator(Operator::LogicalAnd)),
+    ("||", TokenType::Operator(Operator::LogicalOr)),
+    ("==", TokenType::Operator(Operator::Equal)),
+    ("!=", TokenType::Operator(Operator::NotEqual)),
+    ("<=", TokenType::Operator(Operator::LessEqual)),
+    (">=", TokenType::Operator(Operator::GreaterEqual)),
+    ("++", TokenType::Operator(Operator::Increment)),
+    ("--", TokenType::Operator(Operator::Decrement)),
+    ("+", TokenType::Operator(Operator::Plus)),
+    ("-", TokenType::Operator(Operator::Minus)),
+    ("*", TokenType::Operator(Operator::Multiply)),
+    ("&", TokenType::Operator(Operator::Ampersand)),
+    ("/", TokenType::Operator(Operator::Divide)),
+    ("%", TokenType::Operator(Operator::Modulo)),
+    ("=", TokenType::Operator(Operator::Assign)),
+    ("<", TokenType::Operator(Operator::LessThan)),
+    (">", TokenType::Operator(Operator::GreaterThan)),
+    ("!", TokenType::Operator(Operator::LogicalNot)),
+];
+
+const DELIMITERS: &[(&str, TokenType)] = &[
+    (";", TokenType::Delimiter(Delimiter::Semicolon)),
+    (",", TokenType::Delimiter(Delimiter::Comma)),
+    ("(", TokenType::Delimiter(Delimiter::LeftParen)),
+    (")", TokenType::Delimiter(Delimiter::RightParen)),
+    ("{", TokenType::Delimiter(Delimiter::LeftBrace)),
+    ("}", TokenType::Delimiter(Delimiter::RightBrace)),
+    ("[", TokenType::Delimiter(Delimiter::LeftBracket)),
+    ("]", TokenType::Delimiter(Delimiter::RightBracket)),
+    (":", TokenType::Delimiter(Delimiter::Colon)),
+    ("...", TokenType::Delimiter(Delimiter::Ellipsis)),
+];
+
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for TokenType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = std::string::String::deserialize(deserializer)?;
+        TokenType::from_name(&name).ok_or_else(|| de::Error::custom(format!("unknown token type '{}'", name)))
+    }
+}
+
+impl From<Keyword> for TokenType {
+    fn from(keyword: Keyword) -> Self {
+        TokenType::Keyword(keyword)
+    }
+}
+
+impl From<Operator> for TokenType {
+    fn from(operator: Operator) -> Self {
+        TokenType::Operator(operator)
+    }
+}
+
+impl From<Delimiter> for TokenType {
+    fn from(delimiter: Delimiter) -> Self {
+        TokenType::Delimiter(delimiter)
+    }
+}
+
+impl From<LiteralKind> for TokenType {
+    fn from(kind: LiteralKind) -> Self {
+        TokenType::Literal(kind)
+    }
+}
+
+impl TryFrom<TokenType> for Keyword {
+    type Error = TokenType;
+    
+    fn try_from(token_type: TokenType) -> Result<Self, Self::Error> {
+        match token_type {
+            TokenType::Keyword(keyword) => Ok(keyword),
+            other => Err(other),
+        }
+    }
+}
+
+impl TryFrom<TokenType> for Operator {
+    type Error = TokenType;
+    
+    fn try_from(token_type: TokenType) -> Result<Self, Self::Error> {
+        match token_type {
+            TokenType::Operator(operator) => Ok(operator),
+            other => Err(other),
+        }
+    }
+}
+
+impl TryFrom<TokenType> for Delimiter {
+    type Error = TokenType;
+    
+    fn try_from(token_type: TokenType) -> Result<Self, Self::Error> {
+        match token_type {
+            TokenType::Delimiter(delimiter) => Ok(delimiter),
+            other => Err(other),
+        }
+    }
+}
+
+impl TryFrom<TokenType> for LiteralKind {
+    type Error = TokenType;
     
-    // Literals
+    fn try_from(token_type: TokenType) -> Result<Self, Self::Error> {
+        match token_type {
+            TokenType::Literal(kind) => Ok(kind),
+            other => Err(other),
+        }
+    }
+}
+
+// The pre-restructuring flat enum, kept for one release so downstream
+// matches can migrate gradually.
+#[deprecated(note = "use TokenType with the Keyword, Operator, Delimiter and LiteralKind sub-enums")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlatTokenType {
+    Int,
+    Float,
+    Char,
+    Bool,
+    String,
+    Void,
+    If,
+    Else,
+    While,
+    For,
+    Return,
+    Include,
+    Define,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    Assign,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    LessEqual,
+    GreaterEqual,
+    LogicalAnd,
+    LogicalOr,
+    Increment,
+    Decrement,
+    Semicolon,
+    Comma,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
     IntegerLiteral,
     FloatLiteral,
     CharLiteral,
     StringLiteral,
     BoolLiteral,
-    
-    // Identifiers
     Identifier,
-    
-    // Special
     Comment,
+    Ampersand,
+    Error,
+    Directive,
+    Goto,
+    Colon,
+    Class,
+    Public,
+    Private,
+    Protected,
+    Typedef,
+    Using,
+    New,
+    Delete,
+    Ellipsis,
+    Attribute,
+    Auto,
+    LogicalNot,
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(deprecated)]
+impl From<FlatTokenType> for TokenType {
+    fn from(flat: FlatTokenType) -> Self {
+        match flat {
+            FlatTokenType::Int => TokenType::Keyword(Keyword::Int),
+            FlatTokenType::Float => TokenType::Keyword(Keyword::Float),
+            FlatTokenType::Char => TokenType::Keyword(Keyword::Char),
+            FlatTokenType::Bool => TokenType::Keyword(Keyword::Bool),
+            FlatTokenType::String => TokenType::Keyword(Keyword::String),
+            FlatTokenType::Void => TokenType::Keyword(Keyword::Void),
+            FlatTokenType::Auto => TokenType::Keyword(Keyword::Auto),
+            FlatTokenType::If => TokenType::Keyword(Keyword::If),
+            FlatTokenType::Else => TokenType::Keyword(Keyword::Else),
+            FlatTokenType::While => TokenType::Keyword(Keyword::While),
+            FlatTokenType::For => TokenType::Keyword(Keyword::For),
+            FlatTokenType::Return => TokenType::Keyword(Keyword::Return),
+            FlatTokenType::Goto => TokenType::Keyword(Keyword::Goto),
+            FlatTokenType::Class => TokenType::Keyword(Keyword::Class),
+            FlatTokenType::Public => TokenType::Keyword(Keyword::Public),
+            FlatTokenType::Private => TokenType::Keyword(Keyword::Private),
+            FlatTokenType::Protected => TokenType::Keyword(Keyword::Protected),
+            FlatTokenType::Typedef => TokenType::Keyword(Keyword::Typedef),
+            FlatTokenType::Using => TokenType::Keyword(Keyword::Using),
+            FlatTokenType::New => TokenType::Keyword(Keyword::New),
+            FlatTokenType::Delete => TokenType::Keyword(Keyword::Delete),
+            FlatTokenType::Include => TokenType::Keyword(Keyword::Include),
+            FlatTokenType::Directive => TokenType::Keyword(Keyword::Directive),
+            FlatTokenType::Define => TokenType::Keyword(Keyword::Define),
+            FlatTokenType::Plus => TokenType::Operator(Operator::Plus),
+            FlatTokenType::Minus => TokenType::Operator(Operator::Minus),
+            FlatTokenType::Multiply => TokenType::Operator(Operator::Multiply),
+            FlatTokenType::Divide => TokenType::Operator(Operator::Divide),
+            FlatTokenType::Modulo => TokenType::Operator(Operator::Modulo),
+            FlatTokenType::Assign => TokenType::Operator(Operator::Assign),
+            FlatTokenType::Equal => TokenType::Operator(Operator::Equal),
+            FlatTokenType::NotEqual => TokenType::Operator(Operator::NotEqual),
+            FlatTokenType::LessThan => TokenType::Operator(Operator::LessThan),
+            FlatTokenType::GreaterThan => TokenType::Operator(Operator::GreaterThan),
+            FlatTokenType::LessEqual => TokenType::Operator(Operator::LessEqual),
+            FlatTokenType::GreaterEqual => TokenType::Operator(Operator::GreaterEqual),
+            FlatTokenType::LogicalAnd => TokenType::Operator(Operator::LogicalAnd),
+            FlatTokenType::Ampersand => TokenType::Operator(Operator::Ampersand),
+            FlatTokenType::LogicalOr => TokenType::Operator(Operator::LogicalOr),
+            FlatTokenType::Increment => TokenType::Operator(Operator::Increment),
+            FlatTokenType::Decrement => TokenType::Operator(Operator::Decrement),
+            FlatTokenType::LogicalNot => TokenType::Operator(Operator::LogicalNot),
+            FlatTokenType::Semicolon => TokenType::Delimiter(Delimiter::Semicolon),
+            FlatTokenType::Comma => TokenType::Delimiter(Delimiter::Comma),
+            FlatTokenType::LeftParen => TokenType::Delimiter(Delimiter::LeftParen),
+            FlatTokenType::RightParen => TokenType::Delimiter(Delimiter::RightParen),
+            FlatTokenType::LeftBrace => TokenType::Delimiter(Delimiter::LeftBrace),
+            FlatTokenType::RightBrace => TokenType::Delimiter(Delimiter::RightBrace),
+            FlatTokenType::LeftBracket => TokenType::Delimiter(Delimiter::LeftBracket),
+            FlatTokenType::RightBracket => TokenType::Delimiter(Delimiter::RightBracket),
+            FlatTokenType::Colon => TokenType::Delimiter(Delimiter::Colon),
+            FlatTokenType::Ellipsis => TokenType::Delimiter(Delimiter::Ellipsis),
+            FlatTokenType::IntegerLiteral => TokenType::Literal(LiteralKind::Integer),
+            FlatTokenType::FloatLiteral => TokenType::Literal(LiteralKind::Float),
+            FlatTokenType::CharLiteral => TokenType::Literal(LiteralKind::Char),
+            FlatTokenType::StringLiteral => TokenType::Literal(LiteralKind::String),
+            FlatTokenType::BoolLiteral => TokenType::Literal(LiteralKind::Bool),
+            FlatTokenType::Identifier => TokenType::Identifier,
+            FlatTokenType::Comment => TokenType::Comment,
+            FlatTokenType::Attribute => TokenType::Attribute,
+            FlatTokenType::Error => TokenType::Error,
+            FlatTokenType::EOF => TokenType::EOF,
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl From<TokenType> for FlatTokenType {
+    fn from(token_type: TokenType) -> Self {
+        match token_type {
+            TokenType::Keyword(Keyword::Int) => FlatTokenType::Int,
+            TokenType::Keyword(Keyword::Float) => FlatTokenType::Float,
+            TokenType::Keyword(Keyword::Char) => FlatTokenType::Char,
+            TokenType::Keyword(Keyword::Bool) => FlatTokenType::Bool,
+            TokenType::Keyword(Keyword::String) => FlatTokenType::String,
+            TokenType::Keyword(Keyword::Void) => FlatTokenType::Void,
+            TokenType::Keyword(Keyword::Auto) => FlatTokenType::Auto,
+            TokenType::Keyword(Keyword::If) => FlatTokenType::If,
+            TokenType::Keyword(Keyword::Else) => FlatTokenType::Else,
+            TokenType::Keyword(Keyword::While) => FlatTokenType::While,
+            TokenType::Keyword(Keyword::For) => FlatTokenType::For,
+            TokenType::Keyword(Keyword::Return) => FlatTokenType::Return,
+            TokenType::Keyword(Keyword::Goto) => FlatTokenType::Goto,
+            TokenType::Keyword(Keyword::Class) => FlatTokenType::Class,
+            TokenType::Keyword(Keyword::Public) => FlatTokenType::Public,
+            TokenType::Keyword(Keyword::Private) => FlatTokenType::Private,
+            TokenType::Keyword(Keyword::Protected) => FlatTokenType::Protected,
+            TokenType::Keyword(Keyword::Typedef) => FlatTokenType::Typedef,
+            TokenType::Keyword(Keyword::Using) => FlatTokenType::Using,
+            TokenType::Keyword(Keyword::New) => FlatTokenType::New,
+            TokenType::Keyword(Keyword::Delete) => FlatTokenType::Delete,
+            TokenType::Keyword(Keyword::Include) => FlatTokenType::Include,
+            TokenType::Keyword(Keyword::Directive) => FlatTokenType::Directive,
+            TokenType::Keyword(Keyword::Define) => FlatTokenType::Define,
+            TokenType::Operator(Operator::Plus) => FlatTokenType::Plus,
+            TokenType::Operator(Operator::Minus) => FlatTokenType::Minus,
+            TokenType::Operator(Operator::Multiply) => FlatTokenType::Multiply,
+            TokenType::Operator(Operator::Divide) => FlatTokenType::Divide,
+            TokenType::Operator(Operator::Modulo) => FlatTokenType::Modulo,
+            TokenType::Operator(Operator::Assign) => FlatTokenType::Assign,
+            TokenType::Operator(Operator::Equal) => FlatTokenType::Equal,
+            TokenType::Operator(Operator::NotEqual) => FlatTokenType::NotEqual,
+            TokenType::Operator(Operator::LessThan) => FlatTokenType::LessThan,
+            TokenType::Operator(Operator::GreaterThan) => FlatTokenType::GreaterThan,
+            TokenType::Operator(Operator::LessEqual) => FlatTokenType::LessEqual,
+            TokenType::Operator(Operator::GreaterEqual) => FlatTokenType::GreaterEqual,
+            TokenType::Operator(Operator::LogicalAnd) => FlatTokenType::LogicalAnd,
+            TokenType::Operator(Operator::Ampersand) => FlatTokenType::Ampersand,
+            TokenType::Operator(Operator::LogicalOr) => FlatTokenType::LogicalOr,
+            TokenType::Operator(Operator::Increment) => FlatTokenType::Increment,
+            TokenType::Operator(Operator::Decrement) => FlatTokenType::Decrement,
+            TokenType::Operator(Operator::LogicalNot) => FlatTokenType::LogicalNot,
+            TokenType::Delimiter(Delimiter::Semicolon) => FlatTokenType::Semicolon,
+            TokenType::Delimiter(Delimiter::Comma) => FlatTokenType::Comma,
+            TokenType::Delimiter(Delimiter::LeftParen) => FlatTokenType::LeftParen,
+            TokenType::Delimiter(Delimiter::RightParen) => FlatTokenType::RightParen,
+            TokenType::Delimiter(Delimiter::LeftBrace) => FlatTokenType::LeftBrace,
+            TokenType::Delimiter(Delimiter::RightBrace) => FlatTokenType::RightBrace,
+            TokenType::Delimiter(Delimiter::LeftBracket) => FlatTokenType::LeftBracket,
+            TokenType::Delimiter(Delimiter::RightBracket) => FlatTokenType::RightBracket,
+            TokenType::Delimiter(Delimiter::Colon) => FlatTokenType::Colon,
+            TokenType::Delimiter(Delimiter::Ellipsis) => FlatTokenType::Ellipsis,
+            TokenType::Literal(LiteralKind::Integer) => FlatTokenType::IntegerLiteral,
+            TokenType::Literal(LiteralKind::Float) => FlatTokenType::FloatLiteral,
+            TokenType::Literal(LiteralKind::Char) => FlatTokenType::CharLiteral,
+            TokenType::Literal(LiteralKind::String) => FlatTokenType::StringLiteral,
+            TokenType::Literal(LiteralKind::Bool) => FlatTokenType::BoolLiteral,
+            TokenType::Identifier => FlatTokenType::Identifier,
+            TokenType::Comment => FlatTokenType::Comment,
+            TokenType::Attribute => FlatTokenType::Attribute,
+            TokenType::Error => FlatTokenType::Error,
+            TokenType::EOF => FlatTokenType::EOF,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "TokenRecord")]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Lexeme,
     pub line: usize,
     pub column: usize,
+    // Position of the token's last character. It differs in line from the
+    // start only for block comments and strings containing a newline; EOF
+    // ends where it starts.
+    pub end_line: usize,
+    pub end_column: usize,
+    // Macro invocation a token was expanded from. Nothing sets it yet, as
+    // the lexer does not expand macros; a preprocessing pass that does
+    // should, so diagnostics can point at the use site.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expanded_from: Option<Span>,
+    // What a raw string literal, a string continued with backslash-newline
+    // or adjacent string literals merged into one token contains, without
+    // the quotes: a raw string's contents verbatim, otherwise with the
+    // backslash-newlines removed and the pieces joined, escapes as written.
+    // The lexeme keeps the source spelling, with merged pieces separated by
+    // spaces. A numeric literal `signs::fold_signs` gave a sign holds the
+    // signed number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    // Whether the token follows a directive name on its line, making it an
+    // argument to the preprocessor rather than program code.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub directive: bool,
+    // Where a `#line` directive says the token's line comes from. `line`
+    // and `column` stay its physical position.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<LineOrigin>,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, line: usize, column: usize) -> Self {
+    pub fn new(token_type: TokenType, lexeme: impl Into<Lexeme>, line: usize, column: usize) -> Self {
+        Self::new_with_unit(token_type, lexeme, line, column, ColumnUnit::Chars)
+    }
+    
+    // A token whose columns count `unit`s rather than characters.
+    pub fn new_with_unit(token_type: TokenType, lexeme: impl Into<Lexeme>, line: usize, column: usize, unit: ColumnUnit) -> Self {
+        let lexeme = lexeme.into();
+        let (mut end_line, mut end_column) = (line, column);
+        if token_type != TokenType::EOF {
+            let (mut next_line, mut next_column) = (line, column);
+            for ch in lexeme.chars() {
+                (end_line, end_column) = (next_line, next_column);
+                if ch == '\n' {
+                    next_line += 1;
+                    next_column = 1;
+                } else {
+                    next_column += unit.width(ch);
+                }
+            }
+        }
         Token {
             token_type,
             lexeme,
             line,
             column,
+            end_line,
+            end_column,
+            expanded_from: None,
+            value: None,
+            directive: false,
+            origin: None,
         }
     }
     
     pub fn to_compiler_format(&self) -> String {
-        format!("<{:?}, {}, {}, {}>", self.token_type, self.lexeme, self.line, self.column)
+        format!("<{}, {}, {}, {}>", self.token_type, self.lexeme, self.line, self.column)
+    }
+    
+    // Start line and column counted from `base`.
+    pub fn position(&self, base: PositionBase) -> (usize, usize) {
+        (PositionBase::One.rebase(self.line, base), PositionBase::One.rebase(self.column, base))
+    }
+    
+    pub fn position_zero_based(&self) -> (usize, usize) {
+        self.position(PositionBase::Zero)
+    }
+    
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        self.line = from.rebase(self.line, to);
+        self.column = from.rebase(self.column, to);
+        self.end_line = from.rebase(self.end_line, to);
+        self.end_column = from.rebase(self.end_column, to);
+        if let Some(span) = self.expanded_from.as_mut() {
+            span.line = from.rebase(span.line, to);
+            span.column = from.rebase(span.column, to);
+            span.end_line = from.rebase(span.end_line, to);
+            span.end_column = from.rebase(span.end_column, to);
+        }
+    }
+    
+    // Each character of a string or character literal's contents as the
+    // source spells it, with an escape sequence counting as one character:
+    // `\x` with the hex digits after it, as C reads it, and any other escape
+    // with the character after the backslash. A backslash-newline is no
+    // character at all, and a raw string's contents are taken verbatim.
+    // Empty for other tokens. A string merged by `--concat-strings` gives
+    // only its first piece, as the lexeme does not record where the others
+    // were.
+    pub fn value_chars(&self, unit: ColumnUnit) -> Vec<ValueChar<'_>> {
+        let TokenType::Literal(kind @ (LiteralKind::String | LiteralKind::Char)) = self.token_type else {
+            return Vec::new();
+        };
+        let quote = if kind == LiteralKind::Char { '\'' } else { '"' };
+        let raw = kind == LiteralKind::String && self.lexeme.starts_with('R');
+        let lexeme = self.lexeme.as_str();
+        let (start, end) = if raw {
+            let start = lexeme.find('(').map_or(lexeme.len(), |open| open + 1);
+            (start, start + scanner::raw_string_contents(lexeme).len())
+        } else {
+            (lexeme.find(quote).map_or(lexeme.len(), |open| open + 1), lexeme.len())
+        };
+        let (mut line, mut column) = (self.line, self.column + unit.measure(&lexeme[..start]));
+        let mut chars = Vec::new();
+        let mut rest = &lexeme[start..end];
+        while let Some(ch) = rest.chars().next() {
+            if !raw && ch == quote {
+                break;
+            }
+            let mut length = ch.len_utf8();
+            if !raw && ch == '\\' {
+                let after = &rest[1..];
+                if after.starts_with('\n') || after.starts_with("\r\n") {
+                    rest = &after[if after.starts_with('\n') { 1 } else { 2 }..];
+                    line += 1;
+                    column = 1;
+                    continue;
+                }
+                length += match after.chars().next() {
+                    Some('x') => 1 + after[1..].bytes().take_while(u8::is_ascii_hexdigit).count(),
+                    Some(escaped) => escaped.len_utf8(),
+                    None => 0,
+                };
+            }
+            let spelling = &rest[..length];
+            let width = unit.measure(spelling);
+            chars.push(ValueChar { line, column, width, spelling });
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += width;
+            }
+            rest = &rest[length..];
+        }
+        chars
+    }
+    
+    // Line and column where character `index` of a literal's contents
+    // starts in the source, counting as `value_chars` does; None past the
+    // end or for tokens that are not string or character literals.
+    pub fn column_of_value_index(&self, index: usize) -> Option<(usize, usize)> {
+        self.column_of_value_index_with_unit(index, ColumnUnit::Chars)
+    }
+    
+    // The same for a token whose columns count `unit`s.
+    pub fn column_of_value_index_with_unit(&self, index: usize, unit: ColumnUnit) -> Option<(usize, usize)> {
+        self.value_chars(unit).get(index).map(|ch| (ch.line, ch.column))
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Symbol {
-    pub name: String,
-    pub symbol_type: String,
-    pub data_type: String,   
-    pub scope: String,        
+// One character of a literal's contents: where its spelling starts, how
+// many columns the spelling takes, and the spelling itself, a backslash
+// and more for an escape sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueChar<'a> {
     pub line: usize,
+    pub column: usize,
+    pub width: usize,
+    pub spelling: &'a str,
 }
 
-#[derive(Debug, Clone)]
-pub struct SymbolTable {
-    symbols: Vec<Symbol>,
-    current_scope: String,
+// Token as read from JSON. Files written before end positions existed lack
+// them, so they are recomputed from the lexeme.
+#[derive(Deserialize)]
+struct TokenRecord {
+    token_type: TokenType,
+    lexeme: String,
+    line: usize,
+    column: usize,
+    end_line: Option<usize>,
+    end_column: Option<usize>,
+    #[serde(default)]
+    expanded_from: Option<Span>,
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    directive: bool,
+    #[serde(default)]
+    origin: Option<LineOrigin>,
 }
 
-impl SymbolTable {
-    pub fn new() -> Self {
-        SymbolTable {
-            symbols: Vec::new(),
-            current_scope: "global".to_string(),
+impl From<TokenRecord> for Token {
+    fn from(record: TokenRecord) -> Self {
+        let mut token = Token::new(record.token_type, record.lexeme, record.line, record.column);
+        if let (Some(end_line), Some(end_column)) = (record.end_line, record.end_column) {
+            token.end_line = end_line;
+            token.end_column = end_column;
         }
+        token.expanded_from = record.expanded_from;
+        token.value = record.value;
+        token.directive = record.directive;
+        token.origin = record.origin;
+        token
     }
-    
-    pub fn add_symbol(&mut self, name: String, symbol_type: String, data_type: String, line: usize) {
-        let scope = self.current_scope.clone();
-        let symbol = Symbol {
-            name,
-            symbol_type,
-            data_type,
-            scope,
+}
+
+// Every error the lexer can report: (code, name, description).
+pub const ERROR_CODES: &[(&str, &str, &str)] = &[
+    ("E0001", "invalid-character", "Character that does not start any MCPP token"),
+    ("E0002", "confusable-character", "Unicode look-alike of an ASCII character, such as a curly quote or an en dash"),
+    ("E0003", "unknown-escape", "Escape sequence MCPP does not define, when the strict profile makes it an error"),
+    ("E0004", "unterminated-string", "String literal with no closing quote, or, when error recovery is on, none on its line"),
+    ("E0005", "integer-overflow", "Integer literal too large for the integer width, when the strict profile makes it an error"),
+    ("E0006", "float-out-of-range", "Float literal that overflows to infinity or underflows to zero, when the strict profile makes it an error"),
+    ("E0007", "keyword-as-identifier", "Reserved keyword declared as a name, as in `int if = 3;`"),
+    ("E0008", "empty-char-literal", "Character literal with nothing between its quotes, as in `''`"),
+    ("E0009", "multi-character-literal", "Character literal holding more than one character, as in `'abc'`"),
+    ("E0010", "unterminated-char-literal", "Character literal with no closing quote on its line"),
+    ("E0011", "unterminated-raw-string", "Raw string literal `R\"delimiter(` with no matching `)delimiter\"` before the end of the file"),
+    ("E0012", "unterminated-attribute", "Attribute `[[` with no matching `]]` before the end of the file"),
+    ("E0013", "leading-underscore", "Identifier starting with an underscore, when `--leading-underscore error` forbids them"),
+    ("E0014", "token-too-long", "Identifier or literal longer than `--max-token-length` allows"),
+    ("E0015", "unterminated-comment", "Block comment `/*` with no matching `*/` before the end of the file"),
+    ("E0016", "non-ascii-character", "Character outside ASCII anywhere in the source, when `--ascii-only` forbids them"),
+];
+
+// Every warning the lexer can report: (code, name, description). Warnings
+// are collected as diagnostics and never stop lexing.
+pub const WARNING_CODES: &[(&str, &str, &str)] = &[
+    ("W0001", "homoglyph-identifier", "Identifier containing a letter that looks like a different ASCII letter"),
+    ("W0002", "unknown-escape", "Escape sequence MCPP does not define, such as \\q"),
+    ("W0003", "integer-overflow", "Integer literal too large for the integer width"),
+    ("W0004", "float-out-of-range", "Float literal that overflows to infinity or underflows to zero"),
+    ("W0005", "naming-convention", "Declaration whose name breaks the configured naming convention for its kind"),
+    ("W0006", "use-before-assignment", "Variable declared without an initializer whose first later use is not an assignment"),
+    ("W0007", "shadowed-declaration", "Declaration hiding a variable or parameter of the same name from an enclosing scope"),
+    ("W0008", "assignment-in-condition", "`=` directly inside the condition of an if, while or for, where `==` was likely meant"),
+    ("W0009", "undefined-label", "`goto` naming a label that the enclosing function never defines"),
+    ("W0010", "const-candidate", "Initialized variable never assigned, incremented or decremented afterwards, when `--lint const-candidates` asks"),
+    ("W0011", "missing-final-newline", "Source file whose last line is not ended by a newline"),
+    ("W0012", "trailing-whitespace", "Spaces or tabs at the end of a line"),
+    ("W0013", "leading-underscore", "Identifier starting with an underscore, when `--leading-underscore warn` reports them"),
+    ("W0014", "deep-nesting", "Brace, parenthesis or bracket nested deeper than `--max-nesting` allows"),
+    ("W0015", "non-ascii-strings", "String or character literal holding a character outside ASCII, when `--warn non-ascii-strings` asks"),
+    ("W0016", "empty-for-clause", "`for` header with an empty initializer, condition or increment, other than `for (;;)`"),
+    ("W0017", "unmatched-new", "Pointer assigned the result of `new` that no `delete` in the file names"),
+    ("W0018", "misspelled-keyword", "Undeclared identifier starting a statement that is one or two edits away from a keyword"),
+    ("W0019", "mixed-indentation", "Line indented with both tabs and spaces"),
+    ("W0020", "indent-style", "Line indented with tabs under `--indent-style spaces`, or with spaces under `--indent-style tabs`"),
+    ("W0021", "unevaluable-conditional", "`#if` or `#elif` condition using something other than integers, macros defined in the file with an integer value and `defined`"),
+    ("W0022", "unterminated-conditional", "`#if`, `#ifdef` or `#ifndef` with no matching `#endif` before the end of the file"),
+    ("W0023", "unmatched-conditional", "`#elif`, `#else` or `#endif` with no open `#if`, or `#elif` or `#else` after the `#else` of its chain"),
+    ("W0024", "invalid-line-directive", "`#line` without a line number from 1, optionally followed by a quoted file name and nothing else"),
+    ("W0025", "unbalanced-delimiter", "Brace, parenthesis or bracket never closed, closed by the closer of an enclosing one, or closing nothing"),
+    ("W0026", "complex-function", "Function whose cyclomatic complexity exceeds `--max-complexity`"),
+];
+
+// Every note the lexer can report: (code, name, description). Notes point
+// at code that is probably deliberate but worth a second look.
+pub const NOTE_CODES: &[(&str, &str, &str)] = &[
+    ("N0001", "endless-for", "`for (;;)`, which loops until a break, return or goto"),
+];
+
+// The code of the error `next_token` returns when a run is cancelled.
+pub const CANCELLED_CODE: &str = "cancelled";
+
+// Tokens scanned between looks at the cancel token.
+pub const CANCEL_CHECK_INTERVAL: usize = 256;
+
+// Whether the lexer shares one copy of each spelling of a token type: the
+// names and keywords that large programs repeat.
+fn interned(token_type: &TokenType) -> bool {
+    matches!(token_type, TokenType::Identifier | TokenType::Keyword(_))
+}
+
+// Characters that may follow a backslash in string and character literals.
+pub const VALID_ESCAPES: &str = "ntr0\\'\"abfv?";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LexError {
+    pub code: String,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+impl LexError {
+    pub fn new(code: &str, message: String, line: usize, column: usize, length: usize) -> Self {
+        LexError {
+            code: code.to_string(),
+            message,
             line,
-        };
-        self.symbols.push(symbol);
+            column,
+            length,
+        }
     }
     
-    #[allow(dead_code)]
-    pub fn set_scope(&mut self, scope: String) {
-        self.current_scope = scope;
+    // Lexing stopped because the cancel token was set, at the position it
+    // had reached. Not a diagnostic: the source is not at fault.
+    pub fn cancelled(line: usize, column: usize) -> Self {
+        LexError::new(CANCELLED_CODE, "Lexing cancelled".to_string(), line, column, 0)
     }
     
-    #[allow(dead_code)]
-    pub fn get_symbols(&self) -> &Vec<Symbol> {
-        &self.symbols
+    pub fn is_cancelled(&self) -> bool {
+        self.code == CANCELLED_CODE
     }
-    
-    pub fn print(&self) {
-        println!("\n=== SYMBOL TABLE ===");
-        println!("{:<15} {:<12} {:<12} {:<10} {:<8}", "Name", "Type", "Data Type", "Scope", "Line");
-        println!("{}", "-".repeat(70));
-        for symbol in &self.symbols {
-            println!("{:<15} {:<12} {:<12} {:<10} {:<8}", 
-                symbol.name, 
-                symbol.symbol_type, 
-                symbol.data_type, 
-                symbol.scope, 
-                symbol.line
-            );
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Lexical Error: {} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+// An error for a character outside ASCII under `ascii_only`, giving its
+// code point and the bytes that encode it.
+fn non_ascii_character(ch: char, line: usize, column: usize, length: usize) -> LexError {
+    let mut bytes = [0; 4];
+    let bytes: Vec<String> = ch.encode_utf8(&mut bytes).bytes().map(|b| format!("0x{:02X}", b)).collect();
+    let shown = if ch.is_control() || ch.is_whitespace() { String::new() } else { format!("'{}' ", ch) };
+    let mut message = format!("Non-ASCII character {}(U+{:04X}, bytes {}) in an ASCII-only source", shown, ch as u32, bytes.join(" "));
+    if ch == char::REPLACEMENT_CHARACTER {
+        message.push_str("; the file may not be valid UTF-8");
+    }
+    LexError::new("E0016", message, line, column, length)
+}
+
+// Names the codepoint of anything outside ASCII and, for look-alikes of
+// ASCII punctuation, suggests the character that was probably meant.
+fn invalid_character(ch: char, line: usize, column: usize) -> LexError {
+    if let Some((ascii, name)) = confusables::lookup(ch) {
+        return LexError::new(
+            "E0002",
+            format!("Invalid character '{}' (U+{:04X}, {}); did you mean '{}'?", ch, ch as u32, name, ascii),
+            line,
+            column,
+            1,
+        );
+    }
+    let message = if ch.is_ascii() {
+        format!("Invalid character '{}'", ch)
+    } else {
+        format!("Invalid character '{}' (U+{:04X})", ch, ch as u32)
+    };
+    LexError::new("E0001", message, line, column, 1)
+}
+
+// How far the line a string literal starts on runs into `text`, in bytes,
+// not counting a `\r` before the newline. A backslash-newline continues the
+// line.
+fn string_line_end(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if bytes[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'\\' => i += 2,
+            b'\n' if i > 0 && bytes[i - 1] == b'\r' => return i - 1,
+            b'\n' => return i,
+            _ => i += 1,
         }
-        println!("{}", "-".repeat(70));
-        println!("Total symbols: {}", self.symbols.len());
     }
+    text.len()
 }
 
-pub struct Lexer {
-    source: String,
-    position: usize,
-    line: usize,
-    column: usize,
-    tokens: Vec<Token>,
-    symbol_table: SymbolTable,
-    patterns: Vec<(TokenType, Regex)>,
-    last_type_keyword: Option<String>,
+// The contents of a string literal with each backslash-newline removed.
+fn splice_lines(contents: &str) -> String {
+    let mut spliced = String::with_capacity(contents.len());
+    let mut chars = contents.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            spliced.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('\n') => {}
+            Some('\r') if chars.as_str().starts_with('\n') => {
+                chars.next();
+            }
+            Some(escaped) => {
+                spliced.push('\\');
+                spliced.push(escaped);
+            }
+            None => spliced.push('\\'),
+        }
+    }
+    spliced
 }
 
-impl Lexer {
-    pub fn new(source: String) -> Self {
-        let mut lexer = Lexer {
-            source,
-            position: 0,
-            line: 1,
-            column: 1,
-            tokens: Vec::new(),
-            symbol_table: SymbolTable::new(),
-            patterns: Vec::new(),
-            last_type_keyword: None,
-        };
-        lexer.initialize_patterns();
-        lexer
+// What is wrong with a character literal the patterns rejected, and how
+// many bytes of `text` it covers: through its closing quote, or to the end
+// of the line when it has none. Escapes count as one character.
+fn malformed_char_literal(text: &str) -> (&'static str, String, usize) {
+    let line_end = text.find('\n').unwrap_or(text.len());
+    let line = text[..line_end].strip_suffix('\r').unwrap_or(&text[..line_end]);
+    let mut chars = line.char_indices().skip(1);
+    let mut count = 0;
+    while let Some((index, ch)) = chars.next() {
+        if ch == '\'' {
+            if count == 0 {
+                return ("E0008", "Empty character literal".to_string(), index + 1);
+            }
+            return ("E0009", format!("Multi-character literal {}", diagnostics::quote(&line[..index + 1])), index + 1);
+        }
+        if ch == '\\' {
+            chars.next();
+        }
+        count += 1;
     }
-    
+    ("E0010", "Unterminated character literal".to_string(), line.len())
+}
 
-    fn initialize_patterns(&mut self) {
-        self.patterns.push((
-            TokenType::Comment,
-            Regex::new(r"(?s)/\*.*?\*/").unwrap()
-        ));
-        
-        self.patterns.push((
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub symbol_type: String,
+    pub data_type: String,   
+    pub scope: String,        
+    pub line: usize,
+    // Declared with an initializer (`int x = 1;`) or as a parameter.
+    #[serde(default)]
+    pub initialized: bool,
+    // Text of the `///` or `/** */` comment right above the declaration.
+    #[serde(default)]
+    pub doc: Option<String>,
+    // "public", "private" or "protected" for a name declared directly in a
+    // class body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<String>,
+    // A function whose parameter list ends in `...`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub variadic: bool,
+    // For an `auto` declaration initialized with a single literal, the
+    // literal's type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inferred_type: Option<String>,
+    // In a conditional branch that is not compiled, such as `#if 0`; such a
+    // row declares nothing and is left out of the printed tables.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub excluded: bool,
+}
+
+// A call of `callee` at line and column; `caller` is None outside any
+// function body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallSite {
+    pub callee: String,
+    pub caller: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+// One brace-delimited scope. Function bodies are named after the function,
+// other blocks `block@LINE` after the line of their `{`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ScopeInfo {
+    name: String,
+    parent: Option<usize>,
+    open_line: Option<usize>,
+    close_line: Option<usize>,
+}
+
+// A scope with its symbols and nested scopes, as returned by
+// `SymbolTable::scope_tree`. The global scope has no braces, and a scope
+// still open at EOF has no closing line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScopeNode {
+    pub name: String,
+    pub open_line: Option<usize>,
+    pub close_line: Option<usize>,
+    pub symbols: Vec<Symbol>,
+    pub children: Vec<ScopeNode>,
+}
+
+impl ScopeNode {
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        self.open_line = self.open_line.map(|line| from.rebase(line, to));
+        self.close_line = self.close_line.map(|line| from.rebase(line, to));
+        self.symbols.iter_mut().for_each(|s| s.rebase(from, to));
+        self.children.iter_mut().for_each(|c| c.rebase(from, to));
+    }
+    
+    fn span(&self) -> String {
+        match (self.open_line, self.close_line) {
+            (Some(open), Some(close)) => format!(" (lines {}-{})", open, close),
+            (Some(open), None) => format!(" (line {}, not closed)", open),
+            _ => String::new(),
+        }
+    }
+    
+    // This scope and every one inside it with their depths, each before
+    // the scopes it contains and in the order they open.
+    fn flatten(&self, depth: usize) -> Vec<(usize, &ScopeNode)> {
+        let mut scopes = vec![(depth, self)];
+        for child in &self.children {
+            scopes.extend(child.flatten(depth + 1));
+        }
+        scopes
+    }
+    
+    fn print(&self, depth: usize) {
+        println!("{}{}{}", "  ".repeat(depth), self.name, self.span());
+        for symbol in &self.symbols {
+            println!("{}- {} {} (line {})", "  ".repeat(depth + 1), symbol.data_type, symbol.name, symbol.line);
+        }
+        for child in &self.children {
+            child.print(depth + 1);
+        }
+    }
+}
+
+// One scope's symbols by line, as `SymbolTable::symbols_by_scope` lists
+// them, with how deep it is below the global scope and the lines of its
+// braces as in `ScopeNode`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScopeSymbols {
+    pub scope: String,
+    pub depth: usize,
+    pub open_line: Option<usize>,
+    pub close_line: Option<usize>,
+    pub symbols: Vec<Symbol>,
+}
+
+// Every scope once, in the order they open, so scopes that share a name,
+// such as two blocks opened on one line, stay apart. Written as a JSON
+// array in that order.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SymbolsByScope(pub Vec<ScopeSymbols>);
+
+impl SymbolsByScope {
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        for scope in self.0.iter_mut() {
+            scope.open_line = scope.open_line.map(|line| from.rebase(line, to));
+            scope.close_line = scope.close_line.map(|line| from.rebase(line, to));
+            scope.symbols.iter_mut().for_each(|s| s.rebase(from, to));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    call_sites: Vec<CallSite>,
+    current_scope: String,
+    scopes: Vec<ScopeInfo>,
+    // Index into `scopes` of each symbol's scope.
+    symbol_scopes: Vec<usize>,
+    open_scopes: Vec<usize>,
+    // Rows of the symbols declared as function parameters, in order.
+    parameters: Vec<usize>,
+    // Rows of each name, in order, and the rows among them that declare it,
+    // so resolving a name looks at its declarations, not every use.
+    rows_by_name: HashMap<String, Vec<usize>>,
+    #[serde(default)]
+    declarations_by_name: HashMap<String, Vec<usize>>,
+    // The same declarations by the scope they are in, so resolving a name
+    // only looks in the scopes around the use.
+    #[serde(default)]
+    declarations_by_scope: HashMap<String, HashMap<usize, Vec<usize>>>,
+}
+
+impl Symbol {
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        self.line = from.rebase(self.line, to);
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable {
+            symbols: Vec::new(),
+            call_sites: Vec::new(),
+            current_scope: "global".to_string(),
+            scopes: vec![ScopeInfo {
+                name: "global".to_string(),
+                parent: None,
+                open_line: None,
+                close_line: None,
+            }],
+            symbol_scopes: Vec::new(),
+            open_scopes: vec![0],
+            parameters: Vec::new(),
+            rows_by_name: HashMap::new(),
+            declarations_by_name: HashMap::new(),
+            declarations_by_scope: HashMap::new(),
+        }
+    }
+    
+    pub fn add_symbol(&mut self, name: String, symbol_type: String, data_type: String, line: usize) {
+        let scope = self.current_scope.clone();
+        self.symbol_scopes.push(self.innermost_scope());
+        self.rows_by_name.entry(name.clone()).or_default().push(self.symbols.len());
+        let symbol = Symbol {
+            name,
+            symbol_type,
+            data_type,
+            scope,
+            line,
+            initialized: false,
+            doc: None,
+            access: None,
+            variadic: false,
+            inferred_type: None,
+            excluded: false,
+        };
+        self.symbols.push(symbol);
+        if self.is_declaration(self.symbols.len() - 1) {
+            self.index_declaration(self.symbols.len() - 1);
+        }
+    }
+    
+    // Records that `row` declares its name, as when `using` turns out to
+    // introduce an alias.
+    fn index_declaration(&mut self, row: usize) {
+        let name = &self.symbols[row].name;
+        let by_scope = self.declarations_by_scope.entry(name.clone()).or_default();
+        for rows in [self.declarations_by_name.entry(name.clone()).or_default(), by_scope.entry(self.symbol_scopes[row]).or_default()] {
+            if let Err(position) = rows.binary_search(&row) {
+                rows.insert(position, row);
+            }
+        }
+    }
+    
+    // Takes `row` out of the declarations, returning whether it was one.
+    fn unindex_declaration(&mut self, row: usize) -> bool {
+        let name = &self.symbols[row].name;
+        let Some(rows) = self.declarations_by_name.get_mut(name) else {
+            return false;
+        };
+        let Ok(position) = rows.binary_search(&row) else {
+            return false;
+        };
+        rows.remove(position);
+        if rows.is_empty() {
+            self.declarations_by_name.remove(name);
+        }
+        if let Some(by_scope) = self.declarations_by_scope.get_mut(name) {
+            if let Some(rows) = by_scope.get_mut(&self.symbol_scopes[row]) {
+                rows.retain(|r| *r != row);
+                if rows.is_empty() {
+                    by_scope.remove(&self.symbol_scopes[row]);
+                }
+            }
+            if by_scope.is_empty() {
+                self.declarations_by_scope.remove(name);
+            }
+        }
+        true
+    }
+    
+    // Moves `row` into `scope`, as when a parameter turns out to belong to
+    // the function body.
+    fn move_to_scope(&mut self, row: usize, scope: usize) {
+        let declared = self.unindex_declaration(row);
+        self.symbol_scopes[row] = scope;
+        self.symbols[row].scope = self.scopes[scope].name.clone();
+        if declared {
+            self.index_declaration(row);
+        }
+    }
+    
+    #[allow(dead_code)]
+    pub fn set_scope(&mut self, scope: String) {
+        self.current_scope = scope;
+    }
+    
+    #[allow(dead_code)]
+    pub fn get_symbols(&self) -> &Vec<Symbol> {
+        &self.symbols
+    }
+    
+    pub fn call_sites(&self) -> &[CallSite] {
+        &self.call_sites
+    }
+    
+    pub fn get_scope(&self) -> &str {
+        &self.current_scope
+    }
+    
+    // Marks the rows on lines `excludes` holds as not compiled, so no later
+    // name resolves to them.
+    fn exclude(&mut self, excludes: impl Fn(usize) -> bool) {
+        for row in 0..self.symbols.len() {
+            if !excludes(self.symbols[row].line) {
+                continue;
+            }
+            self.unindex_declaration(row);
+            self.symbols[row].excluded = true;
+        }
+    }
+    
+    // Rows in compiled code.
+    fn compiled_len(&self) -> usize {
+        self.symbols.iter().filter(|s| !s.excluded).count()
+    }
+    
+    // Drops every symbol added after the first `len`.
+    pub fn truncate(&mut self, len: usize) {
+        for symbol in self.symbols.drain(len.min(self.symbols.len())..) {
+            for index in [&mut self.rows_by_name, &mut self.declarations_by_name] {
+                if let Some(rows) = index.get_mut(&symbol.name) {
+                    rows.retain(|row| *row < len);
+                    if rows.is_empty() {
+                        index.remove(&symbol.name);
+                    }
+                }
+            }
+            if let Some(by_scope) = self.declarations_by_scope.get_mut(&symbol.name) {
+                by_scope.retain(|_, rows| {
+                    rows.retain(|row| *row < len);
+                    !rows.is_empty()
+                });
+                if by_scope.is_empty() {
+                    self.declarations_by_scope.remove(&symbol.name);
+                }
+            }
+        }
+        self.symbol_scopes.truncate(len);
+        self.parameters.retain(|row| *row < len);
+    }
+    
+    // The rows from `from` on, with their scope and whether they are
+    // indexed as declarations, for `restore_rows`.
+    fn rows_from(&self, from: usize) -> Vec<(Symbol, usize, bool)> {
+        (from..self.symbols.len())
+            .map(|row| {
+                let declared = self.declarations_by_name.get(&self.symbols[row].name).is_some_and(|rows| rows.contains(&row));
+                (self.symbols[row].clone(), self.symbol_scopes[row], declared)
+            })
+            .collect()
+    }
+    
+    // Replaces the rows from `from` on with ones saved by `rows_from`.
+    fn restore_rows(&mut self, from: usize, rows: Vec<(Symbol, usize, bool)>) {
+        self.truncate(from);
+        for (symbol, scope, declared) in rows {
+            let row = self.symbols.len();
+            self.rows_by_name.entry(symbol.name.clone()).or_default().push(row);
+            self.symbol_scopes.push(scope);
+            self.symbols.push(symbol);
+            if declared {
+                self.index_declaration(row);
+            }
+        }
+    }
+    
+    pub fn is_parameter(&self, row: usize) -> bool {
+        self.parameters.binary_search(&row).is_ok()
+    }
+    
+    fn mark_parameter(&mut self, row: usize) {
+        if let Err(position) = self.parameters.binary_search(&row) {
+            self.parameters.insert(position, row);
+        }
+    }
+    
+    // The first use after the declaration at `row` that refers to it,
+    // skipping other declarations of the name, the uses they hide and
+    // uses in scopes the declaration is not visible from.
+    fn first_use(&self, row: usize) -> Option<usize> {
+        let rows = self.rows_by_name.get(&self.symbols[row].name)?;
+        rows[rows.partition_point(|r| *r <= row)..]
+            .iter()
+            .copied()
+            .find(|r| self.declaration_row(*r) == Some(row))
+    }
+    
+    // Whether a row declares its name rather than using it.
+    pub fn is_declaration(&self, row: usize) -> bool {
+        let symbol = &self.symbols[row];
+        !symbol.excluded && (matches!(symbol.symbol_type.as_str(), "constant" | "class" | "type_alias") || symbol.data_type != "unknown")
+    }
+    
+    // Whether `name`, where the next symbol would be recorded, refers to a
+    // `typedef` or `using` alias.
+    pub fn is_type_alias(&self, name: &str) -> bool {
+        self.lookup_from(name, self.symbols.len(), Some(self.innermost_scope()))
+            .is_some_and(|row| self.symbols[row].symbol_type == "type_alias")
+    }
+    
+    // The declaration `name` refers to at symbol row `row`: the latest one
+    // before it in the row's scope or, failing that, in the nearest
+    // enclosing scope that has one.
+    pub fn lookup(&self, name: &str, row: usize) -> Option<&Symbol> {
+        self.lookup_from(name, row, Some(self.symbol_scopes[row])).map(|found| &self.symbols[found])
+    }
+    
+    // The row of the declaration the use at row `row` refers to, found as
+    // `lookup` finds it. None for a declaration, a label or a name never
+    // declared.
+    pub fn declaration_row(&self, row: usize) -> Option<usize> {
+        let symbol = &self.symbols[row];
+        if self.is_declaration(row) || symbol.symbol_type == "label" {
+            return None;
+        }
+        self.lookup_from(&symbol.name, row, Some(self.symbol_scopes[row]))
+    }
+    
+    // The label a `goto` at symbol row `row` jumps to. Labels belong to the
+    // whole function, so one defined after the `goto`, or in another block
+    // of the same function, counts.
+    pub fn lookup_label(&self, name: &str, row: usize) -> Option<&Symbol> {
+        let scope = self.symbol_scopes[row];
+        self.rows_by_name
+            .get(name)?
+            .iter()
+            .find(|r| self.symbols[**r].symbol_type == "label" && self.encloses(self.symbol_scopes[**r], scope))
+            .map(|r| &self.symbols[*r])
+    }
+    
+    fn lookup_from(&self, name: &str, row: usize, mut scope: Option<usize>) -> Option<usize> {
+        let by_scope = self.declarations_by_scope.get(name)?;
+        // The nearest scope wins, then the latest row within it
+        while let Some(index) = scope {
+            if let Some(rows) = by_scope.get(&index) {
+                let before = rows.partition_point(|r| *r < row);
+                if before > 0 {
+                    return Some(rows[before - 1]);
+                }
+            }
+            scope = self.scopes[index].parent;
+        }
+        None
+    }
+    
+    // Every declaration hiding an earlier one of the same name, as (row,
+    // hidden row) pairs in table order. The hidden declaration is in an
+    // enclosing scope, or is a parameter of the function whose body the
+    // declaration is in.
+    pub fn shadowing(&self) -> Vec<(usize, usize)> {
+        // Declarations of each name in scopes still open. Rows are in source
+        // order and a closed scope never reopens, so any other scope is done
+        // with for good.
+        let mut visible: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut found = Vec::new();
+        for row in (0..self.symbols.len()).filter(|r| self.is_declaration(*r)) {
+            let scope = self.symbol_scopes[row];
+            let declarations = visible.entry(self.symbols[row].name.as_str()).or_default();
+            declarations.retain(|r| self.encloses(self.symbol_scopes[*r], scope));
+            if !self.is_parameter(row) {
+                let parameter = declarations
+                    .iter()
+                    .rev()
+                    .find(|r| self.is_parameter(**r) && self.symbol_scopes[**r] == scope);
+                let outer = declarations.iter().rev().find(|r| self.symbol_scopes[**r] != scope);
+                if let Some(hidden) = parameter.or(outer) {
+                    found.push((row, *hidden));
+                }
+            }
+            declarations.push(row);
+        }
+        found
+    }
+    
+    // A declaration of `name` in the scope of some row of `other` or one
+    // enclosing it, which `other` renamed to `name` would clash with or be
+    // hidden by.
+    pub fn clashing_declaration(&self, name: &str, other: &str) -> Option<&Symbol> {
+        let rows = self.rows_by_name.get(other)?;
+        self.declarations_by_name
+            .get(name)?
+            .iter()
+            .find(|&&declaration| rows.iter().any(|&row| self.encloses(self.symbol_scopes[declaration], self.symbol_scopes[row])))
+            .map(|&declaration| &self.symbols[declaration])
+    }
+    
+    // Whether scope `outer` is `inner` or one of its ancestors.
+    fn encloses(&self, outer: usize, inner: usize) -> bool {
+        let mut scope = Some(inner);
+        while let Some(index) = scope {
+            if index == outer {
+                return true;
+            }
+            scope = self.scopes[index].parent;
+        }
+        false
+    }
+    
+    fn innermost_scope(&self) -> usize {
+        self.open_scopes.last().copied().unwrap_or(0)
+    }
+    
+    // Enters a scope opened by `{` on `line`; symbols added until the
+    // matching `close_scope` belong to it.
+    pub fn open_scope(&mut self, name: String, line: usize) {
+        self.scopes.push(ScopeInfo {
+            name: name.clone(),
+            parent: Some(self.innermost_scope()),
+            open_line: Some(line),
+            close_line: None,
+        });
+        self.open_scopes.push(self.scopes.len() - 1);
+        self.current_scope = name;
+    }
+    
+    // Leaves the innermost scope at the `}` on `line`. A stray `}` at global
+    // scope is ignored.
+    pub fn close_scope(&mut self, line: usize) {
+        if self.open_scopes.len() > 1 {
+            let index = self.open_scopes.pop().unwrap();
+            self.scopes[index].close_line = Some(line);
+            self.current_scope = self.scopes[self.innermost_scope()].name.clone();
+        }
+    }
+    
+    // The scopes as a tree rooted at the global scope, each holding the
+    // symbols recorded while it was innermost.
+    pub fn scope_tree(&self) -> ScopeNode {
+        let mut symbols = vec![Vec::new(); self.scopes.len()];
+        for (symbol, scope) in self.symbols.iter().zip(&self.symbol_scopes) {
+            symbols[*scope].push(symbol.clone());
+        }
+        let mut children = vec![Vec::new(); self.scopes.len()];
+        for (index, info) in self.scopes.iter().enumerate() {
+            if let Some(parent) = info.parent {
+                children[parent].push(index);
+            }
+        }
+        self.scope_node(0, &mut symbols, &children)
+    }
+    
+    fn scope_node(&self, index: usize, symbols: &mut [Vec<Symbol>], children: &[Vec<usize>]) -> ScopeNode {
+        let info = &self.scopes[index];
+        ScopeNode {
+            name: info.name.clone(),
+            open_line: info.open_line,
+            close_line: info.close_line,
+            symbols: std::mem::take(&mut symbols[index]),
+            children: children[index].iter().map(|child| self.scope_node(*child, symbols, children)).collect(),
+        }
+    }
+    
+    pub fn print_scope_tree(&self) {
+        println!("\n=== SCOPE TREE ===");
+        self.scope_tree().print(0);
+    }
+    
+    pub fn symbols_by_scope(&self) -> SymbolsByScope {
+        let tree = self.scope_tree();
+        let scopes = tree
+            .flatten(0)
+            .into_iter()
+            .map(|(depth, scope)| {
+                let mut symbols = scope.symbols.clone();
+                symbols.sort_by_key(|s| s.line);
+                ScopeSymbols {
+                    scope: scope.name.clone(),
+                    depth,
+                    open_line: scope.open_line,
+                    close_line: scope.close_line,
+                    symbols,
+                }
+            })
+            .collect();
+        SymbolsByScope(scopes)
+    }
+    
+    pub fn filtered<P: FnMut(&Symbol) -> bool>(&self, mut predicate: P) -> Vec<&Symbol> {
+        self.symbols.iter().filter(|symbol| predicate(symbol)).collect()
+    }
+    
+    // A declaration whose name never appears again. Every occurrence of an
+    // identifier is a row, so a used name has more than one.
+    pub fn is_unused(&self, symbol: &Symbol) -> bool {
+        let declared = matches!(symbol.symbol_type.as_str(), "constant" | "class" | "type_alias") || symbol.data_type != "unknown";
+        declared && self.symbols.iter().filter(|other| !other.excluded && other.name == symbol.name).count() == 1
+    }
+    
+    pub fn print(&self) {
+        self.print_with(&SymbolTablePrintOptions::default());
+    }
+    
+    // Columns are at least as wide as the classic layout and grow to fit
+    // the longest entry.
+    pub fn print_with(&self, options: &SymbolTablePrintOptions) {
+        if options.by_scope {
+            return self.print_by_scope(options);
+        }
+        let mut symbols = self.filtered(|symbol| options.shows(self, symbol));
+        if let Some(sort) = options.sort {
+            symbols.sort_by(|a, b| sort.compare(a, b));
+        }
+        
+        let rows: Vec<[String; 5]> = symbols.iter().map(|symbol| symbol_row(symbol)).collect();
+        let mut widths = [15, 12, 12, 10, 8];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let rule = "-".repeat(70.max(widths.iter().sum::<usize>() + widths.len()));
+        let format_row = |cells: [&str; 5]| {
+            cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        
+        println!("\n=== SYMBOL TABLE ===");
+        println!("{}", format_row(["Name", "Type", "Data Type", "Scope", "Line"]));
+        println!("{}", rule);
+        for row in &rows {
+            println!("{}", format_row([&row[0], &row[1], &row[2], &row[3], &row[4]]));
+        }
+        println!("{}", rule);
+        if rows.len() == self.compiled_len() {
+            println!("Total symbols: {}", rows.len());
+        } else {
+            println!("Total symbols: {} (of {})", rows.len(), self.compiled_len());
+        }
+    }
+    
+    // One table per scope, parents before the scopes inside them and each
+    // indented by its depth, with the symbols by line unless a sort is set.
+    // The Scope column is left out and the columns line up across tables.
+    fn print_by_scope(&self, options: &SymbolTablePrintOptions) {
+        let tree = self.scope_tree();
+        let scopes: Vec<(usize, &ScopeNode, Vec<[String; 5]>)> = tree
+            .flatten(0)
+            .into_iter()
+            .map(|(depth, scope)| {
+                let mut symbols: Vec<&Symbol> = scope.symbols.iter().filter(|symbol| options.shows(self, symbol)).collect();
+                match options.sort {
+                    Some(sort) => symbols.sort_by(|a, b| sort.compare(a, b)),
+                    None => symbols.sort_by_key(|s| s.line),
+                }
+                (depth, scope, symbols.iter().map(|symbol| symbol_row(symbol)).collect())
+            })
+            .collect();
+        let mut widths = [15, 12, 12, 8];
+        for row in scopes.iter().flat_map(|(_, _, rows)| rows) {
+            for (width, cell) in widths.iter_mut().zip(row.iter().take(3).chain([&row[4]])) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let rule = "-".repeat(widths.iter().sum::<usize>() + widths.len() - 1);
+        let format_row = |cells: [&str; 4]| {
+            cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        
+        println!("\n=== SYMBOLS BY SCOPE ===");
+        let mut shown = 0;
+        for (index, (depth, scope, rows)) in scopes.iter().enumerate() {
+            let indent = "  ".repeat(*depth);
+            if index > 0 {
+                println!();
+            }
+            let count = if rows.len() == 1 { "1 symbol".to_string() } else { format!("{} symbols", rows.len()) };
+            println!("{}{}{}: {}", indent, scope.name, scope.span(), count);
+            if rows.is_empty() {
+                continue;
+            }
+            println!("{}  {}", indent, format_row(["Name", "Type", "Data Type", "Line"]));
+            println!("{}  {}", indent, rule);
+            for row in rows {
+                println!("{}  {}", indent, format_row([&row[0], &row[1], &row[2], &row[4]]));
+            }
+            shown += rows.len();
+        }
+        println!();
+        if shown == self.compiled_len() {
+            println!("Total symbols: {}", shown);
+        } else {
+            println!("Total symbols: {} (of {})", shown, self.compiled_len());
+        }
+    }
+}
+
+// Name, type, data type, scope and line as the symbol tables print them.
+fn symbol_row(symbol: &Symbol) -> [String; 5] {
+    [
+        // A generated name can be too wide to pad
+        diagnostics::quote(&symbol.name),
+        symbol.symbol_type.clone(),
+        match &symbol.inferred_type {
+            Some(inferred) => format!("{} ({})", symbol.data_type, inferred),
+            None => symbol.data_type.clone(),
+        },
+        symbol.scope.clone(),
+        symbol.line.to_string(),
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolSort {
+    Name,
+    Line,
+    Scope,
+    Type,
+}
+
+pub const SYMBOL_SORT_NAMES: &[&str] = &["name", "line", "scope", "type"];
+
+impl SymbolSort {
+    pub fn from_name(name: &str) -> Option<SymbolSort> {
+        match name {
+            "name" => Some(SymbolSort::Name),
+            "line" => Some(SymbolSort::Line),
+            "scope" => Some(SymbolSort::Scope),
+            "type" => Some(SymbolSort::Type),
+            _ => None,
+        }
+    }
+    
+    // Ties keep table order.
+    pub fn compare(&self, a: &Symbol, b: &Symbol) -> std::cmp::Ordering {
+        match self {
+            SymbolSort::Name => a.name.cmp(&b.name),
+            SymbolSort::Line => a.line.cmp(&b.line),
+            SymbolSort::Scope => a.scope.cmp(&b.scope),
+            SymbolSort::Type => a.symbol_type.cmp(&b.symbol_type),
+        }
+    }
+}
+
+// How `SymbolTable::print_with` orders and narrows the table. Every filter
+// that is set must match for a symbol to be shown.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SymbolTablePrintOptions {
+    pub sort: Option<SymbolSort>,
+    pub symbol_type: Option<String>,
+    pub scope: Option<String>,
+    pub unused_only: bool,
+    // One table per scope instead of a single one
+    pub by_scope: bool,
+}
+
+impl SymbolTablePrintOptions {
+    // Adds a filter written as `type=function`, `scope=global` or `unused`.
+    pub fn add_filter(&mut self, filter: &str) -> Result<(), String> {
+        match filter.split_once('=') {
+            Some(("type", value)) => self.symbol_type = Some(value.to_string()),
+            Some(("scope", value)) => self.scope = Some(value.to_string()),
+            None if filter == "unused" => self.unused_only = true,
+            _ => return Err(format!("Unknown symbol filter '{}' (expected type=NAME, scope=NAME or unused)", filter)),
+        }
+        Ok(())
+    }
+    
+    pub fn shows(&self, table: &SymbolTable, symbol: &Symbol) -> bool {
+        !symbol.excluded
+            && self.symbol_type.as_ref().is_none_or(|t| *t == symbol.symbol_type)
+            && self.scope.as_ref().is_none_or(|s| *s == symbol.scope)
+            && (!self.unused_only || table.is_unused(symbol))
+    }
+}
+
+// Tokens printed when no `--head`, `--tail` or `--all` is given.
+pub const DEFAULT_TOKEN_PRINT_LIMIT: usize = 2000;
+
+// Which tokens `Lexer::print_token_stream_with` prints. `function` keeps
+// those of the named function, `lines` those starting in an inclusive range
+// of lines, then `head` or `tail` that many
+// from the start or end of what is left; with neither, only the first
+// `limit` are printed and the rest are counted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenStreamPrintOptions {
+    pub function: Option<String>,
+    pub lines: Option<(usize, usize)>,
+    pub head: Option<usize>,
+    pub tail: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+impl Default for TokenStreamPrintOptions {
+    fn default() -> Self {
+        TokenStreamPrintOptions {
+            function: None,
+            lines: None,
+            head: None,
+            tail: None,
+            limit: Some(DEFAULT_TOKEN_PRINT_LIMIT),
+        }
+    }
+}
+
+impl TokenStreamPrintOptions {
+    // Every token, as `Lexer::print_token_stream` prints them.
+    pub fn all() -> Self {
+        TokenStreamPrintOptions {
+            limit: None,
+            ..Self::default()
+        }
+    }
+    
+    // Sets `lines` from a range written as `10-40`.
+    pub fn set_lines(&mut self, range: &str) -> Result<(), String> {
+        let parsed = range
+            .split_once('-')
+            .and_then(|(start, end)| Some((start.trim().parse::<usize>().ok()?, end.trim().parse::<usize>().ok()?)));
+        match parsed {
+            Some((start, end)) if start >= 1 && start <= end => {
+                self.lines = Some((start, end));
+                Ok(())
+            }
+            _ => Err(format!("Invalid line range '{}' (expected FIRST-LAST, such as 10-40)", range)),
+        }
+    }
+}
+
+// `498000` as `498,000`.
+fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Builds the symbol table from a token stream, independently of the backend
+// that produced it. Every identifier is recorded as a variable, typed by the
+// most recent data-type keyword not yet claimed by another identifier, with
+// any `*` and `&` declarators in between (`int*`, `char**`, `int&`). The
+// name after `#define` is a constant, and a typed name followed by `(` is a
+// function. An untyped name followed by `(` is also a call site, attributed
+// to the function whose body encloses it. An untyped name starting a
+// statement in a function body and followed by `:` is a label, scoped to the
+// function rather than the block it is in. The name after `class` is a
+// class, its body a scope named after it, and each name declared directly
+// in the body a member with the access of the latest `public:`, `private:`
+// or `protected:`, private before any. The name declared by `typedef`, or
+// by `using NAME =`, is a type alias of the type it names, and a later
+// use of the alias types the next name as the alias does a keyword. A doc
+// comment (`///` lines or
+// `/** */`) is attached to the next declaration, unless a blank line or the
+// end of a statement comes first. Other comments are ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SymbolTableBuilder {
+    table: SymbolTable,
+    state: BuilderState,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+struct BuilderState {
+    last_type_keyword: Option<String>,
+    after_define: bool,
+    // Index of the symbol just declared with a type, until the next token
+    // after any array bounds shows whether it is a function or initialized.
+    last_declaration: Option<usize>,
+    array_bounds: usize,
+    // An untyped identifier, until the next token shows whether it is called.
+    last_use: Option<(String, usize, usize)>,
+    // Function declared but not yet known to be a prototype or a definition,
+    // and the symbols seen since, which are its parameters.
+    pending_body: Option<String>,
+    parameters: Vec<usize>,
+    // Function whose body is open, with the brace depth of that body.
+    current_function: Option<(String, usize)>,
+    depth: usize,
+    // Whether the last token ended a statement, a block or a label, and an
+    // untyped name right after one, until the next token shows whether it
+    // is a label.
+    statement_start: bool,
+    label_candidate: Option<usize>,
+    after_class: bool,
+    // Class named but whose body has not opened yet, and the class bodies
+    // open, innermost last, with their brace depth and current access.
+    pending_class: Option<String>,
+    classes: Vec<(usize, String)>,
+    paren_depth: usize,
+    // Inside `typedef ... ;`, and the name after `using`, until `=` shows
+    // it is an alias, and then the alias until the `;` ends its type.
+    in_typedef: bool,
+    after_using: bool,
+    using_name: Option<usize>,
+    using_alias: Option<usize>,
+    after_new: bool,
+    // An `auto` declaration after its `=`, with the type of the literal
+    // that followed, until the token after shows whether it was alone.
+    auto_initializer: Option<(usize, Option<String>)>,
+    // Doc comment text waiting for a declaration, and the line it ends on.
+    pending_doc: Option<(String, usize)>,
+    // The parenthesis depth a `for` header opened at and the rows seen in
+    // it, then, once it closes, those rows until the next token shows
+    // whether a block follows for them to belong to.
+    #[serde(default)]
+    after_for: bool,
+    #[serde(default)]
+    for_header: Option<(usize, Vec<usize>)>,
+    #[serde(default)]
+    loop_rows: Vec<usize>,
+}
+
+impl SymbolTableBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn observe(&mut self, token: &Token) {
+        // Nothing inside an attribute is declared or used
+        if token.token_type == TokenType::Attribute {
+            return;
+        }
+        let state = &mut self.state;
+        if token.token_type == TokenType::Comment {
+            state.pending_doc = match (doc_text(&token.lexeme), state.pending_doc.take()) {
+                // Stacked `///` lines make one comment
+                (Some(text), Some((mut doc, end))) if token.lexeme.starts_with("///") && end + 1 == token.line => {
+                    doc.push('\n');
+                    doc.push_str(&text);
+                    Some((doc, token.end_line))
+                }
+                (Some(text), _) => Some((text, token.end_line)),
+                (None, _) => None,
+            };
+            return;
+        }
+        if state.pending_doc.as_ref().is_some_and(|(_, end)| token.line > end + 1) {
+            state.pending_doc = None;
+        }
+        // Directive arguments are not program code; only the name after
+        // `#define` is declared
+        if token.directive && !(state.after_define && token.token_type == TokenType::Identifier) {
+            state.after_define = false;
+            return;
+        }
+        let open_paren = token.token_type == TokenType::Delimiter(Delimiter::LeftParen);
+        let loop_rows = std::mem::take(&mut state.loop_rows);
+        if let Some(index) = state.last_declaration.take() {
+            let symbol = &mut self.table.symbols[index];
+            match token.token_type {
+                // Array bounds (`int a[5] = ...`) come before the initializer
+                TokenType::Delimiter(Delimiter::LeftBracket) => {
+                    state.array_bounds += 1;
+                    state.last_declaration = Some(index);
+                }
+                TokenType::Delimiter(Delimiter::RightBracket) if state.array_bounds > 0 => {
+                    state.array_bounds -= 1;
+                    state.last_declaration = Some(index);
+                }
+                _ if state.array_bounds > 0 => state.last_declaration = Some(index),
+                TokenType::Delimiter(Delimiter::LeftParen) => {
+                    symbol.symbol_type = "function".to_string();
+                    state.pending_body = Some(symbol.name.clone());
+                }
+                TokenType::Operator(Operator::Assign) => {
+                    symbol.initialized = true;
+                    if symbol.data_type == "auto" {
+                        state.auto_initializer = Some((index, None));
+                    }
+                }
+                _ => {}
+            }
+        } else if let Some((index, literal)) = state.auto_initializer.take() {
+            // The type is the literal's only if nothing else follows it
+            match (literal, &token.token_type) {
+                (None, TokenType::Literal(kind)) => state.auto_initializer = Some((index, Some(literal_type_name(*kind).to_string()))),
+                (Some(data_type), TokenType::Delimiter(Delimiter::Semicolon | Delimiter::Comma | Delimiter::RightParen)) => {
+                    self.table.symbols[index].inferred_type = Some(data_type);
+                }
+                _ => {}
+            }
+        }
+        if let Some(row) = state.label_candidate.take() {
+            let function_scope = state.current_function.as_ref().and_then(|(_, depth)| self.table.open_scopes.get(*depth));
+            if let (TokenType::Delimiter(Delimiter::Colon), Some(&scope)) = (&token.token_type, function_scope) {
+                self.table.move_to_scope(row, scope);
+                self.table.symbols[row].symbol_type = "label".to_string();
+            }
+        }
+        if let Some(row) = state.using_name.take() {
+            if token.token_type == TokenType::Operator(Operator::Assign) {
+                self.table.symbols[row].symbol_type = "type_alias".to_string();
+                self.table.index_declaration(row);
+                state.using_alias = Some(row);
+            }
+        }
+        if let Some((callee, line, column)) = state.last_use.take() {
+            if open_paren {
+                self.table.call_sites.push(CallSite {
+                    callee,
+                    caller: state.current_function.as_ref().map(|(name, _)| name.clone()),
+                    line,
+                    column,
+                });
+            }
+        }
+        
+        match token.token_type {
+            TokenType::Delimiter(Delimiter::LeftBrace) => {
+                state.depth += 1;
+                let mut scope = format!("block@{}", token.line);
+                let mut parameters = Vec::new();
+                if let Some(name) = state.pending_class.take() {
+                    scope = name;
+                    state.classes.push((state.depth, "private".to_string()));
+                } else if let Some(name) = state.pending_body.take() {
+                    if state.current_function.is_none() {
+                        scope = name.clone();
+                        state.current_function = Some((name, state.depth));
+                        parameters = std::mem::take(&mut state.parameters);
+                    }
+                }
+                self.table.open_scope(scope, token.line);
+                // Parameters belong to the function body, not the scope
+                // around the declaration, and so does a loop header
+                let body = self.table.innermost_scope();
+                for index in parameters {
+                    self.table.move_to_scope(index, body);
+                    if self.table.is_declaration(index) {
+                        self.table.mark_parameter(index);
+                    }
+                }
+                for index in loop_rows {
+                    self.table.move_to_scope(index, body);
+                }
+            }
+            TokenType::Delimiter(Delimiter::RightBrace) => {
+                if state.current_function.as_ref().is_some_and(|(_, depth)| *depth == state.depth) {
+                    state.current_function = None;
+                }
+                if state.classes.last().is_some_and(|(depth, _)| *depth == state.depth) {
+                    state.classes.pop();
+                }
+                state.depth = state.depth.saturating_sub(1);
+                self.table.close_scope(token.line);
+            }
+            TokenType::Delimiter(Delimiter::Semicolon) => {
+                state.pending_body = None;
+                state.pending_class = None;
+                state.parameters.clear();
+                state.in_typedef = false;
+                if let Some(row) = state.using_alias.take() {
+                    self.table.symbols[row].data_type = state.last_type_keyword.take().unwrap_or_else(|| "unknown".to_string());
+                }
+            }
+            TokenType::Keyword(Keyword::Typedef) => state.in_typedef = true,
+            TokenType::Delimiter(Delimiter::Ellipsis) => {
+                if let Some(name) = &state.pending_body {
+                    let function = self.table.symbols.iter_mut().rev().find(|s| s.symbol_type == "function" && s.name == *name);
+                    if let Some(function) = function {
+                        function.variadic = true;
+                    }
+                }
+            }
+            TokenType::Delimiter(Delimiter::LeftParen) => {
+                if state.after_for && state.for_header.is_none() {
+                    state.for_header = Some((state.paren_depth, Vec::new()));
+                }
+                state.paren_depth += 1;
+            }
+            TokenType::Delimiter(Delimiter::RightParen) => {
+                state.paren_depth = state.paren_depth.saturating_sub(1);
+                if state.for_header.as_ref().is_some_and(|(depth, _)| *depth == state.paren_depth) {
+                    state.loop_rows = state.for_header.take().map(|(_, rows)| rows).unwrap_or_default();
+                }
+            }
+            TokenType::Keyword(keyword @ (Keyword::Public | Keyword::Private | Keyword::Protected)) => {
+                if let Some((depth, access)) = state.classes.last_mut() {
+                    if *depth == state.depth {
+                        *access = keyword_access(keyword).to_string();
+                    }
+                }
+            }
+            _ => {}
+        }
+        if matches!(
+            token.token_type,
+            TokenType::Delimiter(Delimiter::LeftBrace | Delimiter::RightBrace | Delimiter::Semicolon)
+        ) {
+            state.pending_doc = None;
+        }
+        
+        // The type after `new` is what is allocated, not a declaration
+        let after_new = std::mem::replace(&mut state.after_new, token.token_type == TokenType::Keyword(Keyword::New));
+        if let Some(data_type) = data_type_name(&token.token_type).filter(|_| !after_new) {
+            state.last_type_keyword = Some(data_type.to_string());
+        } else if let Some(data_type) = state.last_type_keyword.as_mut() {
+            // `*` and `&` between the type and the name are declarators;
+            // anything else means the type was not followed by a name
+            match token.token_type {
+                TokenType::Operator(Operator::Multiply) => data_type.push('*'),
+                TokenType::Operator(Operator::Ampersand) => data_type.push('&'),
+                TokenType::Operator(Operator::LogicalAnd) => data_type.push_str("&&"),
+                _ => {}
+            }
+        }
+        
+        let after_define = std::mem::replace(&mut state.after_define, token.token_type == TokenType::Keyword(Keyword::Define));
+        let after_class = std::mem::replace(&mut state.after_class, token.token_type == TokenType::Keyword(Keyword::Class));
+        let after_using = std::mem::replace(&mut state.after_using, token.token_type == TokenType::Keyword(Keyword::Using));
+        state.after_for = token.token_type == TokenType::Keyword(Keyword::For);
+        let statement_start = std::mem::replace(
+            &mut state.statement_start,
+            matches!(
+                token.token_type,
+                TokenType::Delimiter(Delimiter::LeftBrace | Delimiter::RightBrace | Delimiter::Semicolon | Delimiter::Colon)
+            ),
+        );
+        
+        if token.token_type == TokenType::Identifier {
+            let typed = state.last_type_keyword.is_some();
+            let data_type = state.last_type_keyword.take().unwrap_or_else(|| "unknown".to_string());
+            // Differently composed spellings of one name are one symbol
+            let name = if token.lexeme.is_ascii() {
+                token.lexeme.to_string()
+            } else {
+                nfc::compose(&token.lexeme)
+            };
+            let symbol_type = if after_define {
+                "constant"
+            } else if after_class {
+                "class"
+            } else if typed && state.in_typedef {
+                "type_alias"
+            } else {
+                "variable"
+            };
+            // An alias in use stands for a type keyword
+            let alias = !typed && !after_define && !after_class && !after_using && !after_new && self.table.is_type_alias(&name);
+            let access = state
+                .classes
+                .last()
+                .filter(|(depth, _)| *depth == state.depth && state.paren_depth == 0)
+                .map(|(_, access)| access.clone());
+            if after_class {
+                state.pending_class = Some(name.clone());
+            }
+            if after_using {
+                state.using_name = Some(self.table.symbols.len());
+            }
+            let parameter = state.pending_body.is_some();
+            if parameter {
+                state.parameters.push(self.table.symbols.len());
+            }
+            if let Some((_, rows)) = state.for_header.as_mut() {
+                rows.push(self.table.symbols.len());
+            }
+            if typed {
+                state.last_declaration = Some(self.table.symbols.len());
+            } else if !after_define && !after_class && !after_new && !alias {
+                state.last_use = Some((name.clone(), token.line, token.column));
+                if statement_start && state.current_function.is_some() {
+                    state.label_candidate = Some(self.table.symbols.len());
+                }
+            }
+            if alias {
+                state.last_type_keyword = Some(name.clone());
+            }
+            self.table.add_symbol(
+                name,
+                symbol_type.to_string(),
+                data_type,
+                token.line,
+            );
+            let symbol = self.table.symbols.last_mut().unwrap();
+            if parameter && typed {
+                symbol.initialized = true;
+            }
+            symbol.access = access;
+            if typed || after_define || after_class || after_using {
+                symbol.doc = state.pending_doc.take().map(|(doc, _)| doc);
+            }
+        }
+    }
+    
+    pub fn get_symbol_table(&self) -> &SymbolTable {
+        &self.table
+    }
+    
+    // How many rows, from the first, no later token can change. A row stays
+    // open while the tokens after it may still make it a function, a label
+    // or an alias, give it parameters, an initializer or an inferred type,
+    // mark it variadic or move it from a loop header into the loop's block.
+    pub fn settled_rows(&self) -> usize {
+        let state = &self.state;
+        let function = state.pending_body.as_ref().and_then(|name| {
+            self.table.symbols.iter().rposition(|s| s.symbol_type == "function" && s.name == *name)
+        });
+        [
+            state.last_declaration,
+            state.auto_initializer.as_ref().map(|(row, _)| *row),
+            state.label_candidate,
+            state.using_name,
+            state.using_alias,
+            state.parameters.first().copied(),
+            state.for_header.as_ref().and_then(|(_, rows)| rows.first().copied()),
+            state.loop_rows.first().copied(),
+            function,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(self.table.symbols.len())
+    }
+    
+    // The function whose body the last token was in, if any.
+    pub fn current_function(&self) -> Option<&str> {
+        self.state.current_function.as_ref().map(|(name, _)| name.as_str())
+    }
+    
+    pub fn into_symbol_table(self) -> SymbolTable {
+        self.table
+    }
+    
+    fn checkpoint(&self) -> SymbolCheckpoint {
+        // Later tokens can still change the rows that are not settled, so
+        // those are saved whole
+        let settled = self.settled_rows();
+        SymbolCheckpoint {
+            settled,
+            open_rows: self.table.rows_from(settled),
+            call_site_count: self.table.call_sites.len(),
+            scope: self.table.current_scope.clone(),
+            scopes: self.table.scopes.clone(),
+            open_scopes: self.table.open_scopes.clone(),
+            parameters: self.table.parameters.clone(),
+            state: self.state.clone(),
+        }
+    }
+    
+    fn rollback(&mut self, checkpoint: SymbolCheckpoint) {
+        self.table.restore_rows(checkpoint.settled, checkpoint.open_rows);
+        self.table.call_sites.truncate(checkpoint.call_site_count);
+        self.table.current_scope = checkpoint.scope;
+        self.table.scopes = checkpoint.scopes;
+        self.table.open_scopes = checkpoint.open_scopes;
+        self.table.parameters = checkpoint.parameters;
+        self.state = checkpoint.state;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SymbolCheckpoint {
+    settled: usize,
+    open_rows: Vec<(Symbol, usize, bool)>,
+    call_site_count: usize,
+    scope: String,
+    scopes: Vec<ScopeInfo>,
+    open_scopes: Vec<usize>,
+    parameters: Vec<usize>,
+    state: BuilderState,
+}
+
+// The text of a doc comment without its markers, or None for an ordinary
+// comment. `////` and `/**/` are not doc comments. Each line of a block is
+// trimmed along with its leading `*`, and blank lines around the text are
+// dropped.
+fn doc_text(comment: &str) -> Option<String> {
+    if let Some(text) = comment.strip_prefix("///") {
+        if text.starts_with('/') {
+            return None;
+        }
+        return Some(text.trim().to_string());
+    }
+    let body = comment.strip_prefix("/**")?.strip_suffix("*/")?;
+    if body.is_empty() || body.starts_with('*') {
+        return None;
+    }
+    let lines: Vec<&str> = body
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix('*').map_or(line, str::trim_start).trim_end()
+        })
+        .collect();
+    let first = lines.iter().position(|line| !line.is_empty())?;
+    let last = lines.iter().rposition(|line| !line.is_empty())?;
+    Some(lines[first..=last].join("\n"))
+}
+
+fn literal_type_name(kind: LiteralKind) -> &'static str {
+    match kind {
+        LiteralKind::Integer => "int",
+        LiteralKind::Float => "float",
+        LiteralKind::Char => "char",
+        LiteralKind::String => "string",
+        LiteralKind::Bool => "bool",
+    }
+}
+
+fn keyword_access(keyword: Keyword) -> &'static str {
+    match keyword {
+        Keyword::Public => "public",
+        Keyword::Protected => "protected",
+        _ => "private",
+    }
+}
+
+fn data_type_name(token_type: &TokenType) -> Option<&'static str> {
+    match token_type {
+        TokenType::Keyword(Keyword::Int) => Some("int"),
+        TokenType::Keyword(Keyword::Float) => Some("float"),
+        TokenType::Keyword(Keyword::Char) => Some("char"),
+        TokenType::Keyword(Keyword::Bool) => Some("bool"),
+        TokenType::Keyword(Keyword::String) => Some("string"),
+        TokenType::Keyword(Keyword::Void) => Some("void"),
+        TokenType::Keyword(Keyword::Auto) => Some("auto"),
+        _ => None,
+    }
+}
+
+// Anything that produces MCPP tokens one at a time, ending with EOF.
+pub trait TokenSource {
+    fn next_token(&mut self) -> Option<Result<Token, LexError>>;
+}
+
+// Lexes `source` with the default options and backend, returning every
+// token through EOF or the first error.
+pub fn lex(source: &str) -> Result<Vec<Token>, LexError> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.tokenize()?;
+    Ok(lexer.tokens)
+}
+
+// Drains a token source, returning its tokens and the symbol table built
+// from them, or the first error.
+pub fn build_symbol_table<S: TokenSource + ?Sized>(source: &mut S) -> Result<(Vec<Token>, SymbolTable), LexError> {
+    let mut tokens = Vec::new();
+    let mut builder = SymbolTableBuilder::new();
+    while let Some(result) = source.next_token() {
+        let token = result?;
+        builder.observe(&token);
+        tokens.push(token);
+    }
+    Ok((tokens, builder.into_symbol_table()))
+}
+
+// Engine used to find the next lexeme. All accept the same language; the
+// regex pattern table is the reference and the default. The logos backend
+// is only built with the `logos` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Regex,
+    Scanner,
+    #[cfg(feature = "logos")]
+    Logos,
+}
+
+#[cfg(not(feature = "logos"))]
+pub const BACKEND_NAMES: &[&str] = &["regex", "scanner"];
+#[cfg(feature = "logos")]
+pub const BACKEND_NAMES: &[&str] = &["regex", "scanner", "logos"];
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "regex" => Some(Backend::Regex),
+            "scanner" => Some(Backend::Scanner),
+            #[cfg(feature = "logos")]
+            "logos" => Some(Backend::Logos),
+            _ => None,
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Regex => "regex",
+            Backend::Scanner => "scanner",
+            #[cfg(feature = "logos")]
+            Backend::Logos => "logos",
+        }
+    }
+}
+
+// Compiled once per process and shared by every Lexer, so constructing or
+// resetting a lexer never recompiles the regexes.
+// Used instead of the ASCII identifier pattern when Unicode identifiers are on.
+static UNICODE_IDENTIFIER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[\p{XID_Start}_]\p{XID_Continue}*").unwrap());
+
+static SHARED_PATTERNS: LazyLock<Arc<PatternSet>> = LazyLock::new(|| Arc::new(PatternSet::new()));
+
+// One way a token type is matched, as `--emit-spec` documents it: the
+// regex source of a pattern table entry, or None for a token the hand-written
+// code finds, with what it accepts and a lexeme it matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternDoc {
+    pub token_type: TokenType,
+    pub regex: Option<String>,
+    pub description: String,
+    pub example: String,
+}
+
+#[derive(Debug)]
+pub struct PatternSet {
+    patterns: Vec<(TokenType, Regex)>,
+    // The same patterns anchored at the start, so a pattern that does not
+    // match costs the characters it looked at, not a search of the rest of
+    // the input.
+    anchored: Vec<Regex>,
+    // One entry per pattern, in table order, then the hand-written matches
+    docs: Vec<PatternDoc>,
+}
+
+impl PatternSet {
+    pub fn new() -> Self {
+        let mut set = PatternSet {
+            patterns: Vec::new(),
+            anchored: Vec::new(),
+            docs: Vec::new(),
+        };
+        set.initialize_patterns();
+        set.anchored = set
+            .patterns
+            .iter()
+            .map(|(_, pattern)| Regex::new(&format!("^(?:{})", pattern.as_str())).unwrap())
+            .collect();
+        set
+    }
+    
+    pub fn shared() -> Arc<PatternSet> {
+        Arc::clone(&SHARED_PATTERNS)
+    }
+    
+    pub fn patterns(&self) -> &[(TokenType, Regex)] {
+        &self.patterns
+    }
+    
+    pub fn docs(&self) -> &[PatternDoc] {
+        &self.docs
+    }
+    
+    fn add(&mut self, token_type: TokenType, pattern: &str, description: &str, example: &str) {
+        self.docs.push(PatternDoc {
+            token_type: token_type.clone(),
+            regex: Some(pattern.to_string()),
+            description: description.to_string(),
+            example: example.to_string(),
+        });
+        self.patterns.push((token_type, Regex::new(pattern).unwrap()));
+    }
+    
+    fn initialize_patterns(&mut self) {
+        self.add(
             TokenType::Comment,
-            Regex::new(r"//.*").unwrap()
+            r"(?s)/\*.*?\*/",
+            "Block comment, up to the first `*/`",
+            "/* note */",
+        );
+        self.add(TokenType::Comment, r"//.*", "Line comment, to the end of the line", "// note");
+        self.add(
+            TokenType::Literal(LiteralKind::String),
+            r#""([^"\\]|\\.|\\\n)*""#,
+            "Characters and escapes in double quotes; a backslash-newline continues it",
+            r#""hello\n""#,
+        );
+        self.add(
+            TokenType::Literal(LiteralKind::Char),
+            r"'([^'\\]|\\.)'",
+            "One character or escape in single quotes",
+            "'a'",
+        );
+        self.add(
+            TokenType::Literal(LiteralKind::Float),
+            r"\d+\.\d+([eE][+-]?\d+)?",
+            "Digits, a point and digits, then an optional exponent",
+            "2.5e3",
+        );
+        self.add(TokenType::Literal(LiteralKind::Integer), r"\d+", "Decimal digits", "42");
+        self.add(TokenType::Literal(LiteralKind::Bool), r"\b(true|false)\b", "`true` or `false` as a whole word", "true");
+        
+        for (lexeme, token_type) in OPERATORS.iter().chain(DELIMITERS) {
+            let description = format!("The {} `{}`", if lexeme.len() == 1 { "character" } else { "characters" }, lexeme);
+            self.add(token_type.clone(), &regex::escape(lexeme), &description, lexeme);
+        }
+        
+        // `#include` and `#define` before any other directive name
+        let (directives, words): (Vec<_>, Vec<_>) = KEYWORDS.iter().partition(|(lexeme, _)| lexeme.starts_with('#'));
+        for (lexeme, token_type) in directives {
+            let description = format!("`{}` at the start of a line", lexeme);
+            self.add(token_type.clone(), &format!(r"{}\b", lexeme), &description, lexeme);
+        }
+        self.add(
+            TokenType::Keyword(Keyword::Directive),
+            r"#[a-zA-Z_][a-zA-Z0-9_]*",
+            "Any other `#` and name at the start of a line",
+            "#pragma",
+        );
+        for (lexeme, token_type) in words {
+            let description = format!("`{}` as a whole word", lexeme);
+            self.add(token_type.clone(), &format!(r"\b{}\b", lexeme), &description, lexeme);
+        }
+        
+        self.add(
+            TokenType::Identifier,
+            r"[a-zA-Z_][a-zA-Z0-9_]*",
+            "A letter or `_`, then letters, digits and `_`; any Unicode identifier with `--unicode-identifiers`",
+            "count",
+        );
+        
+        // Found before the table is tried, by both backends
+        self.docs.push(PatternDoc {
+            token_type: TokenType::Literal(LiteralKind::String),
+            regex: None,
+            description: "Raw string: `R\"`, a delimiter of up to 16 characters and `(`, up to the first `)` with the same delimiter and `\"`".to_string(),
+            example: r#"R"x(a\b)x""#.to_string(),
+        });
+        self.docs.push(PatternDoc {
+            token_type: TokenType::Attribute,
+            regex: None,
+            description: "`[[` up to the `]]` that closes it, with any brackets and strings inside".to_string(),
+            example: "[[nodiscard]]".to_string(),
+        });
+    }
+}
+
+impl Default for PatternSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// One pattern-matching decision, reported to the hook installed with
+// `Lexer::set_trace`. `attempted` lists the patterns tried before the one
+// that matched, in table order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    pub position: usize,
+    pub line: usize,
+    pub column: usize,
+    pub attempted: Vec<TokenType>,
+    pub matched: Option<TokenType>,
+    pub token_type: Option<TokenType>,
+    pub lexeme: String,
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attempted: Vec<String> = self.attempted.iter().map(|t| t.to_string()).collect();
+        write!(f, "[trace] {}:{} (offset {})", self.line, self.column, self.position)?;
+        if !attempted.is_empty() {
+            write!(f, " no match: {};", attempted.join(", "))?;
+        }
+        match (&self.matched, &self.token_type) {
+            (Some(matched), Some(token_type)) if matched != token_type => {
+                write!(f, " matched {} {:?}, reclassified as {}", matched, self.lexeme, token_type)
+            }
+            (Some(matched), Some(_)) => write!(f, " matched {} {:?}", matched, self.lexeme),
+            (Some(matched), None) => write!(f, " matched {} {:?}, skipped", matched, self.lexeme),
+            (None, _) => write!(f, " no pattern matched {:?}", self.lexeme),
+        }
+    }
+}
+
+pub type TraceHook = Box<dyn FnMut(&TraceEvent)>;
+
+// Observers installed with `Lexer::on_token` and `Lexer::on_symbol`.
+pub type TokenHook = Box<dyn FnMut(&Token)>;
+pub type SymbolHook = Box<dyn FnMut(&Symbol)>;
+
+// Lists the look-alikes of ASCII letters in a non-ASCII identifier.
+fn homoglyph_warning(lexeme: &str, line: usize, column: usize, unit: ColumnUnit) -> Option<Diagnostic> {
+    let mut found: Vec<String> = Vec::new();
+    for ch in lexeme.chars() {
+        if let Some((ascii, name)) = confusables::homoglyph(ch) {
+            let description = format!("'{}' (U+{:04X}, {}) looks like '{}'", ch, ch as u32, name, ascii);
+            if !found.contains(&description) {
+                found.push(description);
+            }
+        }
+    }
+    if found.is_empty() {
+        return None;
+    }
+    Some(Diagnostic::warning(
+        "W0001",
+        format!("Identifier '{}' contains look-alikes of ASCII letters: {}", diagnostics::quote(lexeme), found.join(", ")),
+        Span::new(line, column, unit.measure(lexeme)),
+    ))
+}
+
+// Behavior switches. The defaults are MCPP's rules as documented.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct LexerOptions {
+    // Treat no-break spaces (U+00A0, U+2007, U+202F) as ordinary whitespace
+    // instead of reporting them.
+    pub lenient_whitespace: bool,
+    // Accept identifiers made of Unicode XID_Start/XID_Continue characters,
+    // warning about Cyrillic or Greek letters that look like ASCII ones.
+    pub unicode_identifiers: bool,
+    // Emit comments as Comment tokens instead of dropping them.
+    pub keep_comments: bool,
+    // Report unknown escape sequences such as `\q` as errors, not warnings.
+    pub strict_escapes: bool,
+    // After an error, skip the offending character and keep lexing instead
+    // of stopping.
+    pub error_recovery: bool,
+    // Report integer and float literals out of range as errors, not warnings.
+    pub strict_literals: bool,
+    // Width integer literals must fit in.
+    pub integer_width: IntegerWidth,
+    // Naming conventions declarations are checked against once lexing
+    // reaches EOF.
+    pub naming: Option<NamingRules>,
+    // What columns and diagnostic lengths count.
+    pub column_unit: ColumnUnit,
+    // Merge string literals separated only by whitespace and comments into
+    // one token, as C does with `"Hello, " "world"`.
+    pub concat_strings: bool,
+    // Lex `and`, `or` and `not` as `&&`, `||` and `!`, keeping the word as
+    // the lexeme.
+    pub word_operators: bool,
+    // Characters accepted in identifiers besides letters, digits and `_`,
+    // anywhere in the name, such as `$` for generated code's `tmp$1`.
+    pub identifier_chars: String,
+    // Whether identifiers may start with `_`.
+    pub leading_underscore: LeadingUnderscore,
+    // Deepest nesting of any one kind of bracket allowed before a warning.
+    pub max_nesting: Option<usize>,
+    // Warn about string and character literals holding characters outside
+    // ASCII, for code generators that only handle ASCII data.
+    pub warn_non_ascii_strings: bool,
+    // Longest identifier or literal accepted, in characters, for inputs
+    // where a huge one means something generated went wrong.
+    pub max_token_length: Option<usize>,
+    // Warn about initialized variables nothing assigns to afterwards, which
+    // could be declared const.
+    pub lint_const_candidates: bool,
+    // Indentation every line must use; lines mixing the two are reported
+    // either way.
+    pub indent_style: Option<IndentStyle>,
+    // Report every character outside ASCII as an error, in comments and
+    // literals too, for toolchains that only take ASCII sources.
+    pub ascii_only: bool,
+    // How many of those errors to report before lexing stops; by default
+    // the first stops it unless error recovery is on.
+    pub max_ascii_errors: Option<usize>,
+    // Highest cyclomatic complexity a function may have before a warning.
+    pub max_complexity: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LeadingUnderscore {
+    #[default]
+    Allow,
+    Warn,
+    Error,
+}
+
+pub const LEADING_UNDERSCORE_NAMES: &[&str] = &["allow", "warn", "error"];
+
+impl LeadingUnderscore {
+    pub fn from_name(name: &str) -> Option<LeadingUnderscore> {
+        match name {
+            "allow" => Some(LeadingUnderscore::Allow),
+            "warn" => Some(LeadingUnderscore::Warn),
+            "error" => Some(LeadingUnderscore::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces,
+}
+
+pub const INDENT_STYLE_NAMES: &[&str] = &["tabs", "spaces"];
+
+impl IndentStyle {
+    pub fn from_name(name: &str) -> Option<IndentStyle> {
+        match name {
+            "tabs" => Some(IndentStyle::Tabs),
+            "spaces" => Some(IndentStyle::Spaces),
+            _ => None,
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndentStyle::Tabs => "tabs",
+            IndentStyle::Spaces => "spaces",
+        }
+    }
+}
+
+// The identifier pattern widened by `identifier_chars`, or None when there
+// are none to add and the pattern table's own pattern applies.
+fn identifier_pattern(options: &LexerOptions) -> Option<Regex> {
+    if options.identifier_chars.is_empty() {
+        return None;
+    }
+    let extra: String = options.identifier_chars.chars().map(|ch| regex::escape(&ch.to_string())).collect();
+    let pattern = if options.unicode_identifiers {
+        format!(r"^[\p{{XID_Start}}_{0}][\p{{XID_Continue}}{0}]*", extra)
+    } else {
+        format!(r"^[a-zA-Z_{0}][a-zA-Z0-9_{0}]*", extra)
+    };
+    Some(Regex::new(&pattern).unwrap())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IntegerWidth {
+    I32,
+    #[default]
+    I64,
+}
+
+pub const INTEGER_WIDTH_NAMES: &[&str] = &["32", "64"];
+
+impl IntegerWidth {
+    pub fn from_name(name: &str) -> Option<IntegerWidth> {
+        match name {
+            "32" => Some(IntegerWidth::I32),
+            "64" => Some(IntegerWidth::I64),
+            _ => None,
+        }
+    }
+    
+    pub fn bits(&self) -> u32 {
+        match self {
+            IntegerWidth::I32 => 32,
+            IntegerWidth::I64 => 64,
+        }
+    }
+    
+    pub fn max(&self) -> i64 {
+        match self {
+            IntegerWidth::I32 => i64::from(i32::MAX),
+            IntegerWidth::I64 => i64::MAX,
+        }
+    }
+}
+
+impl LexerOptions {
+    pub fn for_profile(profile: LexerProfile) -> Self {
+        match profile {
+            LexerProfile::Strict => LexerOptions {
+                strict_escapes: true,
+                strict_literals: true,
+                ..LexerOptions::default()
+            },
+            LexerProfile::Standard => LexerOptions::default(),
+            LexerProfile::Permissive => LexerOptions {
+                lenient_whitespace: true,
+                unicode_identifiers: true,
+                error_recovery: true,
+                ..LexerOptions::default()
+            },
+        }
+    }
+}
+
+// Presets for LexerOptions, from failing on any oddity (for CI) to carrying
+// on through mistakes (for classroom demos). Individual fields can still be
+// changed after picking a profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LexerProfile {
+    Strict,
+    #[default]
+    Standard,
+    Permissive,
+}
+
+pub const PROFILE_NAMES: &[&str] = &["strict", "standard", "permissive"];
+
+impl LexerProfile {
+    pub fn from_name(name: &str) -> Option<LexerProfile> {
+        match name {
+            "strict" => Some(LexerProfile::Strict),
+            "standard" => Some(LexerProfile::Standard),
+            "permissive" => Some(LexerProfile::Permissive),
+            _ => None,
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            LexerProfile::Strict => "strict",
+            LexerProfile::Standard => "standard",
+            LexerProfile::Permissive => "permissive",
+        }
+    }
+}
+
+// The tokens that start on one source line; see `Lexer::tokens_by_line`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineTokens<'a> {
+    pub line: usize,
+    pub text: &'a str,
+    pub tokens: &'a [Token],
+    // Index of the first of `tokens` in `Lexer::get_tokens()`.
+    pub first_token: usize,
+}
+
+// Lexer state saved by `Lexer::checkpoint`. Checkpoints are plain values, so
+// they nest: rolling back to an outer one also discards every inner one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    position: usize,
+    line: usize,
+    column: usize,
+    token_count: usize,
+    diagnostic_count: usize,
+    finished: bool,
+    queued: VecDeque<Token>,
+    in_directive: bool,
+    lines: LineTracker,
+    symbols: SymbolCheckpoint,
+    line_markers: usize,
+    pending_line_directive: Option<usize>,
+    ascii_errors: usize,
+    pending_ascii_errors: VecDeque<LexError>,
+}
+
+// Lexer progress saved by `Lexer::save_state` for `Lexer::resume`, possibly
+// in another process: everything but the source text and trace hook. The
+// source is identified by its hash instead, so resuming on a different text
+// fails rather than producing nonsense.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LexerState {
+    source_hash: u64,
+    position: usize,
+    line: usize,
+    column: usize,
+    tokens: Vec<Token>,
+    diagnostics: Vec<Diagnostic>,
+    symbols: SymbolTableBuilder,
+    backend: Backend,
+    options: LexerOptions,
+    finished: bool,
+    queued: VecDeque<Token>,
+    in_directive: bool,
+    #[serde(default)]
+    lines: LineTracker,
+    #[serde(default)]
+    line_map: LineMap,
+    #[serde(default)]
+    pending_line_directive: Option<usize>,
+    #[serde(default)]
+    ascii_errors: usize,
+    #[serde(default)]
+    pending_ascii_errors: VecDeque<LexError>,
+}
+
+impl LexerState {
+    // Byte offset in the source where lexing resumes.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+}
+
+// Why `Lexer::resume` refused a saved state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResumeError {
+    // The source given is not the text the state was saved from
+    SourceMismatch { expected: u64, found: u64 },
+}
+
+impl fmt::Display for ResumeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResumeError::SourceMismatch { expected, found } => write!(
+                f,
+                "Source does not match the saved lexer state (hash {:016x}, expected {:016x})",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResumeError {}
+
+impl Checkpoint {
+    pub fn token_count(&self) -> usize {
+        self.token_count
+    }
+    
+    pub fn symbol_count(&self) -> usize {
+        self.symbols.settled + self.symbols.open_rows.len()
+    }
+}
+
+pub struct Lexer {
+    source: String,
+    position: usize,
+    line: usize,
+    column: usize,
+    tokens: Vec<Token>,
+    diagnostics: Vec<Diagnostic>,
+    symbols: SymbolTableBuilder,
+    patterns: Arc<PatternSet>,
+    backend: Backend,
+    options: LexerOptions,
+    // Built from `options.identifier_chars` by `set_options`.
+    identifier_pattern: Option<Regex>,
+    trace: Option<TraceHook>,
+    token_hook: Option<TokenHook>,
+    symbol_hook: Option<SymbolHook>,
+    // How many tokens and symbol rows have been passed to the hooks, or
+    // would have been had one been installed.
+    delivered_tokens: usize,
+    delivered_symbols: usize,
+    finished: bool,
+    // Kept comments found between merged string literals, returned after
+    // the merged token.
+    queued: VecDeque<Token>,
+    // Set by a directive name and cleared by the next newline.
+    in_directive: bool,
+    lines: LineTracker,
+    // Set from another thread to stop lexing; looked at every
+    // `CANCEL_CHECK_INTERVAL` tokens.
+    cancel_token: Option<Arc<AtomicBool>>,
+    until_cancel_check: usize,
+    // Whether the cancellation error has been returned, after which
+    // `next_token` returns None until the token is cleared.
+    cancel_reported: bool,
+    // The `#line` directives applied so far, and the index of one whose
+    // line is still being lexed
+    line_map: LineMap,
+    pending_line_directive: Option<usize>,
+    // Under `ascii_only`: how many non-ASCII characters have been
+    // reported, and the errors for them not yet returned
+    ascii_errors: usize,
+    pending_ascii_errors: VecDeque<LexError>,
+    // Shared identifier and keyword lexemes
+    interner: Interner,
+    // How many tokens and diagnostics have been given their origin
+    located_tokens: usize,
+    located_diagnostics: usize,
+}
+
+impl Lexer {
+    pub fn new(source: String) -> Self {
+        Self::with_patterns(source, PatternSet::shared())
+    }
+    
+    pub fn with_patterns(source: String, patterns: Arc<PatternSet>) -> Self {
+        Lexer {
+            source,
+            position: 0,
+            line: 1,
+            column: 1,
+            tokens: Vec::new(),
+            diagnostics: Vec::new(),
+            symbols: SymbolTableBuilder::new(),
+            patterns,
+            backend: Backend::Regex,
+            options: LexerOptions::default(),
+            identifier_pattern: None,
+            trace: None,
+            token_hook: None,
+            symbol_hook: None,
+            delivered_tokens: 0,
+            delivered_symbols: 0,
+            finished: false,
+            queued: VecDeque::new(),
+            in_directive: false,
+            lines: LineTracker::default(),
+            cancel_token: None,
+            until_cancel_check: 0,
+            cancel_reported: false,
+            line_map: LineMap::default(),
+            pending_line_directive: None,
+            ascii_errors: 0,
+            pending_ascii_errors: VecDeque::new(),
+            interner: Interner::new(),
+            located_tokens: 0,
+            located_diagnostics: 0,
+        }
+    }
+    
+    // Prepares the lexer for another input while keeping the compiled patterns.
+    pub fn reset(&mut self, new_source: String) {
+        self.source = new_source;
+        self.position = 0;
+        self.line = 1;
+        self.column = 1;
+        self.tokens.clear();
+        self.diagnostics.clear();
+        self.symbols = SymbolTableBuilder::new();
+        self.finished = false;
+        self.queued.clear();
+        self.in_directive = false;
+        self.lines = LineTracker::default();
+        self.until_cancel_check = 0;
+        self.cancel_reported = false;
+        self.line_map = LineMap::default();
+        self.pending_line_directive = None;
+        self.ascii_errors = 0;
+        self.pending_ascii_errors.clear();
+        self.interner.clear();
+        self.mark_delivered();
+    }
+    
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+    
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+    
+    pub fn set_options(&mut self, options: LexerOptions) {
+        self.identifier_pattern = identifier_pattern(&options);
+        self.options = options;
+    }
+    
+    pub fn options(&self) -> &LexerOptions {
+        &self.options
+    }
+    
+    // Makes lexing stop soon after `token` is set: `next_token` and the
+    // iterator return a `LexError::cancelled` error, then None, and
+    // `tokenize` returns that error. What was lexed so far stays, so the
+    // tokens can be inspected or the state saved, and clearing the token
+    // lets lexing carry on where it stopped.
+    pub fn set_cancel_token(&mut self, token: Arc<AtomicBool>) {
+        self.cancel_token = Some(token);
+        self.until_cancel_check = 0;
+        self.cancel_reported = false;
+    }
+    
+    fn is_cancelled(&mut self) -> bool {
+        let Some(token) = &self.cancel_token else {
+            return false;
+        };
+        if self.until_cancel_check > 0 && !self.cancel_reported {
+            self.until_cancel_check -= 1;
+            return false;
+        }
+        self.until_cancel_check = CANCEL_CHECK_INTERVAL;
+        let cancelled = token.load(Ordering::Relaxed);
+        if !cancelled {
+            self.cancel_reported = false;
+        }
+        cancelled
+    }
+    
+    // Saves the scan position, line/column and how many tokens and symbols
+    // exist, for speculative scanning or a backtracking parser.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            position: self.position,
+            line: self.line,
+            column: self.column,
+            token_count: self.tokens.len(),
+            diagnostic_count: self.diagnostics.len(),
+            finished: self.finished,
+            queued: self.queued.clone(),
+            in_directive: self.in_directive,
+            lines: self.lines,
+            symbols: self.symbols.checkpoint(),
+            line_markers: self.line_map.len(),
+            pending_line_directive: self.pending_line_directive,
+            ascii_errors: self.ascii_errors,
+            pending_ascii_errors: self.pending_ascii_errors.clone(),
+        }
+    }
+    
+    // Returns to a checkpoint taken on the current input, removing every
+    // token and symbol emitted since.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.position = checkpoint.position;
+        self.line = checkpoint.line;
+        self.column = checkpoint.column;
+        self.tokens.truncate(checkpoint.token_count);
+        self.diagnostics.truncate(checkpoint.diagnostic_count);
+        self.finished = checkpoint.finished;
+        self.queued = checkpoint.queued;
+        self.in_directive = checkpoint.in_directive;
+        self.lines = checkpoint.lines;
+        self.symbols.rollback(checkpoint.symbols);
+        self.line_map.truncate(checkpoint.line_markers);
+        self.pending_line_directive = checkpoint.pending_line_directive;
+        self.ascii_errors = checkpoint.ascii_errors;
+        self.pending_ascii_errors = checkpoint.pending_ascii_errors;
+        self.located_tokens = self.located_tokens.min(self.tokens.len());
+        self.located_diagnostics = self.located_diagnostics.min(self.diagnostics.len());
+        self.delivered_tokens = self.delivered_tokens.min(self.tokens.len());
+        self.delivered_symbols = self.delivered_symbols.min(self.symbols.get_symbol_table().get_symbols().len());
+    }
+    
+    pub fn save_state(&self) -> LexerState {
+        LexerState {
+            source_hash: content_hash(&[self.source.as_bytes()]),
+            position: self.position,
+            line: self.line,
+            column: self.column,
+            tokens: self.tokens.clone(),
+            diagnostics: self.diagnostics.clone(),
+            symbols: self.symbols.clone(),
+            backend: self.backend,
+            options: self.options.clone(),
+            finished: self.finished,
+            queued: self.queued.clone(),
+            in_directive: self.in_directive,
+            lines: self.lines,
+            line_map: self.line_map.clone(),
+            pending_line_directive: self.pending_line_directive,
+            ascii_errors: self.ascii_errors,
+            pending_ascii_errors: self.pending_ascii_errors.clone(),
+        }
+    }
+    
+    // A lexer that carries on from a saved state, given the same source
+    // text again; the tokens it goes on to produce are those of an
+    // uninterrupted run.
+    pub fn resume(source: String, state: LexerState) -> Result<Lexer, ResumeError> {
+        let hash = content_hash(&[source.as_bytes()]);
+        if hash != state.source_hash {
+            return Err(ResumeError::SourceMismatch {
+                expected: state.source_hash,
+                found: hash,
+            });
+        }
+        let mut lexer = Lexer::new(source);
+        lexer.position = state.position;
+        lexer.line = state.line;
+        lexer.column = state.column;
+        lexer.tokens = state.tokens;
+        lexer.diagnostics = state.diagnostics;
+        lexer.symbols = state.symbols;
+        lexer.backend = state.backend;
+        lexer.set_options(state.options);
+        lexer.finished = state.finished;
+        lexer.queued = state.queued;
+        lexer.in_directive = state.in_directive;
+        lexer.lines = state.lines;
+        lexer.line_map = state.line_map;
+        lexer.pending_line_directive = state.pending_line_directive;
+        lexer.ascii_errors = state.ascii_errors;
+        lexer.pending_ascii_errors = state.pending_ascii_errors;
+        lexer.intern_tokens();
+        lexer.mark_delivered();
+        Ok(lexer)
+    }
+    
+    // Installs a hook that receives one event per scanning decision. Nothing
+    // is collected while no hook is installed.
+    pub fn set_trace<F: FnMut(&TraceEvent) + 'static>(&mut self, hook: F) {
+        self.trace = Some(Box::new(hook));
+    }
+    
+    pub fn clear_trace(&mut self) {
+        self.trace = None;
+    }
+    
+    // Installs a hook that sees every token as `next_token` (and so
+    // `tokenize` and the iterator) records it, kept comments, `Error` tokens
+    // and EOF included, in source order. Tokens recorded before it was
+    // installed are not replayed. A panic in the hook unwinds through the
+    // call that ran it.
+    pub fn on_token<F: FnMut(&Token) + 'static>(&mut self, hook: F) {
+        self.token_hook = Some(Box::new(hook));
+    }
+    
+    // Installs a hook that sees every symbol table row, declarations and
+    // references alike, in table order. A row is passed on once no later
+    // token can change it, which for most rows is the call that lexed its
+    // name and at the latest the one returning EOF, so what the hook sees
+    // matches the final table. A row taken back by `rollback` that the hook
+    // has seen stays seen; lexing it again passes it on again.
+    pub fn on_symbol<F: FnMut(&Symbol) + 'static>(&mut self, hook: F) {
+        self.symbol_hook = Some(Box::new(hook));
+    }
+    
+    pub fn clear_hooks(&mut self) {
+        self.token_hook = None;
+        self.symbol_hook = None;
+    }
+    
+    // Counts everything recorded so far as passed to the hooks.
+    fn mark_delivered(&mut self) {
+        self.located_tokens = self.tokens.len();
+        self.located_diagnostics = self.diagnostics.len();
+        self.delivered_tokens = self.tokens.len();
+        self.delivered_symbols = self.symbols.get_symbol_table().get_symbols().len();
+    }
+    
+    // Passes the tokens and settled symbol rows recorded since the last call
+    // to the hooks.
+    fn deliver(&mut self) {
+        let tokens = &self.tokens[self.delivered_tokens..];
+        self.delivered_tokens = self.tokens.len();
+        if let Some(hook) = self.token_hook.as_mut() {
+            tokens.iter().for_each(hook);
+        }
+        let table = self.symbols.get_symbol_table();
+        let settled = if self.finished { table.get_symbols().len() } else { self.symbols.settled_rows() };
+        if settled > self.delivered_symbols {
+            let rows = &table.get_symbols()[self.delivered_symbols..settled];
+            self.delivered_symbols = settled;
+            if let Some(hook) = self.symbol_hook.as_mut() {
+                rows.iter().for_each(hook);
+            }
+        }
+    }
+    
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.source[self.position..].chars().next() {
+            if ch == '\n' {
+                self.track(ch);
+                self.line += 1;
+                self.column = 1;
+                self.in_directive = false;
+            } else if ch.is_whitespace() && (self.options.lenient_whitespace || !confusables::is_non_breaking_space(ch)) {
+                self.track(ch);
+                self.column += self.options.column_unit.width(ch);
+            } else {
+                break;
+            }
+            self.position += ch.len_utf8();
+        }
+    }
+    
+    fn check_keyword(&self, lexeme: &str) -> Option<TokenType> {
+        match lexeme {
+            "and" if self.options.word_operators => Some(TokenType::Operator(Operator::LogicalAnd)),
+            "or" if self.options.word_operators => Some(TokenType::Operator(Operator::LogicalOr)),
+            "not" if self.options.word_operators => Some(TokenType::Operator(Operator::LogicalNot)),
+            _ => TokenType::keyword(lexeme),
+        }
+    }
+    
+    fn advance(&mut self, lexeme: &str) {
+        for ch in lexeme.chars() {
+            self.track(ch);
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+                self.in_directive = false;
+            } else {
+                self.column += self.options.column_unit.width(ch);
+            }
+            self.position += ch.len_utf8();
+        }
+    }
+    
+    // Called with every character consumed, before the column moves past it,
+    // so line lengths and trailing whitespace come out of the one scan.
+    fn track(&mut self, ch: char) {
+        let width = self.options.column_unit.width(ch);
+        if self.options.ascii_only && !ch.is_ascii() && self.ascii_errors < self.ascii_error_limit() {
+            let error = non_ascii_character(ch, self.line, self.column, width);
+            self.diagnostics.push(Diagnostic::from(&error));
+            self.ascii_errors += 1;
+            self.pending_ascii_errors.push_back(error);
+        }
+        match self.lines.track(ch, self.column, width) {
+            Some(LineEvent::TrailingWhitespace(column, width)) => self.trailing_whitespace((column, width)),
+            Some(LineEvent::Indentation(indentation)) => self.check_indentation(indentation),
+            None => {}
+        }
+    }
+    
+    fn ascii_error_limit(&self) -> usize {
+        match self.options.max_ascii_errors {
+            Some(limit) => limit,
+            None if self.options.error_recovery => usize::MAX,
+            None => 1,
+        }
+    }
+    
+    // The next error `track` found for a non-ASCII character, returned once
+    // the token holding it is in the stream. Returning the last one allowed
+    // stops lexing.
+    fn take_ascii_error(&mut self) -> Option<LexError> {
+        let error = self.pending_ascii_errors.pop_front()?;
+        if self.pending_ascii_errors.is_empty() && self.ascii_errors >= self.ascii_error_limit() {
+            self.finished = true;
+        }
+        Some(error)
+    }
+    
+    // A style violation starts no later than the switch between tabs and
+    // spaces, so it is reported first.
+    fn check_indentation(&mut self, indentation: Indentation) {
+        if let Some(style) = self.options.indent_style {
+            if let Some(column) = indentation.violation(style) {
+                let found = if style == IndentStyle::Spaces { "a tab" } else { "a space" };
+                self.diagnostics.push(Diagnostic::warning(
+                    "W0020",
+                    format!("Indentation uses {}; the indent style is {}", found, style.name()),
+                    Span::new(self.line, column, indentation.width_from(column)),
+                ));
+            }
+        }
+        if let Some(column) = indentation.mixed_at {
+            self.diagnostics.push(Diagnostic::warning(
+                "W0019",
+                "Indentation mixes tabs and spaces".to_string(),
+                Span::new(self.line, column, indentation.width_from(column)),
+            ));
+        }
+    }
+    
+    fn trailing_whitespace(&mut self, (column, width): (usize, usize)) {
+        self.diagnostics.push(Diagnostic::warning(
+            "W0012",
+            "Trailing whitespace at end of line".to_string(),
+            Span::new(self.line, column, width),
+        ));
+    }
+    
+    // Closes the last line at EOF, reporting it if no newline ends it.
+    fn end_file(&mut self) {
+        if self.lines.width == 0 {
+            return;
+        }
+        if let Some(run) = self.lines.end_line() {
+            self.trailing_whitespace(run);
+        }
+        self.diagnostics.push(Diagnostic::warning(
+            "W0011",
+            "File does not end with a newline".to_string(),
+            Span::new(self.line, self.column, 0),
+        ));
+    }
+    
+    pub fn file_summary(&self) -> FileSummary {
+        self.lines.summary
+    }
+    
+    // Adopts a token stream produced earlier for the current input, e.g. from
+    // the cache, as if it had just been lexed. The symbol table is rebuilt
+    // from the tokens.
+    pub fn load_tokens(&mut self, tokens: Vec<Token>) {
+        self.symbols = SymbolTableBuilder::new();
+        for token in &tokens {
+            self.symbols.observe(token);
+        }
+        let excluded: Vec<_> = conditional::conditionals(&tokens).branches.into_iter().filter(|b| !b.included).collect();
+        self.symbols.table.exclude(|line| excluded.iter().any(|b| b.excludes(line)));
+        if let Some(last) = tokens.last() {
+            self.line = last.line;
+            self.column = last.column;
+        }
+        // The tokens keep no whitespace, so the summary comes from the text;
+        // a cached stream had no hygiene warnings to restore
+        self.lines = LineTracker::default();
+        let mut column = 1;
+        for ch in self.source.chars() {
+            let width = self.options.column_unit.width(ch);
+            self.lines.track(ch, column, width);
+            column = if ch == '\n' { 1 } else { column + width };
+        }
+        if self.lines.width > 0 {
+            self.lines.end_line();
+        }
+        self.position = self.source.len();
+        self.tokens = tokens;
+        self.intern_tokens();
+        self.finished = true;
+        self.queued.clear();
+        self.mark_delivered();
+    }
+    
+    // Makes the identifiers and keywords of tokens from elsewhere, such as
+    // the cache or a saved state, share the lexer's copies of their text.
+    fn intern_tokens(&mut self) {
+        for token in self.tokens.iter_mut().filter(|t| interned(&t.token_type)) {
+            token.lexeme = self.interner.intern(&token.lexeme);
+        }
+    }
+    
+    // How much sharing identifier and keyword lexemes saves over the
+    // tokens lexed so far.
+    pub fn intern_stats(&self) -> InternStats {
+        InternStats::of(self.tokens.iter().filter(|t| interned(&t.token_type)).map(|t| &t.lexeme))
+    }
+    
+    // Lexes to the end, returning the first error. With error recovery on,
+    // lexing continues past errors and every one is in `get_diagnostics()`.
+    pub fn tokenize(&mut self) -> Result<(), LexError> {
+        let mut first_error = None;
+        while let Some(result) = self.next_token() {
+            match result {
+                Err(e) if e.is_cancelled() => return Err(e),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+                Ok(_) => {}
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+    
+    // `tokenize`, also measuring how long it took.
+    pub fn tokenize_timed(&mut self) -> (Result<(), LexError>, LexStats) {
+        let (start_tokens, start_position) = (self.tokens.len(), self.position);
+        let started = Instant::now();
+        let result = self.tokenize();
+        let stats = LexStats {
+            tokens: self.tokens.len() - start_tokens,
+            bytes: self.position - start_position,
+            elapsed: started.elapsed(),
+        };
+        (result, stats)
+    }
+    
+    // The index of the token behind each symbol row: every identifier token
+    // outside a directive's arguments, and the name a `#define` declares,
+    // has exactly one row, in order. None if the two do not line up.
+    fn symbol_tokens(&self) -> Option<Vec<usize>> {
+        let mut previous = None;
+        let mut identifiers = Vec::new();
+        for (index, token) in self.tokens.iter().enumerate().filter(|(_, t)| t.token_type != TokenType::Comment) {
+            let defined = previous.is_some_and(|p: &Token| p.token_type == TokenType::Keyword(Keyword::Define));
+            if token.token_type == TokenType::Identifier && (!token.directive || defined) {
+                identifiers.push(index);
+            }
+            previous = Some(token);
+        }
+        (identifiers.len() == self.symbols.get_symbol_table().get_symbols().len()).then_some(identifiers)
+    }
+    
+    // A token-level heuristic: a variable declared without an initializer is
+    // reported if the first use referring to it is anything but the left
+    // side of `=`. Control flow is not followed, so assigning in only one
+    // branch still counts as assigned.
+    fn check_initialization(&mut self) {
+        let Some(identifiers) = self.symbol_tokens() else {
+            return;
+        };
+        let table = self.symbols.get_symbol_table();
+        let symbols = table.get_symbols();
+        let mut warnings = Vec::new();
+        for (row, symbol) in symbols.iter().enumerate() {
+            if symbol.symbol_type != "variable" || symbol.data_type == "unknown" || symbol.initialized {
+                continue;
+            }
+            let Some(use_row) = table.first_use(row) else {
+                continue;
+            };
+            let use_token = &self.tokens[identifiers[use_row]];
+            let assigned = self.tokens[identifiers[use_row] + 1..]
+                .iter()
+                .find(|t| t.token_type != TokenType::Comment)
+                .is_some_and(|t| t.token_type == TokenType::Operator(Operator::Assign));
+            if !assigned {
+                warnings.push(Diagnostic::warning(
+                    "W0006",
+                    format!(
+                        "variable '{}' may be used before being assigned (declared line {}, used line {})",
+                        diagnostics::quote(&symbol.name),
+                        symbol.line,
+                        use_token.line
+                    ),
+                    Span::new(use_token.line, use_token.column, self.options.column_unit.measure(&use_token.lexeme)),
+                ));
+            }
+        }
+        self.diagnostics.extend(warnings);
+    }
+    
+    fn check_shadowing(&mut self) {
+        let identifiers = self.symbol_tokens();
+        let table = self.symbols.get_symbol_table();
+        let mut warnings = Vec::new();
+        for (row, outer) in table.shadowing() {
+            let symbol = &table.get_symbols()[row];
+            if symbol.symbol_type != "variable" {
+                continue;
+            }
+            let kind = if table.is_parameter(outer) { "parameter" } else { "variable" };
+            let outer = &table.get_symbols()[outer];
+            let column = match &identifiers {
+                Some(identifiers) => self.tokens[identifiers[row]].column,
+                None => self
+                    .tokens
+                    .iter()
+                    .find(|t| t.line == symbol.line && t.token_type == TokenType::Identifier && t.lexeme == symbol.name)
+                    .map_or(1, |t| t.column),
+            };
+            warnings.push(Diagnostic::warning(
+                "W0007",
+                format!(
+                    "'{}' shadows a {} declared at line {} (scope {})",
+                    diagnostics::quote(&symbol.name), kind, outer.line, outer.scope
+                ),
+                Span::new(symbol.line, column, self.options.column_unit.measure(&symbol.name)),
+            ));
+        }
+        self.diagnostics.extend(warnings);
+    }
+    
+    fn check_labels(&mut self) {
+        let Some(identifiers) = self.symbol_tokens() else {
+            return;
+        };
+        let table = self.symbols.get_symbol_table();
+        let mut warnings = Vec::new();
+        for (row, index) in identifiers.iter().enumerate() {
+            let after_goto = self.tokens[..*index]
+                .iter()
+                .rev()
+                .find(|t| t.token_type != TokenType::Comment)
+                .is_some_and(|t| t.token_type == TokenType::Keyword(Keyword::Goto));
+            let target = &self.tokens[*index];
+            if after_goto && table.lookup_label(&target.lexeme, row).is_none() {
+                warnings.push(Diagnostic::warning(
+                    "W0009",
+                    format!("goto target '{}' is not a label defined in the enclosing function", diagnostics::quote(&target.lexeme)),
+                    Span::new(target.line, target.column, self.options.column_unit.measure(&target.lexeme)),
+                ));
+            }
+        }
+        self.diagnostics.extend(warnings);
+    }
+    
+    // Suggests the keyword an identifier starting a statement was probably
+    // meant to be, as `while` for `wihle (x < 10)`. A name the file declares
+    // anywhere is taken as meant.
+    fn check_keyword_spelling(&mut self) {
+        let table = self.symbols.get_symbol_table();
+        let declared: HashSet<&str> = (0..table.get_symbols().len())
+            .filter(|row| table.is_declaration(*row) || table.get_symbols()[*row].symbol_type == "label")
+            .map(|row| table.get_symbols()[row].name.as_str())
+            .collect();
+        let mut previous: Option<&Token> = None;
+        let mut warnings = Vec::new();
+        for token in self.tokens.iter().filter(|t| t.token_type != TokenType::Comment) {
+            let starts_statement = previous.is_none_or(|p| {
+                p.line < token.line
+                    || matches!(p.token_type, TokenType::Delimiter(Delimiter::Semicolon | Delimiter::LeftBrace | Delimiter::RightBrace))
+            });
+            previous = Some(token);
+            if token.token_type != TokenType::Identifier || !starts_statement || declared.contains(token.lexeme.as_str()) {
+                continue;
+            }
+            if let Some(keyword) = spelling::keyword_suggestion(&token.lexeme) {
+                warnings.push(Diagnostic::warning(
+                    "W0018",
+                    format!("'{}' is not declared; did you mean '{}'?", diagnostics::quote(&token.lexeme), keyword),
+                    Span::over(token.line, token.column, &token.lexeme, self.options.column_unit),
+                ));
+            }
+        }
+        self.diagnostics.extend(warnings);
+    }
+    
+    fn check_allocations(&mut self) {
+        for entry in self.allocations().iter().filter(|e| e.is_unmatched()) {
+            let (name, site) = (entry.name.as_deref().unwrap_or_default(), entry.allocated[0]);
+            self.diagnostics.push(Diagnostic::warning(
+                "W0017",
+                format!("'{}' is allocated with new but never deleted", diagnostics::quote(name)),
+                Span::new(site.line, site.column, 3),
+            ));
+        }
+    }
+    
+    fn check_nesting(&mut self) {
+        let Some(limit) = self.options.max_nesting else {
+            return;
+        };
+        for (kind, token) in nesting::over_limit(&self.tokens, limit) {
+            self.diagnostics.push(Diagnostic::warning(
+                "W0014",
+                format!("{} nesting depth {} exceeds the limit of {}", kind.label(), limit + 1, limit),
+                Span::new(token.line, token.column, 1),
+            ));
+        }
+    }
+    
+    // Reported at the function's name where it is declared.
+    fn check_complexity(&mut self) {
+        let Some(limit) = self.options.max_complexity else {
+            return;
+        };
+        for function in metrics::function_metrics(&self.tokens).into_iter().filter(|f| f.complexity > limit) {
+            let column = self
+                .tokens
+                .iter()
+                .find(|t| t.line == function.line && t.token_type == TokenType::Identifier && t.lexeme == function.name)
+                .map_or(1, |t| t.column);
+            self.diagnostics.push(Diagnostic::warning(
+                "W0026",
+                format!("Function '{}' has cyclomatic complexity {}, over the limit of {}", function.name, function.complexity, limit),
+                Span::new(function.line, column, self.options.column_unit.measure(&function.name)),
+            ));
+        }
+    }
+    
+    // Reports each empty clause of a `for` header at the delimiter ending
+    // it, except in `for (;;)`, which gets a note instead.
+    fn check_for_loops(&mut self) {
+        for header in self.for_loops() {
+            if header.is_endless() {
+                self.diagnostics.push(Diagnostic::note(
+                    "N0001",
+                    "'for (;;)' loops until a break, return or goto".to_string(),
+                    Span::new(header.line, header.column, 3),
+                ));
+                continue;
+            }
+            let clauses = [("initializer", header.init), ("condition", header.condition), ("increment", header.increment)];
+            for ((clause, present), (line, column)) in clauses.into_iter().zip(header.ends) {
+                if !present {
+                    self.diagnostics.push(Diagnostic::warning(
+                        "W0016",
+                        format!("Empty {} in 'for' loop", clause),
+                        Span::new(line, column, 1),
+                    ));
+                }
+            }
+        }
+    }
+    
+    // Reports conditional directives whose branch cannot be worked out, and
+    // chains left open or directives outside one. Warnings about code in a
+    // branch known not to be compiled are dropped and its symbols marked
+    // excluded; errors are kept, as the tokens are lexed either way.
+    fn check_conditional_directives(&mut self) {
+        let report = self.conditionals();
+        let excluded: Vec<_> = report.branches.iter().filter(|b| !b.included).collect();
+        if !excluded.is_empty() {
+            self.diagnostics
+                .retain(|d| d.severity == Severity::Error || !excluded.iter().any(|b| b.excludes(d.span.line)));
+            self.symbols.table.exclude(|line| excluded.iter().any(|b| b.excludes(line)));
+        }
+        for problem in report.problems {
+            let (code, message) = match problem.kind {
+                ConditionalProblemKind::Unevaluable(reason) => (
+                    "W0021",
+                    format!("Cannot evaluate the '{}' condition: {}; its branch is kept", problem.directive, reason),
+                ),
+                ConditionalProblemKind::Unterminated => ("W0022", format!("'{}' has no matching #endif before the end of the file", problem.directive)),
+                ConditionalProblemKind::Unmatched => ("W0023", format!("'{}' without an open #if", problem.directive)),
+                ConditionalProblemKind::AfterElse(line) => ("W0023", format!("'{}' after the #else of the #if opened at line {}", problem.directive, line)),
+            };
+            let length = problem.directive.chars().count();
+            self.diagnostics.push(Diagnostic::warning(code, message, Span::new(problem.line, problem.column, length)));
+        }
+    }
+    
+    // Reports delimiters the token tree could not pair.
+    fn check_delimiters(&mut self) {
+        for problem in token_tree::token_tree(&self.tokens).problems {
+            let label = problem.delimiter.label();
+            let message = match problem.kind {
+                DelimiterProblemKind::Unclosed => format!("{} '{}' is never closed", label, problem.lexeme),
+                DelimiterProblemKind::ClosedBy(closer, line, _) => format!(
+                    "{} '{}' is still open where the '{}' on line {} closes the group around it",
+                    label,
+                    problem.lexeme,
+                    closer.closer(),
+                    line
+                ),
+                DelimiterProblemKind::Unmatched => format!("{} '{}' closes nothing", label, problem.lexeme),
+            };
+            self.diagnostics.push(Diagnostic::warning("W0025", message, Span::new(problem.line, problem.column, 1)));
+        }
+    }
+    
+    // Reports each initialized local or global variable whose uses never
+    // assign to it, counting `x = ...` and `x[i] = ...` as assignments and
+    // `++`/`--` on either side as changes. Parameters and class members,
+    // which may be written from elsewhere, are left out.
+    fn check_const_candidates(&mut self) {
+        if !self.options.lint_const_candidates {
+            return;
+        }
+        let Some(identifiers) = self.symbol_tokens() else {
+            return;
+        };
+        let table = self.symbols.get_symbol_table();
+        let symbols = table.get_symbols();
+        let mut modified = vec![false; symbols.len()];
+        for (row, &index) in identifiers.iter().enumerate() {
+            if let Some(declaration) = table.declaration_row(row) {
+                modified[declaration] |= self.is_modified_at(index);
+            }
+        }
+        let mut warnings = Vec::new();
+        for (row, symbol) in symbols.iter().enumerate() {
+            if symbol.symbol_type != "variable"
+                || !symbol.initialized
+                || !table.is_declaration(row)
+                || table.is_parameter(row)
+                || symbol.access.is_some()
+                || modified[row]
+            {
+                continue;
+            }
+            let token = &self.tokens[identifiers[row]];
+            warnings.push(Diagnostic::warning(
+                "W0010",
+                format!(
+                    "variable '{}' is never changed after its initialization and could be const",
+                    diagnostics::quote(&symbol.name)
+                ),
+                Span::over(token.line, token.column, &token.lexeme, self.options.column_unit),
+            ));
+        }
+        self.diagnostics.extend(warnings);
+    }
+    
+    // Whether the identifier at token `index` is assigned, incremented or
+    // decremented there, looking past any subscripts after it.
+    fn is_modified_at(&self, index: usize) -> bool {
+        let is_step = |t: &Token| matches!(t.token_type, TokenType::Operator(Operator::Increment | Operator::Decrement));
+        let mut before = self.tokens[..index].iter().rev().filter(|t| t.token_type != TokenType::Comment);
+        if before.next().is_some_and(is_step) {
+            return true;
+        }
+        let mut depth = 0usize;
+        for token in self.tokens[index + 1..].iter().filter(|t| t.token_type != TokenType::Comment) {
+            match token.token_type {
+                TokenType::Delimiter(Delimiter::LeftBracket) => depth += 1,
+                TokenType::Delimiter(Delimiter::RightBracket) if depth > 0 => depth -= 1,
+                _ if depth > 0 => {}
+                TokenType::Operator(Operator::Assign) => return true,
+                _ => return is_step(token),
+            }
+        }
+        false
+    }
+    
+    // Looks for `=` at the top level of the parentheses after `if`, `while`
+    // or `for`; in a `for`, only the condition between the two semicolons
+    // counts. An assignment wrapped in its own parentheses, as in
+    // `if ((x = next()) != 0)`, is taken as intended.
+    fn check_conditions(&mut self) {
+        let tokens: Vec<&Token> = self.tokens.iter().filter(|t| t.token_type != TokenType::Comment).collect();
+        let mut warnings = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let keyword = match token.token_type {
+                TokenType::Keyword(Keyword::If) => "if",
+                TokenType::Keyword(Keyword::While) => "while",
+                TokenType::Keyword(Keyword::For) => "for",
+                _ => continue,
+            };
+            if tokens.get(i + 1).is_none_or(|t| t.token_type != TokenType::Delimiter(Delimiter::LeftParen)) {
+                continue;
+            }
+            let (mut depth, mut clause) = (1, 0);
+            for inner in &tokens[i + 2..] {
+                match inner.token_type {
+                    TokenType::Delimiter(Delimiter::LeftParen) => depth += 1,
+                    TokenType::Delimiter(Delimiter::RightParen) => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    TokenType::Delimiter(Delimiter::Semicolon) if depth == 1 => clause += 1,
+                    TokenType::Operator(Operator::Assign) if depth == 1 && (keyword != "for" || clause == 1) => {
+                        warnings.push(Diagnostic::warning(
+                            "W0008",
+                            format!("Assignment in the condition of '{}'; did you mean '=='?", keyword),
+                            Span::new(inner.line, inner.column, 1),
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.diagnostics.extend(warnings);
+    }
+    
+    fn check_naming(&mut self) {
+        let Some(rules) = &self.options.naming else {
+            return;
+        };
+        for violation in naming::check(self.symbols.get_symbol_table().get_symbols(), rules) {
+            let column = self
+                .tokens
+                .iter()
+                .find(|t| t.line == violation.line && t.token_type == TokenType::Identifier && t.lexeme == violation.name)
+                .map_or(1, |t| t.column);
+            let mut diagnostic = violation.to_diagnostic(column);
+            diagnostic.span = Span::new(violation.line, column, self.options.column_unit.measure(&violation.name));
+            self.diagnostics.push(diagnostic);
+        }
+    }
+    
+    // Records a diagnostic for a numeric literal whose value cannot be
+    // represented, returning it if it is an error. Literals have no sign, so
+    // the largest accepted integer is the width's maximum.
+    fn check_number(&mut self, token: &Token) -> Option<LexError> {
+        let (error_code, warning_code, message) = match token.token_type {
+            TokenType::Literal(LiteralKind::Integer) => {
+                let width = self.options.integer_width;
+                let fits = token.lexeme.parse::<i64>().is_ok_and(|value| value <= width.max());
+                if fits {
+                    return None;
+                }
+                (
+                    "E0005",
+                    "W0003",
+                    format!(
+                        "Integer literal {} does not fit in a {}-bit integer (maximum {})",
+                        diagnostics::quote(&token.lexeme),
+                        width.bits(),
+                        width.max()
+                    ),
+                )
+            }
+            TokenType::Literal(LiteralKind::Float) => {
+                let value = token.lexeme.parse::<f64>().ok()?;
+                let mantissa = token.lexeme.split(['e', 'E']).next().unwrap_or_default();
+                let problem = if value.is_infinite() {
+                    "overflows to infinity"
+                } else if value == 0.0 && mantissa.bytes().any(|b| (b'1'..=b'9').contains(&b)) {
+                    "underflows to zero"
+                } else {
+                    return None;
+                };
+                ("E0006", "W0004", format!("Float literal {} {} as a 64-bit float", diagnostics::quote(&token.lexeme), problem))
+            }
+            _ => return None,
+        };
+        let span = Span::new(token.line, token.column, self.options.column_unit.measure(&token.lexeme));
+        if self.options.strict_literals {
+            let error = LexError::new(error_code, message, span.line, span.column, span.length);
+            self.diagnostics.push(Diagnostic::from(&error));
+            Some(error)
+        } else {
+            self.diagnostics.push(Diagnostic::warning(warning_code, message, span));
+            None
+        }
+    }
+    
+    // Reports an identifier starting with `_` as the leading underscore
+    // policy asks, returning it if it is an error.
+    fn check_leading_underscore(&mut self, token: &Token) -> Option<LexError> {
+        let message = format!("Identifier '{}' starts with an underscore", diagnostics::quote(&token.lexeme));
+        let span = Span::new(token.line, token.column, self.options.column_unit.measure(&token.lexeme));
+        match self.options.leading_underscore {
+            LeadingUnderscore::Allow => None,
+            LeadingUnderscore::Warn => {
+                self.diagnostics.push(Diagnostic::warning("W0013", message, span));
+                None
+            }
+            LeadingUnderscore::Error => {
+                let error = LexError::new("E0013", message, span.line, span.column, span.length);
+                self.diagnostics.push(Diagnostic::from(&error));
+                Some(error)
+            }
+        }
+    }
+    
+    // Reports an identifier or literal longer than `max_token_length`. The
+    // token stays in the stream as it is.
+    fn check_token_length(&mut self, token: &Token) -> Option<LexError> {
+        let limit = self.options.max_token_length?;
+        if !matches!(token.token_type, TokenType::Identifier | TokenType::Literal(_)) {
+            return None;
+        }
+        let length = token.lexeme.chars().count();
+        if length <= limit {
+            return None;
+        }
+        let error = LexError::new(
+            "E0014",
+            format!(
+                "{} {} is {} characters long, over the limit of {}",
+                token.token_type,
+                diagnostics::quote(&token.lexeme),
+                length,
+                limit
+            ),
+            token.line,
+            token.column,
+            self.options.column_unit.measure(&token.lexeme),
+        );
+        let span = Span::over(token.line, token.column, &token.lexeme, self.options.column_unit);
+        self.diagnostics.push(Diagnostic { span, ..Diagnostic::from(&error) });
+        Some(error)
+    }
+    
+    // Reports the first character outside ASCII in a string or character
+    // literal, if `warn_non_ascii_strings` is on. An escape such as `\xE9` is
+    // ASCII as written and passes.
+    fn check_non_ascii(&mut self, token: &Token) {
+        if !self.options.warn_non_ascii_strings {
+            return;
+        }
+        let chars = token.value_chars(self.options.column_unit);
+        let Some((found, ch)) = chars.iter().find_map(|found| Some((found, found.spelling.chars().find(|ch| !ch.is_ascii())?))) else {
+            return;
+        };
+        let kind = if token.token_type == TokenType::Literal(LiteralKind::Char) { "Character" } else { "String" };
+        self.diagnostics.push(Diagnostic::warning(
+            "W0015",
+            format!("{} literal contains non-ASCII character '{}' (U+{:04X})", kind, ch, ch as u32),
+            Span::new(found.line, found.column, found.width),
+        ));
+    }
+    
+    // Records a diagnostic for each escape sequence in a literal that MCPP
+    // does not define, returning the first one if they are errors.
+    fn check_escapes(&mut self, token: &Token) -> Option<LexError> {
+        let mut first_error = None;
+        let unit = self.options.column_unit;
+        for ch in token.value_chars(unit) {
+            let Some(escaped) = ch.spelling.strip_prefix('\\').and_then(|rest| rest.chars().next()) else {
+                continue;
+            };
+            if VALID_ESCAPES.contains(escaped) {
+                continue;
+            }
+            let length = 1 + unit.width(escaped);
+            let message = format!("Unknown escape sequence '\\{}' in {}", escaped, token.token_type);
+            if self.options.strict_escapes {
+                let error = LexError::new("E0003", message, ch.line, ch.column, length);
+                self.diagnostics.push(Diagnostic::from(&error));
+                first_error.get_or_insert(error);
+            } else {
+                self.diagnostics.push(Diagnostic::warning("W0002", message, Span::new(ch.line, ch.column, length)));
+            }
+        }
+        first_error
+    }
+    
+    // The comments and string literals (marked true) continuing the string
+    // literal `length` bytes long at the scan position, as byte ranges from
+    // that position. Empty unless another string literal follows; a comment
+    // after the last one is left alone.
+    fn adjacent_strings(&self, length: usize) -> Vec<(bool, Range<usize>)> {
+        let remaining = &self.source[self.position..];
+        let mut segments = Vec::new();
+        let mut strings = 0;
+        let mut offset = length;
+        loop {
+            let rest = &remaining[offset..];
+            let text = rest.trim_start_matches(|ch: char| {
+                ch.is_whitespace() && (self.options.lenient_whitespace || !confusables::is_non_breaking_space(ch))
+            });
+            let start = offset + rest.len() - text.len();
+            let length = match scanner::scan_token(text) {
+                Some((TokenType::Comment, length)) => {
+                    segments.push((false, start..start + length));
+                    length
+                }
+                // Under error recovery a string ends at its line, as above
+                Some((TokenType::Literal(LiteralKind::String), length))
+                    if !(self.options.error_recovery && string_line_end(&text[..length]) < length) =>
+                {
+                    segments.push((true, start..start + length));
+                    strings = segments.len();
+                    length
+                }
+                _ => break,
+            };
+            offset = start + length;
+        }
+        segments.truncate(strings);
+        segments
+    }
+    
+    // Advances over a string literal `length` bytes long and the segments
+    // continuing it, returning one token for all the strings, the comments
+    // between them and the first error in their escapes.
+    fn merge_strings(&mut self, length: usize, segments: Vec<(bool, Range<usize>)>) -> (Token, Vec<Token>, Option<LexError>) {
+        let unit = self.options.column_unit;
+        let end = segments.last().map_or(length, |(_, range)| range.end);
+        let text = self.source[self.position..self.position + end].to_string();
+        let (line, column) = (self.line, self.column);
+        let (mut end_line, mut end_column) = (line, column);
+        let mut pieces = Vec::new();
+        let mut comments = Vec::new();
+        let mut first_error = None;
+        let mut consumed = 0;
+        for (is_string, range) in std::iter::once((true, 0..length)).chain(segments) {
+            self.advance(&text[consumed..range.start]);
+            let token_type = if is_string { TokenType::Literal(LiteralKind::String) } else { TokenType::Comment };
+            let token = Token::new_with_unit(token_type, text[range.clone()].to_string(), self.line, self.column, unit);
+            self.advance(&token.lexeme);
+            consumed = range.end;
+            if is_string {
+                self.check_non_ascii(&token);
+                let error = self.check_escapes(&token);
+                first_error = first_error.or(error);
+                (end_line, end_column) = (token.end_line, token.end_column);
+                pieces.push(token.lexeme);
+            } else {
+                comments.push(token);
+            }
+        }
+        let value: String = pieces.iter().map(|piece| splice_lines(&piece[1..piece.len() - 1])).collect();
+        let mut token = Token::new_with_unit(TokenType::Literal(LiteralKind::String), pieces.join(" "), line, column, unit);
+        (token.end_line, token.end_column) = (end_line, end_column);
+        token.value = Some(value);
+        (token, comments, first_error)
+    }
+    
+    // Whether only whitespace precedes the scan position on its line.
+    fn at_line_start(&self) -> bool {
+        let before = &self.source[..self.position];
+        before[before.rfind('\n').map_or(0, |i| i + 1)..].chars().all(char::is_whitespace)
+    }
+    
+    // Scans and records the next token, skipping comments. The EOF token is
+    // returned once; after it every call returns None. An error also ends
+    // lexing unless error recovery is on.
+    pub fn next_token(&mut self) -> Option<Result<Token, LexError>> {
+        if !self.finished && self.is_cancelled() {
+            if std::mem::replace(&mut self.cancel_reported, true) {
+                return None;
+            }
+            return Some(Err(LexError::cancelled(self.line, self.column)));
+        }
+        let mut result = self.scan_next();
+        self.locate();
+        if let Some(Ok(token)) = &mut result {
+            token.origin = self.line_map.origin(token.line);
+        }
+        self.deliver();
+        result
+    }
+    
+    // Gives the tokens and diagnostics recorded since the last call their
+    // origin. A `#line` directive is applied once a token after its line,
+    // or the end of the file, shows that its arguments are complete.
+    fn locate(&mut self) {
+        while self.located_tokens < self.tokens.len() {
+            let index = self.located_tokens;
+            let token = &self.tokens[index];
+            let line_done = token.token_type == TokenType::EOF;
+            if let Some(directive) = self.pending_line_directive.filter(|&d| line_done || self.tokens[d].line < token.line) {
+                self.pending_line_directive = None;
+                self.apply_line_directive(directive);
+            }
+            let token = &mut self.tokens[index];
+            if token.token_type == TokenType::Keyword(Keyword::Directive) && token.lexeme == "#line" {
+                self.pending_line_directive = Some(index);
+            }
+            token.origin = self.line_map.origin(token.line);
+            self.located_tokens += 1;
+        }
+        for diagnostic in &mut self.diagnostics[self.located_diagnostics..] {
+            diagnostic.origin = self.line_map.origin(diagnostic.span.line);
+        }
+        self.located_diagnostics = self.diagnostics.len();
+    }
+    
+    // Reads `#line number "file"` at token `index`, the file being optional,
+    // and numbers the lines after it from there. Anything else is reported
+    // and changes nothing.
+    fn apply_line_directive(&mut self, index: usize) {
+        let directive = &self.tokens[index];
+        let arguments: Vec<&Token> = self.tokens[index + 1..]
+            .iter()
+            .take_while(|t| t.directive && t.line == directive.line)
+            .filter(|t| t.token_type != TokenType::Comment)
+            .collect();
+        let line = match arguments.first() {
+            Some(number) if number.token_type == TokenType::Literal(LiteralKind::Integer) => number.lexeme.parse::<usize>().ok().filter(|&n| n > 0),
+            _ => None,
+        };
+        let file = match arguments.get(1) {
+            None => Some(None),
+            Some(name) if name.token_type == TokenType::Literal(LiteralKind::String) && arguments.len() == 2 && !name.lexeme.starts_with('R') => {
+                Some(Some(name.lexeme[1..name.lexeme.len() - 1].to_string()))
+            }
+            Some(_) => None,
+        };
+        match (line, file) {
+            (Some(line), Some(file)) => self.line_map.push(directive.line + 1, LineOrigin { line, file }),
+            _ => {
+                let diagnostic = Diagnostic::warning(
+                    "W0024",
+                    "Invalid #line directive; expected a line number from 1 and an optional quoted file name, so it is ignored".to_string(),
+                    Span::new(directive.line, directive.column, directive.lexeme.len()),
+                );
+                self.diagnostics.push(diagnostic);
+            }
+        }
+    }
+    
+    fn scan_next(&mut self) -> Option<Result<Token, LexError>> {
+        if self.finished {
+            return None;
+        }
+        if let Some(error) = self.take_ascii_error() {
+            return Some(Err(error));
+        }
+        if let Some(token) = self.queued.pop_front() {
+            self.tokens.push(token.clone());
+            return Some(Ok(token));
+        }
+        
+        loop {
+            self.skip_whitespace();
+            
+            if self.position >= self.source.len() {
+                break;
+            }
+            
+            let mut matched = false;
+            let start_line = self.line;
+            let start_col = self.column;
+            let start_position = self.position;
+            
+            let mut skipped = false;
+            let tracing = self.trace.is_some();
+            let mut attempted = Vec::new();
+            let mut traced = None;
+            let mut pending_error = None;
+            
+            let remaining = &self.source[self.position..];
+            let found = match self.backend {
+                Backend::Regex => {
+                    let mut found = None;
+                    for ((token_type, _), pattern) in self.patterns.patterns.iter().zip(&self.patterns.anchored) {
+                        if let Some(mat) = pattern.find(remaining) {
+                            found = Some((token_type.clone(), mat.end()));
+                            break;
+                        }
+                        if tracing {
+                            attempted.push(token_type.clone());
+                        }
+                    }
+                    found
+                }
+                Backend::Scanner => scanner::scan_token(remaining),
+                #[cfg(feature = "logos")]
+                Backend::Logos => crate::logos_scanner::scan_token(remaining),
+            };
+            
+            // The ASCII patterns stop at the first non-ASCII letter, so a
+            // longer Unicode identifier wins over whatever they found
+            let unicode_identifier = if self.options.unicode_identifiers {
+                UNICODE_IDENTIFIER.find(remaining).map(|mat| mat.end())
+            } else {
+                None
+            };
+            let found = match unicode_identifier {
+                Some(len) if found.as_ref().is_none_or(|(_, found_len)| len > *found_len) => Some((TokenType::Identifier, len)),
+                _ => found,
+            };
+            
+            // Likewise a name with an extra identifier character, so `int$`
+            // is one identifier rather than the keyword and an invalid `$`
+            let extended_identifier = self.identifier_pattern.as_ref().and_then(|pattern| pattern.find(remaining)).map(|mat| mat.end());
+            let found = match extended_identifier {
+                Some(len) if found.as_ref().is_none_or(|(_, found_len)| len > *found_len) => Some((TokenType::Identifier, len)),
+                _ => found,
+            };
+            
+            // With error recovery a string may not run past the end of its
+            // line, so a missing closing quote costs one line of tokens
+            // instead of the rest of the file. Lines continued with a
+            // backslash count as one
+            let mut malformed = None;
+            let found = if remaining.starts_with('"') && match &found {
+                Some((_, len)) => self.options.error_recovery && string_line_end(&remaining[..*len]) < *len,
+                None => true,
+            } {
+                let length = string_line_end(remaining);
+                // A string continued with a backslash spans several lines
+                let span = Span::over(start_line, start_col, &remaining[..length], self.options.column_unit);
+                let error = LexError::new("E0004", "Unterminated string literal".to_string(), start_line, start_col, span.length);
+                self.diagnostics.push(Diagnostic { span, ..Diagnostic::from(&error) });
+                malformed = Some(error);
+                Some((TokenType::Literal(LiteralKind::String), length))
+            } else {
+                found
+            };
+            
+            // A quote that starts no valid character literal is reported for
+            // what is wrong with it and kept as one literal token, so that
+            // recovery resumes after it rather than at the next character
+            let found = if found.is_none() && remaining.starts_with('\'') {
+                let (code, message, length) = malformed_char_literal(remaining);
+                let error = LexError::new(
+                    code,
+                    message,
+                    start_line,
+                    start_col,
+                    self.options.column_unit.measure(&remaining[..length]),
+                );
+                self.diagnostics.push(Diagnostic::from(&error));
+                malformed = Some(error);
+                Some((TokenType::Literal(LiteralKind::Char), length))
+            } else {
+                found
+            };
+            
+            // Checked here, for both backends, since a raw string would
+            // otherwise lex as the identifier R and an ordinary string. One
+            // never closed is reported where it opens and ends at that line,
+            // like an unterminated string
+            let mut raw = false;
+            let found = match scanner::raw_string(remaining) {
+                Some(Ok(length)) => {
+                    raw = true;
+                    Some((TokenType::Literal(LiteralKind::String), length))
+                }
+                Some(Err(delimiter)) => {
+                    let line_end = remaining.find('\n').unwrap_or(remaining.len());
+                    let length = remaining[..line_end].strip_suffix('\r').map_or(line_end, str::len);
+                    let error = LexError::new(
+                        "E0011",
+                        format!("Unterminated raw string literal; no ){}\" closes it", diagnostics::quote(delimiter)),
+                        start_line,
+                        start_col,
+                        self.options.column_unit.measure(&remaining[..length]),
+                    );
+                    self.diagnostics.push(Diagnostic::from(&error));
+                    malformed = Some(error);
+                    raw = true;
+                    Some((TokenType::Literal(LiteralKind::String), length))
+                }
+                None => found,
+            };
+            
+            // An attribute may hold brackets and strings of its own, so it is
+            // found here, for both backends, like a raw string
+            let found = match scanner::attribute(remaining) {
+                Some(Ok(length)) => Some((TokenType::Attribute, length)),
+                Some(Err(())) => {
+                    let line_end = remaining.find('\n').unwrap_or(remaining.len());
+                    let length = remaining[..line_end].strip_suffix('\r').map_or(line_end, str::len);
+                    let error = LexError::new(
+                        "E0012",
+                        "Unterminated attribute; no ]] closes it".to_string(),
+                        start_line,
+                        start_col,
+                        self.options.column_unit.measure(&remaining[..length]),
+                    );
+                    self.diagnostics.push(Diagnostic::from(&error));
+                    malformed = Some(error);
+                    Some((TokenType::Attribute, length))
+                }
+                None => found,
+            };
+            
+            // A block comment never closed runs to the end of the file, as a
+            // compiler reads it, instead of lexing as `/` and `*`
+            let found = if remaining.starts_with("/*") && !matches!(found, Some((TokenType::Comment, _))) {
+                let line_end = remaining.find('\n').unwrap_or(remaining.len());
+                let length = remaining[..line_end].strip_suffix('\r').map_or(line_end, str::len);
+                let error = LexError::new(
+                    "E0015",
+                    "Unterminated block comment; no */ closes it".to_string(),
+                    start_line,
+                    start_col,
+                    self.options.column_unit.measure(&remaining[..length]),
+                );
+                self.diagnostics.push(Diagnostic::from(&error));
+                malformed = Some(error);
+                Some((TokenType::Comment, remaining.len()))
+            } else {
+                found
+            };
+            
+            // A directive name counts as one only where it begins its line;
+            // elsewhere its `#` is an invalid character
+            let found = match found {
+                Some((TokenType::Keyword(Keyword::Include | Keyword::Define | Keyword::Directive), _)) if !self.at_line_start() => None,
+                found => found,
+            };
+            
+            let concatenated = match &found {
+                Some((TokenType::Literal(LiteralKind::String), length)) if self.options.concat_strings && malformed.is_none() && !raw => {
+                    Some((*length, self.adjacent_strings(*length))).filter(|(_, segments)| !segments.is_empty())
+                }
+                _ => None,
+            };
+            
+            if let Some((length, segments)) = concatenated {
+                let (mut token, mut comments, escape_error) = self.merge_strings(length, segments);
+                token.directive = self.in_directive;
+                comments.iter_mut().for_each(|comment| comment.directive = self.in_directive);
+                if tracing {
+                    traced = Some((token.token_type.clone(), Some(token.token_type.clone()), token.lexeme.to_string()));
+                }
+                self.symbols.observe(&token);
+                for comment in &comments {
+                    self.symbols.observe(comment);
+                }
+                if self.options.keep_comments {
+                    self.queued.extend(comments);
+                }
+                let length_error = self.check_token_length(&token);
+                self.tokens.push(token);
+                matched = true;
+                pending_error = escape_error.or(length_error);
+            } else if let Some((token_type, length)) = found {
+                let lexeme = remaining[..length].to_string();
+                
+                if token_type == TokenType::Comment && !self.options.keep_comments {
+                    // Still seen by the symbol table, for doc comments
+                    self.symbols.observe(&Token::new_with_unit(
+                        token_type.clone(),
+                        lexeme.clone(),
+                        start_line,
+                        start_col,
+                        self.options.column_unit,
+                    ));
+                    self.advance(&lexeme);
+                    if tracing {
+                        traced = Some((token_type, None, lexeme));
+                    }
+                    matched = true;
+                    skipped = true;
+                    pending_error = malformed;
+                } else {
+                    let mut final_token_type = token_type.clone();
+                    if token_type == TokenType::Identifier {
+                        if let Some(keyword_type) = self.check_keyword(&lexeme) {
+                            final_token_type = keyword_type;
+                        }
+                    }
+                    if tracing {
+                        traced = Some((token_type, Some(final_token_type.clone()), lexeme.clone()));
+                    }
+                    
+                    let text = if interned(&final_token_type) {
+                        self.interner.intern(&lexeme)
+                    } else {
+                        Lexeme::from(lexeme.as_str())
+                    };
+                    let mut token = Token::new_with_unit(
+                        final_token_type,
+                        text,
+                        start_line,
+                        start_col,
+                        self.options.column_unit,
+                    );
+                    token.directive = self.in_directive;
+                    if raw && malformed.is_none() {
+                        token.value = Some(scanner::raw_string_contents(&lexeme).to_string());
+                    } else if token.token_type == TokenType::Attribute && malformed.is_none() {
+                        token.value = Some(scanner::attribute_contents(&lexeme).to_string());
+                    } else if token.token_type == TokenType::Literal(LiteralKind::String) && malformed.is_none() {
+                        let contents = &lexeme[1..lexeme.len() - 1];
+                        let spliced = splice_lines(contents);
+                        if spliced.len() != contents.len() {
+                            token.value = Some(spliced);
+                        }
+                    }
+                    
+                    if token.token_type == TokenType::Identifier && !lexeme.is_ascii() {
+                        self.diagnostics.extend(homoglyph_warning(&lexeme, start_line, start_col, self.options.column_unit));
+                    }
+                    if matches!(token.token_type, TokenType::Literal(LiteralKind::String | LiteralKind::Char)) {
+                        self.check_non_ascii(&token);
+                    }
+                    
+                    let escape_error = match token.token_type {
+                        TokenType::Literal(LiteralKind::String) if raw => None,
+                        TokenType::Literal(LiteralKind::String | LiteralKind::Char) => self.check_escapes(&token),
+                        TokenType::Literal(LiteralKind::Integer | LiteralKind::Float) => self.check_number(&token),
+                        TokenType::Identifier if lexeme.starts_with('_') => self.check_leading_underscore(&token),
+                        // A keyword straight after a type keyword is meant
+                        // as the declared name
+                        TokenType::Keyword(_) if self.tokens.last().is_some_and(|t| data_type_name(&t.token_type).is_some()) => {
+                            let error = LexError::new(
+                                "E0007",
+                                format!("'{}' is a reserved keyword and cannot be used as an identifier", diagnostics::quote(&lexeme)),
+                                start_line,
+                                start_col,
+                                self.options.column_unit.measure(&lexeme),
+                            );
+                            self.diagnostics.push(Diagnostic::from(&error));
+                            Some(error)
+                        }
+                        _ => None,
+                    };
+                    
+                    if matches!(token.token_type, TokenType::Keyword(Keyword::Include | Keyword::Define | Keyword::Directive)) {
+                        self.in_directive = true;
+                    }
+                    let length_error = self.check_token_length(&token);
+                    self.symbols.observe(&token);
+                    self.tokens.push(token);
+                    self.advance(&lexeme);
+                    matched = true;
+                    pending_error = malformed.or(escape_error).or(length_error);
+                }
+            }
+            
+            if let Some(hook) = self.trace.as_mut() {
+                let (matched_type, token_type, lexeme) = match traced {
+                    Some((matched_type, token_type, lexeme)) => (Some(matched_type), token_type, lexeme),
+                    None => (None, None, self.source[start_position..].chars().next().unwrap().to_string()),
+                };
+                hook(&TraceEvent {
+                    position: start_position,
+                    line: start_line,
+                    column: start_col,
+                    attempted,
+                    matched: matched_type,
+                    token_type,
+                    lexeme,
+                });
+            }
+            
+            if !matched {
+                let ch = self.source[self.position..].chars().next().unwrap();
+                // Reported by `track` alone when ASCII is all that is allowed
+                if self.options.ascii_only && !ch.is_ascii() {
+                    if self.options.error_recovery {
+                        let mut token = Token::new_with_unit(TokenType::Error, ch.to_string(), self.line, self.column, self.options.column_unit);
+                        token.directive = self.in_directive;
+                        self.tokens.push(token);
+                    }
+                    self.advance(&ch.to_string());
+                    if let Some(error) = self.take_ascii_error() {
+                        return Some(Err(error));
+                    }
+                    continue;
+                }
+                let mut error = invalid_character(ch, self.line, self.column);
+                error.length = self.options.column_unit.width(ch);
+                // Two dots are taken together as a mistyped ellipsis
+                let mut skipped_text = ch.to_string();
+                if ch == '#' && !self.at_line_start() {
+                    error.message.push_str("; directives must start at the beginning of a line");
+                } else if self.source[self.position..].starts_with("..") {
+                    skipped_text = "..".to_string();
+                    error.message = "Invalid characters '..'; did you mean '...'?".to_string();
+                    error.length = 2;
+                }
+                self.diagnostics.push(Diagnostic::from(&error));
+                if self.options.error_recovery {
+                    // Left out of the symbol table, which only follows
+                    // valid tokens
+                    let mut token = Token::new_with_unit(TokenType::Error, skipped_text.clone(), self.line, self.column, self.options.column_unit);
+                    token.directive = self.in_directive;
+                    self.tokens.push(token);
+                    self.advance(&skipped_text);
+                } else {
+                    self.finished = true;
+                }
+                return Some(Err(error));
+            }
+            
+            // The literal with a bad escape, no closing quote, the wrong
+            // number of characters or an unrepresentable value, or the
+            // misused keyword, stays in the stream
+            if let Some(error) = pending_error {
+                self.finished = !self.options.error_recovery;
+                return Some(Err(error));
+            }
+            if let Some(error) = self.take_ascii_error() {
+                return Some(Err(error));
+            }
+            
+            if !skipped {
+                return self.tokens.last().cloned().map(Ok);
+            }
+        }
+        
+        // Found in whitespace or a comment after the last token
+        if let Some(error) = self.take_ascii_error() {
+            return Some(Err(error));
+        }
+        self.finished = true;
+        self.tokens.push(Token::new(
+            TokenType::EOF,
+            "EOF".to_string(),
+            self.line,
+            self.column,
         ));
+        self.end_file();
+        self.check_naming();
+        self.check_initialization();
+        self.check_shadowing();
+        self.check_conditions();
+        self.check_labels();
+        self.check_keyword_spelling();
+        self.check_allocations();
+        self.check_nesting();
+        self.check_delimiters();
+        self.check_complexity();
+        self.check_for_loops();
+        self.check_const_candidates();
+        self.check_conditional_directives();
+        
+        self.tokens.last().cloned().map(Ok)
+    }
+    
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    
+    pub fn current_line(&self) -> usize {
+        self.line
+    }
+    
+    pub fn current_column(&self) -> usize {
+        self.column
+    }
+    
+    pub fn get_tokens(&self) -> &Vec<Token> {
+        &self.tokens
+    }
+    
+    pub fn get_symbol_table(&self) -> &SymbolTable {
+        self.symbols.get_symbol_table()
+    }
+    
+    // Everything reported so far, in source order: warnings, and the error
+    // that stopped lexing, if any.
+    pub fn get_diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+    
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+    
+    // Groups the tokens by the line they start on, with one entry for every
+    // line of the source, including lines without tokens. A token spanning
+    // several lines is listed on its first; `Token::end_line` is its last.
+    pub fn tokens_by_line(&self) -> Vec<LineTokens<'_>> {
+        let index = LineIndex::new(&self.source);
+        let mut lines = Vec::with_capacity(index.line_count());
+        let mut first = 0;
+        for line in 1..=index.line_count() {
+            let count = self.tokens[first..].iter().take_while(|t| t.line == line).count();
+            lines.push(LineTokens {
+                line,
+                text: index.line_text(&self.source, line).unwrap_or(""),
+                tokens: &self.tokens[first..first + count],
+                first_token: first,
+            });
+            first += count;
+        }
+        lines
+    }
+    
+    // Tokens starting on any line from `start_line` to `end_line` inclusive.
+    pub fn tokens_in_range(&self, start_line: usize, end_line: usize) -> &[Token] {
+        let start = self.tokens.partition_point(|t| t.line < start_line);
+        let end = self.tokens.partition_point(|t| t.line <= end_line);
+        &self.tokens[start..end.max(start)]
+    }
+    
+    // Length, branching and nesting of each function body lexed so far.
+    pub fn metrics(&self) -> Vec<FunctionMetrics> {
+        metrics::function_metrics(&self.tokens)
+    }
+    
+    // Blocks lexed so far that look copied from each other, at least
+    // `threshold` percent alike.
+    pub fn duplicates(&self, threshold: f64) -> Vec<Duplicate> {
+        duplication::duplicates(&self.tokens, threshold)
+    }
+    
+    // Each function definition lexed so far with its tokens and lines.
+    pub fn functions(&self) -> Vec<FunctionSpan> {
+        functions::function_spans(&self.tokens)
+    }
+    
+    // The tokens of the first function named `name`, from its signature
+    // through its closing brace.
+    pub fn function_tokens(&self, name: &str) -> Option<&[Token]> {
+        self.all_function_tokens(name).into_iter().next()
+    }
+    
+    // The tokens of every function named `name`, in source order.
+    pub fn all_function_tokens(&self, name: &str) -> Vec<&[Token]> {
+        self.functions()
+            .into_iter()
+            .filter(|span| span.name == name)
+            .map(|span| &self.tokens[span.tokens])
+            .collect()
+    }
+    
+    pub fn allocations(&self) -> Vec<Allocations> {
+        allocation::allocations(&self.tokens)
+    }
+    
+    // Declaration, first and last use of each variable and constant.
+    pub fn lifetimes(&self) -> Vec<Lifetime> {
+        lifetime::lifetimes(self.get_symbol_table(), &self.metrics())
+    }
+    
+    // Deepest brace, parenthesis and bracket nesting lexed so far.
+    pub fn nesting_report(&self) -> NestingReport {
+        nesting::nesting_report(&self.tokens)
+    }
+    
+    // The tokens lexed so far, without EOF, with matched delimiters made
+    // groups; see `token_tree::token_tree`.
+    pub fn token_tree(&self) -> Vec<TokenTree> {
+        token_tree::token_tree(&self.tokens).trees
+    }
+    
+    // Each branch of the conditional directives lexed so far and whether it
+    // is compiled.
+    pub fn conditionals(&self) -> ConditionalReport {
+        conditional::conditionals(&self.tokens)
+    }
+    
+    // Each `for` header lexed so far and which of its clauses are empty.
+    pub fn for_loops(&self) -> Vec<ForLoop> {
+        loops::for_loops(&self.tokens)
+    }
+    
+    // The tokens `options` selects by function, line range, head and tail;
+    // its limit applies only to printing. A function not in the file
+    // selects nothing.
+    pub fn select_tokens(&self, options: &TokenStreamPrintOptions) -> &[Token] {
+        let tokens = match &options.function {
+            Some(name) => self.function_tokens(name).unwrap_or(&[]),
+            None => &self.tokens,
+        };
+        let tokens = match options.lines {
+            Some((start, end)) => {
+                let first = tokens.partition_point(|t| t.line < start);
+                let last = tokens.partition_point(|t| t.line <= end);
+                &tokens[first..last.max(first)]
+            }
+            None => tokens,
+        };
+        match (options.head, options.tail) {
+            (Some(head), _) => &tokens[..head.min(tokens.len())],
+            (None, Some(tail)) => &tokens[tokens.len().saturating_sub(tail)..],
+            (None, None) => tokens,
+        }
+    }
+    
+    pub fn print_token_stream(&self) {
+        self.print_token_stream_with(&TokenStreamPrintOptions::all());
+    }
+    
+    pub fn print_token_stream_with(&self, options: &TokenStreamPrintOptions) {
+        println!("\n=== TOKEN STREAM ===");
+        let selected = self.select_tokens(options);
+        let shown = match options.limit {
+            Some(limit) if options.head.is_none() && options.tail.is_none() => &selected[..limit.min(selected.len())],
+            _ => selected,
+        };
+        for token in shown {
+            println!("{}", token.to_compiler_format());
+        }
+        if shown.len() < selected.len() {
+            println!("... {} more tokens, use --all to print everything", group_digits(selected.len() - shown.len()));
+        }
+    }
+    
+    pub fn to_json(&self) -> String {
+        let mut buffer = Vec::new();
+        self.write_json(&mut buffer, true, &DiagnosticFilter::default(), PositionBase::One, false).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+    
+    // The JSON document `write_json` writes, for callers embedding it in a
+    // larger one.
+    pub(crate) fn document(&self, filter: &DiagnosticFilter, base: PositionBase, by_scope: bool) -> DocumentRef<'_> {
+        let mut nesting = self.nesting_report();
+        nesting.rebase(PositionBase::One, base);
+        let mut lifetimes = self.lifetimes();
+        lifetimes.iter_mut().for_each(|l| l.rebase(PositionBase::One, base));
+        let symbols_by_scope = by_scope.then(|| {
+            let mut groups = self.get_symbol_table().symbols_by_scope();
+            groups.rebase(PositionBase::One, base);
+            groups
+        });
+        let mut conditionals = self.conditionals().branches;
+        conditionals.iter_mut().for_each(|b| b.rebase(PositionBase::One, base));
+        DocumentRef {
+            summary: self.file_summary(),
+            nesting,
+            lifetimes,
+            symbols_by_scope,
+            conditionals,
+            ..DocumentRef::new(
+                !self.has_errors(),
+                &self.tokens,
+                self.get_symbol_table(),
+                self.metrics(),
+                filter.apply(&self.diagnostics),
+                base,
+                self.options.column_unit,
+            )
+        }
+    }
+    
+    // Writes the JSON document (see `document::JsonDocument`) with positions
+    // counted from `base`, leaving out diagnostics the filter hides, and
+    // with `symbols_by_scope` when `by_scope` is set.
+    pub fn write_json<W: io::Write>(&self, w: W, pretty: bool, filter: &DiagnosticFilter, base: PositionBase, by_scope: bool) -> io::Result<()> {
+        let document = self.document(filter, base, by_scope);
+        if pretty {
+            serde_json::to_writer_pretty(w, &document)?;
+        } else {
+            serde_json::to_writer(w, &document)?;
+        }
+        Ok(())
+    }
+}
+
+// Line statistics gathered while scanning, written to the JSON document as
+// `summary`. Lengths are in column units and leave out the line break; a
+// final line counts only if it holds something.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSummary {
+    pub lines: usize,
+    pub longest_line: usize,
+    pub trailing_whitespace_lines: usize,
+    // All zero in older documents.
+    #[serde(default)]
+    pub indentation: IndentationSummary,
+}
+
+// How many indented lines start with only spaces, only tabs or both. Blank
+// lines and lines with no indentation are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndentationSummary {
+    pub spaces: usize,
+    pub tabs: usize,
+    pub mixed: usize,
+}
+
+impl IndentationSummary {
+    // More than one way of indenting is in use.
+    pub fn is_inconsistent(&self) -> bool {
+        self.mixed > 0 || (self.spaces > 0 && self.tabs > 0)
+    }
+}
+
+impl fmt::Display for IndentationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} lines spaces, {} lines tabs, {} mixed", self.spaces, self.tabs, self.mixed)
+    }
+}
+
+// The spaces and tabs a line starts with, given once something follows
+// them: the column of the first of each, the column where the second kind
+// first appears, and the run's width.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Indentation {
+    first_space: Option<usize>,
+    first_tab: Option<usize>,
+    mixed_at: Option<usize>,
+    width: usize,
+}
+
+impl Indentation {
+    // The column where a character of the kind `style` does not allow
+    // first appears.
+    fn violation(&self, style: IndentStyle) -> Option<usize> {
+        match style {
+            IndentStyle::Spaces => self.first_tab,
+            IndentStyle::Tabs => self.first_space,
+        }
+    }
+    
+    // The width from `column` to the end of the run, which starts the line.
+    fn width_from(&self, column: usize) -> usize {
+        self.width + 1 - column
+    }
+}
+
+// What a consumed character ends: the trailing run of spaces and tabs, as
+// its column and width, at a newline; the indentation at a line's first
+// character that is neither.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineEvent {
+    TrailingWhitespace(usize, usize),
+    Indentation(Indentation),
+}
+
+// The line being scanned: its width so far, where its run of trailing
+// spaces and tabs starts, with the run's width, and its indentation while
+// nothing else has been seen on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct LineTracker {
+    summary: FileSummary,
+    width: usize,
+    trailing: Option<(usize, usize)>,
+    indentation: Option<Indentation>,
+}
+
+impl Default for LineTracker {
+    fn default() -> Self {
+        LineTracker {
+            summary: FileSummary::default(),
+            width: 0,
+            trailing: None,
+            indentation: Some(Indentation::default()),
+        }
+    }
+}
+
+impl LineTracker {
+    // Takes one consumed character at `column`.
+    fn track(&mut self, ch: char, column: usize, width: usize) -> Option<LineEvent> {
+        match ch {
+            '\n' => return self.end_line().map(|(column, width)| LineEvent::TrailingWhitespace(column, width)),
+            '\r' => {}
+            ' ' | '\t' => {
+                self.trailing.get_or_insert((column, 0)).1 += width;
+                self.width = column - 1 + width;
+                if let Some(indentation) = self.indentation.as_mut() {
+                    let (first, other) = if ch == ' ' {
+                        (&mut indentation.first_space, indentation.first_tab)
+                    } else {
+                        (&mut indentation.first_tab, indentation.first_space)
+                    };
+                    if first.is_none() {
+                        *first = Some(column);
+                        if other.is_some() {
+                            indentation.mixed_at = Some(column);
+                        }
+                    }
+                    indentation.width += width;
+                }
+            }
+            _ => {
+                self.trailing = None;
+                self.width = column - 1 + width;
+                let indentation = self.indentation.take()?;
+                return self.count_indentation(indentation).map(LineEvent::Indentation);
+            }
+        }
+        None
+    }
+    
+    // Counts a line's indentation, returning it if there is any.
+    fn count_indentation(&mut self, indentation: Indentation) -> Option<Indentation> {
+        let counts = &mut self.summary.indentation;
+        match (indentation.first_space, indentation.first_tab) {
+            (None, None) => return None,
+            (Some(_), Some(_)) => counts.mixed += 1,
+            (Some(_), None) => counts.spaces += 1,
+            (None, Some(_)) => counts.tabs += 1,
+        }
+        Some(indentation)
+    }
+    
+    fn end_line(&mut self) -> Option<(usize, usize)> {
+        self.summary.lines += 1;
+        self.summary.longest_line = self.summary.longest_line.max(self.width);
+        self.width = 0;
+        self.indentation = Some(Indentation::default());
+        let run = self.trailing.take();
+        if run.is_some() {
+            self.summary.trailing_whitespace_lines += 1;
+        }
+        run
+    }
+}
+
+// Throughput of one `tokenize_timed` run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LexStats {
+    pub tokens: usize,
+    pub bytes: usize,
+    pub elapsed: Duration,
+}
+
+impl LexStats {
+    pub fn tokens_per_sec(&self) -> f64 {
+        self.tokens as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+    
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<Token, LexError>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
+impl TokenSource for Lexer {
+    fn next_token(&mut self) -> Option<Result<Token, LexError>> {
+        Lexer::next_token(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn lexed(source: &str) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        lexer
+    }
+    
+    // Lexes to the end whatever the errors, as error recovery would leave it.
+    fn lexed_with(source: &str, options: LexerOptions) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(options);
+        let _ = lexer.tokenize();
+        lexer
+    }
+    
+    fn codes(lexer: &Lexer) -> Vec<&str> {
+        lexer.get_diagnostics().iter().map(|d| d.code.as_str()).collect()
+    }
+    
+    #[test]
+    fn reset_lexes_like_a_fresh_lexer() {
+        let a = "int main() {\n    int x = 1; /* note */\n    return x;\n}\n";
+        let b = "float f(int y) {\n    string s = \"hi\";\n}\n";
+        let mut lexer = lexed(a);
+        lexer.reset(b.to_string());
+        lexer.tokenize().unwrap();
+        let fresh = lexed(b);
+        assert_eq!(lexer.get_tokens(), fresh.get_tokens());
+        assert_eq!(lexer.get_symbol_table().get_symbols(), fresh.get_symbol_table().get_symbols());
+        assert_eq!(lexer.current_line(), fresh.current_line());
+        assert!(lexer.get_diagnostics().is_empty());
+    }
+    
+    #[test]
+    fn lexers_share_one_compiled_pattern_set() {
+        let first = Lexer::new(String::new());
+        let second = Lexer::new(String::new());
+        assert!(Arc::ptr_eq(&first.patterns, &second.patterns));
+    }
+    
+    #[test]
+    fn write_json_matches_to_json() {
+        let lexer = lexed("int main() {\n    int x = 1;\n}\n");
+        let mut pretty = Vec::new();
+        lexer.write_json(&mut pretty, true, &DiagnosticFilter::default(), PositionBase::One, false).unwrap();
+        assert_eq!(String::from_utf8(pretty).unwrap(), lexer.to_json());
         
-        self.patterns.push((
-            TokenType::StringLiteral,
-            Regex::new(r#""([^"\\]|\\.)*""#).unwrap()
-        ));
+        let mut compact = Vec::new();
+        lexer.write_json(&mut compact, false, &DiagnosticFilter::default(), PositionBase::One, false).unwrap();
+        assert!(!compact.contains(&b'\n'));
+        let compact: serde_json::Value = serde_json::from_slice(&compact).unwrap();
+        let pretty: serde_json::Value = serde_json::from_str(&lexer.to_json()).unwrap();
+        assert_eq!(compact, pretty);
+    }
+    
+    fn traced(source: &str) -> Vec<TraceEvent> {
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut lexer = Lexer::new(source.to_string());
+        let sink = events.clone();
+        lexer.set_trace(move |event| sink.borrow_mut().push(event.clone()));
+        lexer.tokenize().unwrap();
+        let events = events.borrow().clone();
+        events
+    }
+    
+    #[test]
+    fn trace_tells_the_pattern_order_story() {
+        let events = traced("1.5 12 whilex while // c\n");
+        assert_eq!(events.len(), 5);
+        let float = TokenType::Literal(LiteralKind::Float);
+        // Float comes before Integer in the table, so "1.5" is one token...
+        assert_eq!(events[0].matched, Some(float.clone()));
+        assert_eq!(events[0].lexeme, "1.5");
+        assert!(!events[0].attempted.contains(&TokenType::Literal(LiteralKind::Integer)));
+        // ...and "12" is an integer only after the float pattern failed
+        assert_eq!(events[1].attempted.last(), Some(&float));
+        assert_eq!(events[1].matched, Some(TokenType::Literal(LiteralKind::Integer)));
+        // A keyword's pattern needs a word boundary, so "whilex" falls
+        // through every keyword to Identifier; "while" stops at its own
+        let while_type = TokenType::Keyword(Keyword::While);
+        assert!(events[2].attempted.contains(&while_type));
+        assert_eq!(events[2].token_type, Some(TokenType::Identifier));
+        assert_eq!(events[3].matched, Some(while_type.clone()));
+        assert!(!events[3].attempted.contains(&while_type));
+        assert_eq!((events[3].line, events[3].column, events[3].position), (1, 15, 14));
+        // A dropped comment is traced but makes no token
+        assert_eq!(events[4].matched, Some(TokenType::Comment));
+        assert_eq!(events[4].token_type, None);
+        assert!(events[4].to_string().ends_with("matched Comment \"// c\", skipped"));
+    }
+    
+    #[test]
+    fn tracing_does_not_change_the_tokens() {
+        let source = "int main() { float f = 2.5; if (f >= 1) { return 0; } }\n";
+        let mut traced_lexer = Lexer::new(source.to_string());
+        traced_lexer.set_trace(|_| {});
+        traced_lexer.tokenize().unwrap();
+        assert_eq!(traced_lexer.get_tokens(), lexed(source).get_tokens());
+    }
+    
+    #[test]
+    #[allow(deprecated)]
+    fn every_flat_variant_maps_to_the_new_shape_and_back() {
+        let mut flats = std::collections::HashSet::new();
+        for token_type in TokenType::all() {
+            let flat = FlatTokenType::from(token_type.clone());
+            assert_eq!(TokenType::from(flat), *token_type);
+            // The flat variant's name is the one serialized and displayed
+            assert_eq!(format!("{:?}", flat), token_type.name());
+            assert_eq!(token_type.to_string(), token_type.name());
+            assert_eq!(TokenType::from_name(token_type.name()).as_ref(), Some(token_type));
+            assert!(flats.insert(flat));
+        }
+        // FlatTokenType::EOF is its last variant
+        assert_eq!(flats.len(), FlatTokenType::EOF as usize + 1);
+    }
+    
+    #[test]
+    fn token_types_serialize_with_their_flat_names() {
+        for token_type in TokenType::all() {
+            let json = serde_json::to_string(token_type).unwrap();
+            assert_eq!(json, format!("\"{}\"", token_type.name()));
+            assert_eq!(serde_json::from_str::<TokenType>(&json).unwrap(), *token_type);
+        }
+        assert!(serde_json::from_str::<TokenType>("\"Plus2\"").is_err());
+    }
+    
+    #[test]
+    fn sub_enums_convert_in_both_directions() {
+        for token_type in TokenType::all() {
+            let categories = [
+                Keyword::try_from(token_type.clone()).map(TokenType::from).ok(),
+                Operator::try_from(token_type.clone()).map(TokenType::from).ok(),
+                Delimiter::try_from(token_type.clone()).map(TokenType::from).ok(),
+                LiteralKind::try_from(token_type.clone()).map(TokenType::from).ok(),
+            ];
+            let converted: Vec<&TokenType> = categories.iter().flatten().collect();
+            let grouped = token_type.is_keyword() || token_type.is_operator() || token_type.is_delimiter() || token_type.is_literal();
+            assert_eq!(converted.len(), usize::from(grouped), "{}", token_type);
+            if grouped {
+                assert_eq!(converted[0], token_type);
+            }
+        }
+        assert_eq!(TokenType::from(Operator::Plus).category(), "operator");
+        assert_eq!(Keyword::try_from(TokenType::Identifier), Err(TokenType::Identifier));
+    }
+    
+    fn advance(lexer: &mut Lexer, count: usize) {
+        for _ in 0..count {
+            lexer.next_token().unwrap().unwrap();
+        }
+    }
+    
+    // Everything a rollback must restore, compared as JSON so scopes count too.
+    fn snapshot(lexer: &Lexer) -> (Vec<Token>, serde_json::Value, usize, usize) {
+        let table = serde_json::to_value(lexer.get_symbol_table()).unwrap();
+        (lexer.get_tokens().clone(), table, lexer.current_line(), lexer.current_column())
+    }
+    
+    #[test]
+    fn rollback_restores_the_checkpointed_state() {
+        let source = "int f(int n) {\n    int a = n;\n    { int b = a; }\n    return a;\n}\nint g;\n";
+        let mut lexer = Lexer::new(source.to_string());
+        advance(&mut lexer, 6);
+        let before = snapshot(&lexer);
+        let checkpoint = lexer.checkpoint();
+        advance(&mut lexer, 5);
+        assert_ne!(snapshot(&lexer), before);
+        lexer.rollback(checkpoint);
+        assert_eq!(snapshot(&lexer), before);
+        lexer.tokenize().unwrap();
+        assert_eq!(lexer.get_tokens(), lexed(source).get_tokens());
+        assert_eq!(lexer.get_symbol_table().get_symbols(), lexed(source).get_symbol_table().get_symbols());
+    }
+    
+    #[test]
+    fn nested_checkpoints_and_scope_changes_roll_back() {
+        let source = "int f() {\n    int a;\n    { int b; }\n    int c;\n}\n";
+        let mut lexer = Lexer::new(source.to_string());
+        advance(&mut lexer, 8);
+        assert_eq!(lexer.get_symbol_table().get_scope(), "f");
+        let outer = lexer.checkpoint();
+        let outer_state = snapshot(&lexer);
+        // Into the inner block and out again
+        advance(&mut lexer, 2);
+        let inner = lexer.checkpoint();
+        let inner_state = snapshot(&lexer);
+        assert_ne!(lexer.get_symbol_table().get_scope(), "f");
+        advance(&mut lexer, 4);
+        assert_eq!(lexer.get_symbol_table().get_scope(), "f");
+        lexer.rollback(inner);
+        assert_eq!(snapshot(&lexer), inner_state);
+        advance(&mut lexer, 7);
+        lexer.rollback(outer);
+        assert_eq!(snapshot(&lexer), outer_state);
+        assert_eq!(lexer.get_symbol_table().get_scope(), "f");
+        lexer.tokenize().unwrap();
+        assert_eq!(serde_json::to_value(lexer.get_symbol_table()).unwrap(), serde_json::to_value(lexed(source).get_symbol_table()).unwrap());
+    }
+    
+    #[test]
+    fn rollback_works_at_every_cut_point() {
+        let source = "typedef int T;\nusing U = float;\nauto z = 1;\nint f(int n, ...) {\n    for (int i = 0; i < n; i++) { x: goto x; }\n    return g(n);\n}\n";
+        let total = lexed(source).get_tokens().len();
+        for cut in 0..total {
+            let mut lexer = Lexer::new(source.to_string());
+            advance(&mut lexer, cut);
+            let before = snapshot(&lexer);
+            let checkpoint = lexer.checkpoint();
+            advance(&mut lexer, 5.min(total - cut));
+            lexer.rollback(checkpoint);
+            assert_eq!(snapshot(&lexer), before, "cut after {} tokens", cut);
+        }
+    }
+    
+    fn unicode() -> LexerOptions {
+        LexerOptions {
+            unicode_identifiers: true,
+            ..LexerOptions::default()
+        }
+    }
+    
+    #[test]
+    fn unicode_identifiers_are_opt_in() {
+        assert_eq!(Lexer::new("int α = 1;\n".to_string()).tokenize().unwrap_err().code, "E0001");
+        let lexer = lexed_with("int α = 1; α = α + 1;\n", unicode());
+        assert!(lexer.get_diagnostics().is_empty());
+        let columns: Vec<(&str, usize)> = lexer.get_tokens().iter().map(|t| (t.lexeme.as_str(), t.column)).collect();
+        assert_eq!(&columns[..6], [("int", 1), ("α", 5), ("=", 7), ("1", 9), (";", 10), ("α", 12)]);
+        assert_eq!(lexer.get_symbol_table().get_symbols()[0].name, "α");
+    }
+    
+    #[test]
+    fn homoglyphs_are_warned_about() {
+        // Cyrillic а next to Latin a: two different names that look the same
+        let lexer = lexed_with("int \u{430} = 1; int a = 2;\n", unicode());
+        assert_eq!(codes(&lexer), ["W0001"]);
+        let warning = &lexer.get_diagnostics()[0];
+        assert!(warning.message.contains("'\u{430}' (U+0430, cyrillic small letter a) looks like 'a'"));
+        assert_eq!((warning.span.line, warning.span.column), (1, 5));
+        let names: Vec<&str> = lexer.get_symbol_table().get_symbols().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["\u{430}", "a"]);
+    }
+    
+    #[test]
+    fn mixed_script_names_are_one_identifier() {
+        let lexer = lexed_with("int p\u{430}y_总 = 1;\n", unicode());
+        assert_eq!(lexer.get_tokens()[1].lexeme, "p\u{430}y_总");
+        assert_eq!(lexer.get_tokens()[2].column, 11);
+        assert_eq!(codes(&lexer), ["W0001"]);
+    }
+    
+    #[test]
+    fn nfc_equivalent_spellings_are_one_symbol() {
+        // Precomposed é declared, e + combining acute used
+        let lexer = lexed_with("int caf\u{e9} = 1; cafe\u{301} = 2;\n", unicode());
+        let table = lexer.get_symbol_table();
+        assert_eq!(table.get_symbols()[1].name, "caf\u{e9}");
+        assert_eq!(table.lookup("caf\u{e9}", 1), Some(&table.get_symbols()[0]));
+        assert!(!table.is_unused(&table.get_symbols()[0]));
+        // The token keeps the spelling as written
+        assert_eq!(lexer.get_tokens()[5].lexeme, "cafe\u{301}");
+    }
+    
+    #[test]
+    fn tokens_are_grouped_by_their_start_line() {
+        let source = "string s = \"end\";\n/* spans\n   three\n   more\n   lines */ int x;\ny = 1;\n";
+        let lexer = lexed_with(source, LexerOptions {
+            keep_comments: true,
+            ..LexerOptions::default()
+        });
+        let lines = lexer.tokens_by_line();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0].text, "string s = \"end\";");
+        let string = &lines[0].tokens[3];
+        assert_eq!((string.lexeme.as_str(), string.column, string.end_column), ("\"end\"", 12, 16));
+        // The comment starts on line 2 and carries its end
+        let comment = &lines[1].tokens[0];
+        assert_eq!(comment.token_type, TokenType::Comment);
+        assert_eq!((comment.line, comment.end_line, comment.end_column), (2, 5, 11));
+        assert!(lines[2].tokens.is_empty() && lines[3].tokens.is_empty());
+        assert_eq!(lines[2].text, "   three");
+        // Line 5's own tokens come after the comment
+        assert_eq!(lines[4].tokens.len(), 3);
+        assert_eq!(lines[4].first_token, 6);
+        assert_eq!(lines[6].tokens[0].token_type, TokenType::EOF);
+        let total: usize = lines.iter().map(|l| l.tokens.len()).sum();
+        assert_eq!(total, lexer.get_tokens().len());
+        for line in &lines {
+            assert_eq!(line.tokens, &lexer.get_tokens()[line.first_token..line.first_token + line.tokens.len()]);
+        }
+        
+        assert_eq!(lexer.tokens_in_range(2, 4).len(), 1);
+        assert_eq!(lexer.tokens_in_range(5, 6).len(), 7);
+        assert!(lexer.tokens_in_range(3, 4).is_empty());
+        assert!(lexer.tokens_in_range(6, 2).is_empty());
+    }
+    
+    fn under(profile: LexerProfile, source: &str) -> (Option<String>, Vec<String>, usize) {
+        let lexer = lexed_with(source, LexerOptions::for_profile(profile));
+        let first_error = lexer.get_diagnostics().iter().find(|d| d.severity == Severity::Error).map(|d| d.code.clone());
+        (first_error, codes(&lexer).iter().map(|c| c.to_string()).collect(), lexer.get_tokens().len())
+    }
+    
+    #[test]
+    fn profiles_decide_unknown_escapes_and_literal_ranges() {
+        let source = "string s = \"\\q\";\n";
+        assert_eq!(under(LexerProfile::Strict, source).0.as_deref(), Some("E0003"));
+        assert_eq!(under(LexerProfile::Standard, source), (None, vec!["W0002".to_string()], 6));
+        let source = "int n = 99999999999999999999;\n";
+        assert_eq!(under(LexerProfile::Strict, source).0.as_deref(), Some("E0005"));
+        assert_eq!(under(LexerProfile::Standard, source).1, ["W0003"]);
+    }
+    
+    #[test]
+    fn profiles_decide_unicode_identifiers_and_no_break_spaces() {
+        for source in ["int é = 1;\n", "int\u{a0}x;\n"] {
+            assert!(under(LexerProfile::Standard, source).0.is_some());
+            assert_eq!(under(LexerProfile::Permissive, source).1, Vec::<String>::new());
+        }
+    }
+    
+    #[test]
+    fn profiles_decide_error_recovery() {
+        // Standard stops at the first error; permissive carries on to EOF
+        let source = "int a = 1 @ 2; string s = \"open\nint y;\n";
+        let (_, standard, standard_tokens) = under(LexerProfile::Standard, source);
+        let (_, permissive, permissive_tokens) = under(LexerProfile::Permissive, source);
+        assert_eq!(standard, ["E0001"]);
+        assert_eq!(permissive, ["E0001", "E0004"]);
+        assert_eq!(standard_tokens, 4);
+        // The @ is kept as an Error token and the string ends at its newline
+        assert_eq!(permissive_tokens, 15);
+        assert!(LexerProfile::from_name("lenient").is_none());
+        for name in PROFILE_NAMES {
+            assert_eq!(LexerProfile::from_name(name).unwrap().name(), *name);
+        }
+    }
+    
+    fn recovering() -> LexerOptions {
+        LexerOptions {
+            error_recovery: true,
+            ..LexerOptions::default()
+        }
+    }
+    
+    fn lexemes(lexer: &Lexer) -> Vec<(String, usize, usize)> {
+        lexer.get_tokens().iter().map(|t| (t.lexeme.to_string(), t.line, t.column)).collect()
+    }
+    
+    #[test]
+    fn unterminated_string_ends_at_its_newline() {
+        let lexer = lexed_with("string s = \"open\nint y = 2;\n", recovering());
+        let error = &lexer.get_diagnostics()[0];
+        assert_eq!((error.code.as_str(), error.span.line, error.span.column), ("E0004", 1, 12));
+        let string = &lexer.get_tokens()[3];
+        assert_eq!(string.token_type, TokenType::Literal(LiteralKind::String));
+        assert_eq!(string.lexeme, "\"open");
+        // Every later line lexes as if the quote had been there
+        let expected: Vec<_> = lexemes(&lexed("int y = 2;\n")).into_iter().map(|(l, line, c)| (l, line + 1, c)).collect();
+        assert_eq!(lexemes(&lexer)[4..], expected[..]);
+        assert_eq!(codes(&lexer), ["E0004"]);
+    }
+    
+    #[test]
+    fn missing_quote_mid_file_spoils_only_its_line() {
+        let tail = "int a = 1;\nwhile (a < 10) { a = a + 1; }\nreturn a;\n";
+        let lexer = lexed_with(&format!("int x = 0;\nstring s = \"oops;\n{}", tail), recovering());
+        assert_eq!(codes(&lexer), ["E0004"]);
+        let after: Vec<_> = lexemes(&lexer).into_iter().filter(|(_, line, _)| *line > 2).collect();
+        let expected: Vec<_> = lexemes(&lexed(tail)).into_iter().map(|(l, line, c)| (l, line + 2, c)).collect();
+        assert_eq!(after, expected);
+        // A continued line belongs to the string
+        let lexer = lexed_with("string s = \"a\\\nb\nint z;\n", recovering());
+        assert_eq!(lexer.get_tokens()[3].lexeme, "\"a\\\nb");
+        assert_eq!(lexemes(&lexer)[4], ("int".to_string(), 3, 1));
+    }
+    
+    #[test]
+    fn unterminated_string_stops_without_recovery() {
+        let mut lexer = Lexer::new("string s = \"open\nint y;\n".to_string());
+        let error = lexer.tokenize().unwrap_err();
+        assert_eq!((error.code.as_str(), error.line, error.column), ("E0004", 1, 12));
+        // The partial string is the last token; nothing after it is lexed
+        assert_eq!(lexer.get_tokens().len(), 4);
+        assert_eq!(lexer.get_tokens()[3].lexeme, "\"open");
+    }
+    
+    fn literal_check(width: IntegerWidth, strict: bool, literal: &str) -> (Vec<String>, bool) {
+        let options = LexerOptions {
+            integer_width: width,
+            strict_literals: strict,
+            ..LexerOptions::default()
+        };
+        let mut lexer = Lexer::new(format!("x = {};\n", literal));
+        lexer.set_options(options);
+        let failed = lexer.tokenize().is_err();
+        (lexer.get_diagnostics().iter().map(|d| format!("{}: {}", d.code, d.message)).collect(), failed)
+    }
+    
+    #[test]
+    fn integer_literals_are_checked_at_the_width_boundary() {
+        assert_eq!(literal_check(IntegerWidth::I64, true, &i64::MAX.to_string()), (vec![], false));
+        let past = (i64::MAX as u64 + 1).to_string();
+        let message = format!("Integer literal {} does not fit in a 64-bit integer (maximum {})", past, i64::MAX);
+        assert_eq!(literal_check(IntegerWidth::I64, false, &past), (vec![format!("W0003: {}", message)], false));
+        assert_eq!(literal_check(IntegerWidth::I64, true, &past), (vec![format!("E0005: {}", message)], true));
+        
+        assert_eq!(literal_check(IntegerWidth::I32, true, &i32::MAX.to_string()), (vec![], false));
+        let past = (i32::MAX as i64 + 1).to_string();
+        let (diagnostics, _) = literal_check(IntegerWidth::I32, false, &past);
+        assert_eq!(diagnostics, [format!("W0003: Integer literal {} does not fit in a 32-bit integer (maximum {})", past, i32::MAX)]);
+        assert_eq!(literal_check(IntegerWidth::I32, false, "99999999999999999999").0.len(), 1);
+    }
+    
+    #[test]
+    fn float_literals_that_leave_the_f64_range_are_reported() {
+        assert_eq!(literal_check(IntegerWidth::I64, true, "1.5e308"), (vec![], false));
+        assert_eq!(literal_check(IntegerWidth::I64, true, "0.1234567890123456789"), (vec![], false));
+        assert_eq!(
+            literal_check(IntegerWidth::I64, false, "1.0e309"),
+            (vec!["W0004: Float literal 1.0e309 overflows to infinity as a 64-bit float".to_string()], false)
+        );
+        assert!(literal_check(IntegerWidth::I64, true, "9.9e999").1);
+        assert_eq!(
+            literal_check(IntegerWidth::I64, true, "1.0e-400").0,
+            ["E0006: Float literal 1.0e-400 underflows to zero as a 64-bit float"]
+        );
+        // Zero spelled with an exponent is exact
+        assert_eq!(literal_check(IntegerWidth::I64, true, "0.0e-400"), (vec![], false));
+    }
+    
+    fn spans(source: &str, keep_comments: bool) -> Vec<(String, usize, usize, usize, usize)> {
+        let options = LexerOptions {
+            keep_comments,
+            ..LexerOptions::default()
+        };
+        let lexer = lexed_with(source, options);
+        lexer.get_tokens().iter().map(|t| (t.lexeme.to_string(), t.line, t.column, t.end_line, t.end_column)).collect()
+    }
+    
+    #[test]
+    fn first_token_after_a_block_comment_has_its_real_position() {
+        // Ending mid-line, at the end of a line, and with no space before the token
+        assert_eq!(spans("/* a\n  b */ x = 1;\n", false)[0], ("x".to_string(), 2, 8, 2, 8));
+        assert_eq!(spans("/* a\n b */\ny;\n", false)[0], ("y".to_string(), 3, 1, 3, 1));
+        assert_eq!(spans("/* a\n*/z;\n", false)[0], ("z".to_string(), 2, 3, 2, 3));
+        assert_eq!(spans("/**/w;", false)[0], ("w".to_string(), 1, 5, 1, 5));
+        assert_eq!(spans("a /* x */ b\n", false)[1], ("b".to_string(), 1, 11, 1, 11));
+    }
+    
+    #[test]
+    fn multi_line_tokens_record_where_they_end() {
+        let kept = spans("/* a\n  b */ x = 1;\n", true);
+        assert_eq!(kept[0], ("/* a\n  b */".to_string(), 1, 1, 2, 6));
+        assert_eq!(kept[1].1..=kept[1].2, 2..=8);
+        assert_eq!(spans("/* a\n*/z;\n", true)[0], ("/* a\n*/".to_string(), 1, 1, 2, 2));
+        assert_eq!(spans("s = \"a\\\nbc\";\n", false)[2], ("\"a\\\nbc\"".to_string(), 1, 5, 2, 3));
+        // Single-line tokens end at their last character; EOF is empty
+        assert_eq!(spans("count == 12;", false), [
+            ("count".to_string(), 1, 1, 1, 5),
+            ("==".to_string(), 1, 7, 1, 8),
+            ("12".to_string(), 1, 10, 1, 11),
+            (";".to_string(), 1, 12, 1, 12),
+            ("EOF".to_string(), 1, 13, 1, 13),
+        ]);
+    }
+    
+    #[test]
+    fn keyword_after_a_type_is_a_misused_identifier() {
+        for (spelling, _) in KEYWORDS.iter().filter(|(s, _)| !s.starts_with('#')) {
+            let mut lexer = Lexer::new(format!("int {} = 3;\n", spelling));
+            let error = lexer.tokenize().unwrap_err();
+            assert_eq!((error.code.as_str(), error.line, error.column), ("E0007", 1, 5), "int {}", spelling);
+            assert_eq!(error.message, format!("'{}' is a reserved keyword and cannot be used as an identifier", spelling));
+        }
+        let lexer = lexed_with("float return;\n", recovering());
+        assert_eq!(codes(&lexer), ["E0007"]);
+        assert_eq!(lexer.get_diagnostics()[0].span.length, 6);
+        assert!(lexer.get_symbol_table().get_symbols().is_empty());
+    }
+    
+    #[test]
+    fn keywords_in_their_own_places_are_fine() {
+        let lexer = lexed("int x = 1;\nif (x) { return x; } else { while (x) { x = x - 1; } }\n");
+        assert!(lexer.get_diagnostics().is_empty());
+        assert!(lexed("bool b = true;\nreturn b;\n").get_diagnostics().is_empty());
+    }
+    
+    #[test]
+    fn symbol_tables_sort_and_filter() {
+        let lexer = lexed("int zeta = 1;\nint add(int a) { int unused = 2; return a + zeta; }\n");
+        let table = lexer.get_symbol_table();
+        let shown = |options: &SymbolTablePrintOptions| -> Vec<String> {
+            let mut symbols = table.filtered(|symbol| options.shows(table, symbol));
+            if let Some(sort) = options.sort {
+                symbols.sort_by(|a, b| sort.compare(a, b));
+            }
+            symbols.iter().map(|s| format!("{}@{}", s.name, s.scope)).collect()
+        };
+        let mut options = SymbolTablePrintOptions {
+            sort: Some(SymbolSort::Name),
+            ..SymbolTablePrintOptions::default()
+        };
+        assert_eq!(shown(&options), ["a@add", "a@add", "add@global", "unused@add", "zeta@global", "zeta@add"]);
+        options.add_filter("type=function").unwrap();
+        assert_eq!(shown(&options), ["add@global"]);
+        let mut options = SymbolTablePrintOptions::default();
+        options.add_filter("unused").unwrap();
+        options.add_filter("scope=add").unwrap();
+        assert_eq!(shown(&options), ["unused@add"]);
+        assert!(options.add_filter("kind=x").unwrap_err().contains("Unknown symbol filter 'kind=x'"));
+        assert_eq!(table.filtered(|s| s.symbol_type == "function").len(), 1);
+        for name in SYMBOL_SORT_NAMES {
+            assert!(SymbolSort::from_name(name).is_some());
+        }
+    }
+    
+    fn declarations(lexer: &Lexer) -> Vec<(String, String)> {
+        lexer
+            .get_symbol_table()
+            .get_symbols()
+            .iter()
+            .filter(|s| s.data_type != "unknown")
+            .map(|s| (s.name.clone(), s.data_type.clone()))
+            .collect()
+    }
+    
+    #[test]
+    fn pointer_and_reference_declarators_join_the_type() {
+        let lexer = lexed("int* p;\nint &r = p;\nchar** argv;\nint *q = &r;\nint* arr[10];\n");
+        let expected = [("p", "int*"), ("r", "int&"), ("argv", "char**"), ("q", "int*"), ("arr", "int*")];
+        assert_eq!(declarations(&lexer), expected.map(|(n, t)| (n.to_string(), t.to_string())));
+        let json = serde_json::to_value(lexer.get_symbol_table()).unwrap();
+        let argv = json["symbols"].as_array().unwrap().iter().find(|s| s["name"] == "argv").unwrap();
+        assert_eq!(argv["data_type"], "char**");
+    }
+    
+    #[test]
+    fn multiplication_and_bitwise_and_declare_nothing() {
+        let lexer = lexed("int a; int b;\na * b;\nx = a & b;\n");
+        assert_eq!(declarations(&lexer), [("a".to_string(), "int".to_string()), ("b".to_string(), "int".to_string())]);
+        let line_two: Vec<&str> = lexer.get_symbol_table().get_symbols().iter().filter(|s| s.line == 2).map(|s| s.name.as_str()).collect();
+        assert_eq!(line_two, ["a", "b"]);
+        assert_eq!(lexer.get_tokens()[7].token_type, TokenType::Operator(Operator::Multiply));
+    }
+    
+    fn outline(node: &ScopeNode) -> String {
+        let symbols: Vec<&str> = node.symbols.iter().map(|s| s.name.as_str()).collect();
+        let children: Vec<String> = node.children.iter().map(outline).collect();
+        format!("{}{}[{}]{{{}}}", node.name, node.span(), symbols.join(" "), children.join(", "))
+    }
+    
+    #[test]
+    fn scope_tree_follows_nested_blocks() {
+        let source = "int g;\nint f(int p) {\n    int a;\n    {\n        int b;\n        {\n            int c;\n        }\n    }\n    { int d; }\n}\nint h() { return 0; }\n";
+        let lexer = lexed(source);
+        let tree = lexer.get_symbol_table().scope_tree();
+        assert_eq!(
+            outline(&tree),
+            "global[g f h]{\
+             f (lines 2-11)[p a]{block@4 (lines 4-9)[b]{block@6 (lines 6-8)[c]{}}, block@10 (lines 10-10)[d]{}}, \
+             h (lines 12-12)[]{}}"
+        );
+        let depths: Vec<(usize, &str)> = tree.flatten(0).into_iter().map(|(depth, s)| (depth, s.name.as_str())).collect();
+        assert_eq!(depths, [(0, "global"), (1, "f"), (2, "block@4"), (3, "block@6"), (2, "block@10"), (1, "h")]);
+        // The JSON keeps the flat list next to the tree
+        let mut buffer = Vec::new();
+        lexer.write_json(&mut buffer, false, &DiagnosticFilter::default(), PositionBase::One, false).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(json["symbols"].as_array().unwrap().len(), lexer.get_symbol_table().get_symbols().len());
+        assert_eq!(json["scope_tree"]["children"][0]["children"][0]["children"][0]["symbols"][0]["name"], "c");
+        assert_eq!(json["scope_tree"]["children"][0]["open_line"], 2);
+    }
+    
+    #[test]
+    fn unclosed_scopes_have_no_end() {
+        let tree = lexed_with("int f() {\n  {\n", recovering()).get_symbol_table().scope_tree();
+        assert_eq!(outline(&tree), "global[f]{f (line 1, not closed)[]{block@2 (line 2, not closed)[]{}}}");
+    }
+    
+    fn warnings(lexer: &Lexer, code: &str) -> Vec<(String, usize, usize)> {
+        lexer
+            .get_diagnostics()
+            .iter()
+            .filter(|d| d.code == code)
+            .map(|d| (d.message.clone(), d.span.line, d.span.column))
+            .collect()
+    }
+    
+    #[test]
+    fn initialization_heuristic_cases() {
+        // Initialized, a parameter, and assigned before use
+        let clean = "int f(int p) {\n    int a = 1;\n    int b;\n    b = a + p;\n    return b;\n}\n";
+        assert!(warnings(&lexed(clean), "W0006").is_empty());
+        let lexer = lexed("int x;\nint y = 0;\ny = x + 1;\n");
+        assert_eq!(
+            warnings(&lexer, "W0006"),
+            [("variable 'x' may be used before being assigned (declared line 1, used line 3)".to_string(), 3, 5)]
+        );
+    }
+    
+    #[test]
+    fn initialization_follows_the_declaration_a_use_refers_to() {
+        // The inner q is a new declaration, not a use of the outer one
+        assert!(warnings(&lexed("{ int q; { int q; } }\n"), "W0006").is_empty());
+        let lexer = lexed("{ int q; { int q = 1; q = q + 1; } q = 2; }\n");
+        assert!(warnings(&lexer, "W0006").is_empty());
+        let lexer = lexed("{ int q; { int q = 1; q = q + 1; } print(q); }\n");
+        assert_eq!(warnings(&lexer, "W0006").len(), 1);
+        assert_eq!(warnings(&lexer, "W0006")[0].2, 42);
+        // A use in a sibling block cannot see the declaration
+        assert!(warnings(&lexed("{ { int r; } { r = 1; } }\n{ int s; }\n{ print(s); }\n"), "W0006").is_empty());
+    }
+    
+    #[test]
+    fn shadowing_is_reported_at_the_inner_declaration() {
+        let lexer = lexed("{ int i = 1; { int i = 2; } }\n");
+        assert_eq!(
+            warnings(&lexer, "W0007"),
+            [("'i' shadows a variable declared at line 1 (scope block@1)".to_string(), 1, 20)]
+        );
+        let source = "int f(int n) {\n    int i = n;\n    {\n        int i = 2;\n        {\n            int i = 3; int n = i;\n        }\n    }\n    return i;\n}\n";
+        let found: Vec<(String, usize, usize)> = warnings(&lexed(source), "W0007");
+        assert_eq!(
+            found,
+            [
+                ("'i' shadows a variable declared at line 2 (scope f)".to_string(), 4, 13),
+                ("'i' shadows a variable declared at line 4 (scope block@3)".to_string(), 6, 17),
+                ("'n' shadows a parameter declared at line 1 (scope f)".to_string(), 6, 28),
+            ]
+        );
+        let lexer = lexed("int g(int k) { int k = 0; return k; }\n");
+        assert_eq!(warnings(&lexer, "W0007"), [("'k' shadows a parameter declared at line 1 (scope g)".to_string(), 1, 20)]);
+    }
+    
+    #[test]
+    fn sibling_scopes_do_not_shadow() {
+        let source = "int f() {\n    { int i = 1; }\n    { int i = 2; { int j = i; } }\n    return 0;\n}\nint g() { int i = 3; return i; }\n";
+        assert!(warnings(&lexed(source), "W0007").is_empty());
+    }
+    
+    fn docs(source: &str) -> Vec<(String, Option<String>)> {
+        lexed(source).get_symbol_table().get_symbols().iter().map(|s| (s.name.clone(), s.doc.clone())).collect()
+    }
+    
+    #[test]
+    fn doc_comments_attach_to_the_next_declaration() {
+        let found = docs("/// adds two numbers\nint add(int a, int b);\n");
+        assert_eq!(found[0], ("add".to_string(), Some("adds two numbers".to_string())));
+        assert!(found[1..].iter().all(|(_, doc)| doc.is_none()));
+        assert_eq!(docs("/** Multi\n * line\n * doc */\nint f();\n")[0].1.as_deref(), Some("Multi\nline\ndoc"));
+        assert_eq!(docs("/// one\n/// two\nint g;\n")[0].1.as_deref(), Some("one\ntwo"));
+        // The comments are not kept as tokens
+        assert_eq!(lexed("/// one\nint g;\n").get_tokens()[0].token_type, TokenType::Keyword(Keyword::Int));
+    }
+    
+    #[test]
+    fn doc_comments_need_to_touch_a_declaration() {
+        assert_eq!(docs("/// lost\n\nint h;\n")[0].1, None);
+        assert_eq!(docs("// plain\nint k;\n")[0].1, None);
+        assert_eq!(docs("/* plain */\nint m;\n")[0].1, None);
+        // A statement in between takes the comment with it
+        assert_eq!(docs("/// trailing\nx = 1;\nint n;\n"), [("x".to_string(), None), ("n".to_string(), None)]);
+        let json = serde_json::to_value(lexed("/// doc\nint d;\n").get_symbol_table()).unwrap();
+        assert_eq!(json["symbols"][0]["doc"], "doc");
+    }
+    
+    // Nothing in the tree expands macros yet, so these build the tokens an
+    // expanding tool would write: `MAX` used on lines 12, 20 and 31.
+    #[test]
+    fn expansion_provenance_survives_json_and_rebasing() {
+        let tokens: Vec<Token> = [12, 20, 31]
+            .into_iter()
+            .map(|line| {
+                let mut token = Token::new(TokenType::Literal(LiteralKind::Integer), "10", 1, 13);
+                token.expanded_from = Some(Span::new(line, 9, 3));
+                token
+            })
+            .collect();
+        let json = serde_json::to_value(&tokens).unwrap();
+        assert_eq!(json[1]["expanded_from"]["line"], 20);
+        assert_eq!(json[2]["expanded_from"]["column"], 9);
+        let read: Vec<Token> = serde_json::from_value(json).unwrap();
+        assert_eq!(read, tokens);
+        
+        let mut zero = read[0].clone();
+        zero.rebase(PositionBase::One, PositionBase::Zero);
+        let span = zero.expanded_from.as_ref().unwrap();
+        assert_eq!((span.line, span.column, span.end_column), (11, 8, 10));
+        // Unset, the field is left out
+        let plain = serde_json::to_value(Token::new(TokenType::Identifier, "x", 1, 1)).unwrap();
+        assert!(plain.get("expanded_from").is_none());
+    }
+    
+    #[test]
+    fn columns_after_an_emoji_follow_the_unit() {
+        let source = "/* 😀 */ x = \"😀\"; y\n";
+        for (unit, x, y, string_end) in [(ColumnUnit::Chars, 9, 18, 15), (ColumnUnit::Utf16, 10, 20, 17), (ColumnUnit::Bytes, 12, 24, 21)] {
+            let options = LexerOptions {
+                column_unit: unit,
+                ..LexerOptions::default()
+            };
+            let lexer = lexed_with(source, options);
+            let tokens = lexer.get_tokens();
+            assert_eq!((tokens[0].lexeme.as_str(), tokens[0].column), ("x", x), "{}", unit.name());
+            assert_eq!(tokens[2].end_column, string_end, "{}", unit.name());
+            assert_eq!((tokens[4].lexeme.as_str(), tokens[4].column), ("y", y), "{}", unit.name());
+            let mut buffer = Vec::new();
+            lexer.write_json(&mut buffer, false, &DiagnosticFilter::default(), PositionBase::One, false).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+            assert_eq!(json["column_unit"], unit.name());
+            // Diagnostics count in the same unit
+            let options = LexerOptions {
+                column_unit: unit,
+                ..LexerOptions::default()
+            };
+            assert_eq!(lexed_with("/* 😀 */ @\n", options).get_diagnostics()[0].span.column, x);
+        }
+    }
+    
+    fn assignment_warnings(source: &str) -> Vec<(String, usize)> {
+        warnings(&lexed(source), "W0008").into_iter().map(|(message, _, column)| (message, column)).collect()
+    }
+    
+    #[test]
+    fn assignment_in_if_and_while_conditions() {
+        assert_eq!(
+            assignment_warnings("if (x = 5) {}\n"),
+            [("Assignment in the condition of 'if'; did you mean '=='?".to_string(), 7)]
+        );
+        assert_eq!(
+            assignment_warnings("while (x = next()) {}\n"),
+            [("Assignment in the condition of 'while'; did you mean '=='?".to_string(), 10)]
+        );
+        assert!(assignment_warnings("if (x == 5) { y = 1; }\n").is_empty());
+    }
+    
+    #[test]
+    fn only_the_for_condition_clause_is_checked() {
+        assert!(assignment_warnings("for (i = 0; i < 3; i = i + 1) {}\n").is_empty());
+        assert!(assignment_warnings("for (int i = 0; i < 3; i = i + 1) {}\n").is_empty());
+        assert_eq!(assignment_warnings("for (i = 0; i = 3; i = i + 1) {}\n"), [("Assignment in the condition of 'for'; did you mean '=='?".to_string(), 15)]);
+        assert_eq!(assignment_warnings("for (; done = 1;) {}\n")[0].1, 13);
+    }
+    
+    #[test]
+    fn parenthesized_assignment_is_the_opt_out() {
+        assert!(assignment_warnings("if ((x = next()) != 0) {}\n").is_empty());
+        assert!(assignment_warnings("while ((x = 1)) {}\n").is_empty());
+        assert!(assignment_warnings("for (; (c = get()); ) {}\n").is_empty());
+    }
+    
+    #[test]
+    fn timed_tokenize_counts_what_it_lexed() {
+        let source = crate::generate::generate_program(7, 1000);
+        let mut lexer = Lexer::new(source.clone());
+        let (result, stats) = lexer.tokenize_timed();
+        result.unwrap();
+        assert_eq!(stats.tokens, lexer.get_tokens().len());
+        assert_eq!(stats.bytes, source.len());
+        assert!(stats.tokens_per_sec() > 0.0 && stats.bytes_per_sec() > stats.tokens_per_sec());
+        // Nothing left to lex
+        let (_, again) = lexer.tokenize_timed();
+        assert_eq!((again.tokens, again.bytes), (0, 0));
+    }
+    
+    #[test]
+    fn malformed_char_literals_have_their_own_diagnostics() {
+        let cases = [
+            ("char e = '';\n", "E0008", "Empty character literal", "''"),
+            ("char m = 'abc';\n", "E0009", "Multi-character literal 'abc'", "'abc'"),
+            ("char u = 'a\n", "E0010", "Unterminated character literal", "'a"),
+            ("char u = '\n", "E0010", "Unterminated character literal", "'"),
+        ];
+        for (line, code, message, lexeme) in cases {
+            let source = format!("{}int y = 2;\n", line);
+            let mut lexer = Lexer::new(source.clone());
+            let error = lexer.tokenize().unwrap_err();
+            assert_eq!((error.code.as_str(), error.message.as_str(), error.column), (code, message, 10));
+            assert_eq!(lexer.get_tokens().len(), 4, "{:?} stops at the literal", line);
+            
+            let lexer = lexed_with(&source, recovering());
+            assert_eq!(codes(&lexer), [code]);
+            assert_eq!(lexer.get_tokens()[3].lexeme, lexeme);
+            // The next line still lexes
+            let rest: Vec<(String, usize, usize)> = lexemes(&lexer).into_iter().filter(|(_, l, _)| *l == 2).collect();
+            let expected: Vec<(String, usize, usize)> = ["int", "y", "=", "2", ";"].iter().zip([1, 5, 7, 9, 10]).map(|(l, c)| (l.to_string(), 2, c)).collect();
+            assert_eq!(rest, expected);
+        }
+    }
+    
+    #[test]
+    fn escaped_quotes_make_one_character() {
+        for literal in ["'\\''", "'\\n'", "'\\\\'", "'\"'"] {
+            let lexer = lexed(&format!("char c = {};\n", literal));
+            assert!(lexer.get_diagnostics().is_empty(), "{}", literal);
+            assert_eq!(lexer.get_tokens()[3].lexeme, literal);
+        }
+    }
+    
+    fn concatenated(source: &str, keep_comments: bool) -> Vec<(String, Option<String>, usize, usize, usize, usize)> {
+        let options = LexerOptions {
+            concat_strings: true,
+            keep_comments,
+            ..LexerOptions::default()
+        };
+        lexed_with(source, options)
+            .get_tokens()
+            .iter()
+            .map(|t| (t.lexeme.to_string(), t.value.clone(), t.line, t.column, t.end_line, t.end_column))
+            .collect()
+    }
+    
+    #[test]
+    fn adjacent_strings_merge_on_one_line_and_across_lines() {
+        let merged = |lexeme: &str, value: &str, end: (usize, usize)| (lexeme.to_string(), Some(value.to_string()), 1, 5, end.0, end.1);
+        assert_eq!(concatenated("s = \"Hello, \" \"world\";\n", false)[2], merged("\"Hello, \" \"world\"", "Hello, world", (1, 21)));
+        assert_eq!(concatenated("s = \"a\"\n    \"b\";\n", false)[2], merged("\"a\" \"b\"", "ab", (2, 7)));
+        assert_eq!(concatenated("s = \"a\" \"b\" \"c\";\n", false)[2], merged("\"a\" \"b\" \"c\"", "abc", (1, 15)));
+        // Off by default, and never across other tokens
+        assert_eq!(lexed("s = \"a\" \"b\";\n").get_tokens().len(), 6);
+        assert_eq!(concatenated("s = \"a\"; t = \"b\";\n", false).len(), 9);
+    }
+    
+    #[test]
+    fn a_comment_between_pieces_is_kept() {
+        assert_eq!(concatenated("s = \"a\" /* c */ \"b\";\n", false).len(), 5);
+        let kept = concatenated("s = \"a\" /* c */ \"b\";\n", true);
+        assert_eq!(kept[2], ("\"a\" \"b\"".to_string(), Some("ab".to_string()), 1, 5, 1, 19));
+        assert_eq!((kept[3].0.as_str(), kept[3].3), ("/* c */", 9));
+        assert_eq!(kept[4].0, ";");
+    }
+    
+    fn literal(source: &str) -> (String, Option<String>, usize, usize, usize) {
+        let lexer = lexed(source);
+        let t = &lexer.get_tokens()[2];
+        assert_eq!(t.token_type, TokenType::Literal(LiteralKind::String));
+        (t.lexeme.to_string(), t.value.clone(), t.line, t.end_line, t.end_column)
+    }
+    
+    #[test]
+    fn raw_strings_keep_quotes_backslashes_and_newlines() {
+        assert_eq!(
+            literal("s = R\"(say \"hi\" \\n)\";\n"),
+            ("R\"(say \"hi\" \\n)\"".to_string(), Some("say \"hi\" \\n".to_string()), 1, 1, 20)
+        );
+        let (_, value, line, end_line, end_column) = literal("s = R\"(one\ntwo\n)\"; x;\n");
+        assert_eq!((value.as_deref(), line, end_line, end_column), (Some("one\ntwo\n"), 1, 3, 2));
+        let lexer = lexed("s = R\"(one\ntwo\n)\"; x;\n");
+        assert_eq!((lexer.get_tokens()[4].lexeme.as_str(), lexer.get_tokens()[4].line), ("x", 3));
+    }
+    
+    #[test]
+    fn raw_string_delimiters_are_not_fooled_by_parens() {
+        assert_eq!(literal("s = R\"xy(a)\"b)xy\";\n").1.as_deref(), Some("a)\"b"));
+        assert_eq!(literal("s = R\"x()\" )\")x\";\n").1.as_deref(), Some(")\" )\""));
+    }
+    
+    #[test]
+    fn unterminated_raw_string_names_its_opening_line() {
+        let mut lexer = Lexer::new("int a;\ns = R\"(open\nmore\n".to_string());
+        let error = lexer.tokenize().unwrap_err();
+        assert_eq!(error.code, "E0011");
+        assert_eq!(error.to_string(), "Lexical Error: Unterminated raw string literal; no )\" closes it at line 2, column 5");
+        assert_eq!((error.line, error.column), (2, 5));
+        // Only the matching delimiter closes it
+        let mut lexer = Lexer::new("s = R\"tag(open)\";\n".to_string());
+        assert_eq!(lexer.tokenize().unwrap_err().code, "E0011");
+    }
+    
+    #[test]
+    fn continued_strings_drop_the_backslash_newline_from_the_value() {
+        assert_eq!(
+            literal("s = \"two\\\nlines\";\n"),
+            ("\"two\\\nlines\"".to_string(), Some("twolines".to_string()), 1, 2, 6)
+        );
+        assert_eq!(literal("s = \"a\\\nb\\\nc\"; y;\n"), ("\"a\\\nb\\\nc\"".to_string(), Some("abc".to_string()), 1, 3, 2));
+        let lexer = lexed("s = \"a\\\nb\\\nc\"; y;\n");
+        assert_eq!((lexer.get_tokens()[4].lexeme.as_str(), lexer.get_tokens()[4].line, lexer.get_tokens()[4].column), ("y", 3, 5));
+        // A plain string has no separate value
+        assert_eq!(literal("s = \"plain\";\n").1, None);
+    }
+    
+    #[test]
+    fn backslash_at_end_of_file_leaves_the_string_open() {
+        let mut lexer = Lexer::new("s = \"end\\".to_string());
+        let error = lexer.tokenize().unwrap_err();
+        assert_eq!((error.code.as_str(), error.line, error.column), ("E0004", 1, 5));
+        assert_eq!(lexer.get_tokens()[2].lexeme, "\"end\\");
+        let mut lexer = Lexer::new("s = \"end\\\n".to_string());
+        assert_eq!(lexer.tokenize().unwrap_err().code, "E0004");
+        // Outside a string a backslash is still an invalid character
+        let mut lexer = Lexer::new("x = 1 \\\n+ 2;\n".to_string());
+        let error = lexer.tokenize().unwrap_err();
+        assert_eq!((error.code.as_str(), error.column), ("E0001", 7));
+    }
+    
+    fn symbol_names(lexer: &Lexer) -> Vec<(String, String)> {
+        lexer.get_symbol_table().get_symbols().iter().map(|s| (s.name.clone(), s.symbol_type.clone())).collect()
+    }
+    
+    #[test]
+    fn directive_lines_may_be_indented() {
+        let lexer = lexed("   #include <iostream>\nint x;\n");
+        let flags: Vec<(&str, bool)> = lexer.get_tokens().iter().map(|t| (t.lexeme.as_str(), t.directive)).collect();
+        assert_eq!(flags, [("#include", false), ("<", true), ("iostream", true), (">", true), ("int", false), ("x", false), (";", false), ("EOF", false)]);
+        assert_eq!(lexer.get_tokens()[0].column, 4);
+        // Directive arguments are not program symbols
+        assert_eq!(symbol_names(&lexer), [("x".to_string(), "variable".to_string())]);
+    }
+    
+    #[test]
+    fn hash_after_code_is_invalid_with_a_hint() {
+        let lexer = lexed_with("int a = 1; # define B 2\n", recovering());
+        let error = &lexer.get_diagnostics()[0];
+        assert_eq!(error.code, "E0001");
+        assert_eq!(error.message, "Invalid character '#'; directives must start at the beginning of a line");
+        assert_eq!(error.span.column, 12);
+        assert!(lexer.get_tokens().iter().all(|t| !t.directive));
+    }
+    
+    #[test]
+    fn code_after_a_directive_line_is_program_code() {
+        let lexer = lexed("#define MAX(a, b) a\nint y = MAX(1, 2);\n");
+        let directive: Vec<bool> = lexer.get_tokens().iter().map(|t| t.directive).collect();
+        assert!(directive[1..8].iter().all(|d| *d) && directive[8..].iter().all(|d| !d));
+        // The macro's name is declared, its parameters and body are not
+        let names = symbol_names(&lexer);
+        assert_eq!(names, [("MAX", "constant"), ("y", "variable"), ("MAX", "variable")].map(|(n, t)| (n.to_string(), t.to_string())));
+        let lexer = lexed("#pragma once\nx = 1;\n");
+        assert_eq!(symbol_names(&lexer), [("x".to_string(), "variable".to_string())]);
+    }
+    
+    #[test]
+    fn row_checks_line_up_after_directives() {
+        // Each check finds its tokens through the rows, which skip directives
+        let source = "#include <iostream>\n#define LIMIT 3\nint main() {\n    int i = 1;\n    { int i = 2; }\n    int n;\n    return n + LIMIT;\n}\n";
+        let lexer = lexed(source);
+        let shadowing = warnings(&lexer, "W0007");
+        assert_eq!((shadowing[0].1, shadowing[0].2), (5, 11));
+        assert_eq!(warnings(&lexer, "W0006"), [("variable 'n' may be used before being assigned (declared line 6, used line 7)".to_string(), 7, 12)]);
+    }
+    
+    const SUSPENDED: &str = "#define N 3\nint total(int n) {\n    /* a block comment\n       across the cut */\n    int s = 0; // running sum\n    string t = \"done\";\n    return s + n @ N;\n}\n";
+    
+    // Lexes `source` one token at a time, saving the state after `cut`
+    // tokens, sending it through JSON and finishing in a resumed lexer.
+    fn suspended_at(source: &str, options: &LexerOptions, cut: usize) -> Lexer {
+        let mut first = Lexer::new(source.to_string());
+        first.set_options(options.clone());
+        for _ in 0..cut {
+            let _ = first.next_token();
+        }
+        let json = serde_json::to_string(&first.save_state()).unwrap();
+        let state: LexerState = serde_json::from_str(&json).unwrap();
+        let mut resumed = Lexer::resume(source.to_string(), state).unwrap();
+        let _ = resumed.tokenize();
+        resumed
+    }
+    
+    #[test]
+    fn resumed_runs_match_straight_through() {
+        for keep_comments in [false, true] {
+            let options = LexerOptions {
+                keep_comments,
+                error_recovery: true,
+                ..LexerOptions::default()
+            };
+            let mut straight = Lexer::new(SUSPENDED.to_string());
+            straight.set_options(options.clone());
+            let _ = straight.tokenize();
+            let tokens = straight.get_tokens().len();
+            // Before anything, inside the directive, just before and after
+            // the block comment, before the error and at the very end
+            let comment = straight.get_tokens().iter().position(|t| t.line == 5 || t.token_type == TokenType::Comment).unwrap();
+            for cut in [0, 2, comment - 1, comment, comment + 1, tokens - 3, tokens] {
+                let resumed = suspended_at(SUSPENDED, &options, cut);
+                let context = format!("cut {} with keep_comments {}", cut, keep_comments);
+                assert_eq!(resumed.get_tokens(), straight.get_tokens(), "{}", context);
+                assert_eq!(resumed.get_diagnostics(), straight.get_diagnostics(), "{}", context);
+                assert_eq!(resumed.get_symbol_table().get_symbols(), straight.get_symbol_table().get_symbols(), "{}", context);
+            }
+        }
+    }
+    
+    #[test]
+    fn saved_state_resumes_mid_block_comment_line() {
+        let options = LexerOptions::default();
+        let mut first = Lexer::new(SUSPENDED.to_string());
+        // Stop on the `{` before the comment, then once more: the comment is
+        // skipped and the cut lands after it
+        while first.next_token().unwrap().unwrap().lexeme != "{" {}
+        let state = first.save_state();
+        assert_eq!(state.position(), SUSPENDED.find('{').unwrap() + 1);
+        assert_eq!(state.tokens().last().unwrap().line, 2);
+        let mut resumed = Lexer::resume(SUSPENDED.to_string(), state).unwrap();
+        let next = resumed.next_token().unwrap().unwrap();
+        assert_eq!((next.lexeme.as_str(), next.line, next.column), ("int", 5, 5));
+        let _ = resumed.tokenize();
+        let mut straight = Lexer::new(SUSPENDED.to_string());
+        straight.set_options(options);
+        let _ = straight.tokenize();
+        assert_eq!(resumed.get_tokens(), straight.get_tokens());
+    }
+    
+    #[test]
+    fn resuming_on_other_text_is_refused() {
+        let mut first = Lexer::new(SUSPENDED.to_string());
+        let _ = first.next_token();
+        let state = first.save_state();
+        let other = SUSPENDED.replace("total", "totals");
+        let expected = content_hash(&[SUSPENDED.as_bytes()]);
+        let found = content_hash(&[other.as_bytes()]);
+        match Lexer::resume(other, state) {
+            Err(error) => {
+                assert_eq!(error, ResumeError::SourceMismatch { expected, found });
+                assert_eq!(
+                    error.to_string(),
+                    format!("Source does not match the saved lexer state (hash {:016x}, expected {:016x})", found, expected)
+                );
+            }
+            Ok(_) => panic!("resumed on a different source"),
+        }
+    }
+    
+    fn labels(lexer: &Lexer) -> Vec<(String, String, usize)> {
+        let table = lexer.get_symbol_table();
+        table.get_symbols().iter().filter(|s| s.symbol_type == "label").map(|s| (s.name.clone(), s.scope.clone(), s.line)).collect()
+    }
+    
+    #[test]
+    fn labels_are_declared_and_goto_targets_checked() {
+        let lexer = lexed("int main() {\n    int i = 0;\nloop:\n    i = i + 1;\n    if (i < 3) goto loop;\n    goto missing;\n    return 0;\n}\n");
+        assert_eq!(lexer.get_tokens().iter().find(|t| t.lexeme == "goto").unwrap().token_type, TokenType::Keyword(Keyword::Goto));
+        assert_eq!(labels(&lexer), [("loop".to_string(), "main".to_string(), 3)]);
+        assert_eq!(
+            warnings(&lexer, "W0009"),
+            [("goto target 'missing' is not a label defined in the enclosing function".to_string(), 6, 10)]
+        );
+    }
+    
+    #[test]
+    fn labels_belong_to_their_function_and_may_share_a_variable_name() {
+        let lexer = lexed("int cleanup = 1;\nint f() {\ncleanup:\n    goto cleanup;\n}\nint g() {\n    goto cleanup;\n}\n");
+        assert_eq!(labels(&lexer), [("cleanup".to_string(), "f".to_string(), 3)]);
+        let global = &lexer.get_symbol_table().get_symbols()[0];
+        assert_eq!((global.name.as_str(), global.symbol_type.as_str()), ("cleanup", "variable"));
+        // The variable is no label, and f's label is not g's
+        assert_eq!(warnings(&lexer, "W0009").iter().map(|w| w.1).collect::<Vec<_>>(), [7]);
+    }
+    
+    #[test]
+    fn class_members_have_scope_and_access() {
+        let lexer = lexed("class Stack { private: int data[100]; public: void push(int x); protected: int top; };\nvoid reset() {}\n");
+        let table = lexer.get_symbol_table();
+        let rows: Vec<(&str, &str, &str, &str, Option<&str>)> = table
+            .get_symbols()
+            .iter()
+            .map(|s| (s.name.as_str(), s.symbol_type.as_str(), s.data_type.as_str(), s.scope.as_str(), s.access.as_deref()))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("Stack", "class", "unknown", "global", None),
+                ("data", "variable", "int", "Stack", Some("private")),
+                ("push", "function", "void", "Stack", Some("public")),
+                // A parameter is no member
+                ("x", "variable", "int", "Stack", None),
+                ("top", "variable", "int", "Stack", Some("protected")),
+                ("reset", "function", "void", "global", None),
+            ]
+        );
+        assert_eq!(TokenType::keyword("void"), Some(TokenType::Keyword(Keyword::Void)));
+    }
+    
+    fn rows(lexer: &Lexer) -> Vec<(String, String, String)> {
+        let table = lexer.get_symbol_table();
+        table.get_symbols().iter().map(|s| (s.name.clone(), s.symbol_type.clone(), s.data_type.clone())).collect()
+    }
+    
+    fn owned(rows: &[(&str, &str, &str)]) -> Vec<(String, String, String)> {
+        rows.iter().map(|(a, b, c)| (a.to_string(), b.to_string(), c.to_string())).collect()
+    }
+    
+    #[test]
+    fn aliases_declare_types_for_later_declarations() {
+        let lexer = lexed("typedef int size_t;\nusing index_t = float;\nsize_t n;\nindex_t i = 1.5;\n");
+        assert_eq!(lexer.get_tokens()[0].token_type, TokenType::Keyword(Keyword::Typedef));
+        assert_eq!(lexer.get_tokens()[4].token_type, TokenType::Keyword(Keyword::Using));
+        assert_eq!(
+            rows(&lexer),
+            owned(&[
+                ("size_t", "type_alias", "int"),
+                ("index_t", "type_alias", "float"),
+                ("size_t", "variable", "unknown"),
+                ("n", "variable", "size_t"),
+                ("index_t", "variable", "unknown"),
+                ("i", "variable", "index_t"),
+            ])
+        );
+    }
+    
+    #[test]
+    fn undeclared_aliases_stay_identifiers() {
+        let lexer = lexed("missing_t m;\ntypedef int late_t;\n");
+        assert_eq!(lexer.get_tokens()[0].token_type, TokenType::Identifier);
+        assert_eq!(
+            rows(&lexer),
+            owned(&[("missing_t", "variable", "unknown"), ("m", "variable", "unknown"), ("late_t", "type_alias", "int")])
+        );
+    }
+    
+    #[test]
+    fn ellipsis_marks_the_function_variadic() {
+        let lexer = lexed("int printf(string fmt, ...);\nint g(int a);\n");
+        assert_eq!(lexer.get_tokens()[6].token_type, TokenType::Delimiter(Delimiter::Ellipsis));
+        let table = lexer.get_symbol_table();
+        let variadic: Vec<(&str, bool)> = table.get_symbols().iter().map(|s| (s.name.as_str(), s.variadic)).collect();
+        // No parameter row for the dots
+        assert_eq!(variadic, [("printf", true), ("fmt", false), ("g", false), ("a", false)]);
+    }
+    
+    #[test]
+    fn two_dots_ask_for_three() {
+        let lexer = lexed_with("int g(int a ..);\nint h(. ..);\n", recovering());
+        let errors: Vec<(String, usize, usize)> =
+            lexer.get_diagnostics().iter().map(|d| (d.to_string(), d.span.line, d.span.column)).collect();
+        assert_eq!(
+            errors,
+            [
+                ("Lexical Error: Invalid characters '..'; did you mean '...'? at line 1, column 13".to_string(), 1, 13),
+                ("Lexical Error: Invalid character '.' at line 2, column 7".to_string(), 2, 7),
+                ("Lexical Error: Invalid characters '..'; did you mean '...'? at line 2, column 9".to_string(), 2, 9),
+            ]
+        );
+        // A `.` and then `..` are not run together into `...`
+        assert!(lexer.get_tokens().iter().all(|t| t.token_type != TokenType::Delimiter(Delimiter::Ellipsis)));
+    }
+    
+    #[test]
+    fn attributes_are_one_token_kept_out_of_the_symbols() {
+        let lexer = lexed("[[deprecated]] int old_api();\n[[nodiscard(\"why\")]] [[maybe_unused]] bool ok;\n[[gnu::aligned(arr[2])]] int z;\n");
+        let attributes: Vec<(&str, Option<&str>)> = lexer
+            .get_tokens()
+            .iter()
+            .filter(|t| t.token_type == TokenType::Attribute)
+            .map(|t| (t.lexeme.as_str(), t.value.as_deref()))
+            .collect();
+        assert_eq!(
+            attributes,
+            [
+                ("[[deprecated]]", Some("deprecated")),
+                ("[[nodiscard(\"why\")]]", Some("nodiscard(\"why\")")),
+                ("[[maybe_unused]]", Some("maybe_unused")),
+                // The inner brackets do not close it
+                ("[[gnu::aligned(arr[2])]]", Some("gnu::aligned(arr[2])")),
+            ]
+        );
+        // The type keyword right after an attribute still declares
+        assert_eq!(
+            declarations(&lexer),
+            [("old_api".to_string(), "int".to_string()), ("ok".to_string(), "bool".to_string()), ("z".to_string(), "int".to_string())]
+        );
+        assert_eq!(lexer.get_symbol_table().get_symbols().len(), 3);
+    }
+    
+    #[test]
+    fn unterminated_attribute_points_at_its_opening() {
+        let lexer = lexed_with("int a;\n  [[never closed\nint b;\n", LexerOptions::default());
+        let error = &lexer.get_diagnostics()[0];
+        assert_eq!((error.code.as_str(), error.span.line, error.span.column), ("E0012", 2, 3));
+        assert_eq!(error.message, "Unterminated attribute; no ]] closes it");
+    }
+    
+    #[test]
+    fn unterminated_block_comment_gives_its_position_once() {
+        let mut lexer = Lexer::new("int a;\n    /* open\nint b;\n".to_string());
+        let error = lexer.tokenize().unwrap_err();
+        assert_eq!(error.code, "E0015");
+        assert_eq!(error.to_string(), "Lexical Error: Unterminated block comment; no */ closes it at line 2, column 5");
+    }
+    
+    #[test]
+    fn auto_infers_the_type_of_a_lone_literal() {
+        let lexer = lexed("auto a = 3.14;\nauto b = 42;\nauto c = \"s\";\nauto d = 'x';\nauto e = true;\nauto f = a + 1;\nauto g = (1);\n");
+        let table = lexer.get_symbol_table();
+        let declared: Vec<(&str, &str, Option<&str>)> = table
+            .get_symbols()
+            .iter()
+            .filter(|s| s.data_type == "auto")
+            .map(|s| (s.name.as_str(), s.data_type.as_str(), s.inferred_type.as_deref()))
+            .collect();
+        assert_eq!(
+            declared,
+            [
+                ("a", "auto", Some("float")),
+                ("b", "auto", Some("int")),
+                ("c", "auto", Some("string")),
+                ("d", "auto", Some("char")),
+                ("e", "auto", Some("bool")),
+                // Anything but a single literal is left alone
+                ("f", "auto", None),
+                ("g", "auto", None),
+            ]
+        );
+        assert_eq!(symbol_row(&table.get_symbols()[0])[2], "auto (float)");
+        assert_eq!(symbol_row(&table.get_symbols()[5])[2], "auto");
+    }
+    
+    #[test]
+    fn word_operators_are_opt_in() {
+        let source = "if (a and b or not c) x;\n";
+        let plain = lexed(source);
+        let words = lexed_with(
+            source,
+            LexerOptions {
+                word_operators: true,
+                ..LexerOptions::default()
+            },
+        );
+        let types = |lexer: &Lexer| lexer.get_tokens().iter().map(|t| t.token_type.name()).collect::<Vec<_>>();
+        assert_eq!(
+            types(&plain),
+            ["If", "LeftParen", "Identifier", "Identifier", "Identifier", "Identifier", "Identifier", "Identifier", "RightParen", "Identifier", "Semicolon", "EOF"]
+        );
+        assert_eq!(
+            types(&words),
+            ["If", "LeftParen", "Identifier", "LogicalAnd", "Identifier", "LogicalOr", "LogicalNot", "Identifier", "RightParen", "Identifier", "Semicolon", "EOF"]
+        );
+        // The words stay the lexemes, so the source comes back
+        assert_eq!(words.get_tokens()[3].lexeme, "and");
+        assert_eq!(crate::token_stream::detokenize(words.get_tokens()), source);
+        let names = |lexer: &Lexer| lexer.get_symbol_table().get_symbols().iter().map(|s| s.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&plain), ["a", "and", "b", "or", "not", "c", "x"]);
+        assert_eq!(names(&words), ["a", "b", "c", "x"]);
+    }
+    
+    #[test]
+    fn hygiene_findings_and_the_file_summary() {
+        let untidy = lexed_with("int x = 1;  \nint y = 2;\t\nint z;", LexerOptions::default());
+        assert_eq!(
+            codes(&untidy).into_iter().zip(untidy.get_diagnostics().iter().map(|d| (d.span.line, d.span.column))).collect::<Vec<_>>(),
+            [("W0012", (1, 11)), ("W0012", (2, 11)), ("W0011", (3, 7))]
+        );
+        let summary = untidy.file_summary();
+        assert_eq!((summary.lines, summary.longest_line, summary.trailing_whitespace_lines), (3, 12, 2));
+        let document: serde_json::Value = serde_json::from_str(&untidy.to_json()).unwrap();
+        assert_eq!(document["summary"]["trailing_whitespace_lines"], 2);
+        assert_eq!(document["summary"]["longest_line"], 12);
         
-        self.patterns.push((
-            TokenType::CharLiteral,
-            Regex::new(r"'([^'\\]|\\.)'").unwrap()
-        ));
+        let tidy = lexed("int x = 1;\n");
+        assert!(tidy.get_diagnostics().is_empty());
+        let summary = tidy.file_summary();
+        assert_eq!((summary.lines, summary.longest_line, summary.trailing_whitespace_lines), (1, 10, 0));
+    }
+    
+    #[test]
+    fn every_keyword_round_trips_through_check_keyword() {
+        let lexer = Lexer::new(String::new());
+        assert_eq!(TokenType::keywords().len(), KEYWORDS.len());
+        for (spelling, token_type) in TokenType::keywords() {
+            assert_eq!(lexer.check_keyword(spelling).as_ref(), Some(token_type), "{}", spelling);
+            assert!(TokenType::all().contains(token_type), "{}", spelling);
+            let alone = lexed(&format!("{}\n", spelling));
+            assert_eq!(alone.get_tokens()[0].token_type, *token_type, "{}", spelling);
+            assert_eq!(alone.get_tokens()[0].lexeme, *spelling);
+        }
+        assert_eq!(lexer.check_keyword("and"), None);
+        assert_eq!(lexer.check_keyword("Int"), None);
+    }
+    
+    #[test]
+    fn every_operator_and_delimiter_lexes_to_its_type() {
+        for (lexeme, token_type) in TokenType::operators().iter().chain(TokenType::delimiters()) {
+            let lexer = lexed(&format!("a {} b\n", lexeme));
+            let token = &lexer.get_tokens()[1];
+            assert_eq!((&token.token_type, token.lexeme.as_str()), (token_type, *lexeme), "{}", lexeme);
+            assert_eq!(lexer.get_tokens().len(), 4, "{}", lexeme);
+            assert!(TokenType::all().contains(token_type), "{}", lexeme);
+        }
+    }
+    
+    #[test]
+    fn token_documents_round_trip_through_json() {
+        let source = "#define N 3\nint main() {\n    string s = \"a\\tb\";\n    char c = '\\n';\n    return N && !0;\n}\n";
+        let lexer = lexed(source);
+        let stream = crate::token_stream::TokenStream::from_json_str(&lexer.to_json()).unwrap();
+        assert_eq!(stream.tokens(), lexer.get_tokens());
+        let tokens: Vec<Token> = serde_json::from_str(&serde_json::to_string(lexer.get_tokens()).unwrap()).unwrap();
+        assert_eq!(&tokens, lexer.get_tokens());
+    }
+    
+    #[test]
+    fn token_selection_by_lines_head_and_tail() {
+        let lexer = lexed("int a;\nint b;\nint c;\nint d;\n");
+        let lines = |options: &TokenStreamPrintOptions| -> Vec<(String, usize)> {
+            lexer.select_tokens(options).iter().map(|t| (t.lexeme.to_string(), t.line)).collect()
+        };
+        let mut options = TokenStreamPrintOptions::all();
+        options.set_lines("2-3").unwrap();
+        assert_eq!(lines(&options).iter().map(|t| t.1).collect::<Vec<_>>(), [2, 2, 2, 3, 3, 3]);
+        options.head = Some(2);
+        assert_eq!(lines(&options), [("int".to_string(), 2), ("b".to_string(), 2)]);
+        options.head = None;
+        options.tail = Some(1);
+        assert_eq!(lines(&options), [(";".to_string(), 3)]);
+        // A range past the end selects nothing
+        options.set_lines("9-12").unwrap();
+        assert!(lines(&options).is_empty());
+        for bad in ["3-2", "0-4", "4", "a-b"] {
+            assert_eq!(options.set_lines(bad).unwrap_err(), format!("Invalid line range '{}' (expected FIRST-LAST, such as 10-40)", bad));
+        }
+        assert_eq!((group_digits(498000), group_digits(999), group_digits(1234567)), ("498,000".to_string(), "999".to_string(), "1,234,567".to_string()));
+    }
+    
+    fn identifier_rule(identifier_chars: &str, leading_underscore: LeadingUnderscore) -> LexerOptions {
+        LexerOptions {
+            identifier_chars: identifier_chars.to_string(),
+            leading_underscore,
+            error_recovery: true,
+            ..LexerOptions::default()
+        }
+    }
+    
+    #[test]
+    fn extra_identifier_characters_reach_the_pattern_and_keywords() {
+        let source = "int tmp$1 = 2; int$ y = if$;\n";
+        for backend in [Backend::Regex, Backend::Scanner] {
+            let mut lexer = Lexer::new(source.to_string());
+            lexer.set_options(identifier_rule("$", LeadingUnderscore::Allow));
+            lexer.set_backend(backend);
+            let _ = lexer.tokenize();
+            let identifiers: Vec<&str> = lexer
+                .get_tokens()
+                .iter()
+                .filter(|t| t.token_type == TokenType::Identifier)
+                .map(|t| t.lexeme.as_ref())
+                .collect();
+            assert_eq!(identifiers, ["tmp$1", "int$", "y", "if$"], "{:?}", backend);
+            assert!(!codes(&lexer).contains(&"E0001"));
+            assert_eq!(lexer.check_keyword("int$"), None);
+            assert_eq!(lexer.check_keyword("int"), Some(TokenType::Keyword(Keyword::Int)));
+        }
         
-        self.patterns.push((
-            TokenType::FloatLiteral,
-            Regex::new(r"\d+\.\d+([eE][+-]?\d+)?").unwrap()
-        ));
+        let lexer = lexed_with(source, identifier_rule("", LeadingUnderscore::Allow));
+        let dollars: Vec<(usize, usize)> = lexer
+            .get_tokens()
+            .iter()
+            .filter(|t| t.token_type == TokenType::Error)
+            .map(|t| (t.line, t.column))
+            .collect();
+        assert_eq!(dollars, [(1, 8), (1, 19), (1, 27)]);
+        assert_eq!(codes(&lexer), ["E0001", "E0001", "E0001"]);
+    }
+    
+    #[test]
+    fn each_leading_underscore_policy() {
+        let source = "int _x = 1; int y_ = _x;\n";
+        let allowed = lexed_with(source, identifier_rule("", LeadingUnderscore::Allow));
+        assert!(allowed.get_diagnostics().is_empty());
         
-        self.patterns.push((
-            TokenType::IntegerLiteral,
-            Regex::new(r"\d+").unwrap()
-        ));
+        let warned = lexed_with(source, identifier_rule("", LeadingUnderscore::Warn));
+        let message = "Identifier '_x' starts with an underscore".to_string();
+        assert_eq!(warnings(&warned, "W0013"), [(message.clone(), 1, 5), (message.clone(), 1, 22)]);
+        assert_eq!(warned.get_diagnostics()[0].severity, Severity::Warning);
         
-        self.patterns.push((
-            TokenType::BoolLiteral,
-            Regex::new(r"\b(true|false)\b").unwrap()
-        ));
+        let refused = lexed_with(source, identifier_rule("", LeadingUnderscore::Error));
+        assert_eq!(codes(&refused), ["E0013", "E0013"]);
+        assert_eq!(refused.get_diagnostics()[0].severity, Severity::Error);
+        assert!(refused.get_tokens().iter().any(|t| t.lexeme.as_ref() == "_x" && t.token_type == TokenType::Identifier));
+    }
+    
+    type Seen<T> = std::rc::Rc<std::cell::RefCell<Vec<T>>>;
+    
+    fn observed(source: &str) -> (Lexer, Seen<Token>, Seen<Symbol>) {
+        let tokens: Seen<Token> = Default::default();
+        let symbols: Seen<Symbol> = Default::default();
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            keep_comments: true,
+            ..LexerOptions::default()
+        });
+        let sink = tokens.clone();
+        lexer.on_token(move |token| sink.borrow_mut().push(token.clone()));
+        let sink = symbols.clone();
+        lexer.on_symbol(move |symbol| sink.borrow_mut().push(symbol.clone()));
+        (lexer, tokens, symbols)
+    }
+    
+    const OBSERVED: &str = "int add(int a, int b) {\n    int c = a + b; // sum\n    return c;\n}\nclass P { public: int x; };\n";
+    
+    #[test]
+    fn hooks_see_everything_before_tokenize_returns() {
+        let (mut lexer, tokens, symbols) = observed(OBSERVED);
+        lexer.tokenize().unwrap();
+        assert_eq!(&*tokens.borrow(), lexer.get_tokens());
+        assert_eq!(&*symbols.borrow(), lexer.get_symbol_table().get_symbols());
+        assert!(tokens.borrow().iter().any(|t| t.token_type == TokenType::Comment));
+        assert_eq!(symbols.borrow().len(), 9);
+    }
+    
+    #[test]
+    fn hooks_keep_pace_with_the_iterator() {
+        let (mut lexer, tokens, symbols) = observed(OBSERVED);
+        let mut pulled = Vec::new();
+        while let Some(token) = lexer.next() {
+            pulled.push(token.unwrap());
+            assert_eq!(*tokens.borrow(), pulled);
+            let seen = symbols.borrow();
+            assert_eq!(seen[..], lexer.get_symbol_table().get_symbols()[..seen.len()]);
+        }
+        assert_eq!(pulled.last().map(|t| &t.token_type), Some(&TokenType::EOF));
+        assert_eq!(&*symbols.borrow(), lexer.get_symbol_table().get_symbols());
+    }
+    
+    #[test]
+    fn a_panicking_hook_unwinds_through_tokenize() {
+        let mut lexer = Lexer::new("int a; int b;\n".to_string());
+        lexer.on_symbol(|symbol| {
+            if symbol.name == "b" {
+                panic!("saw {}", symbol.name);
+            }
+        });
+        let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lexer.tokenize()));
+        let payload = unwound.unwrap_err();
+        assert_eq!(payload.downcast_ref::<String>().map(String::as_str), Some("saw b"));
+    }
+    
+    fn non_ascii_strings(source: &str) -> Lexer {
+        lexed_with(
+            source,
+            LexerOptions {
+                warn_non_ascii_strings: true,
+                unicode_identifiers: true,
+                ..LexerOptions::default()
+            },
+        )
+    }
+    
+    #[test]
+    fn non_ascii_literals_are_warned_about_once_each() {
+        let source = "string s = \"café crème\";\nchar c = 'é';\n";
+        let lexer = non_ascii_strings(source);
+        assert_eq!(
+            warnings(&lexer, "W0015"),
+            [
+                ("String literal contains non-ASCII character 'é' (U+00E9)".to_string(), 1, 16),
+                ("Character literal contains non-ASCII character 'é' (U+00E9)".to_string(), 2, 11),
+            ]
+        );
+        assert!(lexer.get_diagnostics().iter().all(|d| d.severity == Severity::Warning));
+        assert_eq!(lexer.get_tokens()[3].lexeme.as_ref(), "\"café crème\"");
+        assert_eq!(lexer.get_tokens()[8].token_type, TokenType::Literal(LiteralKind::Char));
+        assert!(lexed(source).get_diagnostics().is_empty());
+    }
+    
+    #[test]
+    fn escaped_and_ascii_literals_pass() {
+        let escaped = non_ascii_strings("string s = \"caf\\xE9\";\n");
+        assert!(warnings(&escaped, "W0015").is_empty());
+        let ascii = non_ascii_strings("// café\nint naïve = 1;\nstring s = \"cafe\";\nchar c = 'e';\n");
+        assert!(ascii.get_diagnostics().is_empty(), "{:?}", ascii.get_diagnostics());
+    }
+    
+    #[test]
+    fn megabyte_lines_lex_quickly_with_short_messages() {
+        let name = "n".repeat(1 << 20);
+        let text = "s".repeat(500 * 1024);
+        let source = format!("int {} = 1; string t = \"{}\"; @\n", name, text);
+        let started = Instant::now();
+        let lexer = lexed_with(
+            &source,
+            LexerOptions {
+                max_token_length: Some(4096),
+                error_recovery: true,
+                ..LexerOptions::default()
+            },
+        );
+        assert!(started.elapsed() < Duration::from_secs(10), "{:?}", started.elapsed());
+        assert_eq!(codes(&lexer), ["E0014", "E0014", "E0001"]);
+        let messages: Vec<&str> = lexer.get_diagnostics().iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages[0],
+            format!("Identifier {}… (1048516 more) is 1048576 characters long, over the limit of 4096", "n".repeat(60))
+        );
+        assert!(messages[1].ends_with("… (511942 more) is 512002 characters long, over the limit of 4096"), "{}", messages[1]);
+        assert!(messages.iter().all(|m| m.len() < 200));
+        for diagnostic in lexer.get_diagnostics() {
+            let excerpt = diagnostic.excerpt(&source, ColumnUnit::Chars, 4).unwrap();
+            assert!(excerpt.len() < 2 * (diagnostics::EXCERPT_WIDTH + 20), "{}", excerpt.len());
+        }
         
-        self.patterns.push((
-            TokenType::LogicalAnd,
-            Regex::new(r"&&").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::LogicalOr,
-            Regex::new(r"\|\|").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Equal,
-            Regex::new(r"==").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::NotEqual,
-            Regex::new(r"!=").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::LessEqual,
-            Regex::new(r"<=").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::GreaterEqual,
-            Regex::new(r">=").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Increment,
-            Regex::new(r"\+\+").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Decrement,
-            Regex::new(r"--").unwrap()
-        ));
+        // The tokens and the document keep every character
+        assert_eq!(lexer.get_tokens()[1].lexeme.len(), 1 << 20);
+        assert_eq!(lexer.get_tokens()[8].lexeme.len(), text.len() + 2);
+        let json: serde_json::Value = serde_json::from_str(&lexer.to_json()).unwrap();
+        assert_eq!(json["tokens"][1]["lexeme"].as_str().map(str::len), Some(1 << 20));
+        assert!(codes(&lexed_with(&source, recovering())).iter().all(|&code| code != "E0014"));
+    }
+    
+    #[test]
+    fn const_candidates_are_opt_in_and_skip_changed_variables() {
+        let source = "int limit = 10;\nint main(int argc) {\n    int total = 0;\n    int step = 2;\n    for (int i = 0; i < limit; i++) {\n        total = total + step;\n    }\n    int j = 0;\n    --j;\n    return total;\n}\n";
+        let linted = lexed_with(
+            source,
+            LexerOptions {
+                lint_const_candidates: true,
+                ..LexerOptions::default()
+            },
+        );
+        let candidate = |name: &str| format!("variable '{}' is never changed after its initialization and could be const", name);
+        // `total` is reassigned, `i` and `j` are incremented and decremented
+        assert_eq!(warnings(&linted, "W0010"), [(candidate("limit"), 1, 5), (candidate("step"), 4, 9)]);
+        assert!(lexed(source).get_diagnostics().is_empty());
+    }
+    
+    #[test]
+    fn unmatched_new_and_const_candidates_keep_their_codes() {
+        let source = "int main() {\n    int *p = new int;\n    return 0;\n}\n";
+        let linted = lexed_with(
+            source,
+            LexerOptions {
+                lint_const_candidates: true,
+                ..LexerOptions::default()
+            },
+        );
+        assert_eq!(codes(&linted), ["W0017", "W0010"]);
+        let names: Vec<&str> = ["W0010", "W0017"].iter().map(|code| WARNING_CODES.iter().find(|c| c.0 == *code).unwrap().1).collect();
+        assert_eq!(names, ["const-candidate", "unmatched-new"]);
+    }
+    
+    const NESTED_SCOPES: &str = "int g = 1;\nclass Box {\n    public: int size;\n};\nint main() {\n    int a = 0;\n    if (a) { int b = 1; } else { int c = 2; }\n    while (a) {\n        int d = 3;\n        { int e = d; }\n    }\n    return a;\n}\n";
+    
+    #[test]
+    fn symbols_by_scope_keeps_every_scope_apart() {
+        let groups = lexed(NESTED_SCOPES).get_symbol_table().symbols_by_scope();
+        let layout: Vec<_> = groups
+            .0
+            .iter()
+            .map(|g| (g.scope.as_str(), g.depth, g.open_line, g.close_line, g.symbols.iter().map(|s| s.name.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                ("global", 0, None, None, vec!["g", "Box", "main"]),
+                ("Box", 1, Some(2), Some(4), vec!["size"]),
+                ("main", 1, Some(5), Some(13), vec!["a", "a", "a", "a"]),
+                // Two blocks on line 7, one entry each
+                ("block@7", 2, Some(7), Some(7), vec!["b"]),
+                ("block@7", 2, Some(7), Some(7), vec!["c"]),
+                ("block@8", 2, Some(8), Some(11), vec!["d"]),
+                ("block@10", 3, Some(10), Some(10), vec!["e", "d"]),
+            ]
+        );
+    }
+    
+    #[test]
+    fn symbols_by_scope_serialize_as_an_ordered_array() {
+        let lexer = lexed(NESTED_SCOPES);
+        let mut json = Vec::new();
+        lexer.write_json(&mut json, false, &DiagnosticFilter::default(), PositionBase::Zero, true).unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let groups = &document["symbols_by_scope"];
+        let heads: Vec<String> = groups
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|g| format!("{} {} {} {}", g["scope"].as_str().unwrap(), g["depth"], g["open_line"], g["symbols"].as_array().unwrap().len()))
+            .collect();
+        assert_eq!(heads, ["global 0 null 3", "Box 1 1 1", "main 1 4 4", "block@7 2 6 1", "block@7 2 6 1", "block@8 2 7 1", "block@10 3 9 2"]);
+        assert_eq!(groups[4]["symbols"][0]["line"], 6);
         
-        self.patterns.push((
-            TokenType::Plus,
-            Regex::new(r"\+").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Minus,
-            Regex::new(r"-").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Multiply,
-            Regex::new(r"\*").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Divide,
-            Regex::new(r"/").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Modulo,
-            Regex::new(r"%").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Assign,
-            Regex::new(r"=").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::LessThan,
-            Regex::new(r"<").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::GreaterThan,
-            Regex::new(r">").unwrap()
-        ));
+        let mut parsed = crate::document::parse_document(json.as_slice()).unwrap();
+        parsed.rebase(PositionBase::One);
+        assert_eq!(parsed.symbols_by_scope, Some(lexer.get_symbol_table().symbols_by_scope()));
+        // Left out unless asked for
+        assert!(serde_json::from_str::<serde_json::Value>(&lexer.to_json()).unwrap().get("symbols_by_scope").is_none());
+    }
+    
+    fn indented(source: &str, indent_style: Option<IndentStyle>) -> Lexer {
+        lexed_with(
+            source,
+            LexerOptions {
+                indent_style,
+                ..LexerOptions::default()
+            },
+        )
+    }
+    
+    #[test]
+    fn pure_indentation_is_counted_but_quiet() {
+        let spaces = indented("int main() {\n    int a = 1;\n\n        a = a  +  1;\n}\n", None);
+        assert!(spaces.get_diagnostics().is_empty());
+        let summary = spaces.file_summary().indentation;
+        assert_eq!(summary, IndentationSummary { spaces: 2, tabs: 0, mixed: 0 });
+        assert!(!summary.is_inconsistent());
         
-        // Delimiters
-        self.patterns.push((
-            TokenType::Semicolon,
-            Regex::new(r";").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Comma,
-            Regex::new(r",").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::LeftParen,
-            Regex::new(r"\(").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::RightParen,
-            Regex::new(r"\)").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::LeftBrace,
-            Regex::new(r"\{").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::RightBrace,
-            Regex::new(r"\}").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::LeftBracket,
-            Regex::new(r"\[").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::RightBracket,
-            Regex::new(r"\]").unwrap()
-        ));
+        // A tab after the start of the line is alignment, not indentation
+        let tabs = indented("int main() {\n\tint a =\t1;\n\t\treturn a;\n}\n", None);
+        assert!(tabs.get_diagnostics().is_empty());
+        assert_eq!(tabs.file_summary().indentation.to_string(), "0 lines spaces, 2 lines tabs, 0 mixed");
+    }
+    
+    #[test]
+    fn mixed_indentation_is_warned_about_where_it_starts() {
+        let source = "int main() {\n  \tint a = 1;\n\t  a = 2;\n    a = 3;\n}\n";
+        let lexer = indented(source, None);
+        let mixed = "Indentation mixes tabs and spaces".to_string();
+        assert_eq!(warnings(&lexer, "W0019"), [(mixed.clone(), 2, 3), (mixed, 3, 2)]);
+        let summary = lexer.file_summary().indentation;
+        assert_eq!(summary, IndentationSummary { spaces: 1, tabs: 0, mixed: 2 });
+        assert!(summary.is_inconsistent());
+        assert!(IndentationSummary { spaces: 1, tabs: 1, mixed: 0 }.is_inconsistent());
+    }
+    
+    #[test]
+    fn indent_style_flags_every_line_against_it() {
+        let source = "int main() {\n    int a = 1;\n\ta = 2;\n  \ta = 3;\n}\n";
+        let lexer = indented(source, Some(IndentStyle::Spaces));
+        let tab = "Indentation uses a tab; the indent style is spaces".to_string();
+        assert_eq!(warnings(&lexer, "W0020"), [(tab.clone(), 3, 1), (tab, 4, 3)]);
+        assert_eq!(codes(&lexer), ["W0020", "W0020", "W0019"]);
         
-        self.patterns.push((
-            TokenType::Include,
-            Regex::new(r"#include\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Define,
-            Regex::new(r"#define\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Int,
-            Regex::new(r"\bint\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Float,
-            Regex::new(r"\bfloat\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Char,
-            Regex::new(r"\bchar\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Bool,
-            Regex::new(r"\bbool\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::String,
-            Regex::new(r"\bstring\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::If,
-            Regex::new(r"\bif\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Else,
-            Regex::new(r"\belse\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::While,
-            Regex::new(r"\bwhile\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::For,
-            Regex::new(r"\bfor\b").unwrap()
-        ));
-        self.patterns.push((
-            TokenType::Return,
-            Regex::new(r"\breturn\b").unwrap()
-        ));
+        let lexer = indented(source, Some(IndentStyle::Tabs));
+        let space = "Indentation uses a space; the indent style is tabs".to_string();
+        assert_eq!(warnings(&lexer, "W0020"), [(space.clone(), 2, 1), (space, 4, 1)]);
+        assert_eq!(IndentStyle::from_name("tabs"), Some(IndentStyle::Tabs));
+        assert_eq!(IndentStyle::from_name("both"), None);
+    }
+    
+    #[test]
+    fn cancelling_stops_within_one_check_and_can_resume() {
+        let source = crate::generate::generate_program(7, 5000);
+        let straight = lexed(&source);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut lexer = Lexer::new(source.clone());
+        lexer.set_cancel_token(cancel.clone());
+        let (flag, mut seen) = (cancel.clone(), 0);
+        lexer.on_token(move |_| {
+            seen += 1;
+            if seen == 300 {
+                flag.store(true, Ordering::SeqCst);
+            }
+        });
+        let error = lexer.tokenize().unwrap_err();
+        assert!(error.is_cancelled());
+        assert_eq!((error.code.as_str(), error.message.as_str()), (CANCELLED_CODE, "Lexing cancelled"));
+        let partial = lexer.get_tokens().len();
+        assert!((300..=300 + CANCEL_CHECK_INTERVAL).contains(&partial), "{}", partial);
+        assert_eq!(lexer.get_tokens()[..], straight.get_tokens()[..partial]);
+        assert!(lexer.next().is_none());
         
-        self.patterns.push((
-            TokenType::Identifier,
-            Regex::new(r"[a-zA-Z_][a-zA-Z0-9_]*").unwrap()
-        ));
+        // The state saved at the stop resumes to the straight-through result
+        let state = lexer.save_state();
+        let mut resumed = Lexer::resume(source.clone(), state).unwrap();
+        resumed.tokenize().unwrap();
+        assert_eq!(resumed.get_tokens(), straight.get_tokens());
+        // So does clearing the token
+        cancel.store(false, Ordering::SeqCst);
+        lexer.clear_hooks();
+        lexer.tokenize().unwrap();
+        assert_eq!(lexer.get_tokens(), straight.get_tokens());
     }
     
-    fn skip_whitespace(&mut self) {
-        while self.position < self.source.len() {
-            let ch = self.source.chars().nth(self.position).unwrap();
-            if ch == '\n' {
-                self.line += 1;
-                self.column = 1;
-                self.position += 1;
-            } else if ch.is_whitespace() {
-                self.column += 1;
-                self.position += 1;
-            } else {
-                break;
+    #[test]
+    fn another_thread_can_cancel_a_large_run() {
+        let source = crate::generate::generate_program(11, 200_000);
+        let length = source.len();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (started, start) = std::sync::mpsc::channel();
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                start.recv().unwrap();
+                cancel.store(true, Ordering::SeqCst);
+                Instant::now()
+            })
+        };
+        let mut lexer = Lexer::new(source);
+        lexer.set_cancel_token(cancel);
+        let mut sent = false;
+        lexer.on_token(move |_| {
+            if !sent {
+                sent = true;
+                started.send(()).unwrap();
             }
-        }
+        });
+        let error = lexer.tokenize().unwrap_err();
+        let stopped = Instant::now();
+        let set = canceller.join().unwrap();
+        assert!(error.is_cancelled());
+        assert!(stopped.duration_since(set) < Duration::from_secs(1), "{:?}", stopped.duration_since(set));
+        let partial = lexer.get_tokens().len();
+        assert!(partial > 0 && partial < 200_000, "{}", partial);
+        assert!(lexer.save_state().position() < length);
     }
     
-    fn check_keyword(&self, lexeme: &str) -> Option<TokenType> {
-        match lexeme {
-            "int" => Some(TokenType::Int),
-            "float" => Some(TokenType::Float),
-            "char" => Some(TokenType::Char),
-            "bool" => Some(TokenType::Bool),
-            "string" => Some(TokenType::String),
-            "if" => Some(TokenType::If),
-            "else" => Some(TokenType::Else),
-            "while" => Some(TokenType::While),
-            "for" => Some(TokenType::For),
-            "return" => Some(TokenType::Return),
-            "#include" => Some(TokenType::Include),
-            "#define" => Some(TokenType::Define),
-            _ => None,
-        }
+    #[test]
+    fn value_indices_map_back_to_source_columns() {
+        let lexer = lexed("string s = \"a\\t\\x41\\qz\";\nchar c = '\\n';\nstring r = R\"(a\\q)\";\nint n;\nstring m = \"ab\\\ncd\";\n");
+        let columns = |index: usize| {
+            let token = &lexer.get_tokens()[index];
+            (0..6).map(|i| token.column_of_value_index(i)).collect::<Vec<_>>()
+        };
+        // a, \t, \x41, \q, z
+        assert_eq!(columns(3), [Some((1, 13)), Some((1, 14)), Some((1, 16)), Some((1, 20)), Some((1, 22)), None]);
+        assert_eq!(columns(8), [Some((2, 11)), None, None, None, None, None]);
+        // Raw strings have no escapes
+        assert_eq!(columns(13), [Some((3, 15)), Some((3, 16)), Some((3, 17)), None, None, None]);
+        assert_eq!(columns(16), [None; 6]);
+        // A backslash-newline continues on the next line
+        assert_eq!(columns(21), [Some((5, 13)), Some((5, 14)), Some((6, 1)), Some((6, 2)), None, None]);
     }
     
-    fn get_data_type(&self, token_type: &TokenType) -> Option<String> {
-        match token_type {
-            TokenType::Int => Some("int".to_string()),
-            TokenType::Float => Some("float".to_string()),
-            TokenType::Char => Some("char".to_string()),
-            TokenType::Bool => Some("bool".to_string()),
-            TokenType::String => Some("string".to_string()),
-            _ => None,
-        }
+    #[test]
+    fn escape_and_non_ascii_carets_land_on_their_character() {
+        let source = "string s = \"a\\t\\x41\\qz\";\nstring u = \"\\t\\x41é\";\n";
+        let lexer = non_ascii_strings(source);
+        let escape = |name: &str| format!("Unknown escape sequence '\\{}' in StringLiteral", name);
+        assert_eq!(
+            warnings(&lexer, "W0002"),
+            [(escape("x"), 1, 16), (escape("q"), 1, 20), (escape("x"), 2, 15)]
+        );
+        let at = |line: usize, column: usize| source.lines().nth(line - 1).unwrap().chars().nth(column - 1);
+        assert_eq!((at(1, 16), at(1, 20), at(1, 21)), (Some('\\'), Some('\\'), Some('q')));
+        assert_eq!(warnings(&lexer, "W0015"), [("String literal contains non-ASCII character 'é' (U+00E9)".to_string(), 2, 19)]);
+        assert_eq!(at(2, 19), Some('é'));
+        
+        // In bytes, é takes two columns and the escape after it moves along
+        let lexer = lexed_with(
+            "string v = \"é\\q\";\n",
+            LexerOptions {
+                column_unit: ColumnUnit::Bytes,
+                ..LexerOptions::default()
+            },
+        );
+        assert_eq!(warnings(&lexer, "W0002"), [(escape("q"), 1, 15)]);
     }
     
-    pub fn tokenize(&mut self) -> Result<(), String> {
-        while self.position < self.source.len() {
-            self.skip_whitespace();
-            
-            if self.position >= self.source.len() {
-                break;
-            }
-            
-            let mut matched = false;
-            let start_line = self.line;
-            let start_col = self.column;
-            
-            for (token_type, pattern) in &self.patterns {
-                let remaining = &self.source[self.position..];
-                
-                if let Some(mat) = pattern.find(remaining) {
-                    if mat.start() == 0 {
-                        let lexeme = mat.as_str().to_string();
-                        
-                        if *token_type == TokenType::Comment {
-                            for ch in lexeme.chars() {
-                                if ch == '\n' {
-                                    self.line += 1;
-                                    self.column = 1;
-                                } else {
-                                    self.column += 1;
-                                }
-                                self.position += 1;
-                            }
-                            matched = true;
-                            break;
-                        }
-                        
-                        let mut final_token_type = token_type.clone();
-                        
-                        if let Some(data_type) = self.get_data_type(token_type) {
-                            self.last_type_keyword = Some(data_type);
-                        }
-                        
-                        if *token_type == TokenType::Identifier {
-                            if let Some(keyword_type) = self.check_keyword(&lexeme) {
-                                match keyword_type {
-                                    TokenType::If | TokenType::Else | TokenType::While | 
-                                    TokenType::For | TokenType::Return => {
-                                        self.last_type_keyword = None;
-                                    }
-                                    _ => {}
-                                }
-                                final_token_type = keyword_type;
-                            } else {
-                                let data_type = self.last_type_keyword.clone().unwrap_or_else(|| "unknown".to_string());
-                                
-
-                                let symbol_type = "variable".to_string();
-                                
-                                self.symbol_table.add_symbol(
-                                    lexeme.clone(),
-                                    symbol_type,
-                                    data_type,
-                                    start_line,
-                                );
-                                self.last_type_keyword = None;
-                            }
-                        }                        
-                        let token = Token::new(
-                            final_token_type,
-                            lexeme.clone(),
-                            start_line,
-                            start_col,
-                        );
-                        
-                        self.tokens.push(token);
-                        
-                        for ch in lexeme.chars() {
-                            if ch == '\n' {
-                                self.line += 1;
-                                self.column = 1;
-                            } else {
-                                self.column += 1;
-                            }
-                            self.position += 1;
-                        }
-                        
-                        matched = true;
-                        break;
+    // Each way a file can end on a slash, on both backends and with comments
+    // kept or dropped: the tokens after `a = b;` (or `a = b`), and the errors.
+    #[test]
+    fn every_slash_ending_at_eof() {
+        let endings = [
+            ("a = b /", "/", None),
+            ("a = b /*", "/*", Some((1, 7))),
+            ("a = b;\n// last", "// last", None),
+            ("a = b;\n///", "///", None),
+            ("a = b;\n/**/", "/**/", None),
+            ("a = b;\n/* open\nstill open", "/* open\nstill open", Some((2, 1))),
+        ];
+        for (source, tail, error) in endings {
+            for backend in [Backend::Regex, Backend::Scanner] {
+                for keep_comments in [false, true] {
+                    let mut lexer = Lexer::new(source.to_string());
+                    lexer.set_options(LexerOptions { keep_comments, ..LexerOptions::default() });
+                    lexer.set_backend(backend);
+                    let context = format!("{:?} on {:?}, keep_comments {}", source, backend, keep_comments);
+                    assert_eq!(lexer.tokenize().is_ok(), error.is_none(), "{}", context);
+                    
+                    let errors: Vec<_> = lexer
+                        .get_diagnostics()
+                        .iter()
+                        .filter(|d| d.severity == Severity::Error)
+                        .map(|d| (d.code.as_str(), d.span.line, d.span.column))
+                        .collect();
+                    assert_eq!(errors, error.map(|(line, column)| ("E0015", line, column)).into_iter().collect::<Vec<_>>(), "{}", context);
+                    
+                    let prefix = if source.starts_with("a = b;") { 4 } else { 3 };
+                    let rest: Vec<&str> = lexer.get_tokens()[prefix..].iter().map(|t| t.lexeme.as_ref()).filter(|l| *l != "EOF").collect();
+                    let divide = tail == "/";
+                    let expected = if keep_comments || divide { vec![tail] } else { vec![] };
+                    assert_eq!(rest, expected, "{}", context);
+                    if divide {
+                        assert_eq!(lexer.get_tokens()[3].token_type, TokenType::Operator(Operator::Divide), "{}", context);
                     }
                 }
             }
-            
-            if !matched {
-                let ch = self.source.chars().nth(self.position).unwrap();
-                return Err(format!(
-                    "Lexical Error: Invalid character '{}' at line {}, column {}",
-                    ch, self.line, self.column
-                ));
-            }
         }
+    }
+    
+    #[test]
+    fn warnings_in_branches_that_are_not_compiled_are_dropped() {
+        let source = "#if 0\nstring s = \"a\\qb\";\nint y = @;\n#else\nstring t = \"c\\qd\";\n#endif\n#if MISSING\nstring u = \"e\\qf\";\n#endif\n";
+        let lexer = lexed_with(source, recovering());
+        let found: Vec<(&str, usize)> = lexer.get_diagnostics().iter().map(|d| (d.code.as_str(), d.span.line)).collect();
+        // The error on line 3 stays, as does the warning in the branch whose
+        // condition cannot be evaluated
+        assert_eq!(found, [("E0001", 3), ("W0002", 5), ("W0002", 8), ("W0021", 7)]);
+    }
+    
+    #[test]
+    fn symbols_in_branches_that_are_not_compiled_are_excluded() {
+        let source = "#if 0\nint hidden = 1;\nint x;\n#else\nint shown = 2;\n#endif\nint main() { return x + shown; }\n";
+        let lexer = lexed(source);
+        let table = lexer.get_symbol_table();
+        let excluded: Vec<(&str, usize, bool)> = table.get_symbols().iter().map(|s| (s.name.as_str(), s.line, s.excluded)).collect();
+        assert_eq!(
+            excluded,
+            [("hidden", 2, true), ("x", 3, true), ("shown", 5, false), ("main", 7, false), ("x", 7, false), ("shown", 7, false)]
+        );
+        // Nothing declared there is declared at all, or resolves a later use
+        assert!(!table.is_declaration(0) && !table.is_declaration(1));
+        assert_eq!((table.declaration_row(4), table.declaration_row(5)), (None, Some(2)));
         
-        self.tokens.push(Token::new(
-            TokenType::EOF,
-            "EOF".to_string(),
-            self.line,
-            self.column,
-        ));
+        let expected = crate::symbol_check::parse_expected_symbols("name,kind,type,scope\nshown,variable,int,global\nmain,function,int,global\n").unwrap();
+        assert!(crate::symbol_check::check_symbols(table, &expected, crate::symbol_check::SymbolCheckPolicy::default()).success());
+        let counts = crate::summary::summarize(&lexer, &[], None);
+        assert_eq!(counts.symbols_by_kind.values().sum::<usize>(), 2);
         
-        Ok(())
+        // Tokens from the cache get the same table
+        let mut cached = Lexer::new(source.to_string());
+        cached.load_tokens(lexer.get_tokens().to_vec());
+        assert_eq!(cached.get_symbol_table().get_symbols(), table.get_symbols());
     }
     
-    pub fn get_tokens(&self) -> &Vec<Token> {
-        &self.tokens
+    #[test]
+    fn conditional_branches_are_written_to_the_document() {
+        let lexer = lexed("#if 0\nint a;\n#else\nint b;\n#endif\n");
+        let document: serde_json::Value = serde_json::from_str(&lexer.to_json()).unwrap();
+        assert_eq!(
+            document["conditionals"],
+            serde_json::json!([
+                { "directive": "#if", "line": 1, "column": 1, "first_line": 2, "last_line": 2, "included": false },
+                { "directive": "#else", "line": 3, "column": 1, "first_line": 4, "last_line": 4, "included": true },
+            ])
+        );
+        let parsed = crate::document::parse_document(lexer.to_json().as_bytes()).unwrap();
+        assert_eq!(parsed.conditionals, lexer.conditionals().branches);
+        
+        let mut buffer = Vec::new();
+        lexer.write_json(&mut buffer, false, &DiagnosticFilter::default(), PositionBase::Zero, false).unwrap();
+        let zero_based: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(zero_based["conditionals"][1]["first_line"], 3);
+        
+        let plain: serde_json::Value = serde_json::from_str(&lexed("int a;\n").to_json()).unwrap();
+        assert!(plain.get("conditionals").is_none());
     }
     
-    pub fn get_symbol_table(&self) -> &SymbolTable {
-        &self.symbol_table
+    const GENERATED: &str = "int a = 1;\n#line 57 \"original.tpl\"\nint b = 2;\nstring s = \"x\\qy\";\n#line 100\nint c;\n#line zero \"f\"\nint d;\n";
+    
+    #[test]
+    fn line_directives_renumber_what_follows() {
+        let lexer = lexed(GENERATED);
+        let origins: Vec<_> = lexer
+            .get_tokens()
+            .iter()
+            .filter(|t| t.token_type == TokenType::Identifier)
+            .map(|t| (t.lexeme.to_string(), t.line, t.origin.as_ref().map(|o| (o.line, o.file.as_deref().unwrap_or("")))))
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                ("a".to_string(), 1, None),
+                ("b".to_string(), 3, Some((57, "original.tpl"))),
+                ("s".to_string(), 4, Some((58, "original.tpl"))),
+                // No file name: still the one named before
+                ("c".to_string(), 6, Some((100, "original.tpl"))),
+                // The invalid directive on line 7 changes nothing
+                ("zero".to_string(), 7, Some((101, "original.tpl"))),
+                ("d".to_string(), 8, Some((102, "original.tpl"))),
+            ]
+        );
     }
     
-    pub fn print_token_stream(&self) {
-        println!("\n=== TOKEN STREAM ===");
-        for token in &self.tokens {
-            println!("{}", token.to_compiler_format());
+    #[test]
+    fn diagnostics_name_the_origin_and_excerpt_the_physical_line() {
+        let lexer = lexed(GENERATED);
+        let found: Vec<_> = lexer.get_diagnostics().iter().map(|d| (d.code.as_str(), d.span.line, d.to_string())).collect();
+        assert_eq!(
+            found,
+            [
+                ("W0002", 4, "Lexical Warning: Unknown escape sequence '\\q' in StringLiteral at line 58, column 14 of original.tpl".to_string()),
+                (
+                    "W0024",
+                    7,
+                    "Lexical Warning: Invalid #line directive; expected a line number from 1 and an optional quoted file name, so it is ignored at line 101, column 1 of original.tpl".to_string()
+                ),
+            ]
+        );
+        let excerpt = lexer.get_diagnostics()[0].excerpt(lexer.source(), ColumnUnit::Chars, 4).unwrap();
+        assert_eq!(excerpt, "4 | string s = \"x\\qy\";\n  |              ^~");
+    }
+    
+    #[test]
+    fn invalid_line_directives_are_ignored() {
+        for source in ["#line\nint x;\n", "#line 0\nint x;\n", "#line 5 file\nint x;\n", "#line 5 \"a\" \"b\"\nint x;\n", "#line 5 R\"(a)\"\nint x;\n"] {
+            let lexer = lexed(source);
+            assert_eq!(codes(&lexer), ["W0024"], "{:?}", source);
+            assert_eq!(lexer.get_tokens().last().unwrap().origin, None, "{:?}", source);
         }
     }
     
-    pub fn to_json(&self) -> String {
-        serde_json::to_string_pretty(&self.tokens).unwrap()
+    fn ascii_only(error_recovery: bool, max_ascii_errors: Option<usize>) -> LexerOptions {
+        LexerOptions {
+            ascii_only: true,
+            error_recovery,
+            max_ascii_errors,
+            ..LexerOptions::default()
+        }
+    }
+    
+    fn ascii_errors(lexer: &Lexer) -> Vec<(&str, usize, usize)> {
+        lexer.get_diagnostics().iter().map(|d| (d.code.as_str(), d.span.line, d.span.column)).collect()
+    }
+    
+    #[test]
+    fn ascii_only_reports_each_place_a_character_can_be() {
+        // Inside a block comment, on its second line
+        let comment = lexed_with("int a;\n/* first line\n   naïve */ int b;\n", ascii_only(false, None));
+        assert_eq!(ascii_errors(&comment), [("E0016", 3, 6)]);
+        assert_eq!(comment.get_diagnostics()[0].message, "Non-ASCII character 'ï' (U+00EF, bytes 0xC3 0xAF) in an ASCII-only source");
+        
+        let string = lexed_with("string s = \"x→y\";\n", ascii_only(false, None));
+        assert_eq!(ascii_errors(&string), [("E0016", 1, 14)]);
+        assert!(string.get_diagnostics()[0].message.contains("(U+2192, bytes 0xE2 0x86 0x92)"));
+        
+        // A stray character is E0016 alone, not E0001 as well
+        let stray = lexed_with("int x = 1 £ 2;\n", ascii_only(false, None));
+        assert_eq!(ascii_errors(&stray), [("E0016", 1, 11)]);
+        let emoji = lexed_with("int 😀x;\n", ascii_only(false, None));
+        assert_eq!(ascii_errors(&emoji), [("E0016", 1, 5)]);
+        assert!(emoji.get_diagnostics()[0].message.contains("bytes 0xF0 0x9F 0x98 0x80"));
+    }
+    
+    #[test]
+    fn ascii_errors_stop_at_the_limit() {
+        let source = "// é\nstring t = \"ü\";\nx £ y;\n";
+        let all = lexed_with(source, ascii_only(true, None));
+        assert_eq!(ascii_errors(&all), [("E0016", 1, 4), ("E0016", 2, 13), ("E0016", 3, 3)]);
+        assert_eq!(all.get_tokens().len(), 10);
+        let two = lexed_with(source, ascii_only(true, Some(2)));
+        assert_eq!(ascii_errors(&two), [("E0016", 1, 4), ("E0016", 2, 13)]);
+        assert_eq!(two.get_tokens().len(), 4);
+        
+        // By default only the stray character is an error
+        assert_eq!(ascii_errors(&lexed_with(source, recovering())), [("E0001", 3, 3)]);
+    }
+    
+    #[test]
+    fn functions_over_max_complexity_are_warned_about() {
+        let source = "int flat(int a) { return a; }\nint  busy(int a) {\n    if (a > 0 && a < 5 || a == 9) { a = 1; }\n    return a;\n}\n";
+        let options = |max_complexity| LexerOptions { max_complexity, ..LexerOptions::default() };
+        assert_eq!(warnings(&lexed_with(source, options(None)), "W0026"), []);
+        assert_eq!(warnings(&lexed_with(source, options(Some(4))), "W0026"), []);
+        let over = lexed_with(source, options(Some(3)));
+        assert_eq!(warnings(&over, "W0026"), [("Function 'busy' has cyclomatic complexity 4, over the limit of 3".to_string(), 2, 6)]);
+        assert_eq!(warnings(&lexed_with(source, options(Some(0))), "W0026").len(), 2);
+    }
+    
+    #[test]
+    fn identifiers_and_keywords_share_their_text() {
+        let mut lexer = lexed("int total = 0;\nint i = total + total;\nstring s = \"total\";\n");
+        let stream = lexer.get_tokens();
+        let totals: Vec<&Token> = stream.iter().filter(|t| t.lexeme == "total" && t.token_type == TokenType::Identifier).collect();
+        assert_eq!(totals.len(), 3);
+        assert!(totals.windows(2).all(|pair| pair[0].lexeme.shares_text(&pair[1].lexeme)));
+        let ints: Vec<&Token> = stream.iter().filter(|t| t.lexeme == "int").collect();
+        assert!(ints[0].lexeme.shares_text(&ints[1].lexeme));
+        // The repeated int and both repeats of total; the literal is not counted
+        assert_eq!(lexer.intern_stats(), InternStats { unique: 5, occurrences: 8, bytes_saved: 13 });
+        lexer.reset("x = x;".to_string());
+        lexer.tokenize().unwrap();
+        assert_eq!(lexer.intern_stats(), InternStats { unique: 1, occurrences: 2, bytes_saved: 1 });
+    }
+    
+    #[test]
+    fn interning_leaves_tokens_and_json_as_they_were() {
+        let source = "int a = b; a = a + b;\n";
+        let first = lexed(source);
+        let second = lexed(source);
+        assert_eq!(first.get_tokens(), second.get_tokens());
+        assert!(!first.get_tokens()[1].lexeme.shares_text(&second.get_tokens()[1].lexeme));
+        assert_eq!(first.to_json(), second.to_json());
+        let document: serde_json::Value = serde_json::from_str(&first.to_json()).unwrap();
+        assert_eq!(document["tokens"][1]["lexeme"], "a");
+        let lexemes: Vec<&str> = first.get_tokens().iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(lexemes, ["int", "a", "=", "b", ";", "a", "=", "a", "+", "b", ";", "EOF"]);
     }
 }
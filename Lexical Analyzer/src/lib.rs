@@ -0,0 +1,51 @@
+pub mod lexer;
+pub mod gzip;
+pub mod markdown;
+pub mod sarif;
+pub mod bison;
+pub mod flex;
+pub mod dfa;
+pub mod scanner;
+#[cfg(feature = "logos")]
+pub mod logos_scanner;
+pub mod cache;
+pub mod confusables;
+pub mod nfc;
+pub mod line_index;
+pub mod diagnostics;
+pub mod document;
+pub mod naming;
+pub mod position;
+pub mod call_graph;
+pub mod token_stream;
+pub mod precedence;
+pub mod metrics;
+pub mod annotate;
+pub mod allocation;
+pub mod generate;
+pub mod batch;
+pub mod legacy;
+pub mod fixture;
+pub mod nesting;
+pub mod lifetime;
+pub mod loops;
+pub mod config;
+pub mod spelling;
+pub mod manifest;
+pub mod histogram;
+pub mod signs;
+pub mod summary;
+pub mod simple;
+pub mod conditional;
+pub mod spec;
+pub mod functions;
+pub mod duplication;
+pub mod token_tree;
+pub mod symbol_check;
+pub mod usage;
+pub mod rename;
+pub mod intern;
+pub mod visual;
+pub mod python;
+
+pub use simple::{lex, lex_file, lex_with, looks_binary, read_source, LexFileError, LexOutput};
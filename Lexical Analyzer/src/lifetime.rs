@@ -0,0 +1,169 @@
+use crate::lexer::SymbolTable;
+use crate::metrics::FunctionMetrics;
+use crate::position::PositionBase;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+// How long one variable or constant stays in use: where it is declared,
+// where the uses the symbol table resolves to it first and last appear, and
+// how many lines that covers, counting both ends. `function` is the
+// innermost function whose lines hold the declaration, and `live_percent`
+// the share of that function's lines the variable is live for. A symbol
+// never used has no uses, no live lines and no percentage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lifetime {
+    pub name: String,
+    pub scope: String,
+    pub line: usize,
+    pub first_use: Option<usize>,
+    pub last_use: Option<usize>,
+    pub live_lines: Option<usize>,
+    pub function: Option<String>,
+    pub live_percent: Option<f64>,
+}
+
+impl Lifetime {
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        self.line = from.rebase(self.line, to);
+        self.first_use = self.first_use.map(|line| from.rebase(line, to));
+        self.last_use = self.last_use.map(|line| from.rebase(line, to));
+    }
+}
+
+// One entry per variable or constant declaration, in table order.
+pub fn lifetimes(table: &SymbolTable, functions: &[FunctionMetrics]) -> Vec<Lifetime> {
+    let symbols = table.get_symbols();
+    let mut uses: Vec<Option<(usize, usize)>> = vec![None; symbols.len()];
+    for (row, symbol) in symbols.iter().enumerate() {
+        if let Some(declaration) = table.declaration_row(row) {
+            let range = uses[declaration].get_or_insert((symbol.line, symbol.line));
+            range.0 = range.0.min(symbol.line);
+            range.1 = range.1.max(symbol.line);
+        }
+    }
+
+    let mut lifetimes = Vec::new();
+    for (row, symbol) in symbols.iter().enumerate() {
+        if !table.is_declaration(row) || !matches!(symbol.symbol_type.as_str(), "variable" | "constant") {
+            continue;
+        }
+        let function = functions.iter().rev().find(|f| f.line <= symbol.line && symbol.line <= f.end_line);
+        let live_lines = uses[row].map(|(_, last)| last.saturating_sub(symbol.line) + 1);
+        let live_percent = match (live_lines, function) {
+            (Some(live), Some(function)) => Some((live as f64 * 1000.0 / function.lines() as f64).round() / 10.0),
+            _ => None,
+        };
+        lifetimes.push(Lifetime {
+            name: symbol.name.clone(),
+            scope: symbol.scope.clone(),
+            line: symbol.line,
+            first_use: uses[row].map(|(first, _)| first),
+            last_use: uses[row].map(|(_, last)| last),
+            live_lines,
+            function: function.map(|f| f.name.clone()),
+            live_percent,
+        });
+    }
+    lifetimes
+}
+
+// Longest-lived first, then by declaration line; symbols never used come
+// last, with dashes for what they lack.
+pub fn lifetime_table(lifetimes: &[Lifetime]) -> String {
+    let mut sorted: Vec<&Lifetime> = lifetimes.iter().collect();
+    sorted.sort_by_key(|l| (std::cmp::Reverse(l.live_lines), l.line));
+    let dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+    let mut out = String::from("=== LIFETIMES ===\n");
+    writeln!(
+        out,
+        "{:<16} {:<12} {:<10} {:<10} {:<10} {:<12} {:<10}",
+        "Name", "Scope", "Declared", "First Use", "Last Use", "Live Lines", "% of Func"
+    )
+    .unwrap();
+    writeln!(out, "{}", "-".repeat(86)).unwrap();
+    for lifetime in sorted {
+        writeln!(
+            out,
+            "{:<16} {:<12} {:<10} {:<10} {:<10} {:<12} {:<10}",
+            lifetime.name,
+            lifetime.scope,
+            lifetime.line,
+            dash(lifetime.first_use.map(|l| l.to_string())),
+            dash(lifetime.last_use.map(|l| l.to_string())),
+            dash(lifetime.live_lines.map(|l| l.to_string())),
+            dash(lifetime.live_percent.map(|p| format!("{:.1}%", p)))
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    const FIXTURE: &str = "int limit = 10;\n\
+        int main() {\n\
+        int total = 0;\n\
+        int unused;\n\
+        for (int i = 0; i < limit; i++) {\n\
+        total = total + i;\n\
+        }\n\
+        int late = total;\n\
+        return late;\n\
+        }\n";
+
+    fn lexed() -> Lexer {
+        let mut lexer = Lexer::new(FIXTURE.to_string());
+        lexer.tokenize().unwrap();
+        lexer
+    }
+
+    #[test]
+    fn spans_of_a_small_fixture() {
+        let lifetimes = lexed().lifetimes();
+        let spans: Vec<_> = lifetimes
+            .iter()
+            .map(|l| (l.name.as_str(), l.line, l.first_use, l.last_use, l.live_lines, l.live_percent))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("limit", 1, Some(5), Some(5), Some(5), None),
+                ("total", 3, Some(6), Some(8), Some(6), Some(66.7)),
+                ("unused", 4, None, None, None, None),
+                ("i", 5, Some(5), Some(6), Some(2), Some(22.2)),
+                ("late", 8, Some(9), Some(9), Some(2), Some(22.2)),
+            ]
+        );
+        assert_eq!(lifetimes[0].function, None);
+        assert_eq!(lifetimes[1].function.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn table_puts_the_longest_lived_first_and_dashes_the_unused() {
+        let table = lifetime_table(&lexed().lifetimes());
+        let rows: Vec<Vec<&str>> = table.lines().skip(3).map(|line| line.split_whitespace().collect()).collect();
+        assert_eq!(
+            rows,
+            [
+                vec!["total", "main", "3", "6", "8", "6", "66.7%"],
+                vec!["limit", "global", "1", "5", "5", "5", "-"],
+                vec!["i", "block@5", "5", "5", "6", "2", "22.2%"],
+                vec!["late", "main", "8", "9", "9", "2", "22.2%"],
+                vec!["unused", "main", "4", "-", "-", "-", "-"],
+            ]
+        );
+    }
+
+    #[test]
+    fn json_holds_the_lifetimes() {
+        let json: serde_json::Value = serde_json::from_str(&lexed().to_json()).unwrap();
+        let unused = &json["lifetimes"][2];
+        assert_eq!(unused["name"], "unused");
+        assert!(unused["first_use"].is_null() && unused["live_lines"].is_null());
+        assert_eq!(json["lifetimes"][1]["live_percent"], 66.7);
+    }
+}
@@ -0,0 +1,58 @@
+// Byte offsets of the start of every line in a source text, for going from
+// a 1-based line number back to the text of that line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(source.match_indices('\n').map(|(index, _)| index + 1));
+        LineIndex {
+            starts,
+            len: source.len(),
+        }
+    }
+
+    // A source ending in a newline has an empty last line, where EOF sits.
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.starts.get(line.checked_sub(1)?).copied()
+    }
+
+    // 1-based line containing a byte offset.
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|start| *start <= offset)
+    }
+
+    // Text of a line without its line terminator (`\n` or `\r\n`).
+    pub fn line_text<'a>(&self, source: &'a str, line: usize) -> Option<&'a str> {
+        let start = self.line_start(line)?;
+        let end = self.starts.get(line).map(|next| next - 1).unwrap_or(self.len);
+        let text = &source[start..end];
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_offsets_and_lines_both_ways() {
+        let source = "ab\r\n\ncd";
+        let index = LineIndex::new(source);
+        assert_eq!(index.line_count(), 3);
+        assert_eq!(index.line_text(source, 1), Some("ab"));
+        assert_eq!(index.line_text(source, 2), Some(""));
+        assert_eq!(index.line_text(source, 3), Some("cd"));
+        assert_eq!(index.line_text(source, 4), None);
+        assert_eq!(index.line_start(0), None);
+        assert_eq!([0, 3, 4, 5, 7].map(|offset| index.line_of(offset)), [1, 1, 2, 3, 3]);
+    }
+}
@@ -0,0 +1,131 @@
+// Scanning backend generated by the `logos` crate, built with the `logos`
+// feature. The token definitions below are the regex pattern table written
+// as logos attributes, so the crate compiles them into one state machine at
+// build time. Logos takes the longest match where the table takes the first
+// pattern that matches; the table is ordered so the first match is also the
+// longest, and the two backends agree, as `tests/backends.rs` checks.
+
+use crate::lexer::{Delimiter, Keyword, LiteralKind, Operator, TokenType};
+use logos::Logos;
+
+#[derive(Logos, Debug, Clone, Copy, PartialEq)]
+enum Pattern {
+    // Logos has no lazy `.*?`, so a callback runs a block comment to the
+    // first `*/`
+    #[token("/*", block_comment)]
+    #[regex(r"//[^\n]*")]
+    Comment,
+    #[regex(r#""([^"\\]|\\(.|\n))*""#)]
+    String,
+    #[regex(r"'([^'\\]|\\.)'")]
+    Char,
+    #[regex(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?")]
+    Float,
+    #[regex(r"[0-9]+")]
+    Integer,
+    #[token("&&", |_| Operator::LogicalAnd)]
+    #[token("||", |_| Operator::LogicalOr)]
+    #[token("==", |_| Operator::Equal)]
+    #[token("!=", |_| Operator::NotEqual)]
+    #[token("<=", |_| Operator::LessEqual)]
+    #[token(">=", |_| Operator::GreaterEqual)]
+    #[token("++", |_| Operator::Increment)]
+    #[token("--", |_| Operator::Decrement)]
+    #[token("+", |_| Operator::Plus)]
+    #[token("-", |_| Operator::Minus)]
+    #[token("*", |_| Operator::Multiply)]
+    #[token("&", |_| Operator::Ampersand)]
+    #[token("/", |_| Operator::Divide)]
+    #[token("%", |_| Operator::Modulo)]
+    #[token("=", |_| Operator::Assign)]
+    #[token("<", |_| Operator::LessThan)]
+    #[token(">", |_| Operator::GreaterThan)]
+    #[token("!", |_| Operator::LogicalNot)]
+    Operator(Operator),
+    #[token(";", |_| Delimiter::Semicolon)]
+    #[token(",", |_| Delimiter::Comma)]
+    #[token("(", |_| Delimiter::LeftParen)]
+    #[token(")", |_| Delimiter::RightParen)]
+    #[token("{", |_| Delimiter::LeftBrace)]
+    #[token("}", |_| Delimiter::RightBrace)]
+    #[token("[", |_| Delimiter::LeftBracket)]
+    #[token("]", |_| Delimiter::RightBracket)]
+    #[token(":", |_| Delimiter::Colon)]
+    #[token("...", |_| Delimiter::Ellipsis)]
+    Delimiter(Delimiter),
+    #[regex(r"#[a-zA-Z_][a-zA-Z0-9_]*")]
+    Directive,
+    // Keywords, `true` and `false` are told apart from identifiers after
+    // the match, as the hand-written scanner does
+    #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*")]
+    Word,
+}
+
+fn block_comment(lexer: &mut logos::Lexer<Pattern>) -> bool {
+    match lexer.remainder().find("*/") {
+        Some(end) => {
+            lexer.bump(end + 2);
+            true
+        }
+        None => false,
+    }
+}
+
+// Finds the token at the start of `text`, as `scanner::scan_token` does:
+// the pattern it matches before keyword reclassification and its length in
+// bytes, or None where the pattern table would report an invalid character.
+pub fn scan_token(text: &str) -> Option<(TokenType, usize)> {
+    let mut lexer = Pattern::lexer(text);
+    let pattern = match lexer.next()? {
+        Ok(pattern) => pattern,
+        // A block comment never closed, where the table matches `/`
+        Err(()) if text.starts_with("/*") => return Some((TokenType::Operator(Operator::Divide), 1)),
+        Err(()) => return None,
+    };
+    let lexeme = lexer.slice();
+    let token_type = match pattern {
+        Pattern::Comment => TokenType::Comment,
+        Pattern::String => TokenType::Literal(LiteralKind::String),
+        Pattern::Char => TokenType::Literal(LiteralKind::Char),
+        Pattern::Float => TokenType::Literal(LiteralKind::Float),
+        Pattern::Integer => TokenType::Literal(LiteralKind::Integer),
+        Pattern::Operator(operator) => TokenType::Operator(operator),
+        Pattern::Delimiter(delimiter) => TokenType::Delimiter(delimiter),
+        Pattern::Directive => keyword(lexeme).unwrap_or(TokenType::Keyword(Keyword::Directive)),
+        Pattern::Word => match lexeme {
+            "true" | "false" => TokenType::Literal(LiteralKind::Bool),
+            word => keyword(word).unwrap_or(TokenType::Identifier),
+        },
+    };
+    Some((token_type, lexeme.len()))
+}
+
+fn keyword(word: &str) -> Option<TokenType> {
+    TokenType::keyword(word).filter(|token_type| matches!(token_type, TokenType::Keyword(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn tokens_match_the_hand_written_scanner() {
+        let samples = [
+            "/* a ** b */ x", "// to the end\nx", "/* open", "/", "\"a\\\"b\" c", "\"line\\\nnext\"", "\"open", "'a'", "'\\n'", "'ab'",
+            "12.5e+3;", "12.5e", "12..3", "007", "&&&", "...", "..", "#include <a>", "#pragma once", "#", "int_", "int", "true",
+            "falsey", "whilex", "_", "@", "é",
+        ];
+        for text in samples {
+            assert_eq!(scan_token(text), scanner::scan_token(text), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn longest_match_backs_off_to_the_last_token() {
+        assert_eq!(scan_token("3.0e+x"), Some((TokenType::Literal(LiteralKind::Float), 3)));
+        assert_eq!(scan_token("/* never closed"), Some((TokenType::Operator(Operator::Divide), 1)));
+        assert_eq!(scan_token("while(x)"), Some((TokenType::Keyword(Keyword::While), 5)));
+        assert_eq!(scan_token("#define N"), Some((TokenType::Keyword(Keyword::Define), 7)));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::lexer::{Delimiter, Keyword, Token, TokenType};
+
+// The shape of one `for (init; condition; increment)` header: whether each
+// clause holds anything, and where each ends, at its `;` or the closing
+// parenthesis. `line` and `column` are the `for` keyword's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForLoop {
+    pub line: usize,
+    pub column: usize,
+    pub init: bool,
+    pub condition: bool,
+    pub increment: bool,
+    pub ends: [(usize, usize); 3],
+}
+
+impl ForLoop {
+    // `for (;;)`, which only ends at a `break`, `return` or `goto`.
+    pub fn is_endless(&self) -> bool {
+        !self.init && !self.condition && !self.increment
+    }
+}
+
+// Every `for` whose header has exactly two semicolons at its own level, in
+// source order; comments are ignored, and a header that never closes or
+// has some other shape is left out.
+pub fn for_loops(tokens: &[Token]) -> Vec<ForLoop> {
+    let tokens: Vec<&Token> = tokens.iter().filter(|t| t.token_type != TokenType::Comment).collect();
+    let mut loops = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.token_type != TokenType::Keyword(Keyword::For)
+            || tokens.get(i + 1).is_none_or(|t| t.token_type != TokenType::Delimiter(Delimiter::LeftParen))
+        {
+            continue;
+        }
+        let (mut depth, mut filled, mut ends) = (1, Vec::new(), Vec::new());
+        let mut current = false;
+        for inner in &tokens[i + 2..] {
+            match inner.token_type {
+                TokenType::Delimiter(Delimiter::LeftParen) => depth += 1,
+                TokenType::Delimiter(Delimiter::RightParen) => depth -= 1,
+                _ => {}
+            }
+            let ends_clause = depth == 0 || (depth == 1 && inner.token_type == TokenType::Delimiter(Delimiter::Semicolon));
+            if ends_clause {
+                filled.push(current);
+                ends.push((inner.line, inner.column));
+                current = false;
+                if depth == 0 {
+                    break;
+                }
+            } else {
+                current = true;
+            }
+        }
+        if depth == 0 && filled.len() == 3 {
+            loops.push(ForLoop {
+                line: token.line,
+                column: token.column,
+                init: filled[0],
+                condition: filled[1],
+                increment: filled[2],
+                ends: [ends[0], ends[1], ends[2]],
+            });
+        }
+    }
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Severity;
+    use crate::lexer::Lexer;
+
+    fn lexed(source: &str) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        lexer
+    }
+
+    fn diagnostics(lexer: &Lexer) -> Vec<(&str, String, usize, usize)> {
+        lexer
+            .get_diagnostics()
+            .iter()
+            .map(|d| (d.code.as_str(), d.message.clone(), d.span.line, d.span.column))
+            .collect()
+    }
+
+    #[test]
+    fn a_standard_loop_is_quiet_and_scopes_its_variable() {
+        let lexer = lexed("int n = 3;\nint main() {\n    for (int i = 0; i < n; i++) { n = i; }\n    return 0;\n}\n");
+        assert_eq!(
+            lexer.for_loops(),
+            [ForLoop {
+                line: 3,
+                column: 5,
+                init: true,
+                condition: true,
+                increment: true,
+                ends: [(3, 19), (3, 26), (3, 31)],
+            }]
+        );
+        assert!(lexer.get_diagnostics().is_empty());
+        let i = &lexer.get_symbol_table().get_symbols()[2];
+        assert_eq!((i.name.as_str(), i.symbol_type.as_str(), i.scope.as_str()), ("i", "variable", "block@3"));
+    }
+
+    #[test]
+    fn empty_clauses_and_assignments_are_warned_about() {
+        let lexer = lexed("int main() {\n    for (int j = 0; ; j++) { }\n    for (int k = 0; k = 1; ) { }\n    for (;;) { return 0; }\n}\n");
+        let shapes: Vec<(bool, bool, bool, bool)> = lexer.for_loops().iter().map(|l| (l.init, l.condition, l.increment, l.is_endless())).collect();
+        assert_eq!(shapes, [(true, false, true, false), (true, true, false, false), (false, false, false, true)]);
+        assert_eq!(
+            diagnostics(&lexer),
+            [
+                ("W0008", "Assignment in the condition of 'for'; did you mean '=='?".to_string(), 3, 23),
+                ("W0016", "Empty condition in 'for' loop".to_string(), 2, 21),
+                ("W0016", "Empty increment in 'for' loop".to_string(), 3, 28),
+                ("N0001", "'for (;;)' loops until a break, return or goto".to_string(), 4, 5),
+            ]
+        );
+        let endless = lexer.get_diagnostics().iter().find(|d| d.code == "N0001").unwrap();
+        assert_eq!(endless.severity, Severity::Note);
+        assert!(!lexer.has_errors());
+    }
+
+    #[test]
+    fn nested_loops_keep_same_named_variables_apart() {
+        let lexer = lexed("int main() {\n    for (int i = 0; i < 3; i++) {\n        for (int i = 0; i < 2; i++) { }\n    }\n}\n");
+        let declared: Vec<(usize, &str)> = lexer
+            .get_symbol_table()
+            .get_symbols()
+            .iter()
+            .enumerate()
+            .filter(|&(row, _)| lexer.get_symbol_table().is_declaration(row))
+            .map(|(_, s)| (s.line, s.scope.as_str()))
+            .collect();
+        assert_eq!(declared, [(1, "global"), (2, "block@2"), (3, "block@3")]);
+        assert_eq!(lexer.for_loops().len(), 2);
+        assert_eq!(
+            diagnostics(&lexer),
+            [("W0007", "'i' shadows a variable declared at line 2 (scope block@2)".to_string(), 3, 18)]
+        );
+    }
+}
@@ -1,59 +1,1630 @@
-mod lexer;
-
-use lexer::Lexer;
+use mcpp_lexer::allocation;
+use mcpp_lexer::batch::BatchWriter;
+use mcpp_lexer::annotate::{self, AnnotateOptions, ColorChoice};
+use mcpp_lexer::bison;
+use mcpp_lexer::cache::Cache;
+use mcpp_lexer::config::{self, ConfigEntry, ConfigValue};
+use mcpp_lexer::call_graph::CallGraph;
+use mcpp_lexer::diagnostics::{self, Diagnostic, DiagnosticFilter, Severity};
+use mcpp_lexer::dfa::{self, Dfa};
+use mcpp_lexer::fixture;
+use mcpp_lexer::flex;
+use mcpp_lexer::generate;
+use mcpp_lexer::histogram;
+use mcpp_lexer::lifetime;
+use mcpp_lexer::manifest::{ManifestFile, RunManifest};
+use mcpp_lexer::gzip::{self, GzEncoder};
+use mcpp_lexer::legacy::{self, LegacyMap};
+use mcpp_lexer::lexer::{self, Backend, IndentStyle, IntegerWidth, LeadingUnderscore, LexError, LexStats, Lexer, LexerOptions, LexerProfile, PatternSet, SymbolSort, SymbolTable, SymbolTablePrintOptions, Token, TokenStreamPrintOptions, TokenType, NOTE_CODES, WARNING_CODES};
+use mcpp_lexer::markdown::{self, ReportOptions};
+use mcpp_lexer::metrics;
+use mcpp_lexer::duplication;
+use mcpp_lexer::naming::NamingRules;
+use mcpp_lexer::position::{self, ColumnUnit, PositionBase};
+use mcpp_lexer::precedence;
+use mcpp_lexer::sarif::{self, SarifArtifact};
+use mcpp_lexer::scanner;
+use mcpp_lexer::signs;
+use mcpp_lexer::simple::BinaryInput;
+use mcpp_lexer::spec;
+use mcpp_lexer::summary::{self, RunSummary};
+use mcpp_lexer::symbol_check::{self, SymbolCheckPolicy};
+use mcpp_lexer::usage::{self, UsageReport};
+use mcpp_lexer::rename;
+use mcpp_lexer::visual;
+use mcpp_lexer::token_stream::{self, ComparePolicy, TokenMismatch, TokenStream};
 use std::env;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::path::Path;
 use std::process;
+use std::time::Instant;
+
+#[derive(PartialEq)]
+enum Format {
+    Json,
+    Markdown,
+    BisonStream,
+    BatchJson,
+    TokenTree,
+}
+
+#[derive(PartialEq)]
+enum SpecFormat {
+    Text,
+    Markdown,
+}
+
+#[derive(PartialEq)]
+enum UsageFormat {
+    Text,
+    Csv,
+}
+
+#[derive(PartialEq)]
+enum CallGraphFormat {
+    Text,
+    Dot,
+}
+
+#[derive(PartialEq)]
+enum DiagnosticsFormat {
+    Text,
+    Sarif,
+}
+
+struct Options {
+    filenames: Vec<String>,
+    format: Format,
+    report_tokens: bool,
+    output: Option<String>,
+    compact_json: bool,
+    compress: bool,
+    compression_level: u32,
+    check: Option<String>,
+    check_policy: ComparePolicy,
+    check_symbols: Option<String>,
+    symbol_check_policy: SymbolCheckPolicy,
+    diagnostics_format: DiagnosticsFormat,
+    diagnostics_output: Option<String>,
+    emit_bison_defs: Option<String>,
+    emit_flex: Option<String>,
+    emit_precedence_table: bool,
+    emit_spec: Option<SpecFormat>,
+    // --generate, with its --seed and --size
+    generate: Option<(u64, usize)>,
+    dfa: Option<String>,
+    emit_dfa: Option<String>,
+    trace_dfa: Option<String>,
+    trace: bool,
+    step: bool,
+    backend: Backend,
+    compare_backends: bool,
+    compare_legacy: Option<String>,
+    emit_rust_fixture: bool,
+    cache_dir: Option<String>,
+    no_cache: bool,
+    // Lex inputs that look binary instead of refusing them
+    force: bool,
+    no_config: bool,
+    fold_signs: bool,
+    manifest: Option<String>,
+    summary_json: bool,
+    // No banner, token stream, symbol table or completion lines
+    quiet: bool,
+    usage_report: Option<UsageFormat>,
+    // --rename old=new
+    rename: Option<(String, String)>,
+    lexer_options: LexerOptions,
+    diagnostic_filter: DiagnosticFilter,
+    deny_warnings: bool,
+    excerpts: bool,
+    // Tab stops for excerpts and --annotate
+    tab_width: usize,
+    position_base: PositionBase,
+    symbol_print: SymbolTablePrintOptions,
+    token_print: TokenStreamPrintOptions,
+    call_graph: Option<CallGraphFormat>,
+    scope_tree: bool,
+    metrics: bool,
+    allocations: bool,
+    lifetimes: bool,
+    detect_duplication: bool,
+    duplication_threshold: f64,
+    histogram: bool,
+    histogram_svg: Option<String>,
+    histogram_top: usize,
+    annotate: bool,
+    color: ColorChoice,
+    time: bool,
+}
+
+// Flags that change a single LexerOptions field, applied on top of the
+// --profile preset wherever they appear on the command line.
+type OptionOverride = fn(&mut LexerOptions);
+
+// How a `.mcpprc` key turns into command-line arguments.
+enum ConfigFlag {
+    // `true` passes the flag, `false` nothing
+    Switch(&'static str),
+    // `true` passes the first flag, `false` the second
+    Toggle(&'static str, &'static str),
+    // The flag followed by the value
+    Value(&'static str),
+    // The flag once per array item, or once for a single value
+    Each(&'static str),
+    // The flag once with the array items joined by commas
+    Joined(&'static str),
+}
+
+const CONFIG_KEYS: &[(&str, ConfigFlag)] = &[
+    ("profile", ConfigFlag::Value("--profile")),
+    ("backend", ConfigFlag::Value("--backend")),
+    ("keep-comments", ConfigFlag::Switch("--keep-comments")),
+    ("lenient-whitespace", ConfigFlag::Switch("--lenient-whitespace")),
+    ("unicode-identifiers", ConfigFlag::Switch("--unicode-identifiers")),
+    ("concat-strings", ConfigFlag::Switch("--concat-strings")),
+    ("word-operators", ConfigFlag::Switch("--word-operators")),
+    ("fold-signs", ConfigFlag::Switch("--fold-signs")),
+    ("strict-escapes", ConfigFlag::Switch("--strict-escapes")),
+    ("strict-literals", ConfigFlag::Switch("--strict-literals")),
+    ("error-recovery", ConfigFlag::Toggle("--error-recovery", "--no-error-recovery")),
+    ("int-width", ConfigFlag::Value("--int-width")),
+    ("column-unit", ConfigFlag::Value("--column-unit")),
+    ("identifier-chars", ConfigFlag::Value("--identifier-chars")),
+    ("leading-underscore", ConfigFlag::Value("--leading-underscore")),
+    ("max-nesting", ConfigFlag::Value("--max-nesting")),
+    ("max-complexity", ConfigFlag::Value("--max-complexity")),
+    ("max-token-length", ConfigFlag::Value("--max-token-length")),
+    ("ascii-only", ConfigFlag::Switch("--ascii-only")),
+    ("max-ascii-errors", ConfigFlag::Value("--max-ascii-errors")),
+    ("indent-style", ConfigFlag::Value("--indent-style")),
+    ("naming", ConfigFlag::Value("--naming")),
+    ("naming-allow", ConfigFlag::Joined("--naming-allow")),
+    ("warn", ConfigFlag::Each("--warn")),
+    ("lint", ConfigFlag::Each("--lint")),
+    ("allow", ConfigFlag::Each("--allow")),
+    ("warn-level", ConfigFlag::Value("--warn-level")),
+    ("deny-warnings", ConfigFlag::Switch("--deny-warnings")),
+    ("excerpts", ConfigFlag::Switch("--excerpts")),
+    ("tab-width", ConfigFlag::Value("--tab-width")),
+    ("position-base", ConfigFlag::Value("--position-base")),
+];
+
+// Exit statuses. When several apply to one run, the highest is used.
+const EXIT_SUCCESS: i32 = 0;
+// Lexical errors, or warnings under --deny-warnings
+const EXIT_LEXICAL_ERRORS: i32 = 1;
+// Bad arguments, or a file that could not be read or written
+const EXIT_USAGE: i32 = 2;
+// --check, --compare-legacy or --compare-backends found a difference
+const EXIT_MISMATCH: i32 = 3;
 
 //Done by Akshay 353
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let started = Instant::now();
+    
+    let options = match load_options(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}", e);
+            }
+            eprintln!("Usage: {} [options] <input.mcpp>...", args[0]);
+            eprintln!("Example: {} examples/example1.mcpp", args[0]);
+            process::exit(EXIT_USAGE);
+        }
+    };
     
-    if args.len() < 2 {
-        eprintln!("Usage: {} <input.mcpp>", args[0]);
-        eprintln!("Example: {} examples/example1.mcpp", args[0]);
-        process::exit(1);
+    let mut manifest = RunManifest::new(args[1..].to_vec());
+    let mut status = run(&options, &mut manifest);
+    if let Some(path) = &options.manifest {
+        manifest.elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        manifest.exit_code = status;
+        let json = serde_json::to_string_pretty(&manifest).expect("manifest serializes");
+        if let Err(e) = fs::write(path, json + "\n") {
+            eprintln!("Error writing manifest '{}': {}", path, e);
+            status = status.max(EXIT_USAGE);
+        }
+    }
+    if status != EXIT_SUCCESS {
+        process::exit(status);
+    }
+}
+
+// Does everything the options ask for, recording inputs and outputs in
+// `manifest`, and returns the exit status.
+fn run(options: &Options, manifest: &mut RunManifest) -> i32 {
+    if let Some(path) = &options.emit_bison_defs {
+        if let Err(e) = emit_bison_defs(path) {
+            eprintln!("Error writing Bison definitions: {}", e);
+            return EXIT_USAGE;
+        }
+        manifest.outputs.push(path.clone());
+        manifest.outputs.push(Path::new(path).with_extension("h").display().to_string());
     }
     
-    let filename = &args[1];
+    if let Some(path) = &options.emit_flex {
+        // Pair the rules with the header from --emit-bison-defs when both are requested
+        let header = options
+            .emit_bison_defs
+            .as_ref()
+            .and_then(|defs| Path::new(defs).with_extension("h").file_name().map(|n| n.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "tokens.h".to_string());
+        match flex::flex_specification(&PatternSet::shared(), &header) {
+            Ok(spec) => match fs::write(path, spec) {
+                Ok(_) => {
+                    println!("Flex specification saved to: {}", path);
+                    manifest.outputs.push(path.clone());
+                }
+                Err(e) => {
+                    eprintln!("Error writing Flex specification: {}", e);
+                    return EXIT_USAGE;
+                }
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        }
+    }
     
-    // Read source file
-    let source = match fs::read_to_string(filename) {
-        Ok(content) => content,
-        Err(e) => {
-            eprintln!("Error reading file '{}': {}", filename, e);
-            process::exit(1);
+    if options.emit_precedence_table {
+        print!("{}", precedence::precedence_table());
+    }
+    
+    if let Some(format) = &options.emit_spec {
+        let patterns = PatternSet::shared();
+        let rows = spec::token_spec(&patterns);
+        match format {
+            SpecFormat::Text => print!("{}", spec::spec_text(&rows)),
+            SpecFormat::Markdown => print!("{}", spec::spec_markdown(&rows)),
+        }
+    }
+    
+    if let Some((seed, size)) = options.generate {
+        print!("{}", generate::generate_program(seed, size));
+    }
+    
+    if options.emit_dfa.is_some() || options.trace_dfa.is_some() {
+        if let Err(e) = run_dfa_tools(options) {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+        manifest.outputs.extend(options.emit_dfa.clone());
+    }
+    
+    let mut lexer = Lexer::new(String::new());
+    lexer.set_backend(options.backend);
+    lexer.set_options(options.lexer_options.clone());
+    if options.trace {
+        lexer.set_trace(|event| eprintln!("{}", event));
+    }
+    let mut artifacts = Vec::new();
+    let mut status = EXIT_SUCCESS;
+    let mut summary = options.summary_json.then(RunSummary::new);
+    let mut usage = options.usage_report.as_ref().map(|_| UsageReport::new());
+    // Only the combined report is printed
+    let aggregate = summary.is_some() || usage.is_some();
+    
+    let batch_filename = options.output.clone().unwrap_or_else(|| "mcpp-batch.json".to_string());
+    let mut batch = match options.format {
+        Format::BatchJson => match File::create(&batch_filename).and_then(|f| BatchWriter::new(BufWriter::new(f), !options.compact_json)) {
+            Ok(batch) => Some(batch),
+            Err(e) => {
+                eprintln!("Error creating batch JSON file '{}': {}", batch_filename, e);
+                return EXIT_USAGE;
+            }
+        },
+        _ => None,
+    };
+    
+    // Tracing and stepping need a real scan, so they never read the cache
+    let use_cache = !options.no_cache && !options.trace && !options.step;
+    let mut cache = match &options.cache_dir {
+        Some(dir) if use_cache => match Cache::open(dir) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("Warning: Could not open cache directory '{}': {}", dir, e);
+                None
+            }
+        },
+        _ => None,
+    };
+    
+    for filename in &options.filenames {
+        // Read source file
+        let source = match mcpp_lexer::read_source(Path::new(filename), options.force) {
+            Ok(content) => content,
+            Err(e) => {
+                if e.get_ref().is_some_and(|inner| inner.is::<BinaryInput>()) {
+                    eprintln!("Error reading file '{}': {}; use --force to lex it anyway", filename, e);
+                } else {
+                    eprintln!("Error reading file '{}': {}", filename, e);
+                }
+                // The rest of the batch is still lexed and reported
+                manifest.files.push(ManifestFile::unreadable(filename, e.to_string()));
+                status = status.max(EXIT_USAGE);
+                continue;
+            }
+        };
+        // Generated files may be named otherwise, so this is only a warning
+        if Path::new(filename).extension().is_none_or(|extension| extension != "mcpp") {
+            eprintln!("Warning: '{}' does not have the .mcpp extension; lexing it as MCPP", filename);
+        }
+        let mut entry = ManifestFile::new(filename, &source);
+        
+        // Printed alone, so it can be pasted as it is
+        if options.emit_rust_fixture {
+            let name = Path::new(filename).file_stem().map_or("input".into(), |stem| stem.to_string_lossy());
+            print!("{}", fixture::rust_fixture(&name, &source));
+            continue;
+        }
+        
+        if !aggregate && !options.quiet {
+            println!("=== MCPP Lexical Analyzer ===");
+            println!("Input file: {}\n", filename);
+        }
+        
+        if options.compare_backends {
+            let result = compare_backends(&source, &options.lexer_options);
+            status = status.max(result.status);
+            entry.success = result.status == EXIT_SUCCESS;
+            manifest.files.push(entry);
+            continue;
+        }
+        
+        if let Some(path) = &options.compare_legacy {
+            let result = compare_with_legacy(&source, path, options);
+            status = status.max(result.status);
+            entry.success = result.status == EXIT_SUCCESS;
+            manifest.files.push(entry);
+            continue;
+        }
+        
+        let cache_key = cache.as_ref().map(|_| Cache::key(&source, &cache_fingerprint(options)));
+        
+        // Reuse one lexer for every input
+        lexer.reset(source);
+        
+        let cached = match (&cache, &cache_key) {
+            (Some(cache), Some(key)) => cache.lookup(filename, key),
+            _ => None,
+        };
+        let from_cache = cached.is_some();
+        if let Some(tokens) = cached {
+            lexer.load_tokens(tokens);
+        }
+        
+        let result = if options.step {
+            step_through(&mut lexer, &options.symbol_print)
+        } else {
+            process_file(&mut lexer, filename, options, from_cache, batch.as_mut(), summary.as_mut(), usage.as_mut())
+        };
+        if let (Some(cache), Some(key)) = (cache.as_mut(), &cache_key) {
+            // Diagnostics are not cached, so only clean results are stored
+            if result.status == EXIT_SUCCESS && !from_cache && lexer.get_diagnostics().is_empty() {
+                if let Err(e) = cache.store(filename, key, lexer.get_tokens()) {
+                    eprintln!("Warning: Could not update cache: {}", e);
+                }
+            }
+        }
+        
+        // Errors were reported as they stopped the lexer
+        let diagnostics: Vec<Diagnostic> = options.diagnostic_filter.apply(lexer.get_diagnostics()).into_iter().cloned().collect();
+        for diagnostic in diagnostics.iter().filter(|d| d.severity != Severity::Error && !aggregate) {
+            print_diagnostic(&lexer, diagnostic, options);
+        }
+        let warnings = diagnostics.iter().filter(|d| d.severity == Severity::Warning).count();
+        status = status.max(result.status);
+        if options.deny_warnings && warnings > 0 {
+            status = status.max(EXIT_LEXICAL_ERRORS);
+        }
+        entry.success = result.status == EXIT_SUCCESS;
+        entry.errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+        entry.warnings = warnings;
+        entry.outputs = result.outputs;
+        manifest.files.push(entry);
+        artifacts.push(SarifArtifact {
+            path: filename.clone(),
+            diagnostics,
+        });
+    }
+    
+    if let (Some(usage), Some(format)) = (usage, &options.usage_report) {
+        match format {
+            UsageFormat::Text => print!("{}", usage::usage_table(&usage)),
+            UsageFormat::Csv => print!("{}", usage::usage_csv(&usage)),
+        }
+    }
+    
+    if let Some(summary) = summary {
+        let json = if options.compact_json { serde_json::to_string(&summary) } else { serde_json::to_string_pretty(&summary) };
+        println!("{}", json.unwrap());
+    }
+    
+    if let Some(batch) = batch {
+        let files = batch.summary().files;
+        match batch.finish() {
+            Ok(_) => {
+                println!("\nBatch JSON output for {} files saved to: {}", files, batch_filename);
+                manifest.outputs.push(batch_filename);
+            }
+            Err(e) => eprintln!("Warning: Could not write batch JSON file: {}", e),
+        }
+    }
+    
+    if options.diagnostics_format == DiagnosticsFormat::Sarif {
+        let sarif_filename = options.diagnostics_output.clone().unwrap_or_else(|| "mcpp-diagnostics.sarif".to_string());
+        match fs::write(&sarif_filename, sarif::render_sarif(&artifacts)) {
+            Ok(_) => {
+                println!("\nSARIF diagnostics saved to: {}", sarif_filename);
+                manifest.outputs.push(sarif_filename);
+            }
+            Err(e) => eprintln!("Warning: Could not write SARIF file: {}", e),
+        }
+    }
+    
+    status
+}
+
+// What one input came to: the exit status it calls for and the files
+// written for it.
+struct FileResult {
+    status: i32,
+    outputs: Vec<String>,
+}
+
+impl FileResult {
+    fn new(status: i32) -> Self {
+        FileResult { status, outputs: Vec::new() }
+    }
+    
+    fn lexed(succeeded: bool) -> Self {
+        FileResult::new(if succeeded { EXIT_SUCCESS } else { EXIT_LEXICAL_ERRORS })
+    }
+}
+
+enum StepCommand {
+    Next,
+    Symbols,
+    Run,
+    Quit,
+}
+
+// Reads one command line. Without a terminal no prompt is shown, so scripted
+// input drives the session, and end of input runs to completion.
+fn read_step_command(interactive: bool) -> StepCommand {
+    if interactive {
+        print!("[Enter] next, s symbols, r run, q quit > ");
+        let _ = io::stdout().flush();
+    }
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => StepCommand::Run,
+        Ok(_) => match line.trim() {
+            "s" => StepCommand::Symbols,
+            "r" => StepCommand::Run,
+            "q" => StepCommand::Quit,
+            _ => StepCommand::Next,
+        },
+    }
+}
+
+fn step_through(lexer: &mut Lexer, symbol_print: &SymbolTablePrintOptions) -> FileResult {
+    let interactive = io::stdin().is_terminal();
+    let mut running = false;
+    let mut succeeded = true;
+    
+    loop {
+        let symbols_before = lexer.get_symbol_table().get_symbols().len();
+        let token = match lexer.next_token() {
+            None => break,
+            Some(Ok(token)) => token,
+            Some(Err(e)) => {
+                eprintln!("\n{}", e);
+                if !lexer.options().error_recovery {
+                    return FileResult::lexed(false);
+                }
+                succeeded = false;
+                continue;
+            }
+        };
+        
+        println!("{}", token.to_compiler_format());
+        println!("    now at line {}, column {}", lexer.current_line(), lexer.current_column());
+        for symbol in &lexer.get_symbol_table().get_symbols()[symbols_before..] {
+            println!("    + symbol {} ({}, {}, {})", symbol.name, symbol.symbol_type, symbol.data_type, symbol.scope);
+        }
+        
+        while !running {
+            match read_step_command(interactive) {
+                StepCommand::Next => break,
+                StepCommand::Symbols => lexer.get_symbol_table().print_with(symbol_print),
+                StepCommand::Run => running = true,
+                StepCommand::Quit => {
+                    return FileResult::lexed(succeeded);
+                }
+            }
         }
+    }
+    
+    lexer.get_symbol_table().print_with(symbol_print);
+    println!("\n=== Lexical Analysis Complete ===");
+    println!("Total tokens: {}", lexer.get_tokens().len());
+    FileResult::lexed(succeeded)
+}
+
+fn process_file(
+    lexer: &mut Lexer,
+    filename: &str,
+    options: &Options,
+    cached: bool,
+    batch: Option<&mut Batch>,
+    summary: Option<&mut RunSummary>,
+    usage: Option<&mut UsageReport>,
+) -> FileResult {
+    let (result, stats) = if cached {
+        (Ok(()), None)
+    } else {
+        let (result, stats) = lexer.tokenize_timed();
+        (result, Some(stats))
     };
+    if options.fold_signs {
+        let folded = signs::fold_signs(lexer.get_tokens());
+        lexer.load_tokens(folded);
+    }
+    let cached_note = if cached { " (cached)" } else { "" };
+    if options.time {
+        match stats {
+            Some(stats) => eprintln!(
+                "Lexed {} tokens ({} bytes) in {:.3} ms: {:.0} tokens/s, {:.2} MB/s",
+                stats.tokens,
+                stats.bytes,
+                stats.elapsed.as_secs_f64() * 1000.0,
+                stats.tokens_per_sec(),
+                stats.bytes_per_sec() / 1_000_000.0
+            ),
+            None => eprintln!("Tokens loaded from cache; not timed"),
+        }
+    }
     
-    println!("=== MCPP Lexical Analyzer ===");
-    println!("Input file: {}\n", filename);
+    // Printed by `run` once every input is in
+    if let Some(summary) = summary {
+        let diagnostics = options.diagnostic_filter.apply(lexer.get_diagnostics());
+        summary.add(filename, result.is_ok(), summary::summarize(lexer, &diagnostics, stats.map(|s| s.elapsed)));
+        return FileResult::lexed(result.is_ok());
+    }
+    if let Some(usage) = usage {
+        usage.add(lexer.get_tokens());
+        return FileResult::lexed(result.is_ok());
+    }
+    if let Some((old, new)) = &options.rename {
+        return rename_in_file(lexer, filename, old, new, options);
+    }
     
-    // Create lexer and tokenize
-    let mut lexer = Lexer::new(source);
+    if options.format == Format::Markdown {
+        if result.is_err() {
+            report_errors(lexer, options);
+        } else {
+            lexer.print_token_stream_with(&options.token_print);
+            lexer.get_symbol_table().print_with(&options.symbol_print);
+        }
+        
+        let diagnostics: Vec<String> = options
+            .diagnostic_filter
+            .apply(lexer.get_diagnostics())
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        let report_options = ReportOptions {
+            include_tokens: options.report_tokens,
+            date: markdown::today(),
+        };
+        let report = markdown::render_report(filename, lexer.get_tokens(), lexer.get_symbol_table(), &diagnostics, &report_options);
+        let report_filename = options.output.clone().unwrap_or_else(|| derived_filename(filename, "_report.md"));
+        let mut file_result = FileResult::lexed(result.is_ok());
+        match fs::write(&report_filename, report) {
+            Ok(_) => {
+                println!("\nMarkdown report saved to: {}", report_filename);
+                file_result.outputs.push(report_filename);
+            }
+            Err(e) => eprintln!("Warning: Could not write Markdown report: {}", e),
+        }
+        return file_result;
+    }
     
-    match lexer.tokenize() {
+    // With --function, the tokens of the first function of that name
+    let function_tokens = options.token_print.function.as_ref().map(|name| {
+        lexer.function_tokens(name).unwrap_or_else(|| {
+            eprintln!("Warning: No function named '{}' in {}", name, filename);
+            &[]
+        })
+    });
+    
+    match result {
         Ok(()) => {
-            // Print token stream
-            lexer.print_token_stream();
+            // Print token stream, or the source with each line's tokens
+            if options.annotate {
+                let annotate_options = AnnotateOptions {
+                    color: options.color.enabled(),
+                    width: terminal_width(),
+                    tab_width: options.tab_width,
+                };
+                print!("\n=== ANNOTATED SOURCE ===\n{}", annotate::annotate(&lexer.tokens_by_line(), &annotate_options));
+            } else if !options.quiet {
+                lexer.print_token_stream_with(&options.token_print);
+            }
             
             // Print symbol table
-            lexer.get_symbol_table().print();
+            if !options.quiet {
+                lexer.get_symbol_table().print_with(&options.symbol_print);
+            }
+            
+            if options.scope_tree {
+                lexer.get_symbol_table().print_scope_tree();
+            }
+            
+            if let Some(format) = &options.call_graph {
+                let graph = CallGraph::build(lexer.get_symbol_table());
+                match format {
+                    CallGraphFormat::Text => print!("\n{}", graph.to_text()),
+                    CallGraphFormat::Dot => print!("\n{}", graph.to_dot()),
+                }
+            }
+            
+            if options.metrics {
+                let mut functions = lexer.metrics();
+                if let Some(name) = &options.token_print.function {
+                    functions.retain(|f| f.name == *name);
+                }
+                print!("\n{}", metrics::metrics_table(&functions));
+            }
+            
+            if options.allocations {
+                print!("\n{}", allocation::allocation_table(&lexer.allocations()));
+            }
+            
+            if options.lifetimes {
+                print!("\n{}", lifetime::lifetime_table(&lexer.lifetimes()));
+            }
+            
+            if options.detect_duplication {
+                print!("\n{}", duplication::duplication_table(&lexer.duplicates(options.duplication_threshold)));
+            }
+            
+            let mut file_result = FileResult::new(EXIT_SUCCESS);
+            if options.histogram || options.histogram_svg.is_some() {
+                let counts = histogram::histogram(function_tokens.unwrap_or(lexer.get_tokens()), options.histogram_top);
+                if options.histogram {
+                    print!("\n{}", histogram::histogram_chart(&counts, terminal_width()));
+                }
+                if let Some(path) = &options.histogram_svg {
+                    match fs::write(path, histogram::histogram_svg(&counts)) {
+                        Ok(_) => {
+                            if !options.quiet {
+                                println!("\nHistogram chart saved to: {}", path);
+                            }
+                            file_result.outputs.push(path.clone());
+                        }
+                        Err(e) => eprintln!("Warning: Could not write histogram chart: {}", e),
+                    }
+                }
+            }
+            
+            if options.check.is_some() || options.check_symbols.is_some() {
+                if !options.quiet {
+                    println!("\n=== Lexical Analysis Complete ===");
+                    println!("Total tokens: {}{}", lexer.get_tokens().len(), cached_note);
+                }
+                if let Some(expected_path) = &options.check {
+                    file_result.status = check_against(lexer.get_tokens(), expected_path, options.check_policy, options.quiet);
+                }
+                if let Some(expected_path) = &options.check_symbols {
+                    let status = check_symbols_against(lexer.get_symbol_table(), expected_path, options.symbol_check_policy, options.quiet);
+                    if file_result.status == EXIT_SUCCESS {
+                        file_result.status = status;
+                    }
+                }
+                return file_result;
+            }
+            
+            if options.format == Format::BisonStream {
+                let stream_filename = options.output.clone().unwrap_or_else(|| derived_filename(filename, "_tokens.bison"));
+                match fs::write(&stream_filename, bison::token_stream(lexer.get_tokens())) {
+                    Ok(_) => {
+                        if !options.quiet {
+                            println!("\nBison token stream saved to: {}", stream_filename);
+                        }
+                        file_result.outputs.push(stream_filename);
+                    }
+                    Err(e) => eprintln!("Warning: Could not write Bison token stream: {}", e),
+                }
+                if !options.quiet {
+                    println!("\n=== Lexical Analysis Complete ===");
+                    println!("Total tokens: {}{}", lexer.get_tokens().len(), cached_note);
+                }
+                return file_result;
+            }
+            
+            if options.format == Format::TokenTree {
+                let tree_filename = options.output.clone().unwrap_or_else(|| derived_filename(filename, "_tree.json"));
+                let trees = lexer.token_tree();
+                let json = if options.compact_json { serde_json::to_string(&trees) } else { serde_json::to_string_pretty(&trees) };
+                match json.map_err(io::Error::from).and_then(|json| fs::write(&tree_filename, json)) {
+                    Ok(_) => {
+                        if !options.quiet {
+                            println!("\nToken tree saved to: {}", tree_filename);
+                        }
+                        file_result.outputs.push(tree_filename);
+                    }
+                    Err(e) => eprintln!("Warning: Could not write token tree: {}", e),
+                }
+                if !options.quiet {
+                    println!("\n=== Lexical Analysis Complete ===");
+                    println!("Total tokens: {}{}", lexer.get_tokens().len(), cached_note);
+                }
+                return file_result;
+            }
             
-            // Generate and save JSON output
-            let json_output = lexer.to_json();
-            let json_filename = filename.replace(".mcpp", "_tokens.json");
-            match fs::write(&json_filename, &json_output) {
-                Ok(_) => println!("\nJSON output saved to: {}", json_filename),
-                Err(e) => eprintln!("Warning: Could not write JSON file: {}", e),
+            match batch {
+                Some(batch) => add_to_batch(batch, lexer, filename, options, stats.as_ref()),
+                None => file_result.outputs.extend(save_json(lexer, filename, options, "JSON output")),
+            }
+            if options.quiet {
+                return file_result;
             }
             
             println!("\n=== Lexical Analysis Complete ===");
-            println!("Total tokens: {}", lexer.get_tokens().len());
+            println!("Total tokens: {}{}", lexer.get_tokens().len(), cached_note);
+            if let (Some(name), Some(tokens)) = (&options.token_print.function, function_tokens) {
+                println!("Tokens in {}: {}", name, tokens.len());
+            }
+            let indentation = lexer.file_summary().indentation;
+            if indentation.is_inconsistent() {
+                println!("Indentation: {}", indentation);
+            }
+            file_result
+        }
+        Err(_) => {
+            report_errors(lexer, options);
+            // What was lexed before the failure, with "success": false
+            let mut file_result = FileResult::lexed(false);
+            if options.check.is_some() || options.check_symbols.is_some() {
+            } else if let Some(batch) = batch {
+                add_to_batch(batch, lexer, filename, options, stats.as_ref());
+            } else if options.format == Format::Json {
+                file_result.outputs.extend(save_json(lexer, filename, options, "Partial JSON output"));
+            }
+            file_result
+        }
+    }
+}
+
+// The COLUMNS the shell reports, or the annotated source's default width.
+fn terminal_width() -> usize {
+    env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(AnnotateOptions::default().width)
+}
+
+// Streams the JSON document straight to the output file, returning its
+// path if it was written.
+fn save_json(lexer: &Lexer, filename: &str, options: &Options, description: &str) -> Option<String> {
+    let json_filename = output_filename(filename, options);
+    let compress = options.compress || json_filename.ends_with(".gz");
+    let level = if compress { Some(options.compression_level) } else { None };
+    match write_json_file(lexer, &json_filename, !options.compact_json, level, &options.diagnostic_filter, options.position_base, options.symbol_print.by_scope) {
+        Ok(_) => {
+            if !options.quiet {
+                println!("\n{} saved to: {}", description, json_filename);
+            }
+            Some(json_filename)
+        }
+        Err(e) => {
+            eprintln!("Warning: Could not write JSON file: {}", e);
+            None
+        }
+    }
+}
+
+type Batch = BatchWriter<BufWriter<File>>;
+
+// Appends the file's entry to the --format batch-json document.
+fn add_to_batch(batch: &mut Batch, lexer: &Lexer, filename: &str, options: &Options, stats: Option<&LexStats>) {
+    if let Err(e) = batch.add(lexer, filename, stats, &options.diagnostic_filter, options.position_base, options.symbol_print.by_scope) {
+        eprintln!("Warning: Could not write batch JSON entry for '{}': {}", filename, e);
+    }
+}
+
+// Writes the file with `old` renamed to `new`, to `--output` or next to the
+// input. A file with lexical errors is not renamed, as the tokens would not
+// give back its text.
+fn rename_in_file(lexer: &Lexer, filename: &str, old: &str, new: &str, options: &Options) -> FileResult {
+    if lexer.has_errors() {
+        report_errors(lexer, options);
+        eprintln!("Error: Not renaming '{}' in {}, which has lexical errors", old, filename);
+        return FileResult::lexed(false);
+    }
+    let text = match rename::rename_identifier(lexer.get_tokens(), old, new) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error: Cannot rename '{}' to '{}' in {}: {}", old, new, filename, e);
+            return FileResult::new(EXIT_USAGE);
+        }
+    };
+    let renamed_filename = options.output.clone().unwrap_or_else(|| derived_filename(filename, "_renamed.mcpp"));
+    if let Err(e) = fs::write(&renamed_filename, text) {
+        eprintln!("Error writing file '{}': {}", renamed_filename, e);
+        return FileResult::new(EXIT_USAGE);
+    }
+    let count = lexer.get_tokens().iter().filter(|t| t.token_type == TokenType::Identifier && t.lexeme == old).count();
+    println!("Identifiers renamed from '{}' to '{}': {}", old, new, count);
+    println!("Renamed source saved to: {}", renamed_filename);
+    let mut file_result = FileResult::new(EXIT_SUCCESS);
+    file_result.outputs.push(renamed_filename);
+    file_result
+}
+
+// More than one error is only possible with error recovery on.
+fn report_errors(lexer: &Lexer, options: &Options) {
+    eprintln!();
+    for diagnostic in lexer.get_diagnostics().iter().filter(|d| d.severity == Severity::Error) {
+        print_diagnostic(lexer, diagnostic, options);
+    }
+}
+
+fn print_diagnostic(lexer: &Lexer, diagnostic: &Diagnostic, options: &Options) {
+    eprintln!("{}", diagnostic);
+    let excerpt = options.excerpts.then(|| diagnostic.excerpt(lexer.source(), lexer.options().column_unit, options.tab_width));
+    if let Some(excerpt) = excerpt.flatten() {
+        eprintln!("{}", excerpt);
+    }
+}
+
+// Lexes the input and compares the tokens with a list written by the
+// course's earlier C++ lexer, reporting every token the two disagree on.
+fn compare_with_legacy(source: &str, path: &str, options: &Options) -> FileResult {
+    let legacy_tokens = match fs::read_to_string(path).map_err(|e| format!("Error reading file '{}': {}", path, e)).and_then(|text| legacy::parse_legacy(&text)) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return FileResult::new(EXIT_USAGE);
+        }
+    };
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.set_backend(options.backend);
+    lexer.set_options(options.lexer_options.clone());
+    if lexer.tokenize().is_err() {
+        report_errors(&lexer, options);
+    }
+    
+    let report = legacy::compare_legacy(&legacy_tokens, lexer.get_tokens(), &LegacyMap::builtin());
+    println!("=== LEGACY COMPARISON ===");
+    for discrepancy in &report.discrepancies {
+        println!("{}", discrepancy);
+    }
+    println!(
+        "{} tokens agree, {} disagree with {}",
+        report.agreements,
+        report.discrepancies.len(),
+        path
+    );
+    if !report.discrepancies.is_empty() {
+        FileResult::new(EXIT_MISMATCH)
+    } else {
+        FileResult::lexed(!lexer.has_errors())
+    }
+}
+
+// Runs the input through every backend and reports the first place where
+// a backend disagrees with the regex table, either in its tokens, symbols
+// or in the error it stops at.
+fn compare_backends(source: &str, options: &LexerOptions) -> FileResult {
+    let run = |backend: Backend| mcpp_lexer::lex_with(source, backend, options).map(|output| (output.tokens, output.symbols));
+    let reference = run(Backend::Regex);
+    let mut succeeded = true;
+    
+    for name in &lexer::BACKEND_NAMES[1..] {
+        let backend = Backend::from_name(name).unwrap();
+        let result = run(backend);
+        match (&reference, &result) {
+            (Ok((expected, expected_symbols)), Ok((actual, actual_symbols))) => {
+                let mismatch = expected.iter().zip(actual.iter()).position(|(a, b)| a != b);
+                if let Some(index) = mismatch {
+                    println!("Backend {} differs at token {}:", name, index + 1);
+                    println!("  regex:   {}", expected[index].to_compiler_format());
+                    println!("  {:<8} {}", format!("{}:", name), actual[index].to_compiler_format());
+                    succeeded = false;
+                } else if expected.len() != actual.len() {
+                    println!("Backend {} produced {} tokens, regex produced {}", name, actual.len(), expected.len());
+                    succeeded = false;
+                } else if expected_symbols.get_symbols() != actual_symbols.get_symbols() {
+                    println!("Backend {} recorded a different symbol table", name);
+                    succeeded = false;
+                } else {
+                    println!("Backend {} matches regex: {} tokens", name, actual.len());
+                }
+            }
+            (Err(expected), Err(actual)) if expected == actual => {
+                println!("Backend {} matches regex: both stop with {}", name, actual[0]);
+            }
+            (expected, actual) => {
+                let describe = |r: &Result<(Vec<Token>, lexer::SymbolTable), Vec<LexError>>| match r {
+                    Ok((tokens, _)) => format!("{} tokens", tokens.len()),
+                    Err(errors) => errors[0].to_string(),
+                };
+                println!("Backend {} differs: regex gave {}, {} gave {}", name, describe(expected), name, describe(actual));
+                succeeded = false;
+            }
+        }
+    }
+    
+    FileResult::new(if succeeded { EXIT_SUCCESS } else { EXIT_MISMATCH })
+}
+
+// Parses the command line on top of the settings in the `.mcpprc` nearest
+// the first input, or the current directory without inputs. The file's
+// settings come first, so a flag given on the command line wins; switches
+// and lists the file turns on stay on. Unknown keys are only warned about.
+fn load_options(args: &[String]) -> Result<Options, String> {
+    let options = parse_args(args)?;
+    if options.no_config {
+        return Ok(options);
+    }
+    let start = match options.filenames.first().and_then(|f| Path::new(f).parent()) {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => Path::new(".").to_path_buf(),
+    };
+    let Some(path) = config::find_config(&start) else {
+        return Ok(options);
+    };
+    let in_config = |e: String| format!("{}: {}", path.display(), e);
+    let text = fs::read_to_string(&path).map_err(|e| in_config(e.to_string()))?;
+    let entries = config::parse_config(&text).map_err(in_config)?;
+    let (mut all, warnings) = config_args(&entries).map_err(in_config)?;
+    for warning in warnings {
+        eprintln!("Warning: {}: {}", path.display(), warning);
+    }
+    all.extend(args.iter().cloned());
+    parse_args(&all).map_err(in_config)
+}
+
+// The arguments a config file's settings stand for, and a warning for each
+// key that means nothing.
+fn config_args(entries: &[ConfigEntry]) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut args = Vec::new();
+    let mut warnings = Vec::new();
+    for entry in entries {
+        let Some((_, flag)) = CONFIG_KEYS.iter().find(|(key, _)| *key == entry.key) else {
+            warnings.push(format!("Line {}: unknown key '{}' ignored", entry.line, entry.key));
+            continue;
+        };
+        let mismatch = |expected: &str| format!("Line {}: '{}' expects {}", entry.line, entry.key, expected);
+        let items = match &entry.value {
+            ConfigValue::Array(items) => items.iter().map(|item| item.as_arg().ok_or_else(|| mismatch("a flat array"))).collect(),
+            single => vec![single.as_arg().ok_or_else(|| mismatch("a value"))],
+        }
+        .into_iter()
+        .collect::<Result<Vec<String>, String>>()?;
+        match (flag, &entry.value) {
+            (ConfigFlag::Switch(name), ConfigValue::Bool(on)) => {
+                if *on {
+                    args.push(name.to_string());
+                }
+            }
+            (ConfigFlag::Toggle(on, off), ConfigValue::Bool(value)) => args.push(if *value { on } else { off }.to_string()),
+            (ConfigFlag::Switch(_) | ConfigFlag::Toggle(..), _) => return Err(mismatch("true or false")),
+            (ConfigFlag::Value(_), ConfigValue::Array(_) | ConfigValue::Bool(_)) => return Err(mismatch("a string or integer")),
+            (ConfigFlag::Value(name), _) => args.extend([name.to_string(), items[0].clone()]),
+            (ConfigFlag::Each(name), _) => {
+                for item in items {
+                    args.extend([name.to_string(), item]);
+                }
+            }
+            (ConfigFlag::Joined(name), _) => args.extend([name.to_string(), items.join(",")]),
+        }
+    }
+    Ok((args, warnings))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        filenames: Vec::new(),
+        format: Format::Json,
+        report_tokens: false,
+        output: None,
+        compact_json: false,
+        compress: false,
+        compression_level: gzip::DEFAULT_LEVEL,
+        check: None,
+        check_policy: ComparePolicy::default(),
+        check_symbols: None,
+        symbol_check_policy: SymbolCheckPolicy::default(),
+        diagnostics_format: DiagnosticsFormat::Text,
+        diagnostics_output: None,
+        emit_bison_defs: None,
+        emit_flex: None,
+        emit_precedence_table: false,
+        emit_spec: None,
+        generate: None,
+        dfa: None,
+        emit_dfa: None,
+        trace_dfa: None,
+        trace: false,
+        step: false,
+        backend: Backend::Regex,
+        compare_backends: false,
+        compare_legacy: None,
+        emit_rust_fixture: false,
+        cache_dir: None,
+        no_cache: false,
+        force: false,
+        no_config: false,
+        fold_signs: false,
+        manifest: None,
+        summary_json: false,
+        quiet: false,
+        usage_report: None,
+        rename: None,
+        lexer_options: LexerOptions::default(),
+        diagnostic_filter: DiagnosticFilter::default(),
+        deny_warnings: false,
+        excerpts: false,
+        tab_width: visual::DEFAULT_TAB_WIDTH,
+        position_base: PositionBase::One,
+        symbol_print: SymbolTablePrintOptions::default(),
+        token_print: TokenStreamPrintOptions::default(),
+        call_graph: None,
+        scope_tree: false,
+        metrics: false,
+        allocations: false,
+        lifetimes: false,
+        detect_duplication: false,
+        duplication_threshold: duplication::DEFAULT_DUPLICATION_THRESHOLD,
+        histogram: false,
+        histogram_svg: None,
+        histogram_top: histogram::DEFAULT_TOP_IDENTIFIERS,
+        annotate: false,
+        color: ColorChoice::Auto,
+        time: false,
+    };
+    
+    let mut profile = LexerProfile::Standard;
+    let mut overrides: Vec<OptionOverride> = Vec::new();
+    let mut integer_width = IntegerWidth::default();
+    let mut column_unit = ColumnUnit::default();
+    let mut identifier_chars = String::new();
+    let mut leading_underscore = LeadingUnderscore::default();
+    let mut max_nesting = None;
+    let mut max_complexity = None;
+    let mut warn_non_ascii_strings = false;
+    let mut max_token_length = None;
+    let mut max_ascii_errors = None;
+    let mut lint_const_candidates = false;
+    let mut indent_style = None;
+    let mut naming: Option<NamingRules> = None;
+    let (mut generate, mut seed, mut size) = (false, 0, 1000);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--compact-json" => options.compact_json = true,
+            "--compress" => options.compress = true,
+            "--format" => {
+                options.format = match iter.next().map(|s| s.as_str()) {
+                    Some("json") => Format::Json,
+                    Some("markdown") => Format::Markdown,
+                    Some("bison-stream") => Format::BisonStream,
+                    Some("batch-json") => Format::BatchJson,
+                    Some("token-tree") => Format::TokenTree,
+                    Some(other) => return Err(format!("Unknown format '{}' (expected json, markdown, bison-stream, batch-json or token-tree)", other)),
+                    None => return Err("--format requires a value".to_string()),
+                };
+            }
+            "--call-graph" => {
+                options.call_graph = match iter.next().map(|s| s.as_str()) {
+                    Some("text") => Some(CallGraphFormat::Text),
+                    Some("dot") => Some(CallGraphFormat::Dot),
+                    Some(other) => return Err(format!("Unknown call graph format '{}' (expected text or dot)", other)),
+                    None => return Err("--call-graph requires a value".to_string()),
+                };
+            }
+            "--scope-tree" => options.scope_tree = true,
+            "--metrics" => options.metrics = true,
+            "--allocations" => options.allocations = true,
+            "--lifetimes" => options.lifetimes = true,
+            "--detect-duplication" => options.detect_duplication = true,
+            "--duplication-threshold" => {
+                let value = iter.next().ok_or("--duplication-threshold requires a percentage")?;
+                options.duplication_threshold = match value.parse::<f64>() {
+                    Ok(percent) if (0.0..=100.0).contains(&percent) => percent,
+                    _ => return Err(format!("Invalid duplication threshold '{}' (expected a percentage from 0 to 100)", value)),
+                };
+            }
+            "--histogram" => options.histogram = true,
+            "--histogram-svg" => {
+                options.histogram_svg = Some(iter.next().ok_or("--histogram-svg requires a path")?.clone());
+            }
+            "--histogram-top" => {
+                let value = iter.next().ok_or("--histogram-top requires a count")?;
+                options.histogram_top = value.parse().map_err(|_| format!("Invalid identifier count '{}'", value))?;
+            }
+            "--annotate" => options.annotate = true,
+            "--color" => {
+                let name = iter.next().ok_or("--color requires a value")?;
+                options.color = ColorChoice::from_name(name).ok_or_else(|| {
+                    format!("Unknown color choice '{}' (expected one of: {})", name, annotate::COLOR_CHOICE_NAMES.join(", "))
+                })?;
+            }
+            "--report-tokens" => options.report_tokens = true,
+            "--trace" => options.trace = true,
+            "--step" => options.step = true,
+            "--backend" => {
+                let name = iter.next().ok_or("--backend requires a name")?;
+                options.backend = Backend::from_name(name).ok_or_else(|| {
+                    format!("Unknown backend '{}' (expected one of: {})", name, lexer::BACKEND_NAMES.join(", "))
+                })?;
+            }
+            "--compare-backends" => options.compare_backends = true,
+            "--cache-dir" => {
+                options.cache_dir = Some(iter.next().ok_or("--cache-dir requires a path")?.clone());
+            }
+            "--no-cache" => options.no_cache = true,
+            "--force" => options.force = true,
+            "--no-config" => options.no_config = true,
+            "--manifest" => {
+                options.manifest = Some(iter.next().ok_or("--manifest requires a path")?.clone());
+            }
+            "--profile" => {
+                let name = iter.next().ok_or("--profile requires a name")?;
+                profile = LexerProfile::from_name(name).ok_or_else(|| {
+                    format!("Unknown profile '{}' (expected one of: {})", name, lexer::PROFILE_NAMES.join(", "))
+                })?;
+            }
+            "--lenient-whitespace" => overrides.push(|o| o.lenient_whitespace = true),
+            "--unicode-identifiers" => overrides.push(|o| o.unicode_identifiers = true),
+            "--keep-comments" => overrides.push(|o| o.keep_comments = true),
+            "--concat-strings" => overrides.push(|o| o.concat_strings = true),
+            "--word-operators" => overrides.push(|o| o.word_operators = true),
+            "--ascii-only" => overrides.push(|o| o.ascii_only = true),
+            "--fold-signs" => options.fold_signs = true,
+            "--summary-json" => options.summary_json = true,
+            "-q" | "--quiet" => options.quiet = true,
+            "--usage-report" => {
+                options.usage_report = match iter.next().map(|s| s.as_str()) {
+                    Some("text") => Some(UsageFormat::Text),
+                    Some("csv") => Some(UsageFormat::Csv),
+                    Some(other) => return Err(format!("Unknown usage report format '{}' (expected text or csv)", other)),
+                    None => return Err("--usage-report requires a value".to_string()),
+                };
+            }
+            "--rename" => {
+                let value = iter.next().ok_or("--rename requires old=new")?;
+                let (old, new) = value.split_once('=').ok_or_else(|| format!("Invalid rename '{}' (expected old=new)", value))?;
+                options.rename = Some((old.to_string(), new.to_string()));
+            }
+            "--strict-escapes" => overrides.push(|o| o.strict_escapes = true),
+            "--error-recovery" => overrides.push(|o| o.error_recovery = true),
+            "--no-error-recovery" => overrides.push(|o| o.error_recovery = false),
+            "--strict-literals" => overrides.push(|o| o.strict_literals = true),
+            "--naming" => {
+                let spec = iter.next().ok_or("--naming requires kind=convention pairs")?;
+                let allowed = naming.take().map(|rules| rules.allowed).unwrap_or_default();
+                naming = Some(NamingRules { allowed, ..NamingRules::parse(spec)? });
+            }
+            "--naming-allow" => {
+                let names = iter.next().ok_or("--naming-allow requires a list of names")?;
+                naming
+                    .get_or_insert_with(NamingRules::default)
+                    .allowed
+                    .extend(names.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()));
+            }
+            "--int-width" => {
+                let name = iter.next().ok_or("--int-width requires a width")?;
+                integer_width = IntegerWidth::from_name(name).ok_or_else(|| {
+                    format!("Unknown integer width '{}' (expected one of: {})", name, lexer::INTEGER_WIDTH_NAMES.join(", "))
+                })?;
+            }
+            "--column-unit" => {
+                let name = iter.next().ok_or("--column-unit requires a unit")?;
+                column_unit = ColumnUnit::from_name(name).ok_or_else(|| {
+                    format!("Unknown column unit '{}' (expected one of: {})", name, position::COLUMN_UNIT_NAMES.join(", "))
+                })?;
+            }
+            "--identifier-chars" => {
+                let chars = iter.next().ok_or("--identifier-chars requires characters")?;
+                // A character that starts another token would change how
+                // ordinary code lexes
+                if let Some(ch) = chars.chars().find(|&ch| {
+                    ch.is_whitespace() || ch.is_ascii_alphanumeric() || "_\"'#\\".contains(ch) || scanner::scan_token(&ch.to_string()).is_some()
+                }) {
+                    return Err(format!("'{}' cannot be an extra identifier character", ch));
+                }
+                identifier_chars = chars.clone();
+            }
+            "--leading-underscore" => {
+                let name = iter.next().ok_or("--leading-underscore requires a policy")?;
+                leading_underscore = LeadingUnderscore::from_name(name).ok_or_else(|| {
+                    format!("Unknown leading underscore policy '{}' (expected one of: {})", name, lexer::LEADING_UNDERSCORE_NAMES.join(", "))
+                })?;
+            }
+            "--indent-style" => {
+                let name = iter.next().ok_or("--indent-style requires a style")?;
+                indent_style = Some(IndentStyle::from_name(name).ok_or_else(|| {
+                    format!("Unknown indent style '{}' (expected one of: {})", name, lexer::INDENT_STYLE_NAMES.join(", "))
+                })?);
+            }
+            "--max-nesting" => {
+                let value = iter.next().ok_or("--max-nesting requires a depth")?;
+                max_nesting = Some(value.parse().map_err(|_| format!("Invalid nesting depth '{}'", value))?);
+            }
+            "--max-complexity" => {
+                let value = iter.next().ok_or("--max-complexity requires a number")?;
+                max_complexity = Some(value.parse().map_err(|_| format!("Invalid complexity '{}'", value))?);
+            }
+            "--max-token-length" => {
+                let value = iter.next().ok_or("--max-token-length requires a length")?;
+                max_token_length = Some(value.parse().map_err(|_| format!("Invalid token length '{}'", value))?);
+            }
+            "--max-ascii-errors" => {
+                let value = iter.next().ok_or("--max-ascii-errors requires a count")?;
+                max_ascii_errors = Some(value.parse().ok().filter(|&count| count > 0).ok_or_else(|| format!("Invalid error count '{}'", value))?);
+            }
+            "--warn" => {
+                let name = iter.next().ok_or("--warn requires a warning name")?;
+                match name.as_str() {
+                    "non-ascii-strings" => warn_non_ascii_strings = true,
+                    _ => return Err(format!("Unknown opt-in warning '{}' (expected one of: non-ascii-strings)", name)),
+                }
+            }
+            "--lint" => {
+                let name = iter.next().ok_or("--lint requires a lint name")?;
+                match name.as_str() {
+                    "const-candidates" => lint_const_candidates = true,
+                    _ => return Err(format!("Unknown lint '{}' (expected one of: const-candidates)", name)),
+                }
+            }
+            "--warn-level" => {
+                let name = iter.next().ok_or("--warn-level requires a level")?;
+                options.diagnostic_filter.min_severity = Severity::from_name(name).ok_or_else(|| {
+                    format!("Unknown level '{}' (expected one of: {})", name, diagnostics::SEVERITY_NAMES.join(", "))
+                })?;
+            }
+            "--allow" => {
+                let code = iter.next().ok_or("--allow requires a warning code")?;
+                if !WARNING_CODES.iter().chain(NOTE_CODES).any(|(known, _, _)| known == code) {
+                    return Err(format!("Unknown warning or note code '{}' (errors cannot be allowed)", code));
+                }
+                options.diagnostic_filter.allowed.push(code.clone());
+            }
+            "--deny-warnings" => options.deny_warnings = true,
+            "--excerpts" => options.excerpts = true,
+            "--tab-width" => {
+                let value = iter.next().ok_or("--tab-width requires a width")?;
+                options.tab_width = value.parse().ok().filter(|&width| width > 0).ok_or_else(|| format!("Invalid tab width '{}'", value))?;
+            }
+            "--time" => options.time = true,
+            "--symbols-sort" => {
+                let name = iter.next().ok_or("--symbols-sort requires a key")?;
+                options.symbol_print.sort = Some(SymbolSort::from_name(name).ok_or_else(|| {
+                    format!("Unknown sort key '{}' (expected one of: {})", name, lexer::SYMBOL_SORT_NAMES.join(", "))
+                })?);
+            }
+            "--symbols-filter" => {
+                let filter = iter.next().ok_or("--symbols-filter requires a filter")?;
+                options.symbol_print.add_filter(filter)?;
+            }
+            "--symbols-by-scope" => options.symbol_print.by_scope = true,
+            "--position-base" => {
+                let name = iter.next().ok_or("--position-base requires 0 or 1")?;
+                options.position_base = PositionBase::from_name(name).ok_or_else(|| {
+                    format!("Unknown position base '{}' (expected one of: {})", name, position::POSITION_BASE_NAMES.join(", "))
+                })?;
+            }
+            "--diagnostics-format" => {
+                options.diagnostics_format = match iter.next().map(|s| s.as_str()) {
+                    Some("text") => DiagnosticsFormat::Text,
+                    Some("sarif") => DiagnosticsFormat::Sarif,
+                    Some(other) => return Err(format!("Unknown diagnostics format '{}' (expected text or sarif)", other)),
+                    None => return Err("--diagnostics-format requires a value".to_string()),
+                };
+            }
+            "--emit-bison-defs" => {
+                options.emit_bison_defs = Some(iter.next().ok_or("--emit-bison-defs requires a path")?.clone());
+            }
+            "--emit-flex" => {
+                options.emit_flex = Some(iter.next().ok_or("--emit-flex requires a path")?.clone());
+            }
+            "--emit-precedence-table" => options.emit_precedence_table = true,
+            "--emit-spec" => {
+                options.emit_spec = match iter.next().map(|s| s.as_str()) {
+                    Some("text") => Some(SpecFormat::Text),
+                    Some("markdown") => Some(SpecFormat::Markdown),
+                    Some(other) => return Err(format!("Unknown specification format '{}' (expected text or markdown)", other)),
+                    None => return Err("--emit-spec requires a value".to_string()),
+                };
+            }
+            "--generate" => generate = true,
+            "--seed" => {
+                let value = iter.next().ok_or("--seed requires a number")?;
+                seed = value.parse().map_err(|_| format!("Invalid seed '{}' (expected a non-negative integer)", value))?;
+            }
+            "--head" | "--tail" => {
+                let value = iter.next().ok_or_else(|| format!("{} requires a token count", arg))?;
+                let count = value.parse().map_err(|_| format!("Invalid token count '{}' for {}", value, arg))?;
+                if arg == "--head" {
+                    options.token_print.head = Some(count);
+                } else {
+                    options.token_print.tail = Some(count);
+                }
+            }
+            "--lines" => {
+                let range = iter.next().ok_or("--lines requires a range such as 10-40")?;
+                options.token_print.set_lines(range)?;
+            }
+            "--function" => {
+                let name = iter.next().ok_or("--function requires a function name")?;
+                options.token_print.function = Some(name.clone());
+            }
+            "--all" => options.token_print.limit = None,
+            "--size" => {
+                let value = iter.next().ok_or("--size requires a token count")?;
+                size = value.parse().map_err(|_| format!("Invalid size '{}' (expected a token count)", value))?;
+            }
+            "--dfa" => {
+                let name = iter.next().ok_or("--dfa requires a name")?;
+                if Dfa::by_name(name).is_none() {
+                    return Err(format!("Unknown DFA '{}' (expected one of: {})", name, dfa::DFA_NAMES.join(", ")));
+                }
+                options.dfa = Some(name.clone());
+            }
+            "--emit-dfa" => {
+                options.emit_dfa = Some(iter.next().ok_or("--emit-dfa requires a path")?.clone());
+            }
+            "--trace-dfa" => {
+                options.trace_dfa = Some(iter.next().ok_or("--trace-dfa requires a lexeme")?.clone());
+            }
+            "--diagnostics-output" => {
+                options.diagnostics_output = Some(iter.next().ok_or("--diagnostics-output requires a path")?.clone());
+            }
+            "--compression-level" => {
+                let value = iter.next().ok_or("--compression-level requires a value")?;
+                options.compression_level = match value.parse() {
+                    Ok(level) if level <= 9 => level,
+                    _ => return Err(format!("Invalid compression level '{}' (expected 0-9)", value)),
+                };
+            }
+            "-o" | "--output" => {
+                options.output = Some(iter.next().ok_or("--output requires a path")?.clone());
+            }
+            "--check" => {
+                options.check = Some(iter.next().ok_or("--check requires a path")?.clone());
+            }
+            "--ignore" => {
+                let names = iter.next().ok_or("--ignore requires positions, lexemes or comments")?;
+                for name in names.split(',') {
+                    options.check_policy.ignore(name.trim())?;
+                }
+            }
+            "--check-symbols" => {
+                options.check_symbols = Some(iter.next().ok_or("--check-symbols requires a path")?.clone());
+            }
+            "--ignore-symbol-lines" => options.symbol_check_policy.ignore_lines = true,
+            "--allow-extra-symbols" => options.symbol_check_policy.allow_extra = true,
+            "--compare-legacy" => {
+                options.compare_legacy = Some(iter.next().ok_or("--compare-legacy requires a path")?.clone());
+            }
+            "--emit-rust-fixture" => options.emit_rust_fixture = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => options.filenames.push(arg.clone()),
+        }
+    }
+    
+    options.lexer_options = LexerOptions::for_profile(profile);
+    for apply in overrides {
+        apply(&mut options.lexer_options);
+    }
+    options.lexer_options.integer_width = integer_width;
+    options.lexer_options.column_unit = column_unit;
+    options.lexer_options.identifier_chars = identifier_chars;
+    options.lexer_options.leading_underscore = leading_underscore;
+    options.lexer_options.max_nesting = max_nesting;
+    options.lexer_options.max_complexity = max_complexity;
+    options.lexer_options.warn_non_ascii_strings = warn_non_ascii_strings;
+    options.lexer_options.max_token_length = max_token_length;
+    options.lexer_options.max_ascii_errors = max_ascii_errors;
+    options.lexer_options.lint_const_candidates = lint_const_candidates;
+    options.lexer_options.indent_style = indent_style;
+    options.lexer_options.naming = naming;
+    // Comments are written back out with the renamed source
+    if options.rename.is_some() {
+        options.lexer_options.keep_comments = true;
+    }
+    options.generate = generate.then_some((seed, size));
+    
+    let standalone = options.emit_bison_defs.is_some()
+        || options.emit_flex.is_some()
+        || options.emit_precedence_table
+        || options.emit_spec.is_some()
+        || options.generate.is_some()
+        || options.emit_dfa.is_some()
+        || options.trace_dfa.is_some();
+    if options.filenames.is_empty() && !standalone {
+        return Err(String::new());
+    }
+    if options.token_print.head.is_some() && options.token_print.tail.is_some() {
+        return Err("--head and --tail cannot be combined".to_string());
+    }
+    if options.summary_json && options.usage_report.is_some() {
+        return Err("--summary-json and --usage-report cannot be combined".to_string());
+    }
+    if options.check_policy != ComparePolicy::default() && options.check.is_none() {
+        return Err("--ignore only applies to --check".to_string());
+    }
+    if options.symbol_check_policy != SymbolCheckPolicy::default() && options.check_symbols.is_none() {
+        return Err("--ignore-symbol-lines and --allow-extra-symbols only apply to --check-symbols".to_string());
+    }
+    let single_output = options.output.is_some() && options.format != Format::BatchJson;
+    if options.filenames.len() > 1
+        && (single_output || options.check.is_some() || options.compare_legacy.is_some() || options.emit_rust_fixture || options.histogram_svg.is_some())
+    {
+        return Err("--output, --check, --compare-legacy, --emit-rust-fixture and --histogram-svg accept a single input file, except --output with --format batch-json".to_string());
+    }
+    Ok(options)
+}
+
+// Everything besides the source text that changes the token stream.
+fn cache_fingerprint(options: &Options) -> String {
+    format!("{} {:?} fold_signs={}", options.backend.name(), options.lexer_options, options.fold_signs)
+}
+
+// The input's path with its extension, whatever it is, replaced by
+// `suffix`, so an output never takes the input's own name.
+fn derived_filename(filename: &str, suffix: &str) -> String {
+    format!("{}{}", Path::new(filename).with_extension("").display(), suffix)
+}
+
+fn output_filename(filename: &str, options: &Options) -> String {
+    if let Some(output) = &options.output {
+        return output.clone();
+    }
+    let json_filename = derived_filename(filename, "_tokens.json");
+    if options.compress {
+        format!("{}.gz", json_filename)
+    } else {
+        json_filename
+    }
+}
+
+// Writes the %token block to `path` and a matching C header next to it.
+fn emit_bison_defs(path: &str) -> io::Result<()> {
+    let header_path = Path::new(path).with_extension("h");
+    let guard = header_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_uppercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_"))
+        .unwrap_or_else(|| "MCPP_TOKENS_H".to_string());
+    fs::write(path, bison::token_declarations())?;
+    fs::write(&header_path, bison::token_header(&guard))?;
+    println!("Bison token definitions saved to: {} and {}", path, header_path.display());
+    Ok(())
+}
+
+fn run_dfa_tools(options: &Options) -> Result<(), String> {
+    let selected: Vec<Dfa> = match &options.dfa {
+        Some(name) => Dfa::by_name(name).into_iter().collect(),
+        None => Dfa::all(),
+    };
+    
+    if let Some(path) = &options.emit_dfa {
+        for automaton in &selected {
+            println!("{}", automaton.transition_table());
+        }
+        let dot: Vec<String> = selected.iter().map(|d| d.to_dot()).collect();
+        fs::write(path, dot.join("\n")).map_err(|e| format!("Error writing DFA file '{}': {}", path, e))?;
+        println!("DFA graph saved to: {}", path);
+    }
+    
+    if let Some(lexeme) = &options.trace_dfa {
+        // Without --dfa, use the automaton that can start on the first character
+        let first = lexeme.chars().next().ok_or("--trace-dfa requires a non-empty lexeme")?;
+        let automaton = selected
+            .iter()
+            .find(|d| options.dfa.is_some() || d.step(Dfa::START, first).is_some())
+            .ok_or_else(|| format!("No DFA starts with '{}'", first))?;
+        let trace = automaton.trace(lexeme);
+        println!("DFA: {}", automaton.name);
+        println!("{}", automaton.format_trace(lexeme, &trace));
+    }
+    Ok(())
+}
+
+fn write_json_file(
+    lexer: &Lexer,
+    path: &str,
+    pretty: bool,
+    compression_level: Option<u32>,
+    filter: &DiagnosticFilter,
+    base: PositionBase,
+    by_scope: bool,
+) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    match compression_level {
+        Some(level) => {
+            let mut encoder = GzEncoder::new(writer, level);
+            lexer.write_json(&mut encoder, pretty, filter, base, by_scope)?;
+            encoder.finish()?.flush()
+        }
+        None => {
+            let mut writer = writer;
+            lexer.write_json(&mut writer, pretty, filter, base, by_scope)?;
+            writer.flush()
+        }
+    }
+}
+
+// Expected files may be plain or gzip-compressed JSON, either a document or
+// a legacy bare token array; the magic bytes decide on decompression.
+fn read_expected_tokens(path: &str) -> Result<Vec<Token>, String> {
+    let mut bytes = fs::read(path).map_err(|e| format!("Error reading file '{}': {}", path, e))?;
+    if gzip::is_gzip(&bytes) {
+        bytes = gzip::decompress(&bytes).map_err(|e| format!("Error decompressing '{}': {}", path, e))?;
+    }
+    TokenStream::from_json_slice(&bytes)
+        .map(TokenStream::into_tokens)
+        .map_err(|e| format!("Error loading '{}': {}", path, e))
+}
+
+// Under --quiet, only a check that fails is reported.
+fn check_symbols_against(table: &SymbolTable, expected_path: &str, policy: SymbolCheckPolicy, quiet: bool) -> i32 {
+    let expected = fs::read_to_string(expected_path)
+        .map_err(|e| format!("Error reading file '{}': {}", expected_path, e))
+        .and_then(|text| symbol_check::parse_expected_symbols(&text).map_err(|e| format!("Error loading '{}': {}", expected_path, e)));
+    let expected = match expected {
+        Ok(expected) => expected,
+        Err(e) => {
+            eprintln!("\n{}", e);
+            return EXIT_USAGE;
         }
+    };
+    let report = symbol_check::check_symbols(table, &expected, policy);
+    if !quiet || !report.success() {
+        print!("\n{}", symbol_check::symbol_check_text(&report));
+    }
+    if report.success() {
+        EXIT_SUCCESS
+    } else {
+        EXIT_MISMATCH
+    }
+}
+
+fn check_against(tokens: &[Token], expected_path: &str, policy: ComparePolicy, quiet: bool) -> i32 {
+    let expected = match read_expected_tokens(expected_path) {
+        Ok(expected) => expected,
         Err(e) => {
             eprintln!("\n{}", e);
-            process::exit(1);
+            return EXIT_USAGE;
         }
+    };
+    
+    let mismatch = token_stream::first_mismatch(&expected, tokens, policy);
+    if !quiet || mismatch.is_some() {
+        println!("\n=== CHECK ===");
+        println!("Policy: {}", policy.describe());
+    }
+    match mismatch {
+        Some(TokenMismatch::Differs { index, expected, actual }) => {
+            println!("Mismatch at token {}:", index);
+            println!("  expected: {}", expected.to_compiler_format());
+            println!("  actual:   {}", actual.to_compiler_format());
+            return EXIT_MISMATCH;
+        }
+        Some(TokenMismatch::Count { expected, actual }) => {
+            println!("Token count differs: expected {}, actual {}", expected, actual);
+            return EXIT_MISMATCH;
+        }
+        None => {}
+    }
+    if !quiet {
+        println!("Check passed: {} tokens match {} ({})", tokens.len(), expected_path, policy.describe());
     }
+    EXIT_SUCCESS
 }
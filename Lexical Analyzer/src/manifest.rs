@@ -0,0 +1,76 @@
+use crate::cache::content_hash;
+use serde::{Deserialize, Serialize};
+
+// Bumped whenever a field changes meaning or disappears; new fields may be
+// added without a bump.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+// One run of the command line as `--manifest` records it, for CI wrappers
+// that want a single artifact to read: what was asked, what each input came
+// to, what was written and the exit status.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub schema_version: u32,
+    pub tool: String,
+    pub version: String,
+    // The arguments after the program name, as given
+    pub arguments: Vec<String>,
+    pub files: Vec<ManifestFile>,
+    // Files written for the run as a whole, such as the SARIF log
+    pub outputs: Vec<String>,
+    pub elapsed_ms: f64,
+    pub exit_code: i32,
+}
+
+impl RunManifest {
+    pub fn new(arguments: Vec<String>) -> Self {
+        RunManifest {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            arguments,
+            files: Vec::new(),
+            outputs: Vec::new(),
+            elapsed_ms: 0.0,
+            exit_code: 0,
+        }
+    }
+}
+
+// One input. `content_hash` is the source hash `batch-json` entries carry,
+// and None with `failure` saying why when the file could not be read. The
+// counts are of the diagnostics the filter shows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub path: String,
+    pub content_hash: Option<String>,
+    pub success: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    // Files written for this input, such as its token JSON
+    pub outputs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+impl ManifestFile {
+    pub fn new(path: &str, source: &str) -> Self {
+        ManifestFile {
+            path: path.to_string(),
+            content_hash: Some(format!("{:016x}", content_hash(&[source.as_bytes()]))),
+            success: false,
+            errors: 0,
+            warnings: 0,
+            outputs: Vec::new(),
+            failure: None,
+        }
+    }
+
+    pub fn unreadable(path: &str, failure: String) -> Self {
+        ManifestFile {
+            content_hash: None,
+            failure: Some(failure),
+            ..ManifestFile::new(path, "")
+        }
+    }
+}
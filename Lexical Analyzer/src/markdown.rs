@@ -0,0 +1,228 @@
+use crate::lexer::{SymbolTable, Token};
+use crate::nesting::{self, Nesting};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct ReportOptions {
+    pub include_tokens: bool,
+    pub date: String,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            include_tokens: false,
+            date: today(),
+        }
+    }
+}
+
+pub fn render_report(
+    filename: &str,
+    tokens: &[Token],
+    symbol_table: &SymbolTable,
+    diagnostics: &[String],
+    options: &ReportOptions,
+) -> String {
+    let symbols = symbol_table.get_symbols();
+    let mut out = String::new();
+
+    writeln!(out, "# Lexical Analysis Report: {}", code_span(filename)).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "- **File:** {}", code_span(filename)).unwrap();
+    writeln!(out, "- **Date:** {}", options.date).unwrap();
+    writeln!(out, "- **Tokens:** {}", tokens.len()).unwrap();
+    writeln!(out, "- **Symbols:** {}", symbols.len()).unwrap();
+
+    writeln!(out, "\n## Symbol Table\n").unwrap();
+    if symbols.is_empty() {
+        writeln!(out, "No symbols.").unwrap();
+    } else {
+        writeln!(out, "| Name | Type | Data Type | Scope | Line | Doc |").unwrap();
+        writeln!(out, "|------|------|-----------|-------|------|-----|").unwrap();
+        for symbol in symbols {
+            writeln!(out, "| {} | {} | {} | {} | {} | {} |",
+                escape_cell(&symbol.name),
+                escape_cell(&symbol.symbol_type),
+                escape_cell(&symbol.data_type),
+                escape_cell(&symbol.scope),
+                symbol.line,
+                escape_cell(symbol.doc.as_deref().unwrap_or(""))
+            ).unwrap();
+        }
+    }
+
+    writeln!(out, "\n## Diagnostics\n").unwrap();
+    if diagnostics.is_empty() {
+        writeln!(out, "No diagnostics.").unwrap();
+    } else {
+        for diagnostic in diagnostics {
+            writeln!(out, "- {}", escape_cell(diagnostic)).unwrap();
+        }
+    }
+
+    writeln!(out, "\n## Token Statistics\n").unwrap();
+    writeln!(out, "| Token Type | Count |").unwrap();
+    writeln!(out, "|------------|-------|").unwrap();
+    for (token_type, count) in token_counts(tokens) {
+        writeln!(out, "| {} | {} |", token_type, count).unwrap();
+    }
+
+    writeln!(out, "\n## Nesting\n").unwrap();
+    let report = nesting::nesting_report(tokens);
+    let deepest: Vec<_> = [Nesting::Brace, Nesting::Paren, Nesting::Bracket]
+        .into_iter()
+        .filter_map(|kind| report.get(kind).map(|max| (kind, max)))
+        .collect();
+    if deepest.is_empty() {
+        writeln!(out, "No braces, parentheses or brackets.").unwrap();
+    } else {
+        writeln!(out, "| Kind | Deepest | First Reached |").unwrap();
+        writeln!(out, "|------|---------|---------------|").unwrap();
+        for (kind, max) in deepest {
+            writeln!(out, "| {} | {} | line {}, column {} |", kind.label(), max.depth, max.line, max.column).unwrap();
+        }
+    }
+
+    if options.include_tokens {
+        let stream: Vec<String> = tokens.iter().map(|t| t.to_compiler_format()).collect();
+        let stream = stream.join("\n");
+        let fence = "`".repeat(longest_backtick_run(&stream).max(2) + 1);
+        writeln!(out, "\n## Token Stream\n").unwrap();
+        writeln!(out, "<details>").unwrap();
+        writeln!(out, "<summary>Show all {} tokens</summary>\n", tokens.len()).unwrap();
+        writeln!(out, "{}text\n{}\n{}", fence, stream, fence).unwrap();
+        writeln!(out, "\n</details>").unwrap();
+    }
+
+    out
+}
+
+// Counts per token type, most frequent first and alphabetical among ties.
+fn token_counts(tokens: &[Token]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for token in tokens {
+        *counts.entry(token.token_type.to_string()).or_insert(0) += 1;
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+// Keeps table rows and list items on one line and stops `|` from splitting cells.
+pub fn escape_cell(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '|' => escaped.push_str("\\|"),
+            '`' => escaped.push_str("\\`"),
+            '\n' => escaped.push_str("<br>"),
+            '\r' => {}
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+// Inline code holding `text`, fenced with more backticks than it contains.
+pub fn code_span(text: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", ticks, text, ticks)
+    } else {
+        format!("{}{}{}", ticks, text, ticks)
+    }
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for ch in text.chars() {
+        if ch == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+// Current UTC date as YYYY-MM-DD.
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn report(source: &str, include_tokens: bool) -> String {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        let options = ReportOptions {
+            include_tokens,
+            date: "2026-01-01".to_string(),
+        };
+        render_report("example1.mcpp", lexer.get_tokens(), lexer.get_symbol_table(), &[], &options)
+    }
+
+    #[test]
+    fn example_report_matches_golden_file() {
+        let source = include_str!("../examples/example1.mcpp");
+        assert_eq!(report(source, true), include_str!("../tests/golden/example1_report.md"));
+    }
+
+    #[test]
+    fn token_stream_is_optional() {
+        let report = report("int x = 1;\n", false);
+        assert!(!report.contains("## Token Stream"));
+        assert!(report.contains("| x | variable | int | global | 1 |  |"));
+    }
+
+    #[test]
+    fn cells_escape_pipes_backticks_and_newlines() {
+        assert_eq!(escape_cell("a|b`c\r\nd"), "a\\|b\\`c<br>d");
+        assert_eq!(code_span("x`y"), "``x`y``");
+        assert_eq!(code_span("`x"), "`` `x ``");
+    }
+
+    #[test]
+    fn diagnostics_are_listed_and_escaped() {
+        let options = ReportOptions {
+            include_tokens: false,
+            date: "2026-01-01".to_string(),
+        };
+        let report = render_report("a.mcpp", &[], &SymbolTable::new(), &["Error at line 1: '|'".to_string()], &options);
+        assert!(report.contains("- Error at line 1: '\\|'\n"));
+        assert!(report.contains("No symbols."));
+    }
+
+    #[test]
+    fn dates_are_proleptic_gregorian() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
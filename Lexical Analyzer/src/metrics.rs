@@ -0,0 +1,196 @@
+use crate::lexer::{Delimiter, Keyword, Operator, SymbolTableBuilder, Token, TokenType};
+use crate::position::PositionBase;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+// Size and shape of one function body, found the way the symbol table finds
+// them. `line` is where the function is declared and `end_line` where its
+// body closes; a body still open at EOF ends at the last token and has
+// `closed` false. Token counts cover the body, braces included, without
+// comments. `complexity` is 1 plus the `if`, `while`, `for`, `&&` and `||`
+// tokens in the body: an `else` adds no path of its own, and MCPP has no
+// `case` or `?` to count.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionMetrics {
+    pub name: String,
+    pub line: usize,
+    pub end_line: usize,
+    pub tokens: usize,
+    pub branches: usize,
+    // Deepest brace nesting, counting the body's own braces as 1.
+    pub max_depth: usize,
+    pub closed: bool,
+    #[serde(default)]
+    pub complexity: usize,
+}
+
+impl FunctionMetrics {
+    pub fn lines(&self) -> usize {
+        self.end_line - self.line + 1
+    }
+
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        self.line = from.rebase(self.line, to);
+        self.end_line = from.rebase(self.end_line, to);
+    }
+}
+
+pub fn function_metrics(tokens: &[Token]) -> Vec<FunctionMetrics> {
+    let mut builder = SymbolTableBuilder::new();
+    let mut functions: Vec<FunctionMetrics> = Vec::new();
+    let mut depth = 0;
+    for token in tokens.iter().filter(|t| t.token_type != TokenType::Comment && t.token_type != TokenType::EOF) {
+        let was_open = builder.current_function().is_some();
+        builder.observe(token);
+        if !was_open {
+            let Some(name) = builder.current_function() else {
+                continue;
+            };
+            let line = builder
+                .get_symbol_table()
+                .get_symbols()
+                .iter()
+                .rev()
+                .find(|s| s.name == name && s.symbol_type == "function")
+                .map_or(token.line, |s| s.line);
+            functions.push(FunctionMetrics {
+                name: name.to_string(),
+                line,
+                end_line: token.line,
+                tokens: 0,
+                branches: 0,
+                max_depth: 0,
+                closed: false,
+                complexity: 1,
+            });
+            depth = 0;
+        }
+        let function = functions.last_mut().unwrap();
+        function.tokens += 1;
+        function.end_line = token.end_line;
+        match token.token_type {
+            TokenType::Delimiter(Delimiter::LeftBrace) => {
+                depth += 1;
+                function.max_depth = function.max_depth.max(depth);
+            }
+            TokenType::Delimiter(Delimiter::RightBrace) => depth -= 1,
+            TokenType::Keyword(Keyword::If | Keyword::While | Keyword::For) => {
+                function.branches += 1;
+                function.complexity += 1;
+            }
+            TokenType::Operator(Operator::LogicalAnd | Operator::LogicalOr) => function.complexity += 1,
+            _ => {}
+        }
+        function.closed = builder.current_function().is_none();
+    }
+    functions
+}
+
+pub fn metrics_table(functions: &[FunctionMetrics]) -> String {
+    let mut out = String::from("=== FUNCTION METRICS ===\n");
+    writeln!(
+        out,
+        "{:<20} {:<10} {:<8} {:<8} {:<10} {:<10} {:<10}",
+        "Function", "Span", "Lines", "Tokens", "Branches", "Depth", "Complexity"
+    )
+    .unwrap();
+    writeln!(out, "{}", "-".repeat(81)).unwrap();
+    for function in functions {
+        let span = format!("{}-{}", function.line, function.end_line);
+        let name = if function.closed {
+            function.name.clone()
+        } else {
+            format!("{} (unclosed)", function.name)
+        };
+        writeln!(
+            out,
+            "{:<20} {:<10} {:<8} {:<8} {:<10} {:<10} {:<10}",
+            name,
+            span,
+            function.lines(),
+            function.tokens,
+            function.branches,
+            function.max_depth,
+            function.complexity
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    const SHAPES: &str = "int flat(int a) {\n    return a + 1;\n}\n\nint deep(int n) {\n    int s = 0;\n    for (int i = 0; i < n; i = i + 1) {\n        if (i > 2 && s < 10) {\n            while (s < i) {\n                s = s + 1;\n            }\n        } else {\n            s = s - 1;\n        }\n    }\n    return s;\n}\n";
+
+    fn metrics(source: &str) -> Vec<FunctionMetrics> {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        lexer.metrics()
+    }
+
+    #[test]
+    fn two_shapes_of_function() {
+        let functions = metrics(SHAPES);
+        assert_eq!(functions.len(), 2);
+        let flat = &functions[0];
+        assert_eq!((flat.name.as_str(), flat.line, flat.end_line, flat.lines()), ("flat", 1, 3, 3));
+        // `{ return a + 1 ; }`
+        assert_eq!((flat.tokens, flat.branches, flat.max_depth, flat.complexity, flat.closed), (7, 0, 1, 1, true));
+        let deep = &functions[1];
+        assert_eq!((deep.name.as_str(), deep.line, deep.end_line, deep.lines()), ("deep", 5, 17, 13));
+        // for, if and while branch; `&&` adds a path but is no branch
+        assert_eq!((deep.tokens, deep.branches, deep.max_depth, deep.complexity, deep.closed), (64, 3, 4, 5, true));
+    }
+
+    #[test]
+    fn unclosed_functions_are_flagged() {
+        let functions = metrics(&format!("{}\nint open() {{\n    if (1) {{\n", SHAPES));
+        assert_eq!(functions.len(), 3);
+        let open = &functions[2];
+        assert_eq!((open.name.as_str(), open.line, open.end_line), ("open", 19, 20));
+        assert_eq!((open.tokens, open.branches, open.max_depth, open.closed), (6, 1, 2, false));
+        assert!(functions[..2].iter().all(|f| f.closed));
+
+        let table = metrics_table(&functions);
+        let rows: Vec<&str> = table.lines().map(str::trim_end).collect();
+        assert_eq!(
+            rows,
+            [
+                "=== FUNCTION METRICS ===",
+                "Function             Span       Lines    Tokens   Branches   Depth      Complexity",
+                &"-".repeat(81),
+                "flat                 1-3        3        7        0          1          1",
+                "deep                 5-17       13       64       3          4          5",
+                "open (unclosed)      19-20      2        6        1          2          2",
+            ]
+        );
+    }
+
+    #[test]
+    fn short_circuit_operators_add_a_path_each() {
+        let functions = metrics("bool check(int a, int b, int c) {\n    return a > 0 && b > 0 || c > 0 && !(a > b || b > c);\n}\n");
+        assert_eq!((functions[0].branches, functions[0].complexity), (0, 5));
+    }
+
+    #[test]
+    fn else_and_else_if_count_once_per_if() {
+        let source = "int sign(int x) {\n    if (x > 0) {\n        return 1;\n    } else if (x < 0) {\n        return -1;\n    } else {\n        return 0;\n    }\n}\n";
+        assert_eq!(metrics(source)[0].complexity, 3);
+        // Separate functions keep their own counts
+        let two = metrics(&format!("{}int plain() {{ return 0; }}\n", source));
+        let counts: Vec<usize> = two.iter().map(|f| f.complexity).collect();
+        assert_eq!(counts, [3, 1]);
+    }
+
+    #[test]
+    fn complexity_is_written_to_the_document() {
+        let mut lexer = Lexer::new(SHAPES.to_string());
+        lexer.tokenize().unwrap();
+        let document: serde_json::Value = serde_json::from_str(&lexer.to_json()).unwrap();
+        let complexity: Vec<&serde_json::Value> = document["metrics"].as_array().unwrap().iter().map(|m| &m["complexity"]).collect();
+        assert_eq!(complexity, [1, 5]);
+    }
+}
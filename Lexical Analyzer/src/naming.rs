@@ -0,0 +1,282 @@
+use crate::diagnostics::{self, Diagnostic, Span};
+use crate::lexer::Symbol;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NamingConvention {
+    SnakeCase,
+    CamelCase,
+    PascalCase,
+    ScreamingSnakeCase,
+}
+
+pub const NAMING_CONVENTION_NAMES: &[&str] = &["snake_case", "camelCase", "PascalCase", "SCREAMING_SNAKE_CASE"];
+
+impl NamingConvention {
+    // `screaming` is accepted as a short form of SCREAMING_SNAKE_CASE.
+    pub fn from_name(name: &str) -> Option<NamingConvention> {
+        match name {
+            "snake_case" => Some(NamingConvention::SnakeCase),
+            "camelCase" => Some(NamingConvention::CamelCase),
+            "PascalCase" => Some(NamingConvention::PascalCase),
+            "SCREAMING_SNAKE_CASE" | "screaming" => Some(NamingConvention::ScreamingSnakeCase),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NamingConvention::SnakeCase => "snake_case",
+            NamingConvention::CamelCase => "camelCase",
+            NamingConvention::PascalCase => "PascalCase",
+            NamingConvention::ScreamingSnakeCase => "SCREAMING_SNAKE_CASE",
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let Some(first) = name.chars().next() else {
+            return false;
+        };
+        match self {
+            NamingConvention::SnakeCase => is_snake(name, |ch| ch.is_ascii_lowercase()),
+            NamingConvention::ScreamingSnakeCase => is_snake(name, |ch| ch.is_ascii_uppercase()),
+            NamingConvention::CamelCase => first.is_ascii_lowercase() && is_camel(name),
+            NamingConvention::PascalCase => first.is_ascii_uppercase() && is_camel(name),
+        }
+    }
+
+    // `name` respelled in this convention, keeping its words.
+    pub fn rename(&self, name: &str) -> String {
+        let words = split_words(name);
+        match self {
+            NamingConvention::SnakeCase => words.iter().map(|w| w.to_ascii_lowercase()).collect::<Vec<_>>().join("_"),
+            NamingConvention::ScreamingSnakeCase => words.iter().map(|w| w.to_ascii_uppercase()).collect::<Vec<_>>().join("_"),
+            NamingConvention::CamelCase => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.to_ascii_lowercase() } else { capitalize(w) })
+                .collect(),
+            NamingConvention::PascalCase => words.iter().map(|w| capitalize(w)).collect(),
+        }
+    }
+}
+
+// Words of lowercase (or uppercase) letters and digits joined by single
+// underscores.
+fn is_snake(name: &str, is_letter: fn(char) -> bool) -> bool {
+    name.chars().next().is_some_and(is_letter)
+        && name
+            .split('_')
+            .all(|word| !word.is_empty() && word.chars().all(|ch| is_letter(ch) || ch.is_ascii_digit()))
+}
+
+// Letters and digits only, with no two capitals in a row, so that acronyms
+// are written as words (`parseHttp`, not `parseHTTP`).
+fn is_camel(name: &str) -> bool {
+    let chars: Vec<char> = name.chars().collect();
+    chars.iter().all(|ch| ch.is_ascii_alphanumeric())
+        && !chars.windows(2).any(|pair| pair[0].is_ascii_uppercase() && pair[1].is_ascii_uppercase())
+}
+
+// Splits at underscores, at a lowercase letter or digit followed by a
+// capital, and before the last capital of a run followed by a lowercase
+// letter (`HTTPServer` is `HTTP`, `Server`).
+fn split_words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (i, &ch) in chars.iter().enumerate() {
+            let boundary = i > 0
+                && ch.is_ascii_uppercase()
+                && (!chars[i - 1].is_ascii_uppercase() || chars.get(i + 1).is_some_and(|next| next.is_ascii_lowercase()));
+            if boundary && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            word.push(ch);
+        }
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let lower = word.to_ascii_lowercase();
+    let mut chars = lower.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+// Which convention each kind of declaration must follow. `allowed` names are
+// never reported; an all-capitals entry such as `HTTP` is also accepted as
+// one word inside longer names (`parseHTTPHeader` passes as camelCase).
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct NamingRules {
+    pub variables: Option<NamingConvention>,
+    pub functions: Option<NamingConvention>,
+    pub constants: Option<NamingConvention>,
+    pub allowed: Vec<String>,
+}
+
+impl NamingRules {
+    // Parses `kind=convention` pairs separated by commas, e.g.
+    // `variables=snake_case,constants=screaming`.
+    pub fn parse(spec: &str) -> Result<NamingRules, String> {
+        let mut rules = NamingRules::default();
+        for pair in spec.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (kind, name) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected kind=convention, found '{}'", pair))?;
+            let slot = match kind {
+                "variables" => &mut rules.variables,
+                "functions" => &mut rules.functions,
+                "constants" => &mut rules.constants,
+                _ => return Err(format!("Unknown symbol kind '{}' (expected variables, functions or constants)", kind)),
+            };
+            *slot = Some(NamingConvention::from_name(name).ok_or_else(|| {
+                format!("Unknown naming convention '{}' (expected one of: {})", name, NAMING_CONVENTION_NAMES.join(", "))
+            })?);
+        }
+        Ok(rules)
+    }
+
+    fn convention_for(&self, symbol_type: &str) -> Option<NamingConvention> {
+        match symbol_type {
+            "variable" => self.variables,
+            "function" => self.functions,
+            "constant" => self.constants,
+            _ => None,
+        }
+    }
+
+    // The allowed acronyms respelled as ordinary words.
+    fn normalize(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        for acronym in &self.allowed {
+            if acronym.len() > 1 && acronym.chars().all(|ch| ch.is_ascii_uppercase()) {
+                name = name.replace(acronym.as_str(), &capitalize(acronym));
+            }
+        }
+        name
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamingViolation {
+    pub name: String,
+    pub symbol_type: String,
+    pub line: usize,
+    pub convention: NamingConvention,
+    pub suggestion: String,
+}
+
+impl NamingViolation {
+    pub fn to_diagnostic(&self, column: usize) -> Diagnostic {
+        Diagnostic::warning(
+            "W0005",
+            format!(
+                "{} '{}' (declared line {}) is not {}; consider renaming it to '{}'",
+                self.symbol_type,
+                diagnostics::quote(&self.name),
+                self.line,
+                self.convention.name(),
+                diagnostics::quote(&self.suggestion)
+            ),
+            Span::new(self.line, column, self.name.chars().count()),
+        )
+    }
+}
+
+// Declarations breaking `rules`, in table order. Only constants and symbols
+// declared with a data type are checked, not every use of a name.
+pub fn check(symbols: &[Symbol], rules: &NamingRules) -> Vec<NamingViolation> {
+    symbols
+        .iter()
+        .filter(|symbol| symbol.symbol_type == "constant" || symbol.data_type != "unknown")
+        .filter(|symbol| !rules.allowed.contains(&symbol.name))
+        .filter_map(|symbol| {
+            let convention = rules.convention_for(&symbol.symbol_type)?;
+            if convention.matches(&rules.normalize(&symbol.name)) {
+                return None;
+            }
+            Some(NamingViolation {
+                name: symbol.name.clone(),
+                symbol_type: symbol.symbol_type.clone(),
+                line: symbol.line,
+                convention,
+                suggestion: convention.rename(&symbol.name),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions};
+
+    fn violations(source: &str, rules: NamingRules) -> Vec<String> {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            naming: Some(rules),
+            ..LexerOptions::default()
+        });
+        lexer.tokenize().unwrap();
+        lexer.get_diagnostics().iter().filter(|d| d.code == "W0005").map(|d| d.message.clone()).collect()
+    }
+
+    #[test]
+    fn each_convention_accepts_and_respells() {
+        let cases = [
+            (NamingConvention::SnakeCase, "total_count", "totalCount", "total_count"),
+            (NamingConvention::CamelCase, "totalCount", "total_count", "totalCount"),
+            (NamingConvention::PascalCase, "TotalCount", "totalCount", "TotalCount"),
+            (NamingConvention::ScreamingSnakeCase, "MAX_SIZE", "maxSize", "MAX_SIZE"),
+        ];
+        for (convention, good, bad, suggestion) in cases {
+            assert!(convention.matches(good), "{} should be {}", good, convention.name());
+            assert!(!convention.matches(bad), "{} should not be {}", bad, convention.name());
+            assert_eq!(convention.rename(bad), suggestion);
+            assert_eq!(NamingConvention::from_name(convention.name()), Some(convention));
+        }
+        assert!(!NamingConvention::CamelCase.matches("parseHTTP"));
+        assert_eq!(NamingConvention::SnakeCase.rename("HTTPServer"), "http_server");
+        assert!(!NamingConvention::SnakeCase.matches("double__underscore"));
+    }
+
+    #[test]
+    fn rules_parse_from_the_cli_spec() {
+        let rules = NamingRules::parse("variables=snake_case, constants=screaming,functions=camelCase").unwrap();
+        assert_eq!(rules.variables, Some(NamingConvention::SnakeCase));
+        assert_eq!(rules.constants, Some(NamingConvention::ScreamingSnakeCase));
+        assert_eq!(rules.functions, Some(NamingConvention::CamelCase));
+        assert!(NamingRules::parse("types=snake_case").unwrap_err().contains("Unknown symbol kind 'types'"));
+        assert!(NamingRules::parse("variables=kebab").unwrap_err().contains("expected one of: snake_case"));
+        assert!(NamingRules::parse("variables").is_err());
+    }
+
+    #[test]
+    fn declarations_are_checked_per_kind() {
+        let rules = NamingRules::parse("variables=snake_case,functions=camelCase,constants=screaming").unwrap();
+        let source = "#define maxSize 10\nint ComputeSum(int firstValue) { int okay_name = firstValue; return okay_name; }\n";
+        assert_eq!(
+            violations(source, rules),
+            [
+                "constant 'maxSize' (declared line 1) is not SCREAMING_SNAKE_CASE; consider renaming it to 'MAX_SIZE'",
+                "function 'ComputeSum' (declared line 2) is not camelCase; consider renaming it to 'computeSum'",
+                "variable 'firstValue' (declared line 2) is not snake_case; consider renaming it to 'first_value'",
+            ]
+        );
+    }
+
+    #[test]
+    fn allowlist_excuses_names_and_acronyms() {
+        let source = "int parseHTTPHeader() { for (int I = 0; I < 3; I = I + 1) { } return 0; }\n";
+        let mut rules = NamingRules::parse("variables=snake_case,functions=camelCase").unwrap();
+        assert_eq!(violations(source, rules.clone()).len(), 2);
+        rules.allowed = vec!["I".to_string(), "HTTP".to_string()];
+        assert!(violations(source, rules).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::lexer::{Delimiter, Token, TokenType};
+use crate::position::PositionBase;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Nesting {
+    Brace,
+    Paren,
+    Bracket,
+}
+
+impl Nesting {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Nesting::Brace => "Brace",
+            Nesting::Paren => "Parenthesis",
+            Nesting::Bracket => "Bracket",
+        }
+    }
+
+    pub fn closer(&self) -> char {
+        match self {
+            Nesting::Brace => '}',
+            Nesting::Paren => ')',
+            Nesting::Bracket => ']',
+        }
+    }
+
+    // The kind a delimiter opens (true) or closes (false).
+    pub(crate) fn of(token_type: &TokenType) -> Option<(Nesting, bool)> {
+        match token_type {
+            TokenType::Delimiter(Delimiter::LeftBrace) => Some((Nesting::Brace, true)),
+            TokenType::Delimiter(Delimiter::RightBrace) => Some((Nesting::Brace, false)),
+            TokenType::Delimiter(Delimiter::LeftParen) => Some((Nesting::Paren, true)),
+            TokenType::Delimiter(Delimiter::RightParen) => Some((Nesting::Paren, false)),
+            TokenType::Delimiter(Delimiter::LeftBracket) => Some((Nesting::Bracket, true)),
+            TokenType::Delimiter(Delimiter::RightBracket) => Some((Nesting::Bracket, false)),
+            _ => None,
+        }
+    }
+}
+
+// The deepest nesting of one kind and the opening delimiter that first
+// reached it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaxDepth {
+    pub depth: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Deepest nesting of braces, parentheses and brackets in a token stream,
+// each None if the stream has none. A closing delimiter with nothing open
+// is ignored, so unbalanced input still gets a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct NestingReport {
+    pub braces: Option<MaxDepth>,
+    pub parens: Option<MaxDepth>,
+    pub brackets: Option<MaxDepth>,
+}
+
+impl NestingReport {
+    pub fn get(&self, kind: Nesting) -> Option<MaxDepth> {
+        match kind {
+            Nesting::Brace => self.braces,
+            Nesting::Paren => self.parens,
+            Nesting::Bracket => self.brackets,
+        }
+    }
+
+    pub fn rebase(&mut self, from: PositionBase, to: PositionBase) {
+        for max in [&mut self.braces, &mut self.parens, &mut self.brackets].into_iter().flatten() {
+            max.line = from.rebase(max.line, to);
+            max.column = from.rebase(max.column, to);
+        }
+    }
+}
+
+// Calls `opened` with each opening delimiter, its kind and the depth it
+// opens, counting from 1.
+fn walk<'a>(tokens: &'a [Token], mut opened: impl FnMut(Nesting, usize, &'a Token)) {
+    let mut depths = [0usize; 3];
+    for token in tokens {
+        let Some((kind, opens)) = Nesting::of(&token.token_type) else {
+            continue;
+        };
+        let depth = &mut depths[kind as usize];
+        if opens {
+            *depth += 1;
+            opened(kind, *depth, token);
+        } else {
+            *depth = depth.saturating_sub(1);
+        }
+    }
+}
+
+pub fn nesting_report(tokens: &[Token]) -> NestingReport {
+    let mut report = NestingReport::default();
+    walk(tokens, |kind, depth, token| {
+        let max = match kind {
+            Nesting::Brace => &mut report.braces,
+            Nesting::Paren => &mut report.parens,
+            Nesting::Bracket => &mut report.brackets,
+        };
+        if max.is_none_or(|m| depth > m.depth) {
+            *max = Some(MaxDepth {
+                depth,
+                line: token.line,
+                column: token.column,
+            });
+        }
+    });
+    report
+}
+
+// The opening delimiters that take their kind one past `limit`. Each time the
+// nesting goes back under the limit and past it again counts anew.
+pub fn over_limit(tokens: &[Token], limit: usize) -> Vec<(Nesting, &Token)> {
+    let mut found = Vec::new();
+    walk(tokens, |kind, depth, token| {
+        if depth == limit + 1 {
+            found.push((kind, token));
+        }
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions};
+
+    // Six braces deep inside `main`, with the sixth opened on line 7.
+    const SIX_DEEP: &str = "int main() {\n\
+        if (a) {\n\
+        while (b) {\n\
+        for (;;) {\n\
+        if (c) {\n\
+        if (d) {\n\
+        x = f((1), [2]);\n\
+        }\n}\n}\n}\n}\n}\n";
+
+    fn lexed(source: &str, max_nesting: Option<usize>) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            max_nesting,
+            ..LexerOptions::default()
+        });
+        lexer.tokenize().unwrap();
+        lexer
+    }
+
+    fn deep_nesting(lexer: &Lexer) -> Vec<(String, usize, usize)> {
+        lexer
+            .get_diagnostics()
+            .iter()
+            .filter(|d| d.code == "W0014")
+            .map(|d| (d.message.clone(), d.span.line, d.span.column))
+            .collect()
+    }
+
+    #[test]
+    fn report_holds_the_deepest_opener_of_each_kind() {
+        let report = lexed(SIX_DEEP, None).nesting_report();
+        assert_eq!(report.braces, Some(MaxDepth { depth: 6, line: 6, column: 8 }));
+        assert_eq!(report.parens, Some(MaxDepth { depth: 2, line: 7, column: 7 }));
+        assert_eq!(report.brackets, Some(MaxDepth { depth: 1, line: 7, column: 12 }));
+        assert_eq!(lexed("int x = 1;\n", None).nesting_report(), NestingReport::default());
+    }
+
+    #[test]
+    fn exactly_at_the_limit_is_quiet() {
+        assert!(deep_nesting(&lexed(SIX_DEEP, Some(6))).is_empty());
+    }
+
+    #[test]
+    fn over_the_limit_points_at_the_crossing_opener() {
+        let lexer = lexed(SIX_DEEP, Some(5));
+        assert_eq!(deep_nesting(&lexer), [("Brace nesting depth 6 exceeds the limit of 5".to_string(), 6, 8)]);
+
+        let lexer = lexed(SIX_DEEP, Some(1));
+        assert_eq!(
+            deep_nesting(&lexer),
+            [
+                ("Brace nesting depth 2 exceeds the limit of 1".to_string(), 2, 8),
+                ("Parenthesis nesting depth 2 exceeds the limit of 1".to_string(), 7, 7),
+            ]
+        );
+    }
+
+    fn lexed_report(source: &str) -> NestingReport {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        lexer.nesting_report()
+    }
+
+    #[test]
+    fn crossing_again_counts_anew() {
+        let lexer = lexed("void f() { { { } } { { } } }\n", None);
+        let crossings: Vec<usize> = over_limit(lexer.get_tokens(), 2).iter().map(|(_, t)| t.column).collect();
+        assert_eq!(crossings, [14, 22]);
+        // A stray closer does not make the depth negative.
+        let report = lexed_report("} } { {\n");
+        assert_eq!(report.braces.map(|m| m.depth), Some(2));
+    }
+}
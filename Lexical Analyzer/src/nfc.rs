@@ -0,0 +1,55 @@
+// Canonical composition for identifiers, so that a name typed with a
+// precomposed letter ("é") and one typed as a letter plus a combining mark
+// ("e" + U+0301) end up as the same symbol. This is full NFC from the
+// `unicode-normalization` crate, so marks are put in canonical order before
+// they compose and every script's compositions apply.
+
+use unicode_normalization::UnicodeNormalization;
+
+// `text` in Normalization Form C.
+pub fn compose(text: &str) -> String {
+    text.nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composes_marks_onto_their_base() {
+        assert_eq!(compose("cafe\u{301}"), "caf\u{e9}");
+        assert_eq!(compose("caf\u{e9}"), "caf\u{e9}");
+        // Stacked marks compose in turn: ẹ + circumflex is ệ
+        assert_eq!(compose("e\u{323}\u{302}"), "\u{1EC7}");
+        assert_eq!(compose("\u{443}\u{308}"), "\u{4F1}");
+        // A mark with no precomposed form stays
+        assert_eq!(compose("x\u{301}"), "x\u{301}");
+        assert_eq!(compose("total"), "total");
+    }
+
+    #[test]
+    fn marks_are_ordered_before_they_compose() {
+        // Below-dot before circumflex whichever was typed first
+        assert_eq!(compose("e\u{302}\u{323}"), "\u{1EC7}");
+        assert_eq!(compose("a\u{301}\u{327}x"), compose("a\u{327}\u{301}x"));
+    }
+
+    #[test]
+    fn other_scripts_compose_too() {
+        assert_eq!(compose("\u{1100}\u{1161}\u{11A8}"), "\u{AC01}");
+        assert_eq!(compose("\u{304B}\u{3099}"), "\u{304C}");
+        assert_eq!(compose("\u{3B1}\u{301}"), "\u{3AC}");
+        // Singletons map to their canonical letter: the angstrom sign is Å
+        assert_eq!(compose("\u{212B}"), "\u{C5}");
+    }
+
+    #[test]
+    fn exclusions_and_compatibility_forms_pass_through() {
+        // क़ is excluded from composition, and NFC leaves the ligature ﬁ
+        // and the full-width Ａ, which only NFKC folds
+        assert_eq!(compose("\u{915}\u{93C}"), "\u{915}\u{93C}");
+        assert_eq!(compose("\u{958}"), "\u{915}\u{93C}");
+        assert_eq!(compose("\u{FB01}le"), "\u{FB01}le");
+        assert_eq!(compose("\u{FF21}"), "\u{FF21}");
+    }
+}
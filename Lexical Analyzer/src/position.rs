@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+// Which number the first line and column get. Positions are kept 1-based
+// everywhere in memory, as printed diagnostics and SARIF expect; outputs for
+// 0-based consumers such as LSP clients convert with `rebase` on the way out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionBase {
+    Zero,
+    #[default]
+    One,
+}
+
+pub const POSITION_BASE_NAMES: &[&str] = &["0", "1"];
+
+impl PositionBase {
+    pub fn from_name(name: &str) -> Option<PositionBase> {
+        match name {
+            "0" => Some(PositionBase::Zero),
+            "1" => Some(PositionBase::One),
+            _ => None,
+        }
+    }
+    
+    pub fn from_offset(offset: u32) -> Option<PositionBase> {
+        match offset {
+            0 => Some(PositionBase::Zero),
+            1 => Some(PositionBase::One),
+            _ => None,
+        }
+    }
+    
+    // The number of the first line or column.
+    pub fn offset(&self) -> u32 {
+        match self {
+            PositionBase::Zero => 0,
+            PositionBase::One => 1,
+        }
+    }
+    
+    // Converts a line or column counted in this base to `to`.
+    pub fn rebase(&self, n: usize, to: PositionBase) -> usize {
+        n + to.offset() as usize - self.offset() as usize
+    }
+}
+
+// What a column counts. The lexer's own unit is the Unicode scalar value
+// (`Chars`); editors and LSP clients that count UTF-16 code units disagree
+// after any character outside the Basic Multilingual Plane, such as an
+// emoji, which is two code units and four bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnUnit {
+    Bytes,
+    #[default]
+    Chars,
+    Utf16,
+}
+
+pub const COLUMN_UNIT_NAMES: &[&str] = &["bytes", "chars", "utf16"];
+
+impl ColumnUnit {
+    pub fn from_name(name: &str) -> Option<ColumnUnit> {
+        match name {
+            "bytes" => Some(ColumnUnit::Bytes),
+            "chars" => Some(ColumnUnit::Chars),
+            "utf16" => Some(ColumnUnit::Utf16),
+            _ => None,
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnUnit::Bytes => "bytes",
+            ColumnUnit::Chars => "chars",
+            ColumnUnit::Utf16 => "utf16",
+        }
+    }
+    
+    // Columns taken up by one character.
+    pub fn width(&self, ch: char) -> usize {
+        match self {
+            ColumnUnit::Bytes => ch.len_utf8(),
+            ColumnUnit::Chars => 1,
+            ColumnUnit::Utf16 => ch.len_utf16(),
+        }
+    }
+    
+    // Columns taken up by a run of text on one line.
+    pub fn measure(&self, text: &str) -> usize {
+        match self {
+            ColumnUnit::Bytes => text.len(),
+            ColumnUnit::Chars => text.chars().count(),
+            ColumnUnit::Utf16 => text.encode_utf16().count(),
+        }
+    }
+}
+
+// Where a line of a generated source comes from, as the `#line` directive
+// before it says: its line number there, and the file that directive or an
+// earlier one named.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineOrigin {
+    pub line: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+// The `#line` directives of a source in order, each with the physical line
+// it takes effect from, the line after its own.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LineMap {
+    markers: Vec<(usize, LineOrigin)>,
+}
+
+impl LineMap {
+    // Lines from `first_line` on are numbered from `origin.line`; without a
+    // file they stay in the one named before.
+    pub fn push(&mut self, first_line: usize, mut origin: LineOrigin) {
+        if origin.file.is_none() {
+            origin.file = self.markers.last().and_then(|(_, last)| last.file.clone());
+        }
+        self.markers.push((first_line, origin));
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.markers.is_empty()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.markers.len()
+    }
+    
+    pub fn truncate(&mut self, len: usize) {
+        self.markers.truncate(len);
+    }
+    
+    // The origin of physical line `line`, None before the first directive.
+    pub fn origin(&self, line: usize) -> Option<LineOrigin> {
+        let index = self.markers.partition_point(|(first_line, _)| *first_line <= line);
+        let (first_line, origin) = self.markers.get(index.checked_sub(1)?)?;
+        Some(LineOrigin {
+            line: origin.line + (line - first_line),
+            file: origin.file.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bases_convert_both_ways() {
+        assert_eq!(PositionBase::One.rebase(1, PositionBase::Zero), 0);
+        assert_eq!(PositionBase::Zero.rebase(0, PositionBase::One), 1);
+        assert_eq!(PositionBase::One.rebase(7, PositionBase::One), 7);
+        for name in POSITION_BASE_NAMES {
+            let base = PositionBase::from_name(name).unwrap();
+            assert_eq!(PositionBase::from_offset(base.offset()), Some(base));
+            assert_eq!(base.offset().to_string(), *name);
+        }
+        assert_eq!(PositionBase::from_name("2"), None);
+    }
+
+    #[test]
+    fn units_measure_characters_outside_the_bmp() {
+        let expected = [(ColumnUnit::Bytes, 4, 8), (ColumnUnit::Chars, 1, 4), (ColumnUnit::Utf16, 2, 5)];
+        for (unit, emoji, text) in expected {
+            assert_eq!(unit.width('😀'), emoji);
+            assert_eq!(unit.measure("a😀é!"), text, "{}", unit.name());
+            assert_eq!(unit.width('a'), 1);
+            assert_eq!(ColumnUnit::from_name(unit.name()), Some(unit));
+        }
+        assert_eq!(COLUMN_UNIT_NAMES.len(), 3);
+    }
+
+    #[test]
+    fn line_maps_count_on_from_each_marker() {
+        let mut map = LineMap::default();
+        assert_eq!(map.origin(3), None);
+        map.push(3, LineOrigin { line: 57, file: Some("original.tpl".to_string()) });
+        map.push(10, LineOrigin { line: 1, file: None });
+        let at = |map: &LineMap, line| map.origin(line).map(|o| (o.line, o.file));
+        assert_eq!(at(&map, 2), None);
+        assert_eq!(at(&map, 3), Some((57, Some("original.tpl".to_string()))));
+        assert_eq!(at(&map, 9), Some((63, Some("original.tpl".to_string()))));
+        assert_eq!(at(&map, 12), Some((3, Some("original.tpl".to_string()))));
+        map.truncate(1);
+        assert_eq!((map.len(), at(&map, 12)), (1, Some((66, Some("original.tpl".to_string())))));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::lexer::{Operator, TokenType};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+impl Assoc {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Assoc::Left => "left",
+            Assoc::Right => "right",
+        }
+    }
+}
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Modulo => "%",
+            Operator::Assign => "=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::LessThan => "<",
+            Operator::GreaterThan => ">",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            Operator::LogicalAnd => "&&",
+            Operator::LogicalOr => "||",
+            Operator::Increment => "++",
+            Operator::Decrement => "--",
+            Operator::Ampersand => "&",
+            Operator::LogicalNot => "!",
+        }
+    }
+
+    // Precedence level, higher binding tighter, following C. `&` is ranked
+    // as bitwise and, and `++` and `--`, like `!`, as prefix operators.
+    pub fn precedence(&self) -> (u8, Assoc) {
+        match self {
+            Operator::Assign => (1, Assoc::Right),
+            Operator::LogicalOr => (2, Assoc::Left),
+            Operator::LogicalAnd => (3, Assoc::Left),
+            Operator::Ampersand => (4, Assoc::Left),
+            Operator::Equal | Operator::NotEqual => (5, Assoc::Left),
+            Operator::LessThan | Operator::GreaterThan | Operator::LessEqual | Operator::GreaterEqual => (6, Assoc::Left),
+            Operator::Plus | Operator::Minus => (7, Assoc::Left),
+            Operator::Multiply | Operator::Divide | Operator::Modulo => (8, Assoc::Left),
+            Operator::Increment | Operator::Decrement | Operator::LogicalNot => (9, Assoc::Right),
+        }
+    }
+
+    // Left and right binding powers for a Pratt parser, derived from the
+    // precedence: a loop parsing the right operand with the right power
+    // folds left-associative operators to the left and right-associative
+    // ones to the right.
+    pub fn binding_power(&self) -> (u8, u8) {
+        let (level, assoc) = self.precedence();
+        match assoc {
+            Assoc::Left => (level * 2, level * 2 + 1),
+            Assoc::Right => (level * 2 + 1, level * 2),
+        }
+    }
+}
+
+impl TokenType {
+    // None for everything but operators.
+    pub fn precedence(&self) -> Option<(u8, Assoc)> {
+        match self {
+            TokenType::Operator(op) => Some(op.precedence()),
+            _ => None,
+        }
+    }
+
+    pub fn binding_power(&self) -> Option<(u8, u8)> {
+        match self {
+            TokenType::Operator(op) => Some(op.binding_power()),
+            _ => None,
+        }
+    }
+}
+
+// One line per precedence level, tightest first, for `--emit-precedence-table`.
+pub fn precedence_table() -> String {
+    // The first operator seen at each level stands for it
+    let mut levels: Vec<(Operator, Vec<&str>)> = Vec::new();
+    for op in TokenType::all().iter().filter_map(|t| match t {
+        TokenType::Operator(op) => Some(op),
+        _ => None,
+    }) {
+        match levels.iter_mut().find(|(first, _)| first.precedence() == op.precedence()) {
+            Some((_, symbols)) => symbols.push(op.symbol()),
+            None => levels.push((*op, vec![op.symbol()])),
+        }
+    }
+    levels.sort_by_key(|(first, _)| std::cmp::Reverse(first.precedence().0));
+
+    let mut out = String::from("=== OPERATOR PRECEDENCE ===\n");
+    writeln!(out, "{:<7}{:<7}{:<9}Operators", "Level", "Assoc", "Binding").unwrap();
+    for (first, symbols) in levels {
+        let (level, assoc) = first.precedence();
+        let (left, right) = first.binding_power();
+        writeln!(out, "{:<7}{:<7}{:<9}{}", level, assoc.name(), format!("{} {}", left, right), symbols.join(" ")).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, PatternSet};
+
+    fn operators() -> Vec<Operator> {
+        TokenType::all()
+            .iter()
+            .filter_map(|t| match t {
+                TokenType::Operator(op) => Some(*op),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_operator_has_a_pattern_and_a_level() {
+        let patterns = PatternSet::shared();
+        let table = precedence_table();
+        assert_eq!(operators().len(), 18);
+        for op in operators() {
+            let token_type = TokenType::Operator(op);
+            assert!(patterns.patterns().iter().any(|(t, _)| *t == token_type), "{:?} has no pattern", op);
+            assert_eq!(token_type.precedence(), Some(op.precedence()));
+            let (level, _) = op.precedence();
+            let row = table.lines().find(|line| line.starts_with(&format!("{:<7}", level))).unwrap();
+            assert!(row.split_whitespace().skip(4).any(|s| s == op.symbol()), "{} missing from level {}", op.symbol(), level);
+            // The symbol lexes back to the operator
+            let mut lexer = Lexer::new(format!("a {} b", op.symbol()));
+            lexer.tokenize().unwrap();
+            assert_eq!(lexer.get_tokens()[1].token_type, token_type);
+        }
+        for token_type in TokenType::all().iter().filter(|t| !matches!(t, TokenType::Operator(_))) {
+            assert_eq!(token_type.precedence(), None);
+            assert_eq!(token_type.binding_power(), None);
+        }
+    }
+
+    #[test]
+    fn levels_follow_c() {
+        let level = |op: Operator| op.precedence().0;
+        let chain = [Operator::Multiply, Operator::Plus, Operator::Equal, Operator::LogicalAnd, Operator::Assign];
+        for pair in chain.windows(2) {
+            assert!(level(pair[0]) > level(pair[1]), "{} should bind tighter than {}", pair[0].symbol(), pair[1].symbol());
+        }
+        assert_eq!(Operator::Assign.precedence().1, Assoc::Right);
+        assert_eq!(Operator::Minus.precedence().1, Assoc::Left);
+        // Left-associative operators bind tighter on the right, and the reverse
+        let (left, right) = Operator::Minus.binding_power();
+        assert!(left < right);
+        let (left, right) = Operator::Assign.binding_power();
+        assert!(left > right);
+    }
+
+    #[test]
+    fn table_lists_levels_tightest_first() {
+        let table = precedence_table();
+        let levels: Vec<u8> = table.lines().skip(2).map(|line| line.split_whitespace().next().unwrap().parse().unwrap()).collect();
+        assert_eq!(levels, [9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        assert!(table.contains("\n8      left   16 17    * / %\n"));
+        assert!(table.ends_with("1      right  3 2      =\n"));
+    }
+}
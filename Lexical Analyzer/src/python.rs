@@ -0,0 +1,245 @@
+use crate::lexer::{LexError, SymbolTable, Token};
+use crate::simple;
+use std::fmt;
+
+// What the Python module hands back, built without PyO3 so it can be tested
+// here: each token or symbol becomes a dict, in the order Python would list
+// its keys, and a failed lex becomes a `LexicalError` exception with the
+// position of the first error. With the `python` feature, `bindings` is the
+// `mcpp_lexer` extension module itself, which converts these values and
+// releases the GIL while it lexes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PyValue {
+    Int(usize),
+    Str(String),
+    Bool(bool),
+    None,
+}
+
+pub type PyDict = Vec<(&'static str, PyValue)>;
+
+fn optional(text: Option<&str>) -> PyValue {
+    text.map_or(PyValue::None, |text| PyValue::Str(text.to_string()))
+}
+
+// `type`, `lexeme`, `line`, `column` and `value`, the last None unless the
+// token has one, such as a concatenated string or an attribute.
+pub fn token_dict(token: &Token) -> PyDict {
+    vec![
+        ("type", PyValue::Str(token.token_type.name().to_string())),
+        ("lexeme", PyValue::Str(token.lexeme.to_string())),
+        ("line", PyValue::Int(token.line)),
+        ("column", PyValue::Int(token.column)),
+        ("value", optional(token.value.as_deref())),
+    ]
+}
+
+// `name`, `symbol_type`, `data_type`, `scope`, `line`, `initialized` and
+// `access`, None outside a class.
+pub fn symbol_dicts(table: &SymbolTable) -> Vec<PyDict> {
+    table
+        .get_symbols()
+        .iter()
+        .map(|symbol| {
+            vec![
+                ("name", PyValue::Str(symbol.name.clone())),
+                ("symbol_type", PyValue::Str(symbol.symbol_type.clone())),
+                ("data_type", PyValue::Str(symbol.data_type.clone())),
+                ("scope", PyValue::Str(symbol.scope.clone())),
+                ("line", PyValue::Int(symbol.line)),
+                ("initialized", PyValue::Bool(symbol.initialized)),
+                ("access", optional(symbol.access.as_deref())),
+            ]
+        })
+        .collect()
+}
+
+// The exception raised for source that does not lex: the first error's
+// code, message and position, and how many errors there were in all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexicalError {
+    pub code: String,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub errors: usize,
+}
+
+impl LexicalError {
+    fn from_errors(errors: &[LexError]) -> Self {
+        let first = &errors[0];
+        LexicalError {
+            code: first.code.clone(),
+            message: first.message.clone(),
+            line: first.line,
+            column: first.column,
+            errors: errors.len(),
+        }
+    }
+}
+
+impl fmt::Display for LexicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)?;
+        if self.errors > 1 {
+            write!(f, " (and {} more)", self.errors - 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LexicalError {}
+
+// `mcpp_lexer.tokenize(source)`: one dict per token, EOF included.
+pub fn tokenize(source: &str) -> Result<Vec<PyDict>, LexicalError> {
+    let output = simple::lex(source).map_err(|errors| LexicalError::from_errors(&errors))?;
+    Ok(output.tokens.iter().map(token_dict).collect())
+}
+
+// `mcpp_lexer.symbols(source)`: one dict per symbol table row.
+pub fn symbols(source: &str) -> Result<Vec<PyDict>, LexicalError> {
+    let output = simple::lex(source).map_err(|errors| LexicalError::from_errors(&errors))?;
+    Ok(symbol_dicts(&output.symbols))
+}
+
+#[cfg(feature = "python")]
+pub mod bindings {
+    use super::{PyDict, PyValue};
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict as Dict;
+
+    create_exception!(mcpp_lexer, LexicalError, PyException, "Source that does not lex, with the first error's code, message, line and column.");
+
+    fn to_dict<'py>(py: Python<'py>, entries: &PyDict) -> PyResult<Bound<'py, Dict>> {
+        let dict = Dict::new(py);
+        for (key, value) in entries {
+            match value {
+                PyValue::Int(number) => dict.set_item(key, number)?,
+                PyValue::Str(text) => dict.set_item(key, text)?,
+                PyValue::Bool(flag) => dict.set_item(key, flag)?,
+                PyValue::None => dict.set_item(key, py.None())?,
+            }
+        }
+        Ok(dict)
+    }
+
+    fn to_dicts<'py>(py: Python<'py>, result: Result<Vec<PyDict>, super::LexicalError>) -> PyResult<Vec<Bound<'py, Dict>>> {
+        match result {
+            Ok(entries) => entries.iter().map(|entry| to_dict(py, entry)).collect(),
+            Err(error) => {
+                let exception = LexicalError::new_err(error.to_string());
+                let value = exception.value(py);
+                value.setattr("code", &error.code)?;
+                value.setattr("message", &error.message)?;
+                value.setattr("line", error.line)?;
+                value.setattr("column", error.column)?;
+                value.setattr("errors", error.errors)?;
+                Err(exception)
+            }
+        }
+    }
+
+    // `tokenize(source: str) -> list[dict]`
+    #[pyfunction]
+    fn tokenize<'py>(py: Python<'py>, source: &str) -> PyResult<Vec<Bound<'py, Dict>>> {
+        let result = py.detach(|| super::tokenize(source));
+        to_dicts(py, result)
+    }
+
+    // `symbols(source: str) -> list[dict]`
+    #[pyfunction]
+    fn symbols<'py>(py: Python<'py>, source: &str) -> PyResult<Vec<Bound<'py, Dict>>> {
+        let result = py.detach(|| super::symbols(source));
+        to_dicts(py, result)
+    }
+
+    #[pymodule]
+    pub fn mcpp_lexer(module: &Bound<'_, PyModule>) -> PyResult<()> {
+        module.add_function(wrap_pyfunction!(tokenize, module)?)?;
+        module.add_function(wrap_pyfunction!(symbols, module)?)?;
+        module.add("LexicalError", module.py().get_type::<LexicalError>())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> PyValue {
+        PyValue::Str(value.to_string())
+    }
+
+    #[test]
+    fn tokens_become_dicts() {
+        let tokens = tokenize("int x = 1;\n").unwrap();
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(
+            tokens[1],
+            [("type", text("Identifier")), ("lexeme", text("x")), ("line", PyValue::Int(1)), ("column", PyValue::Int(5)), ("value", PyValue::None)]
+        );
+        assert_eq!(tokens[5][0], ("type", text("EOF")));
+        let attribute = &tokenize("[[nodiscard]] int f();\n").unwrap()[0];
+        assert_eq!(attribute[4], ("value", text("nodiscard")));
+    }
+
+    #[test]
+    fn symbols_become_dicts() {
+        let symbols = symbols("class Box { public: int size = 0; };\n").unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0][..2], [("name", text("Box")), ("symbol_type", text("class"))]);
+        assert_eq!(
+            symbols[1],
+            [
+                ("name", text("size")),
+                ("symbol_type", text("variable")),
+                ("data_type", text("int")),
+                ("scope", text("Box")),
+                ("line", PyValue::Int(1)),
+                ("initialized", PyValue::Bool(true)),
+                ("access", text("public")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexical_errors_carry_the_first_position() {
+        let error = tokenize("int a = 1;\nint b = 2 @ 3 $ 4;\n").unwrap_err();
+        assert_eq!((error.code.as_str(), error.line, error.column, error.errors), ("E0001", 2, 11, 2));
+        assert_eq!(error.to_string(), "Invalid character '@' at line 2, column 11 (and 1 more)");
+        assert_eq!(symbols("int c = @;\n").unwrap_err().to_string(), "Invalid character '@' at line 1, column 9");
+    }
+
+    // The module as Python sees it, run in an embedded interpreter.
+    #[cfg(feature = "python")]
+    #[test]
+    fn the_module_runs_in_python() {
+        use pyo3::prelude::*;
+        use pyo3::types::PyDict as Dict;
+
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "mcpp_lexer").unwrap();
+            bindings::mcpp_lexer(&module).unwrap();
+            let globals = Dict::new(py);
+            globals.set_item("mcpp_lexer", module).unwrap();
+            py.run(
+                c"
+tokens = mcpp_lexer.tokenize('int x = 1;\\n')
+assert tokens[1] == {'type': 'Identifier', 'lexeme': 'x', 'line': 1, 'column': 5, 'value': None}, tokens[1]
+assert mcpp_lexer.symbols('int y;\\n')[0]['initialized'] is False
+try:
+    mcpp_lexer.symbols('int c = @;\\n')
+    raise AssertionError('no LexicalError')
+except mcpp_lexer.LexicalError as error:
+    assert (error.code, error.message, error.line, error.column, error.errors) == ('E0001', \"Invalid character '@'\", 1, 9, 1)
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
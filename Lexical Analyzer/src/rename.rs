@@ -0,0 +1,149 @@
+use crate::lexer::{SymbolTableBuilder, Token, TokenType};
+use crate::token_stream;
+use std::fmt;
+
+// Why `rename_identifier` refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenameError {
+    // The new name is not an identifier at all
+    InvalidName(String),
+    Keyword(String),
+    // No identifier token has the old name
+    NotFound(String),
+    // The new name is already declared, in that scope on that line, where
+    // the old one is used
+    Collision { old: String, new: String, scope: String, line: usize },
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenameError::InvalidName(name) => write!(f, "'{}' is not a valid identifier", name),
+            RenameError::Keyword(name) => write!(f, "'{}' is a reserved keyword and cannot be used as an identifier", name),
+            RenameError::NotFound(name) => write!(f, "No identifier named '{}'", name),
+            RenameError::Collision { old, new, scope, line } => {
+                write!(f, "'{}' is already declared in {} at line {}, where '{}' is used", new, scope, line, old)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenameError {}
+
+// Source text for `tokens` with every identifier `old` renamed to `new`,
+// written out by `token_stream::detokenize`. Strings and comments are
+// tokens of their own, so the name inside one is left alone; comments
+// survive only if the stream kept them. Later tokens on a renamed token's
+// line move by the change in length, so the spacing between tokens stays
+// as it was. Refused when `new` is not an identifier, is a keyword, or is
+// declared in any scope, or one enclosing it, where `old` appears.
+pub fn rename_identifier(tokens: &[Token], old: &str, new: &str) -> Result<String, RenameError> {
+    let mut chars = new.chars();
+    if !chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') || !chars.all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(RenameError::InvalidName(new.to_string()));
+    }
+    if TokenType::keyword(new).is_some() {
+        return Err(RenameError::Keyword(new.to_string()));
+    }
+    let renames = |token: &Token| token.token_type == TokenType::Identifier && token.lexeme == old;
+    if !tokens.iter().any(renames) {
+        return Err(RenameError::NotFound(old.to_string()));
+    }
+    if old != new {
+        let mut builder = SymbolTableBuilder::new();
+        for token in tokens {
+            builder.observe(token);
+        }
+        if let Some(symbol) = builder.get_symbol_table().clashing_declaration(new, old) {
+            return Err(RenameError::Collision {
+                old: old.to_string(),
+                new: new.to_string(),
+                scope: symbol.scope.clone(),
+                line: symbol.line,
+            });
+        }
+    }
+
+    let shift = new.chars().count() as isize - old.chars().count() as isize;
+    let mut renamed = tokens.to_vec();
+    // Columns moved so far on the line being written
+    let (mut line, mut moved) = (0, 0);
+    for token in &mut renamed {
+        if token.line != line {
+            (line, moved) = (token.line, 0);
+        }
+        token.column = token.column.saturating_add_signed(moved);
+        if renames(token) {
+            token.lexeme = new.into();
+            moved += shift;
+        }
+    }
+    Ok(token_stream::detokenize(&renamed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions};
+
+    fn renamed(source: &str, old: &str, new: &str) -> Result<String, RenameError> {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            keep_comments: true,
+            ..LexerOptions::default()
+        });
+        lexer.tokenize().unwrap();
+        rename_identifier(lexer.get_tokens(), old, new)
+    }
+
+    #[test]
+    fn a_clean_rename_keeps_the_spacing_and_comments() {
+        let source = "int count = 0;\nint main() {\n    count = count + 1; // count up\n    return count;\n}\n";
+        assert_eq!(
+            renamed(source, "count", "total").unwrap(),
+            "int total = 0;\nint main() {\n    total = total + 1; // count up\n    return total;\n}\n"
+        );
+        // Shorter names pull the rest of the line in
+        assert_eq!(renamed("int count = count;\n", "count", "n").unwrap(), "int n = n;\n");
+    }
+
+    #[test]
+    fn keywords_and_invalid_names_are_refused() {
+        assert_eq!(renamed("int count = 0;\n", "count", "while"), Err(RenameError::Keyword("while".to_string())));
+        assert_eq!(renamed("int count = 0;\n", "count", "9x"), Err(RenameError::InvalidName("9x".to_string())));
+        assert_eq!(renamed("int count = 0;\n", "missing", "x"), Err(RenameError::NotFound("missing".to_string())));
+        assert_eq!(
+            RenameError::Keyword("while".to_string()).to_string(),
+            "'while' is a reserved keyword and cannot be used as an identifier"
+        );
+    }
+
+    #[test]
+    fn a_name_declared_where_the_old_one_is_used_collides() {
+        let source = "int count = 0;\nint f() {\n    int total = 1;\n    return count + total;\n}\n";
+        let error = renamed(source, "count", "total").unwrap_err();
+        assert_eq!(
+            error,
+            RenameError::Collision {
+                old: "count".to_string(),
+                new: "total".to_string(),
+                scope: "f".to_string(),
+                line: 3,
+            }
+        );
+        assert_eq!(error.to_string(), "'total' is already declared in f at line 3, where 'count' is used");
+
+        // Declared only in a function that never uses `count`
+        let apart = "int count = 0;\nint f() {\n    int total = 1;\n    return total;\n}\nint g() { return count; }\n";
+        assert_eq!(
+            renamed(apart, "count", "total").unwrap(),
+            "int total = 0;\nint f() {\n    int total = 1;\n    return total;\n}\nint g() { return total; }\n"
+        );
+    }
+
+    #[test]
+    fn strings_holding_the_name_are_untouched() {
+        let source = "string s = \"count\";\nint count = 1; /* count */\n";
+        assert_eq!(renamed(source, "count", "n").unwrap(), "string s = \"count\";\nint n = 1; /* count */\n");
+    }
+}
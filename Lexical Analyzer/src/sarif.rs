@@ -0,0 +1,172 @@
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::lexer::{ERROR_CODES, NOTE_CODES, WARNING_CODES};
+use serde_json::{json, Value};
+
+pub const SARIF_VERSION: &str = "2.1.0";
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+// One analyzed input file and the diagnostics it produced.
+pub struct SarifArtifact {
+    pub path: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+fn rule_list() -> Vec<(&'static str, &'static str, &'static str, Severity)> {
+    let errors = ERROR_CODES.iter().map(|(code, name, description)| (*code, *name, *description, Severity::Error));
+    let warnings = WARNING_CODES.iter().map(|(code, name, description)| (*code, *name, *description, Severity::Warning));
+    let notes = NOTE_CODES.iter().map(|(code, name, description)| (*code, *name, *description, Severity::Note));
+    errors.chain(warnings).chain(notes).collect()
+}
+
+// Builds a single-run SARIF 2.1.0 log covering every artifact.
+pub fn to_sarif(artifacts: &[SarifArtifact]) -> Value {
+    let rule_list = rule_list();
+    let rules: Vec<Value> = rule_list
+        .iter()
+        .map(|(code, name, description, severity)| {
+            json!({
+                "id": code,
+                "name": name,
+                "shortDescription": { "text": description },
+                "defaultConfiguration": { "level": severity.name() },
+            })
+        })
+        .collect();
+
+    let artifact_entries: Vec<Value> = artifacts
+        .iter()
+        .map(|artifact| json!({ "location": { "uri": path_to_uri(&artifact.path) } }))
+        .collect();
+
+    let mut results = Vec::new();
+    for (index, artifact) in artifacts.iter().enumerate() {
+        for diagnostic in &artifact.diagnostics {
+            let mut result = json!({
+                "ruleId": diagnostic.code,
+                "level": diagnostic.severity.name(),
+                "message": { "text": diagnostic.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": path_to_uri(&artifact.path),
+                            "index": index,
+                        },
+                        "region": {
+                            "startLine": diagnostic.span.line,
+                            "startColumn": diagnostic.span.column,
+                            "endLine": diagnostic.span.end_line,
+                            "endColumn": diagnostic.span.end_column + 1,
+                        },
+                    },
+                }],
+            });
+            if let Some(rule_index) = rule_list.iter().position(|(code, _, _, _)| *code == diagnostic.code) {
+                result["ruleIndex"] = json!(rule_index);
+            }
+            results.push(result);
+        }
+    }
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "artifacts": artifact_entries,
+            "results": results,
+        }],
+    })
+}
+
+pub fn render_sarif(artifacts: &[SarifArtifact]) -> String {
+    serde_json::to_string_pretty(&to_sarif(artifacts)).unwrap()
+}
+
+// SARIF locations are URI references, so separators are normalized and
+// anything outside the unreserved set is percent-encoded.
+fn path_to_uri(path: &str) -> String {
+    let mut uri = String::new();
+    for byte in path.replace('\\', "/").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions};
+
+    fn diagnostics(source: &str) -> Vec<Diagnostic> {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            error_recovery: true,
+            ..LexerOptions::default()
+        });
+        let _ = lexer.tokenize();
+        lexer.get_diagnostics().to_vec()
+    }
+
+    fn document() -> Value {
+        to_sarif(&[
+            SarifArtifact {
+                path: "src/a b.mcpp".to_string(),
+                diagnostics: diagnostics("int x = 1 @ 2;\n"),
+            },
+            SarifArtifact {
+                path: "src\\b.mcpp".to_string(),
+                diagnostics: diagnostics("string s = \"\\q\";\n"),
+            },
+        ])
+    }
+
+    #[test]
+    fn log_has_one_run_with_a_rule_per_code() {
+        let log = document();
+        assert_eq!(log["version"], SARIF_VERSION);
+        assert_eq!(log["$schema"], SARIF_SCHEMA);
+        let runs = log["runs"].as_array().unwrap();
+        assert_eq!(runs.len(), 1);
+        let rules = runs[0]["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), ERROR_CODES.len() + WARNING_CODES.len() + NOTE_CODES.len());
+        for rule in rules {
+            assert!(rule["id"].is_string() && rule["shortDescription"]["text"].is_string());
+            let level = rule["defaultConfiguration"]["level"].as_str().unwrap();
+            assert!(["error", "warning", "note"].contains(&level));
+        }
+    }
+
+    #[test]
+    fn each_input_is_an_artifact_and_results_point_into_it() {
+        let log = document();
+        let run = &log["runs"][0];
+        let uris: Vec<&str> = run["artifacts"].as_array().unwrap().iter().map(|a| a["location"]["uri"].as_str().unwrap()).collect();
+        assert_eq!(uris, ["src/a%20b.mcpp", "src/b.mcpp"]);
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        for (index, result) in results.iter().enumerate() {
+            let location = &result["locations"][0]["physicalLocation"];
+            assert_eq!(location["artifactLocation"]["index"], index);
+            assert_eq!(location["artifactLocation"]["uri"], uris[index]);
+            let rule = &rules[result["ruleIndex"].as_u64().unwrap() as usize];
+            assert_eq!(rule["id"], result["ruleId"]);
+        }
+        let first = &results[0];
+        assert_eq!(first["ruleId"], "E0001");
+        assert_eq!(first["level"], "error");
+        let region = &first["locations"][0]["physicalLocation"]["region"];
+        assert_eq!((region["startLine"].as_u64(), region["startColumn"].as_u64(), region["endColumn"].as_u64()), (Some(1), Some(11), Some(12)));
+        assert_eq!(results[1]["ruleId"], "W0002");
+        assert_eq!(results[1]["level"], "warning");
+    }
+}
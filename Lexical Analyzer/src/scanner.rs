@@ -0,0 +1,244 @@
+// Hand-written scanning backend. It recognizes exactly the language of the
+// regex pattern table, but decides on the first character instead of trying
+// every pattern in turn, which makes it the baseline for comparing the two
+// approaches. `--compare-backends` checks that both agree on real inputs.
+
+use crate::lexer::{Delimiter, Keyword, LiteralKind, Operator, TokenType};
+
+// Finds the token at the start of `text` and returns the pattern it matches
+// (before keyword reclassification) with its length in bytes. Returns None
+// where the pattern table would report an invalid character.
+pub fn scan_token(text: &str) -> Option<(TokenType, usize)> {
+    let bytes = text.as_bytes();
+    let first = *bytes.first()?;
+    let next = bytes.get(1).copied();
+
+    match first {
+        b'/' if next == Some(b'*') => {
+            if let Some(end) = text[2..].find("*/") {
+                return Some((TokenType::Comment, end + 4));
+            }
+        }
+        b'/' if next == Some(b'/') => {
+            let end = text.find('\n').unwrap_or(text.len());
+            return Some((TokenType::Comment, end));
+        }
+        b'"' => {
+            if let Some(len) = quoted_length(text, '"', true) {
+                return Some((TokenType::Literal(LiteralKind::String), len));
+            }
+            return None;
+        }
+        b'\'' => {
+            if let Some(len) = quoted_length(text, '\'', false) {
+                return Some((TokenType::Literal(LiteralKind::Char), len));
+            }
+            return None;
+        }
+        b'0'..=b'9' => return Some(number(bytes)),
+        b'.' if text.starts_with("...") => return Some((TokenType::Delimiter(Delimiter::Ellipsis), 3)),
+        _ => {}
+    }
+
+    if let Some(op) = operator(first, next) {
+        return Some(op);
+    }
+    if let Some(delimiter) = delimiter(first) {
+        return Some((TokenType::Delimiter(delimiter), 1));
+    }
+
+    if first == b'#' {
+        let word_len = word_length(&bytes[1..]);
+        return match keyword(&text[..1 + word_len]) {
+            Some(keyword) => Some((TokenType::Keyword(keyword), 1 + word_len)),
+            None if next.is_some_and(|b| b.is_ascii_alphabetic() || b == b'_') => Some((TokenType::Keyword(Keyword::Directive), 1 + word_len)),
+            _ => None,
+        };
+    }
+
+    if first.is_ascii_alphabetic() || first == b'_' {
+        let len = word_length(bytes);
+        let token_type = match &text[..len] {
+            "true" | "false" => TokenType::Literal(LiteralKind::Bool),
+            word => keyword(word).map(TokenType::Keyword).unwrap_or(TokenType::Identifier),
+        };
+        return Some((token_type, len));
+    }
+
+    None
+}
+
+// A raw string literal `R"delimiter(...)delimiter"` at the start of `text`,
+// running to the first `)` followed by the delimiter and a quote. The
+// delimiter is at most 16 characters, none of them parentheses, backslashes,
+// quotes or whitespace, and is usually empty. Neither backend matches raw
+// strings, which no regular pattern can describe; the lexer looks for them
+// first. Returns the length in bytes, the delimiter if the literal is never
+// closed, or None if `text` does not start one.
+pub fn raw_string(text: &str) -> Option<Result<usize, &str>> {
+    let rest = text.strip_prefix("R\"")?;
+    let open = rest.find('(')?;
+    let delimiter = &rest[..open];
+    if delimiter.len() > 16 || delimiter.chars().any(|ch| matches!(ch, ')' | '\\' | '"') || ch.is_whitespace()) {
+        return None;
+    }
+    let closing = format!("){}\"", delimiter);
+    match rest[open + 1..].find(&closing) {
+        Some(end) => Some(Ok(2 + open + 1 + end + closing.len())),
+        None => Some(Err(delimiter)),
+    }
+}
+
+// An attribute `[[...]]` at the start of `text`, ending at the first `]]`
+// outside any brackets or string literal inside it, so `[[deprecated("use
+// a[0]")]]` is one attribute. Like raw strings, attributes are found by the
+// lexer before either backend runs. Returns the length in bytes, an error if
+// nothing closes it, or None if `text` does not start one.
+pub fn attribute(text: &str) -> Option<Result<usize, ()>> {
+    let rest = text.strip_prefix("[[")?;
+    let mut depth = 0;
+    let mut chars = rest.char_indices().peekable();
+    while let Some((index, ch)) = chars.next() {
+        match ch {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            ']' if chars.peek().is_some_and(|(_, next)| *next == ']') => return Some(Ok(2 + index + 2)),
+            '"' => {
+                while let Some((_, ch)) = chars.next() {
+                    match ch {
+                        '"' | '\n' => break,
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(Err(()))
+}
+
+// The text of an attribute between its double brackets.
+pub fn attribute_contents(lexeme: &str) -> &str {
+    lexeme.get(2..lexeme.len().saturating_sub(2)).unwrap_or("")
+}
+
+// The contents of a raw string literal, between its parentheses.
+pub fn raw_string_contents(lexeme: &str) -> &str {
+    let open = lexeme.find('(').unwrap_or(lexeme.len());
+    lexeme.get(open + 1..lexeme.len().saturating_sub(open)).unwrap_or("")
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+fn word_length(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|b| is_word_byte(**b)).count()
+}
+
+fn digits(bytes: &[u8], start: usize) -> usize {
+    bytes[start..].iter().take_while(|b| b.is_ascii_digit()).count()
+}
+
+// `\d+\.\d+([eE][+-]?\d+)?` or `\d+`.
+fn number(bytes: &[u8]) -> (TokenType, usize) {
+    let int_len = digits(bytes, 0);
+    if bytes.get(int_len) != Some(&b'.') {
+        return (TokenType::Literal(LiteralKind::Integer), int_len);
+    }
+    let fraction = digits(bytes, int_len + 1);
+    if fraction == 0 {
+        return (TokenType::Literal(LiteralKind::Integer), int_len);
+    }
+    let mut len = int_len + 1 + fraction;
+    if matches!(bytes.get(len), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(len + 1), Some(b'+' | b'-')));
+        let exponent = digits(bytes, len + 1 + sign);
+        if exponent > 0 {
+            len += 1 + sign + exponent;
+        }
+    }
+    (TokenType::Literal(LiteralKind::Float), len)
+}
+
+// Length of a quoted literal, including both quotes. Escapes may not be
+// followed by a newline, matching `.` in the regex patterns. Strings hold any
+// number of characters; character literals exactly one.
+fn quoted_length(text: &str, quote: char, many: bool) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1);
+    let mut count = 0;
+    while let Some((index, ch)) = chars.next() {
+        if ch == quote {
+            return if many || count == 1 { Some(index + 1) } else { None };
+        }
+        if !many && count == 1 {
+            return None;
+        }
+        // Only a string may continue on the next line after a backslash
+        if ch == '\\' {
+            match chars.next() {
+                Some((_, '\n')) if !many => return None,
+                None => return None,
+                Some(_) => {}
+            }
+        }
+        count += 1;
+    }
+    None
+}
+
+fn operator(first: u8, next: Option<u8>) -> Option<(TokenType, usize)> {
+    let double = match (first, next) {
+        (b'&', Some(b'&')) => Some(Operator::LogicalAnd),
+        (b'|', Some(b'|')) => Some(Operator::LogicalOr),
+        (b'=', Some(b'=')) => Some(Operator::Equal),
+        (b'!', Some(b'=')) => Some(Operator::NotEqual),
+        (b'<', Some(b'=')) => Some(Operator::LessEqual),
+        (b'>', Some(b'=')) => Some(Operator::GreaterEqual),
+        (b'+', Some(b'+')) => Some(Operator::Increment),
+        (b'-', Some(b'-')) => Some(Operator::Decrement),
+        _ => None,
+    };
+    if let Some(op) = double {
+        return Some((TokenType::Operator(op), 2));
+    }
+    let single = match first {
+        b'+' => Operator::Plus,
+        b'-' => Operator::Minus,
+        b'*' => Operator::Multiply,
+        b'&' => Operator::Ampersand,
+        b'/' => Operator::Divide,
+        b'%' => Operator::Modulo,
+        b'=' => Operator::Assign,
+        b'<' => Operator::LessThan,
+        b'>' => Operator::GreaterThan,
+        b'!' => Operator::LogicalNot,
+        _ => return None,
+    };
+    Some((TokenType::Operator(single), 1))
+}
+
+fn delimiter(byte: u8) -> Option<Delimiter> {
+    match byte {
+        b';' => Some(Delimiter::Semicolon),
+        b',' => Some(Delimiter::Comma),
+        b'(' => Some(Delimiter::LeftParen),
+        b')' => Some(Delimiter::RightParen),
+        b'{' => Some(Delimiter::LeftBrace),
+        b'}' => Some(Delimiter::RightBrace),
+        b'[' => Some(Delimiter::LeftBracket),
+        b']' => Some(Delimiter::RightBracket),
+        b':' => Some(Delimiter::Colon),
+        _ => None,
+    }
+}
+
+fn keyword(word: &str) -> Option<Keyword> {
+    match TokenType::keyword(word) {
+        Some(TokenType::Keyword(keyword)) => Some(keyword),
+        _ => None,
+    }
+}
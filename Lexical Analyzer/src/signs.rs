@@ -0,0 +1,103 @@
+use crate::lexer::{Delimiter, Keyword, LiteralKind, Operator, Token, TokenType};
+
+// Whether a `-` after `previous` (None at the start of the stream) negates
+// what follows rather than subtracting it. `++` and `--` may be postfix, as
+// in `i-- - 1`, so they never make it unary.
+fn makes_unary(previous: Option<&Token>) -> bool {
+    match previous.map(|t| &t.token_type) {
+        None => true,
+        Some(TokenType::Operator(Operator::Increment | Operator::Decrement)) => false,
+        Some(TokenType::Operator(_)) => true,
+        Some(TokenType::Delimiter(delimiter)) => matches!(
+            delimiter,
+            Delimiter::LeftParen | Delimiter::LeftBracket | Delimiter::Comma | Delimiter::LeftBrace | Delimiter::Semicolon
+        ),
+        Some(TokenType::Keyword(Keyword::Return)) => true,
+        _ => false,
+    }
+}
+
+// The tokens with each unary `-` folded into the integer or float literal
+// right after it, as in `x = -5` or `(-3)`: one literal token spanning both,
+// with the sign in its lexeme and its signed number as `value`. A `-` that
+// subtracts, as in `x-1`, or that a space or comment separates from the
+// number is left alone, as is every `--`. Comments are skipped when looking
+// back at what comes before the `-`.
+pub fn fold_signs(tokens: &[Token]) -> Vec<Token> {
+    let mut folded: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let previous = folded.iter().rev().find(|t| t.token_type != TokenType::Comment);
+        let number = tokens.get(i + 1).filter(|next| {
+            matches!(next.token_type, TokenType::Literal(LiteralKind::Integer | LiteralKind::Float))
+                && next.line == token.line
+                && next.column == token.end_column + 1
+        });
+        match number {
+            Some(number) if token.token_type == TokenType::Operator(Operator::Minus) && makes_unary(previous) => {
+                let lexeme = format!("-{}", number.lexeme);
+                folded.push(Token {
+                    line: token.line,
+                    column: token.column,
+                    value: Some(lexeme.clone()),
+                    lexeme: lexeme.into(),
+                    ..number.clone()
+                });
+                i += 2;
+            }
+            _ => {
+                folded.push(token.clone());
+                i += 1;
+            }
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn folded(source: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize().unwrap();
+        fold_signs(lexer.get_tokens())
+    }
+
+    fn lexemes(source: &str) -> Vec<String> {
+        folded(source).iter().filter(|t| t.token_type != TokenType::EOF).map(|t| t.lexeme.to_string()).collect()
+    }
+
+    #[test]
+    fn unary_minus_joins_the_number() {
+        assert_eq!(lexemes("x = -5;"), ["x", "=", "-5", ";"]);
+        assert_eq!(lexemes("(-3)"), ["(", "-3", ")"]);
+        assert_eq!(lexemes("int arr[-1];"), ["int", "arr", "[", "-1", "]", ";"]);
+        assert_eq!(lexemes("-2.5 * f(1, -7)"), ["-2.5", "*", "f", "(", "1", ",", "-7", ")"]);
+        assert_eq!(lexemes("return -4;"), ["return", "-4", ";"]);
+        assert_eq!(lexemes("a - -6"), ["a", "-", "-6"]);
+
+        let tokens = folded("x = -5;");
+        let number = &tokens[2];
+        assert_eq!(number.token_type, TokenType::Literal(LiteralKind::Integer));
+        assert_eq!(number.value.as_deref(), Some("-5"));
+        assert_eq!((number.line, number.column, number.end_column), (1, 5, 6));
+    }
+
+    #[test]
+    fn binary_minus_and_decrements_are_left_alone() {
+        assert_eq!(lexemes("x-1"), ["x", "-", "1"]);
+        assert_eq!(lexemes("a - -b"), ["a", "-", "-", "b"]);
+        assert_eq!(lexemes("f(x) -2"), ["f", "(", "x", ")", "-", "2"]);
+        assert_eq!(lexemes("i-- - 1"), ["i", "--", "-", "1"]);
+        assert_eq!(lexemes("i---1"), ["i", "--", "-", "1"]);
+        assert_eq!(lexemes("x = --5;"), ["x", "=", "--", "5", ";"]);
+        // A space or a comment keeps the sign apart
+        assert_eq!(lexemes("x = - 5;"), ["x", "=", "-", "5", ";"]);
+        assert_eq!(lexemes("x = -/* c */5;"), ["x", "=", "-", "5", ";"]);
+        let tokens = folded("x-1");
+        assert_eq!(tokens[2].value, None);
+    }
+}
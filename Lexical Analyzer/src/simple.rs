@@ -0,0 +1,268 @@
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Backend, LexError, Lexer, LexerOptions, SymbolTable, Token};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Everything one run of the lexer produces, for callers that only want the
+/// results and not a `Lexer` to drive. `file` is the path the source was read
+/// from, None for text passed in directly.
+///
+/// ```
+/// let output = mcpp_lexer::lex("int count = 0;\n").unwrap();
+/// assert_eq!(output.file, None);
+/// assert_eq!(output.tokens.last().unwrap().lexeme, "EOF");
+/// assert_eq!(output.symbols.get_symbols()[0].name, "count");
+/// assert!(output.diagnostics.is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct LexOutput {
+    pub file: Option<String>,
+    pub tokens: Vec<Token>,
+    pub symbols: SymbolTable,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Lexes `source` with MCPP's rules and error recovery on, so a failure
+/// lists every error in the source, in order, rather than the first.
+///
+/// ```
+/// let output = mcpp_lexer::lex("int x = 1;\n").unwrap();
+/// assert_eq!(output.tokens.len(), 6);
+///
+/// let errors = mcpp_lexer::lex("int a = @;\nint b = $;\n").unwrap_err();
+/// let positions: Vec<(usize, usize)> = errors.iter().map(|e| (e.line, e.column)).collect();
+/// assert_eq!(positions, [(1, 9), (2, 9)]);
+/// ```
+pub fn lex(source: &str) -> Result<LexOutput, Vec<LexError>> {
+    let options = LexerOptions {
+        error_recovery: true,
+        ..LexerOptions::default()
+    };
+    lex_with(source, Backend::default(), &options)
+}
+
+// `lex` with a chosen backend and options. Without error recovery lexing
+// stops at the first error, which is then the only one returned.
+pub fn lex_with(source: &str, backend: Backend, options: &LexerOptions) -> Result<LexOutput, Vec<LexError>> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.set_backend(backend);
+    lexer.set_options(options.clone());
+    let mut errors = Vec::new();
+    while let Some(result) = lexer.next_token() {
+        if let Err(error) = result {
+            errors.push(error);
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(LexOutput {
+        file: None,
+        tokens: lexer.get_tokens().clone(),
+        symbols: lexer.get_symbol_table().clone(),
+        diagnostics: lexer.get_diagnostics().to_vec(),
+    })
+}
+
+/// Reads and lexes the file at `path` like `lex`, naming it in the output.
+/// A file that looks binary is refused.
+///
+/// ```
+/// use mcpp_lexer::LexFileError;
+///
+/// let path = std::env::temp_dir().join("lex_file_doctest.mcpp");
+/// // A byte order mark is dropped before lexing
+/// std::fs::write(&path, "\u{feff}int x = 1;\n").unwrap();
+/// let output = mcpp_lexer::lex_file(&path).unwrap();
+/// assert_eq!(output.file, Some(path.display().to_string()));
+/// assert_eq!(output.tokens[0].column, 1);
+///
+/// std::fs::remove_file(&path).unwrap();
+/// assert!(matches!(mcpp_lexer::lex_file(&path), Err(LexFileError::Read(..))));
+/// ```
+pub fn lex_file(path: &Path) -> Result<LexOutput, LexFileError> {
+    let file = path.display().to_string();
+    let source = read_source(path, false).map_err(|error| LexFileError::Read(file.clone(), error))?;
+    match lex(&source) {
+        Ok(output) => Ok(LexOutput { file: Some(file), ..output }),
+        Err(errors) => Err(LexFileError::Lex(file, errors)),
+    }
+}
+
+// Bytes of a file `looks_binary` examines.
+pub const BINARY_SAMPLE: usize = 8192;
+
+// Percentage of control characters in the sample above which a file is
+// taken for binary.
+pub const BINARY_THRESHOLD: usize = 30;
+
+// Whether `bytes` seem to be something other than text, such as an object
+// file or an image: the first `BINARY_SAMPLE` hold a NUL byte, or more than
+// `BINARY_THRESHOLD` percent of them are control characters other than
+// whitespace. Bytes outside ASCII count as text, for UTF-8.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SAMPLE)];
+    if sample.contains(&0) {
+        return true;
+    }
+    let control = sample.iter().filter(|&&b| (b < 0x20 && !b"\t\n\r\x0c".contains(&b)) || b == 0x7F).count();
+    control * 100 > sample.len() * BINARY_THRESHOLD
+}
+
+// The error `read_source` returns, as the source of an `InvalidData` I/O
+// error, for a file `looks_binary` refuses.
+#[derive(Debug)]
+pub struct BinaryInput;
+
+impl fmt::Display for BinaryInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input appears to be binary")
+    }
+}
+
+impl std::error::Error for BinaryInput {}
+
+// The text of a source file. A UTF-8 byte order mark is dropped, and one
+// for UTF-16 in either byte order has the rest decoded from it; anything
+// else must be UTF-8. Unless `allow_binary` is set, a file without a byte
+// order mark that `looks_binary` is refused with `BinaryInput`; with it,
+// bytes that are not UTF-8 are read as U+FFFD.
+pub fn read_source(path: &Path, allow_binary: bool) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("stream did not contain valid {}", what));
+    let utf16 = |rest: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        if !rest.len().is_multiple_of(2) {
+            return Err(invalid("UTF-16"));
+        }
+        let units: Vec<u16> = rest.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]])).collect();
+        String::from_utf16(&units).map_err(|_| invalid("UTF-16"))
+    };
+    match bytes.as_slice() {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8(rest.to_vec()).map_err(|_| invalid("UTF-8")),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ if !allow_binary && looks_binary(&bytes) => Err(io::Error::new(io::ErrorKind::InvalidData, BinaryInput)),
+        // What could not be decoded becomes U+FFFD, under `allow_binary`
+        _ if allow_binary => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        _ => String::from_utf8(bytes).map_err(|_| invalid("UTF-8")),
+    }
+}
+
+// Why `lex_file` failed, with the path it was given.
+#[derive(Debug)]
+pub enum LexFileError {
+    Read(String, io::Error),
+    Lex(String, Vec<LexError>),
+}
+
+impl fmt::Display for LexFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexFileError::Read(path, error) => write!(f, "Error reading file '{}': {}", path, error),
+            LexFileError::Lex(path, errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}: {}", path, error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LexFileError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // A file under the temporary directory, removed when dropped.
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, bytes: &[u8]) -> Self {
+            let path = env::temp_dir().join(format!("mcpp-simple-{}-{}", std::process::id(), name));
+            fs::write(&path, bytes).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn lex_returns_tokens_symbols_and_warnings() {
+        let output = lex("int add(int a, int b) { return a + b; }").unwrap();
+        assert_eq!(output.file, None);
+        assert_eq!(output.tokens.len(), 17);
+        let names: Vec<&str> = output.symbols.get_symbols().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["add", "a", "b", "a", "b"]);
+        // No newline at the end of the file
+        let codes: Vec<&str> = output.diagnostics.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, ["W0011"]);
+    }
+
+    #[test]
+    fn lex_returns_every_error_in_order() {
+        let errors = lex("int a = @;\nstring s = \"open\nint b = $;\n").unwrap_err();
+        let found: Vec<(&str, usize, usize)> = errors.iter().map(|e| (e.code.as_str(), e.line, e.column)).collect();
+        assert_eq!(found, [("E0001", 1, 9), ("E0004", 2, 12), ("E0001", 3, 9)]);
+
+        // Without recovery only the first is found
+        let first = lex_with("int a = @;\nint b = $;\n", Backend::Scanner, &LexerOptions::default()).unwrap_err();
+        assert_eq!(first.len(), 1);
+        assert_eq!((first[0].line, first[0].column), (1, 9));
+    }
+
+    #[test]
+    fn lex_file_names_the_file_and_decodes_it() {
+        let utf16: Vec<u8> = [0xFF, 0xFE].into_iter().chain("int x = 1;\n".encode_utf16().flat_map(|u| u.to_le_bytes())).collect();
+        let file = TempFile::new("utf16.mcpp", &utf16);
+        let output = lex_file(&file.0).unwrap();
+        assert_eq!(output.file.as_deref(), Some(file.0.display().to_string().as_str()));
+        assert_eq!(output.tokens.len(), 6);
+        assert_eq!(output.symbols.get_symbols()[0].name, "x");
+    }
+
+    #[test]
+    fn lex_file_errors_name_the_file() {
+        let file = TempFile::new("errors.mcpp", b"int a = @;\nint b = $;\n");
+        let path = file.0.display().to_string();
+        let error = lex_file(&file.0).unwrap_err();
+        assert!(matches!(&error, LexFileError::Lex(p, errors) if *p == path && errors.len() == 2));
+        let lines: Vec<String> = error.to_string().lines().map(String::from).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.starts_with(&format!("{}: ", path))));
+
+        let binary = TempFile::new("binary.mcpp", b"\x7fELF\x00\x00");
+        match lex_file(&binary.0) {
+            Err(LexFileError::Read(p, e)) => {
+                assert_eq!(p, binary.0.display().to_string());
+                assert!(e.get_ref().is_some_and(|inner| inner.is::<BinaryInput>()));
+            }
+            other => panic!("expected a read error, got {:?}", other.map(|o| o.tokens.len())),
+        }
+    }
+
+    #[test]
+    fn binary_detection_looks_at_nul_bytes_and_control_characters() {
+        assert!(looks_binary(b"int x = 1;\0"));
+        assert!(!looks_binary("int x = 1;\n\tstring s = \"café\";\r\n\x0c".as_bytes()));
+        // 3 control characters in 10 bytes is exactly the threshold
+        assert!(!looks_binary(b"\x01\x02\x03abcdefg"));
+        assert!(looks_binary(b"\x01\x02\x03\x04abcdef"));
+        // Only the first BINARY_SAMPLE bytes are examined
+        let mut late = vec![b'a'; BINARY_SAMPLE];
+        late.push(0);
+        assert!(!looks_binary(&late));
+        assert!(!looks_binary(b""));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::lexer::{PatternDoc, PatternSet, TokenType};
+use crate::markdown::code_span;
+use std::fmt::Write;
+
+// One token type of the specification with every way it is matched, in the
+// order the lexer tries them, and the first of their examples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecRow<'a> {
+    pub token_type: &'a TokenType,
+    pub example: &'a str,
+    pub patterns: Vec<&'a PatternDoc>,
+}
+
+// The token specification `--emit-spec` prints, read from the pattern table
+// the lexer itself uses: a row per token type in `TokenType::all()` order.
+// EOF and Error, which no pattern produces, are left out.
+pub fn token_spec(patterns: &PatternSet) -> Vec<SpecRow<'_>> {
+    TokenType::all()
+        .iter()
+        .filter_map(|token_type| {
+            let docs: Vec<&PatternDoc> = patterns.docs().iter().filter(|doc| doc.token_type == *token_type).collect();
+            Some(SpecRow {
+                token_type,
+                example: &docs.first()?.example,
+                patterns: docs,
+            })
+        })
+        .collect()
+}
+
+// What a pattern column shows: the regex, or that hand-written code finds it.
+fn pattern_text(doc: &PatternDoc) -> &str {
+    doc.regex.as_deref().unwrap_or("(hand-written)")
+}
+
+// Aligned columns, with a line for each further pattern of a token type.
+pub fn spec_text(rows: &[SpecRow]) -> String {
+    let widest = |lengths: Vec<usize>, title: &str| lengths.into_iter().chain([title.len()]).max().unwrap_or(0);
+    let type_width = widest(rows.iter().map(|row| row.token_type.name().len()).collect(), "Type");
+    let category_width = widest(rows.iter().map(|row| row.token_type.category().len()).collect(), "Category");
+    let example_width = widest(rows.iter().map(|row| row.example.chars().count()).collect(), "Example");
+    let pattern_width = widest(rows.iter().flat_map(|row| &row.patterns).map(|doc| pattern_text(doc).chars().count()).collect(), "Pattern");
+
+    let mut out = String::from("=== TOKEN SPECIFICATION ===\n");
+    writeln!(
+        out,
+        "{:<tw$}  {:<cw$}  {:<ew$}  {:<pw$}  Description",
+        "Type",
+        "Category",
+        "Example",
+        "Pattern",
+        tw = type_width,
+        cw = category_width,
+        ew = example_width,
+        pw = pattern_width
+    )
+    .unwrap();
+    for row in rows {
+        for (i, doc) in row.patterns.iter().enumerate() {
+            let (name, category, example) = match i {
+                0 => (row.token_type.name(), row.token_type.category(), row.example),
+                _ => ("", "", ""),
+            };
+            let line = format!(
+                "{:<tw$}  {:<cw$}  {:<ew$}  {:<pw$}  {}",
+                name,
+                category,
+                example,
+                pattern_text(doc),
+                doc.description,
+                tw = type_width,
+                cw = category_width,
+                ew = example_width,
+                pw = pattern_width
+            );
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+    }
+    writeln!(out, "{} token types", rows.len()).unwrap();
+    out
+}
+
+// A Markdown table, a token type's patterns and descriptions sharing its
+// row on separate lines.
+pub fn spec_markdown(rows: &[SpecRow]) -> String {
+    let cell = |text: &str| code_span(text).replace('|', "\\|");
+    let mut out = String::from("# MCPP Token Specification\n\n");
+    writeln!(out, "| Type | Category | Example | Pattern | Description |").unwrap();
+    writeln!(out, "|------|----------|---------|---------|-------------|").unwrap();
+    for row in rows {
+        let patterns: Vec<String> = row
+            .patterns
+            .iter()
+            .map(|doc| doc.regex.as_deref().map_or_else(|| "(hand-written)".to_string(), cell))
+            .collect();
+        let descriptions: Vec<String> = row.patterns.iter().map(|doc| doc.description.replace('|', "\\|")).collect();
+        writeln!(
+            out,
+            "| {} | {} | {} | {} | {} |",
+            row.token_type.name(),
+            row.token_type.category(),
+            cell(row.example),
+            patterns.join("<br>"),
+            descriptions.join("<br>")
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // The first column of each row of the printed spec, continuation lines
+    // of a token type with several patterns left out.
+    fn text_names(text: &str) -> Vec<String> {
+        let lines: Vec<&str> = text.lines().collect();
+        lines[2..lines.len() - 1]
+            .iter()
+            .filter(|line| !line.starts_with(' '))
+            .map(|line| line.split_whitespace().next().unwrap().to_string())
+            .collect()
+    }
+
+    fn markdown_names(markdown: &str) -> Vec<String> {
+        markdown.lines().skip(4).map(|line| line.split(" | ").next().unwrap().trim_start_matches("| ").to_string()).collect()
+    }
+
+    fn assert_every_type_once(names: &[String], format: &str) {
+        let listed: Vec<&TokenType> = TokenType::all().iter().filter(|t| !matches!(t, TokenType::EOF | TokenType::Error)).collect();
+        for token_type in &listed {
+            let count = names.iter().filter(|name| *name == token_type.name()).count();
+            assert_eq!(count, 1, "{} appears {} times in the {} spec", token_type.name(), count, format);
+        }
+        assert_eq!(names.len(), listed.len(), "{} spec rows", format);
+    }
+
+    #[test]
+    fn every_token_type_appears_exactly_once() {
+        let patterns = PatternSet::new();
+        let rows = token_spec(&patterns);
+        assert_every_type_once(&text_names(&spec_text(&rows)), "text");
+        assert_every_type_once(&markdown_names(&spec_markdown(&rows)), "Markdown");
+        for name in ["Void", "Attribute", "Comment", "Identifier"] {
+            assert!(rows.iter().any(|row| row.token_type.name() == name), "{} is missing", name);
+        }
+        assert!(!rows.iter().any(|row| matches!(row.token_type, TokenType::EOF | TokenType::Error)));
+    }
+
+    // `TokenType::all()` is what the spec walks, so no variant may be listed
+    // twice; `name()` is an exhaustive match giving each its own name.
+    #[test]
+    fn all_token_types_are_distinct_variants() {
+        let names: HashSet<&str> = TokenType::all().iter().map(|t| t.name()).collect();
+        assert_eq!(names.len(), TokenType::all().len());
+        assert!(names.contains("Void") && names.contains("EOF") && names.contains("Error"));
+    }
+
+    #[test]
+    fn several_patterns_share_one_row() {
+        let patterns = PatternSet::new();
+        let rows = token_spec(&patterns);
+        let total: usize = rows.iter().map(|row| row.patterns.len()).sum();
+        assert_eq!(total, patterns.docs().len() - patterns.docs().iter().filter(|d| matches!(d.token_type, TokenType::EOF | TokenType::Error)).count());
+        let text = spec_text(&rows);
+        assert_eq!(text.lines().count(), 3 + total);
+        assert!(text.ends_with(&format!("{} token types\n", rows.len())));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::lexer::TokenType;
+
+// Edits allowed between a name and the keyword it is taken for.
+pub const MAX_KEYWORD_DISTANCE: usize = 2;
+
+// Names shorter than this are only taken for a keyword when they are its
+// letters with two neighbours swapped, as `fro` is for `for`.
+pub const MIN_SUGGESTION_LENGTH: usize = 4;
+
+// Insertions, deletions, substitutions and swaps of two adjacent characters
+// needed to turn `a` into `b`, each character edited at most once.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Three rows of the table: two back, the previous one and the current one
+    let mut rows = vec![vec![0; b.len() + 1]; 3];
+    for (j, cell) in rows[1].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        rows[2][0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[1][j] + 1).min(rows[2][j - 1] + 1).min(rows[1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[0][j - 2] + 1);
+            }
+            rows[2][j] = best;
+        }
+        rows.rotate_left(1);
+    }
+    rows[1][b.len()]
+}
+
+fn is_swap(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a.len() != b.len() {
+        return false;
+    }
+    match (0..a.len()).filter(|&i| a[i] != b[i]).collect::<Vec<_>>()[..] {
+        [i, j] => j == i + 1 && a[i] == b[j] && a[j] == b[i],
+        _ => false,
+    }
+}
+
+// The keyword `name` most likely misspells: the only one closest to it
+// within `MAX_KEYWORD_DISTANCE` edits. Directives are left out, and a tie
+// suggests nothing.
+pub fn keyword_suggestion(name: &str) -> Option<&'static str> {
+    let short = name.chars().count() < MIN_SUGGESTION_LENGTH;
+    let mut best: Option<(usize, &'static str)> = None;
+    let mut tied = false;
+    for (spelling, _) in TokenType::keywords().iter().filter(|(spelling, _)| !spelling.starts_with('#')) {
+        if short && !is_swap(name, spelling) {
+            continue;
+        }
+        let distance = edit_distance(name, spelling);
+        if distance == 0 || distance > MAX_KEYWORD_DISTANCE {
+            continue;
+        }
+        match best {
+            Some((closest, _)) if distance > closest => {}
+            Some((closest, _)) if distance == closest => tied = true,
+            _ => {
+                best = Some((distance, spelling));
+                tied = false;
+            }
+        }
+    }
+    best.filter(|_| !tied).map(|(_, spelling)| spelling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn suggestions(source: &str) -> Vec<(String, usize, usize)> {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        lexer
+            .get_diagnostics()
+            .iter()
+            .filter(|d| d.code == "W0018")
+            .map(|d| (d.message.clone(), d.span.line, d.span.column))
+            .collect()
+    }
+
+    #[test]
+    fn distances_count_swaps_as_one_edit() {
+        assert_eq!(edit_distance("wihle", "while"), 1);
+        assert_eq!(edit_distance("retrun", "return"), 1);
+        assert_eq!(edit_distance("fro", "for"), 1);
+        assert_eq!(edit_distance("whale", "while"), 1);
+        assert_eq!(edit_distance("", "int"), 3);
+        assert_eq!(edit_distance("class", "class"), 0);
+        assert_eq!(edit_distance("ca", "abc"), 3);
+    }
+
+    #[test]
+    fn misspellings_suggest_their_keyword() {
+        assert_eq!(keyword_suggestion("wihle"), Some("while"));
+        assert_eq!(keyword_suggestion("retrun"), Some("return"));
+        assert_eq!(keyword_suggestion("fro"), Some("for"));
+        // Short names need a swap, and real keywords or far names get nothing
+        assert_eq!(keyword_suggestion("fo"), None);
+        assert_eq!(keyword_suggestion("while"), None);
+        assert_eq!(keyword_suggestion("counter"), None);
+        assert_eq!(keyword_suggestion("define"), None);
+    }
+
+    #[test]
+    fn only_undeclared_statement_heads_are_warned_about() {
+        let source = "int main() {\n    wihle (x < 10) { x = x + 1; }\n    fro (;;) { retrun 0; }\n    int whale = 1;\n    whale = 2;\n}\n";
+        let did_you_mean = |name: &str, keyword: &str| format!("'{}' is not declared; did you mean '{}'?", name, keyword);
+        assert_eq!(
+            suggestions(source),
+            [(did_you_mean("wihle", "while"), 2, 5), (did_you_mean("fro", "for"), 3, 5), (did_you_mean("retrun", "return"), 3, 16)]
+        );
+        // Undeclared, but not at the head of a statement
+        assert!(suggestions("int f(int n) { return whale + n; }\n").is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Lexer, TokenType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// Bumped whenever a field changes meaning or disappears; new fields may be
+// added without a bump.
+pub const SUMMARY_SCHEMA_VERSION: u32 = 1;
+
+// What `--summary-json` prints: the headline numbers of each input and
+// their total, without tokens or symbols.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub schema_version: u32,
+    pub files: Vec<SourceSummary>,
+    pub total: SummaryCounts,
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        RunSummary {
+            schema_version: SUMMARY_SCHEMA_VERSION,
+            files: Vec::new(),
+            total: SummaryCounts::default(),
+        }
+    }
+
+    pub fn add(&mut self, path: &str, success: bool, counts: SummaryCounts) {
+        self.total.add(&counts);
+        self.files.push(SourceSummary {
+            path: path.to_string(),
+            success,
+            counts,
+        });
+    }
+}
+
+impl Default for RunSummary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceSummary {
+    pub path: String,
+    pub success: bool,
+    #[serde(flatten)]
+    pub counts: SummaryCounts,
+}
+
+// Counts keyed by name are in name order. Tokens leave out EOF, symbols
+// count declarations only, and diagnostics are those the filter shows.
+// `elapsed_ms` is 0 for tokens read from the cache.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SummaryCounts {
+    pub tokens: usize,
+    pub tokens_by_category: BTreeMap<String, usize>,
+    pub symbols_by_kind: BTreeMap<String, usize>,
+    pub diagnostics_by_severity: BTreeMap<String, usize>,
+    pub diagnostics_by_code: BTreeMap<String, usize>,
+    pub lines: LineCounts,
+    pub nesting: NestingDepths,
+    pub elapsed_ms: f64,
+}
+
+impl SummaryCounts {
+    // Adds another file's counts; nesting keeps the deepest of each kind.
+    pub fn add(&mut self, other: &SummaryCounts) {
+        self.tokens += other.tokens;
+        for (totals, counts) in [
+            (&mut self.tokens_by_category, &other.tokens_by_category),
+            (&mut self.symbols_by_kind, &other.symbols_by_kind),
+            (&mut self.diagnostics_by_severity, &other.diagnostics_by_severity),
+            (&mut self.diagnostics_by_code, &other.diagnostics_by_code),
+        ] {
+            for (name, count) in counts {
+                *totals.entry(name.clone()).or_insert(0) += count;
+            }
+        }
+        self.lines.total += other.lines.total;
+        self.lines.code += other.lines.code;
+        self.lines.comment += other.lines.comment;
+        self.lines.blank += other.lines.blank;
+        self.nesting.braces = self.nesting.braces.max(other.nesting.braces);
+        self.nesting.parens = self.nesting.parens.max(other.nesting.parens);
+        self.nesting.brackets = self.nesting.brackets.max(other.nesting.brackets);
+        self.elapsed_ms += other.elapsed_ms;
+    }
+}
+
+// A line holding part of any token but a comment is code; any other line
+// holding more than whitespace is a comment line. Lines after an error that
+// stopped lexing are in the total only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LineCounts {
+    pub total: usize,
+    pub code: usize,
+    pub comment: usize,
+    pub blank: usize,
+}
+
+// Deepest nesting of each kind of bracket, 0 where there is none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct NestingDepths {
+    pub braces: usize,
+    pub parens: usize,
+    pub brackets: usize,
+}
+
+// The counts for the input `lexer` has just lexed, with `elapsed` None when
+// it was not timed.
+pub fn summarize(lexer: &Lexer, diagnostics: &[&Diagnostic], elapsed: Option<Duration>) -> SummaryCounts {
+    let mut counts = SummaryCounts::default();
+    // From the text, so a run stopped by an error still counts every line
+    let total_lines = lexer.source().lines().count();
+    let mut code = vec![false; total_lines + 1];
+    for token in lexer.get_tokens().iter().filter(|t| t.token_type != TokenType::EOF) {
+        counts.tokens += 1;
+        *counts.tokens_by_category.entry(token.token_type.category().to_string()).or_insert(0) += 1;
+        if token.token_type != TokenType::Comment {
+            if let Some(lines) = code.get_mut(token.line..=token.end_line.min(total_lines)) {
+                lines.fill(true);
+            }
+        }
+    }
+
+    let table = lexer.get_symbol_table();
+    for (row, symbol) in table.get_symbols().iter().enumerate() {
+        if table.is_declaration(row) {
+            *counts.symbols_by_kind.entry(symbol.symbol_type.clone()).or_insert(0) += 1;
+        }
+    }
+
+    for diagnostic in diagnostics {
+        *counts.diagnostics_by_severity.entry(diagnostic.severity.name().to_string()).or_insert(0) += 1;
+        *counts.diagnostics_by_code.entry(diagnostic.code.clone()).or_insert(0) += 1;
+    }
+
+    counts.lines.total = total_lines;
+    let scanned = lexer.get_tokens().last().map_or(0, |t| t.end_line);
+    for (index, text) in lexer.source().lines().take(scanned).enumerate() {
+        if code[index + 1] {
+            counts.lines.code += 1;
+        } else if text.trim().is_empty() {
+            counts.lines.blank += 1;
+        } else {
+            counts.lines.comment += 1;
+        }
+    }
+
+    let nesting = lexer.nesting_report();
+    counts.nesting = NestingDepths {
+        braces: nesting.braces.map_or(0, |m| m.depth),
+        parens: nesting.parens.map_or(0, |m| m.depth),
+        brackets: nesting.brackets.map_or(0, |m| m.depth),
+    };
+    counts.elapsed_ms = elapsed.map_or(0.0, |e| e.as_secs_f64() * 1000.0);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::LexerOptions;
+
+    fn counted(source: &str) -> SummaryCounts {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(LexerOptions {
+            keep_comments: true,
+            error_recovery: true,
+            ..LexerOptions::default()
+        });
+        let _ = lexer.tokenize();
+        let diagnostics: Vec<&Diagnostic> = lexer.get_diagnostics().iter().collect();
+        summarize(&lexer, &diagnostics, None)
+    }
+
+    const SOURCE: &str = "// header\nint main() {\n    int a[2];\n\n    return f((a[0]));\n}\n";
+
+    #[test]
+    fn the_schema_is_locked() {
+        let mut summary = RunSummary::new();
+        summary.add("a.mcpp", true, counted(SOURCE));
+        let expected = concat!(
+            r#"{"schema_version":1,"files":[{"path":"a.mcpp","success":true,"#,
+            r#""tokens":24,"tokens_by_category":{"comment":1,"delimiter":14,"identifier":4,"keyword":3,"literal":2},"#,
+            r#""symbols_by_kind":{"function":1,"variable":1},"#,
+            r#""diagnostics_by_severity":{"warning":1},"diagnostics_by_code":{"W0006":1},"#,
+            r#""lines":{"total":6,"code":4,"comment":1,"blank":1},"#,
+            r#""nesting":{"braces":1,"parens":2,"brackets":1},"elapsed_ms":0.0}],"#,
+            r#""total":{"tokens":24,"tokens_by_category":{"comment":1,"delimiter":14,"identifier":4,"keyword":3,"literal":2},"#,
+            r#""symbols_by_kind":{"function":1,"variable":1},"#,
+            r#""diagnostics_by_severity":{"warning":1},"diagnostics_by_code":{"W0006":1},"#,
+            r#""lines":{"total":6,"code":4,"comment":1,"blank":1},"#,
+            r#""nesting":{"braces":1,"parens":2,"brackets":1},"elapsed_ms":0.0}}"#,
+        );
+        assert_eq!(serde_json::to_string(&summary).unwrap(), expected);
+        assert_eq!(serde_json::from_str::<RunSummary>(expected).unwrap(), summary);
+    }
+
+    #[test]
+    fn totals_add_counts_and_keep_the_deepest_nesting() {
+        let mut summary = RunSummary::new();
+        summary.add("a.mcpp", true, counted(SOURCE));
+        summary.add("b.mcpp", false, counted("int x = @;\n{ { { } } }\n"));
+        let total = &summary.total;
+        assert_eq!(total.tokens, 24 + 11);
+        assert_eq!(total.tokens_by_category["delimiter"], 14 + 7);
+        assert_eq!((total.diagnostics_by_severity["error"], total.diagnostics_by_code["E0001"]), (1, 1));
+        assert_eq!((total.lines.total, total.lines.code), (8, 6));
+        assert_eq!((total.nesting.braces, total.nesting.parens, total.nesting.brackets), (3, 2, 1));
+        assert_eq!(summary.files[1].path, "b.mcpp");
+        assert!(!summary.files[1].success);
+    }
+
+    #[test]
+    fn lines_after_a_stopping_error_are_only_in_the_total() {
+        let mut lexer = Lexer::new("int a;\nint b = @;\n\nint c;\n".to_string());
+        let _ = lexer.tokenize();
+        let counts = summarize(&lexer, &[], Some(Duration::from_millis(3)));
+        assert_eq!(counts.lines, LineCounts { total: 4, code: 2, comment: 0, blank: 0 });
+        assert_eq!(counts.elapsed_ms, 3.0);
+        assert!(counts.diagnostics_by_code.is_empty());
+    }
+}
@@ -0,0 +1,390 @@
+use crate::lexer::{Symbol, SymbolTable};
+use std::fmt;
+use std::fmt::Write;
+
+// One row of an expected-symbols CSV: a declaration a correct solution must
+// make. `kind` is a symbol type such as `function` and `data_type` the type
+// it is declared with. `line` is checked only when given, and a row that is
+// not `required` costs points but does not fail the check. `row` is the
+// CSV line it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedSymbol {
+    pub name: String,
+    pub kind: String,
+    pub data_type: String,
+    pub scope: String,
+    pub line: Option<usize>,
+    pub required: bool,
+    pub row: usize,
+}
+
+// Columns an expected-symbols CSV must have; `line` and `required` may
+// follow in any order.
+pub const EXPECTED_SYMBOL_COLUMNS: &[&str] = &["name", "kind", "type", "scope"];
+const OPTIONAL_COLUMNS: &[&str] = &["line", "required"];
+
+// Reads an expected-symbols CSV. The first row names the columns, in any
+// order and any case; fields may be double-quoted, with `""` for a quote
+// inside, and blank lines are skipped. `required` is `yes` or `no`, default
+// `yes`.
+pub fn parse_expected_symbols(text: &str) -> Result<Vec<ExpectedSymbol>, String> {
+    let mut rows = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let Some((index, header)) = rows.next() else {
+        return Err("The file is empty; expected a header row of name, kind, type and scope".to_string());
+    };
+    let header: Vec<String> = csv_fields(header, index + 1)?.iter().map(|name| name.trim().to_ascii_lowercase()).collect();
+    for name in &header {
+        if !EXPECTED_SYMBOL_COLUMNS.contains(&name.as_str()) && !OPTIONAL_COLUMNS.contains(&name.as_str()) {
+            return Err(format!(
+                "Line {}: unknown column '{}', expected {}",
+                index + 1,
+                name,
+                [EXPECTED_SYMBOL_COLUMNS, OPTIONAL_COLUMNS].concat().join(", ")
+            ));
+        }
+    }
+    let column = |name: &str| header.iter().position(|c| c == name);
+    if let Some(missing) = EXPECTED_SYMBOL_COLUMNS.iter().find(|name| column(name).is_none()) {
+        return Err(format!("Line {}: the header has no '{}' column", index + 1, missing));
+    }
+
+    let mut expected = Vec::new();
+    for (index, line) in rows {
+        let row = index + 1;
+        let fields = csv_fields(line, row)?;
+        if fields.len() != header.len() {
+            return Err(format!("Line {}: expected {} fields, found {}", row, header.len(), fields.len()));
+        }
+        let field = |name: &str| column(name).map(|i| fields[i].trim());
+        let line = match field("line").filter(|value| !value.is_empty()) {
+            Some(value) => Some(value.parse().map_err(|_| format!("Line {}: invalid line number '{}'", row, value))?),
+            None => None,
+        };
+        let required = match field("required").map(str::to_ascii_lowercase).as_deref() {
+            None | Some("" | "yes") => true,
+            Some("no") => false,
+            Some(other) => return Err(format!("Line {}: 'required' must be yes or no, found '{}'", row, other)),
+        };
+        expected.push(ExpectedSymbol {
+            name: field("name").unwrap_or_default().to_string(),
+            kind: field("kind").unwrap_or_default().to_string(),
+            data_type: field("type").unwrap_or_default().to_string(),
+            scope: field("scope").unwrap_or_default().to_string(),
+            line,
+            required,
+            row,
+        });
+    }
+    Ok(expected)
+}
+
+// The fields of one CSV line.
+fn csv_fields(line: &str, row: usize) -> Result<Vec<String>, String> {
+    let line = line.trim_end_matches('\r');
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(ch),
+        }
+    }
+    if quoted {
+        return Err(format!("Line {}: unterminated quoted field", row));
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+// How strictly `check_symbols` compares: `ignore_lines` skips the line
+// column, and `allow_extra` lets the table declare symbols the CSV does not
+// list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SymbolCheckPolicy {
+    pub ignore_lines: bool,
+    pub allow_extra: bool,
+}
+
+impl SymbolCheckPolicy {
+    // "exact", or what is relaxed, as in "ignoring lines, allowing extras".
+    pub fn describe(&self) -> String {
+        let relaxed: Vec<&str> = [(self.ignore_lines, "ignoring lines"), (self.allow_extra, "allowing extra symbols")]
+            .into_iter()
+            .filter_map(|(on, name)| on.then_some(name))
+            .collect();
+        if relaxed.is_empty() {
+            "exact".to_string()
+        } else {
+            relaxed.join(", ")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolOutcome {
+    Pass(Symbol),
+    // Declared with that name in that scope, with each field that differs
+    // as (column, expected, actual)
+    Mismatch(Symbol, Vec<(&'static str, String, String)>),
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolCheck {
+    pub expected: ExpectedSymbol,
+    pub outcome: SymbolOutcome,
+}
+
+impl SymbolCheck {
+    pub fn passed(&self) -> bool {
+        matches!(self.outcome, SymbolOutcome::Pass(_))
+    }
+}
+
+impl fmt::Display for SymbolCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = &self.expected;
+        let status = match (&self.outcome, expected.required) {
+            (SymbolOutcome::Pass(_), _) => "PASS",
+            (_, true) => "FAIL",
+            (_, false) => "MISS",
+        };
+        write!(f, "{} {} {} {} in {}", status, expected.kind, expected.data_type, expected.name, expected.scope)?;
+        if let Some(line) = expected.line {
+            write!(f, " at line {}", line)?;
+        }
+        match &self.outcome {
+            SymbolOutcome::Pass(_) => Ok(()),
+            SymbolOutcome::Missing => write!(f, ": not declared"),
+            SymbolOutcome::Mismatch(_, fields) => {
+                let fields: Vec<String> = fields.iter().map(|(column, _, actual)| format!("{} is {}", column, actual)).collect();
+                write!(f, ": {}", fields.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolCheckReport {
+    pub policy: SymbolCheckPolicy,
+    pub checks: Vec<SymbolCheck>,
+    // Declarations no row matched
+    pub extra: Vec<Symbol>,
+}
+
+impl SymbolCheckReport {
+    pub fn passed(&self) -> usize {
+        self.checks.iter().filter(|c| c.passed()).count()
+    }
+
+    // Whether every required row passed, and the table declares nothing
+    // else unless the policy allows it.
+    pub fn success(&self) -> bool {
+        self.checks.iter().all(|c| c.passed() || !c.expected.required) && (self.policy.allow_extra || self.extra.is_empty())
+    }
+
+    // Passed rows as a percentage of all rows, 100 for an empty CSV.
+    pub fn score(&self) -> f64 {
+        if self.checks.is_empty() {
+            return 100.0;
+        }
+        (self.passed() as f64 * 1000.0 / self.checks.len() as f64).round() / 10.0
+    }
+}
+
+// Compares the declarations in `table` with the expected rows. A row is
+// matched to the first declaration not yet matched with its name and scope,
+// and on its line when lines are compared; if none has that line, one
+// elsewhere is a mismatch on the line.
+pub fn check_symbols(table: &SymbolTable, expected: &[ExpectedSymbol], policy: SymbolCheckPolicy) -> SymbolCheckReport {
+    let declarations: Vec<&Symbol> = (0..table.get_symbols().len())
+        .filter(|&row| table.is_declaration(row))
+        .map(|row| &table.get_symbols()[row])
+        .collect();
+    let mut matched = vec![false; declarations.len()];
+    let mut checks = Vec::new();
+    for row in expected {
+        let candidates: Vec<usize> = (0..declarations.len())
+            .filter(|&i| !matched[i] && declarations[i].name == row.name && declarations[i].scope == row.scope)
+            .collect();
+        let line = row.line.filter(|_| !policy.ignore_lines);
+        let found = candidates
+            .iter()
+            .find(|&&i| line.is_none_or(|line| declarations[i].line == line))
+            .or(candidates.first());
+        let outcome = match found {
+            Some(&i) => {
+                matched[i] = true;
+                let symbol = declarations[i];
+                let mut fields = Vec::new();
+                if symbol.symbol_type != row.kind {
+                    fields.push(("kind", row.kind.clone(), symbol.symbol_type.clone()));
+                }
+                if symbol.data_type != row.data_type {
+                    fields.push(("type", row.data_type.clone(), symbol.data_type.clone()));
+                }
+                if let Some(line) = line.filter(|line| *line != symbol.line) {
+                    fields.push(("line", line.to_string(), symbol.line.to_string()));
+                }
+                if fields.is_empty() {
+                    SymbolOutcome::Pass(symbol.clone())
+                } else {
+                    SymbolOutcome::Mismatch(symbol.clone(), fields)
+                }
+            }
+            None => SymbolOutcome::Missing,
+        };
+        checks.push(SymbolCheck {
+            expected: row.clone(),
+            outcome,
+        });
+    }
+    let extra = declarations.iter().zip(&matched).filter(|(_, matched)| !**matched).map(|(s, _)| (*s).clone()).collect();
+    SymbolCheckReport { policy, checks, extra }
+}
+
+pub fn symbol_check_text(report: &SymbolCheckReport) -> String {
+    let mut out = String::from("=== SYMBOL CHECK ===\n");
+    writeln!(out, "Policy: {}", report.policy.describe()).unwrap();
+    for check in &report.checks {
+        writeln!(out, "{}", check).unwrap();
+    }
+    let label = if report.policy.allow_extra { "EXTRA (allowed)" } else { "EXTRA" };
+    for symbol in &report.extra {
+        writeln!(out, "{} {} {} {} in {} at line {}", label, symbol.symbol_type, symbol.data_type, symbol.name, symbol.scope, symbol.line).unwrap();
+    }
+    writeln!(
+        out,
+        "Score: {}/{} symbols ({:.1}%), {}",
+        report.passed(),
+        report.checks.len(),
+        report.score(),
+        if report.success() { "passed" } else { "failed" }
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    const SOURCE: &str = "int total = 0;\nint add(int a, int b) {\n    return a + b;\n}\n";
+
+    const EXACT: &str = "\
+name,kind,type,scope,line
+total,variable,int,global,1
+add,function,int,global,2
+a,variable,int,add,2
+b,variable,int,add,2
+";
+
+    fn table() -> SymbolTable {
+        let mut lexer = Lexer::new(SOURCE.to_string());
+        lexer.tokenize().unwrap();
+        lexer.get_symbol_table().clone()
+    }
+
+    fn checked(csv: &str, policy: SymbolCheckPolicy) -> SymbolCheckReport {
+        check_symbols(&table(), &parse_expected_symbols(csv).unwrap(), policy)
+    }
+
+    fn lines(report: &SymbolCheckReport) -> Vec<String> {
+        symbol_check_text(report).lines().skip(2).map(String::from).collect()
+    }
+
+    #[test]
+    fn an_exact_match_passes_every_row() {
+        let report = checked(EXACT, SymbolCheckPolicy::default());
+        assert!(report.success());
+        assert_eq!(report.score(), 100.0);
+        assert!(report.extra.is_empty());
+        assert_eq!(
+            lines(&report),
+            [
+                "PASS variable int total in global at line 1",
+                "PASS function int add in global at line 2",
+                "PASS variable int a in add at line 2",
+                "PASS variable int b in add at line 2",
+                "Score: 4/4 symbols (100.0%), passed",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_missing_symbol_fails_unless_optional() {
+        let csv = format!("{}count,variable,int,global,5\n", EXACT);
+        let report = checked(&csv, SymbolCheckPolicy::default());
+        assert!(!report.success());
+        assert_eq!(report.checks[4].outcome, SymbolOutcome::Missing);
+        assert_eq!(lines(&report)[4..], ["FAIL variable int count in global at line 5: not declared", "Score: 4/5 symbols (80.0%), failed"]);
+
+        let optional = "name,kind,type,scope,required\ntotal,variable,int,global,yes\nadd,function,int,global,\na,variable,int,add,\nb,variable,int,add,\ncount,variable,int,global,no\n";
+        let report = checked(optional, SymbolCheckPolicy::default());
+        assert!(report.success());
+        assert_eq!(lines(&report)[4], "MISS variable int count in global: not declared");
+    }
+
+    #[test]
+    fn a_wrong_type_or_line_is_a_mismatch() {
+        let csv = EXACT.replace("total,variable,int,global,1", "total,variable,float,global,3");
+        let report = checked(&csv, SymbolCheckPolicy::default());
+        assert!(!report.success());
+        let SymbolOutcome::Mismatch(symbol, fields) = &report.checks[0].outcome else {
+            panic!("expected a mismatch, got {:?}", report.checks[0].outcome);
+        };
+        assert_eq!(symbol.name, "total");
+        assert_eq!(*fields, [("type", "float".to_string(), "int".to_string()), ("line", "3".to_string(), "1".to_string())]);
+        assert_eq!(lines(&report)[0], "FAIL variable float total in global at line 3: type is int, line is 1");
+
+        // With lines ignored only the type is wrong
+        let ignoring = checked(&csv, SymbolCheckPolicy { ignore_lines: true, allow_extra: false });
+        assert!(matches!(&ignoring.checks[0].outcome, SymbolOutcome::Mismatch(_, fields) if fields.len() == 1));
+    }
+
+    #[test]
+    fn extra_declarations_fail_unless_allowed() {
+        let csv: String = EXACT.lines().take(4).map(|line| format!("{}\n", line)).collect();
+        let strict = checked(&csv, SymbolCheckPolicy::default());
+        let extras: Vec<&str> = strict.extra.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(extras, ["b"]);
+        assert!(!strict.success());
+        assert_eq!(strict.score(), 100.0);
+        assert_eq!(lines(&strict)[3..], ["EXTRA variable int b in add at line 2", "Score: 3/3 symbols (100.0%), failed"]);
+
+        let allowing = checked(&csv, SymbolCheckPolicy { ignore_lines: false, allow_extra: true });
+        assert!(allowing.success());
+        assert_eq!(lines(&allowing)[3], "EXTRA (allowed) variable int b in add at line 2");
+        assert_eq!(allowing.policy.describe(), "allowing extra symbols");
+    }
+
+    #[test]
+    fn csv_headers_and_fields_are_checked() {
+        let quoted = parse_expected_symbols("Scope,NAME,kind,type\r\n\n\"global\",\"say \"\"hi\"\"\",function,void\n").unwrap();
+        assert_eq!((quoted[0].name.as_str(), quoted[0].scope.as_str(), quoted[0].row, quoted[0].line), ("say \"hi\"", "global", 3, None));
+        let errors = [
+            ("", "The file is empty; expected a header row of name, kind, type and scope"),
+            ("name,kind,type\n", "Line 1: the header has no 'scope' column"),
+            ("name,kind,type,scope,colour\n", "Line 1: unknown column 'colour', expected name, kind, type, scope, line, required"),
+            ("name,kind,type,scope\nx,variable,int\n", "Line 2: expected 4 fields, found 3"),
+            ("name,kind,type,scope,line\nx,variable,int,global,two\n", "Line 2: invalid line number 'two'"),
+            ("name,kind,type,scope,required\nx,variable,int,global,maybe\n", "Line 2: 'required' must be yes or no, found 'maybe'"),
+            ("name,kind,type,scope\n\"x,variable,int,global\n", "Line 2: unterminated quoted field"),
+        ];
+        for (csv, error) in errors {
+            assert_eq!(parse_expected_symbols(csv).unwrap_err(), error, "{:?}", csv);
+        }
+    }
+}
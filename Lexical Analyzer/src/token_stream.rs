@@ -0,0 +1,433 @@
+use crate::document::{self, SCHEMA_VERSION};
+use crate::lexer::{SymbolTable, SymbolTableBuilder, Token, TokenType};
+use std::io;
+
+// A token stream read back from a saved JSON document (or a bare token
+// array), checked to be one that the lexer could have produced: a schema
+// version this build understands, positions that never go backwards and
+// exactly one EOF, at the end. Positions are 1-based whatever base the file
+// used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenStream {
+    tokens: Vec<Token>,
+}
+
+impl TokenStream {
+    pub fn from_json_str(json: &str) -> Result<TokenStream, String> {
+        Self::from_json_slice(json.as_bytes())
+    }
+
+    pub fn from_json_slice(bytes: &[u8]) -> Result<TokenStream, String> {
+        let document = document::parse_document(bytes).map_err(|e| format!("Malformed token document: {}", e))?;
+        if document.schema_version > SCHEMA_VERSION {
+            return Err(format!(
+                "Unsupported schema version {} (this build reads up to {})",
+                document.schema_version, SCHEMA_VERSION
+            ));
+        }
+        Self::from_tokens(document.tokens)
+    }
+
+    pub fn from_reader<R: io::Read>(mut reader: R) -> Result<TokenStream, String> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("Could not read token document: {}", e))?;
+        Self::from_json_slice(&bytes)
+    }
+
+    pub fn from_tokens(tokens: Vec<Token>) -> Result<TokenStream, String> {
+        for (index, pair) in tokens.windows(2).enumerate() {
+            let (previous, token) = (&pair[0], &pair[1]);
+            if (token.line, token.column) < (previous.line, previous.column) {
+                return Err(format!(
+                    "Token {} ('{}' at line {}, column {}) starts before the previous token (line {}, column {})",
+                    index + 1,
+                    token.lexeme,
+                    token.line,
+                    token.column,
+                    previous.line,
+                    previous.column
+                ));
+            }
+        }
+        match tokens.iter().position(|t| t.token_type == TokenType::EOF) {
+            Some(index) if index + 1 == tokens.len() => Ok(TokenStream { tokens }),
+            Some(index) => Err(format!("EOF token at index {} is followed by {} more tokens", index, tokens.len() - index - 1)),
+            None => Err("Token stream does not end with EOF; it may be from a run that stopped at an error".to_string()),
+        }
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn into_tokens(self) -> Vec<Token> {
+        self.tokens
+    }
+
+    // The symbol table the lexer builds for these tokens.
+    pub fn symbol_table(&self) -> SymbolTable {
+        let mut builder = SymbolTableBuilder::new();
+        for token in &self.tokens {
+            builder.observe(token);
+        }
+        builder.into_symbol_table()
+    }
+}
+
+// Source text that lexes back to `tokens`: each token is written at its
+// line and column, counted in characters, with spaces and newlines filling
+// the gaps where whitespace and dropped comments were, up to where EOF
+// was. A token that would start inside the previous one follows it after a
+// single space instead. For a stream read from the lexer, lexing the text
+// gives back the same tokens at the same positions.
+pub fn detokenize(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    let (mut line, mut column) = (1, 1);
+    for token in tokens {
+        if token.line > line {
+            out.extend(std::iter::repeat_n('\n', token.line - line));
+            (line, column) = (token.line, 1);
+        }
+        if token.line == line && token.column >= column {
+            out.extend(std::iter::repeat_n(' ', token.column - column));
+            column = token.column;
+        } else if !out.is_empty() && !out.ends_with('\n') && token.token_type != TokenType::EOF {
+            out.push(' ');
+            column += 1;
+        }
+        if token.token_type == TokenType::EOF {
+            break;
+        }
+        out.push_str(&token.lexeme);
+        match token.lexeme.rsplit_once('\n') {
+            Some((before, last)) => {
+                line += before.matches('\n').count() + 1;
+                column = last.chars().count() + 1;
+            }
+            None => column += token.lexeme.chars().count(),
+        }
+    }
+    out
+}
+
+// What `first_mismatch` leaves out when it compares two streams. With
+// nothing ignored, tokens must be equal in every field. Ignoring positions
+// compares each token's type and lexeme only, and ignoring lexemes its type
+// only, which implies ignoring positions too. Ignoring comments drops
+// Comment tokens from both streams first, and combines with either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ComparePolicy {
+    pub positions: bool,
+    pub lexemes: bool,
+    pub comments: bool,
+}
+
+pub const COMPARE_POLICY_NAMES: &[&str] = &["positions", "lexemes", "comments"];
+
+impl ComparePolicy {
+    // Adds one of `COMPARE_POLICY_NAMES` to what is ignored.
+    pub fn ignore(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "positions" => self.positions = true,
+            "lexemes" => self.lexemes = true,
+            "comments" => self.comments = true,
+            _ => {
+                return Err(format!(
+                    "Unknown comparison policy '{}', expected one of: {}",
+                    name,
+                    COMPARE_POLICY_NAMES.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    // "exact", or what is ignored, as in "ignoring positions, comments".
+    pub fn describe(&self) -> String {
+        let ignored: Vec<&str> = [self.positions && !self.lexemes, self.lexemes, self.comments]
+            .into_iter()
+            .zip(COMPARE_POLICY_NAMES)
+            .filter_map(|(on, name)| on.then_some(*name))
+            .collect();
+        if ignored.is_empty() {
+            "exact".to_string()
+        } else {
+            format!("ignoring {}", ignored.join(", "))
+        }
+    }
+
+    pub fn matches(&self, expected: &Token, actual: &Token) -> bool {
+        if self.lexemes {
+            expected.token_type == actual.token_type
+        } else if self.positions {
+            expected.token_type == actual.token_type && expected.lexeme == actual.lexeme
+        } else {
+            expected == actual
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenMismatch<'a> {
+    // The first pair the policy does not accept, at `index` among the
+    // tokens compared.
+    Differs { index: usize, expected: &'a Token, actual: &'a Token },
+    // Every pair matched but one stream has more tokens.
+    Count { expected: usize, actual: usize },
+}
+
+// Where `actual` first departs from `expected` under `policy`, or None if
+// they match.
+pub fn first_mismatch<'a>(expected: &'a [Token], actual: &'a [Token], policy: ComparePolicy) -> Option<TokenMismatch<'a>> {
+    fn kept(tokens: &[Token], policy: ComparePolicy) -> Vec<&Token> {
+        tokens
+            .iter()
+            .filter(|t| !policy.comments || t.token_type != TokenType::Comment)
+            .collect()
+    }
+    let (expected, actual) = (kept(expected, policy), kept(actual, policy));
+    for (index, (&wanted, &found)) in expected.iter().zip(actual.iter()).enumerate() {
+        if !policy.matches(wanted, found) {
+            return Some(TokenMismatch::Differs {
+                index,
+                expected: wanted,
+                actual: found,
+            });
+        }
+    }
+    if expected.len() != actual.len() {
+        return Some(TokenMismatch::Count {
+            expected: expected.len(),
+            actual: actual.len(),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::DiagnosticFilter;
+    use crate::histogram::histogram;
+    use crate::lexer::Lexer;
+    use crate::position::PositionBase;
+
+    const SOURCE: &str = "int add(int a, int b) {\n    int c = a + b; // sum\n    return c;\n}\n";
+
+    fn saved(source: &str, base: PositionBase) -> (Lexer, Vec<u8>) {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        let mut buffer = Vec::new();
+        lexer.write_json(&mut buffer, true, &DiagnosticFilter::default(), base, false).unwrap();
+        (lexer, buffer)
+    }
+
+    #[test]
+    fn reloaded_streams_give_the_direct_results() {
+        for base in [PositionBase::One, PositionBase::Zero] {
+            let (lexer, json) = saved(SOURCE, base);
+            let stream = TokenStream::from_reader(json.as_slice()).unwrap();
+            assert_eq!(stream.tokens(), lexer.get_tokens());
+            assert_eq!(stream.symbol_table().get_symbols(), lexer.get_symbol_table().get_symbols());
+            assert_eq!(histogram(stream.tokens(), 5), histogram(lexer.get_tokens(), 5));
+            assert_eq!(first_mismatch(stream.tokens(), lexer.get_tokens(), ComparePolicy::default()), None);
+            let mut again = Lexer::new(detokenize(stream.tokens()));
+            again.tokenize().unwrap();
+            assert_eq!(again.get_tokens(), lexer.get_tokens());
+        }
+        // A bare token array from before the document format
+        let (lexer, _) = saved(SOURCE, PositionBase::One);
+        let array = serde_json::to_string(lexer.get_tokens()).unwrap();
+        assert_eq!(TokenStream::from_json_str(&array).unwrap().into_tokens(), lexer.get_tokens().to_vec());
+    }
+
+    #[test]
+    fn malformed_documents_are_refused() {
+        let (lexer, json) = saved(SOURCE, PositionBase::One);
+        let mut document: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        document["schema_version"] = (SCHEMA_VERSION + 1).into();
+        let error = TokenStream::from_json_str(&document.to_string()).unwrap_err();
+        assert_eq!(error, format!("Unsupported schema version {} (this build reads up to {})", SCHEMA_VERSION + 1, SCHEMA_VERSION));
+        assert!(TokenStream::from_json_str("{\"tokens\": ").unwrap_err().starts_with("Malformed token document: "));
+
+        let mut tokens = lexer.get_tokens().to_vec();
+        tokens.swap(1, 2);
+        let error = TokenStream::from_tokens(tokens).unwrap_err();
+        assert_eq!(error, "Token 2 ('add' at line 1, column 5) starts before the previous token (line 1, column 8)");
+        let mut tokens = lexer.get_tokens().to_vec();
+        tokens.pop();
+        assert!(TokenStream::from_tokens(tokens.clone()).unwrap_err().contains("does not end with EOF"));
+        tokens.insert(3, Token::new(TokenType::EOF, "EOF", 1, 8));
+        assert_eq!(TokenStream::from_tokens(tokens).unwrap_err(), "EOF token at index 3 is followed by 18 more tokens");
+    }
+
+    // Properties checked over seeded inputs: whole generated programs and
+    // soups of tokens with random whitespace between them.
+    struct Soup(u64);
+
+    impl Soup {
+        fn below(&mut self, n: usize) -> usize {
+            // xorshift64*
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 33) as usize % n
+        }
+
+        fn program(&mut self, pieces: usize) -> String {
+            let words: Vec<&str> = TokenType::keywords()
+                .iter()
+                .chain(TokenType::operators())
+                .chain(TokenType::delimiters())
+                .map(|(spelling, _)| *spelling)
+                .filter(|spelling| !spelling.starts_with('#'))
+                .chain(["x", "total_2", "_tmp", "0", "42", "3.5", "1.0e3", "\"s\"", "\"a\\\"b\"", "'c'", "'\\n'", "true", "/* c */", "// c\n"])
+                .collect();
+            let gaps = [" ", "  ", "\t", "\n", "\n\n    "];
+            let mut out = String::new();
+            for _ in 0..pieces {
+                out.push_str(words[self.below(words.len())]);
+                out.push_str(gaps[self.below(gaps.len())]);
+            }
+            out
+        }
+    }
+
+    fn inputs() -> Vec<String> {
+        let mut inputs: Vec<String> = (0..8).map(|seed| crate::generate::generate_program(seed, 40 + 60 * seed as usize)).collect();
+        let mut soup = Soup(0x9e37_79b9_7f4a_7c15);
+        inputs.extend((0..40).map(|i| soup.program(1 + i * 3)));
+        inputs
+    }
+
+    fn lexed(source: &str, keep_comments: bool) -> Lexer {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.set_options(crate::lexer::LexerOptions {
+            keep_comments,
+            error_recovery: true,
+            ..crate::lexer::LexerOptions::default()
+        });
+        let _ = lexer.tokenize();
+        lexer
+    }
+
+    #[test]
+    fn lexing_is_deterministic() {
+        for source in inputs() {
+            assert_eq!(lexed(&source, true).get_tokens(), lexed(&source, true).get_tokens(), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn detokenized_text_lexes_to_the_same_tokens() {
+        for keep_comments in [false, true] {
+            for source in inputs() {
+                let lexer = lexed(&source, keep_comments);
+                let again = lexed(&detokenize(lexer.get_tokens()), keep_comments);
+                let shape = |lexer: &Lexer| -> Vec<(TokenType, String)> {
+                    lexer.get_tokens().iter().map(|t| (t.token_type.clone(), t.lexeme.to_string())).collect()
+                };
+                assert_eq!(shape(&again), shape(&lexer), "{:?}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn positions_never_go_back_and_eof_is_last() {
+        for source in inputs() {
+            let lexer = lexed(&source, true);
+            let tokens = lexer.get_tokens();
+            assert_eq!(tokens.last().map(|t| &t.token_type), Some(&TokenType::EOF), "{:?}", source);
+            assert_eq!(tokens.iter().filter(|t| t.token_type == TokenType::EOF).count(), 1);
+            for pair in tokens.windows(2) {
+                let (before, after) = (&pair[0], &pair[1]);
+                assert!((before.end_line, before.end_column) < (after.line, after.column), "{:?} then {:?} in {:?}", before, after, source);
+            }
+            for token in tokens.iter().filter(|t| t.token_type != TokenType::EOF) {
+                assert!((token.line, token.column) <= (token.end_line, token.end_column), "{:?}", token);
+            }
+        }
+    }
+
+    fn policies() -> impl Iterator<Item = ComparePolicy> {
+        (0..8).map(|bits| ComparePolicy {
+            positions: bits & 1 != 0,
+            lexemes: bits & 2 != 0,
+            comments: bits & 4 != 0,
+        })
+    }
+
+    #[test]
+    fn every_policy_combination() {
+        let original = lexed("int x = 1;\n", true);
+        let moved = lexed("int  x =\n1;\n", true);
+        let renamed = lexed("int y = 1;\n", true);
+        let commented = lexed("int x = 1; // one\n", true);
+        let moved_and_commented = lexed("int  x =\n1; // one\n", true);
+        for policy in policies() {
+            let passes = |other: &Lexer| first_mismatch(original.get_tokens(), other.get_tokens(), policy).is_none();
+            let positions = policy.positions || policy.lexemes;
+            assert!(passes(&original), "{:?}", policy);
+            assert_eq!(passes(&moved), positions, "{:?}", policy);
+            assert_eq!(passes(&renamed), policy.lexemes, "{:?}", policy);
+            assert_eq!(passes(&commented), policy.comments, "{:?}", policy);
+            assert_eq!(passes(&moved_and_commented), positions && policy.comments, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn policies_describe_what_they_ignore() {
+        let described: Vec<String> = policies().map(|p| p.describe()).collect();
+        assert_eq!(
+            described,
+            [
+                "exact",
+                "ignoring positions",
+                "ignoring lexemes",
+                "ignoring lexemes",
+                "ignoring comments",
+                "ignoring positions, comments",
+                "ignoring lexemes, comments",
+                "ignoring lexemes, comments",
+            ]
+        );
+        let mut policy = ComparePolicy::default();
+        policy.ignore("comments").unwrap();
+        assert!(policy.comments);
+        assert!(policy.ignore("spacing").unwrap_err().contains("expected one of: positions, lexemes, comments"));
+    }
+
+    #[test]
+    fn mismatches_say_where_and_how() {
+        let original = lexed("int x = 1;\n", true);
+        let commented = lexed("int x = 1; // one\n", true);
+        let shorter = lexed("int x;\n", true);
+        let policy = ComparePolicy {
+            positions: true,
+            ..ComparePolicy::default()
+        };
+        match first_mismatch(original.get_tokens(), commented.get_tokens(), policy) {
+            Some(TokenMismatch::Differs { index, expected, actual }) => {
+                assert_eq!(index, 5);
+                assert_eq!((expected.token_type.clone(), actual.token_type.clone()), (TokenType::EOF, TokenType::Comment));
+            }
+            other => panic!("{:?}", other),
+        }
+        let lexemes = ComparePolicy {
+            lexemes: true,
+            ..ComparePolicy::default()
+        };
+        assert!(matches!(first_mismatch(original.get_tokens(), shorter.get_tokens(), lexemes), Some(TokenMismatch::Differs { index: 2, .. })));
+        let same_types = lexed("int y = 2;\nfloat z;\n", true);
+        assert_eq!(
+            first_mismatch(original.get_tokens(), same_types.get_tokens(), lexemes),
+            Some(TokenMismatch::Differs {
+                index: 5,
+                expected: &original.get_tokens()[5],
+                actual: &same_types.get_tokens()[5],
+            })
+        );
+    }
+}
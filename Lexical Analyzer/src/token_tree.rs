@@ -0,0 +1,212 @@
+use crate::diagnostics::Span;
+use crate::lexer::{Token, TokenType};
+use crate::nesting::Nesting;
+use serde::{Deserialize, Serialize};
+
+// A token stream with each matched pair of braces, parentheses or brackets
+// made a group of what lies between them. A group whose opener is never
+// closed has no `close_span` and holds the rest of its enclosing group.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenTree {
+    Leaf(Token),
+    Group {
+        delimiter: Nesting,
+        children: Vec<TokenTree>,
+        open_span: Span,
+        close_span: Option<Span>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DelimiterProblemKind {
+    // An opener still open at the end of the file
+    Unclosed,
+    // An opener left open when the closer of an enclosing group, at the
+    // line and column, ended it
+    ClosedBy(Nesting, usize, usize),
+    // A closer with no opener of its kind open, kept as a leaf
+    Unmatched,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelimiterProblem {
+    pub kind: DelimiterProblemKind,
+    pub delimiter: Nesting,
+    pub lexeme: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TokenTreeReport {
+    pub trees: Vec<TokenTree>,
+    pub problems: Vec<DelimiterProblem>,
+}
+
+// A group being read: its opener and the trees after it so far.
+struct Open<'a> {
+    delimiter: Nesting,
+    token: &'a Token,
+    children: Vec<TokenTree>,
+}
+
+// Builds the token tree of `tokens`, matching delimiters on a stack as
+// `nesting` counts them. A closer ends the innermost open group of its
+// kind, any groups still open inside that one ending with it unclosed; a
+// closer with nothing of its kind open stays a leaf. Delimiters in
+// directive arguments, which need not balance, are leaves too, and EOF is
+// left out.
+pub fn token_tree(tokens: &[Token]) -> TokenTreeReport {
+    let mut report = TokenTreeReport::default();
+    let mut stack: Vec<Open> = Vec::new();
+    let problem = |kind, delimiter, token: &Token| DelimiterProblem {
+        kind,
+        delimiter,
+        lexeme: token.lexeme.to_string(),
+        line: token.line,
+        column: token.column,
+    };
+    for token in tokens.iter().filter(|t| t.token_type != TokenType::EOF) {
+        let delimiter = Nesting::of(&token.token_type).filter(|_| !token.directive);
+        match delimiter {
+            Some((delimiter, true)) => stack.push(Open {
+                delimiter,
+                token,
+                children: Vec::new(),
+            }),
+            Some((delimiter, false)) if stack.iter().any(|open| open.delimiter == delimiter) => {
+                while let Some(open) = stack.pop() {
+                    if open.delimiter == delimiter {
+                        let group = group(open, Some(span(token)));
+                        children(&mut stack, &mut report.trees).push(group);
+                        break;
+                    }
+                    let kind = DelimiterProblemKind::ClosedBy(delimiter, token.line, token.column);
+                    report.problems.push(problem(kind, open.delimiter, open.token));
+                    let group = group(open, None);
+                    children(&mut stack, &mut report.trees).push(group);
+                }
+            }
+            Some((delimiter, false)) => {
+                report.problems.push(problem(DelimiterProblemKind::Unmatched, delimiter, token));
+                children(&mut stack, &mut report.trees).push(TokenTree::Leaf(token.clone()));
+            }
+            None => children(&mut stack, &mut report.trees).push(TokenTree::Leaf(token.clone())),
+        }
+    }
+    while let Some(open) = stack.pop() {
+        report.problems.push(problem(DelimiterProblemKind::Unclosed, open.delimiter, open.token));
+        let group = group(open, None);
+        children(&mut stack, &mut report.trees).push(group);
+    }
+    report.problems.sort_by_key(|p| (p.line, p.column));
+    report
+}
+
+// Where the next tree goes: into the innermost open group, or the top level.
+fn children<'a>(stack: &'a mut [Open], top: &'a mut Vec<TokenTree>) -> &'a mut Vec<TokenTree> {
+    match stack.last_mut() {
+        Some(open) => &mut open.children,
+        None => top,
+    }
+}
+
+fn span(token: &Token) -> Span {
+    Span::new(token.line, token.column, token.lexeme.chars().count())
+}
+
+fn group(open: Open, close_span: Option<Span>) -> TokenTree {
+    TokenTree::Group {
+        delimiter: open.delimiter,
+        children: open.children,
+        open_span: span(open.token),
+        close_span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn report(source: &str) -> TokenTreeReport {
+        let mut lexer = Lexer::new(source.to_string());
+        let _ = lexer.tokenize();
+        token_tree(lexer.get_tokens())
+    }
+
+    // The trees written back out, a group as its delimiters around its
+    // children and an unclosed one without its closer.
+    fn shape(trees: &[TokenTree]) -> String {
+        let parts: Vec<String> = trees
+            .iter()
+            .map(|tree| match tree {
+                TokenTree::Leaf(token) => token.lexeme.to_string(),
+                TokenTree::Group { delimiter, children, close_span, .. } => {
+                    let opener = match delimiter {
+                        Nesting::Brace => '{',
+                        Nesting::Paren => '(',
+                        Nesting::Bracket => '[',
+                    };
+                    let mut text = opener.to_string();
+                    if !children.is_empty() {
+                        text = format!("{} {}", text, shape(children));
+                    }
+                    if close_span.is_some() {
+                        text = format!("{} {}", text, delimiter.closer());
+                    }
+                    text
+                }
+            })
+            .collect();
+        parts.join(" ")
+    }
+
+    fn problems(report: &TokenTreeReport) -> Vec<(DelimiterProblemKind, &str, usize, usize)> {
+        report.problems.iter().map(|p| (p.kind.clone(), p.lexeme.as_str(), p.line, p.column)).collect()
+    }
+
+    #[test]
+    fn groups_nest_three_deep() {
+        let report = report("f(a[{x}]);\n");
+        assert_eq!(shape(&report.trees), "f ( a [ { x } ] ) ;");
+        assert!(report.problems.is_empty());
+        let TokenTree::Group { delimiter, children, open_span, close_span } = &report.trees[1] else {
+            panic!("expected a group, got {:?}", report.trees[1]);
+        };
+        assert_eq!((*delimiter, open_span.column, close_span.map(|s| s.column)), (Nesting::Paren, 2, Some(9)));
+        let TokenTree::Group { children: inner, .. } = &children[1] else {
+            panic!("expected the bracket group");
+        };
+        assert!(matches!(&inner[0], TokenTree::Group { delimiter: Nesting::Brace, open_span, close_span: Some(close), .. } if open_span.column == 5 && close.column == 7));
+    }
+
+    #[test]
+    fn an_unmatched_opener_holds_the_rest_of_the_file() {
+        let report = report("int f() {\n    g(1;\n}\nint y;\n{\n");
+        assert_eq!(shape(&report.trees), "int f ( ) { g ( 1 ; } int y ; {");
+        assert_eq!(
+            problems(&report),
+            [(DelimiterProblemKind::ClosedBy(Nesting::Brace, 3, 1), "(", 2, 6), (DelimiterProblemKind::Unclosed, "{", 5, 1)]
+        );
+    }
+
+    #[test]
+    fn an_unmatched_closer_stays_a_leaf() {
+        let report = report("x = (a + b));\n]\n");
+        assert_eq!(shape(&report.trees), "x = ( a + b ) ) ; ]");
+        assert_eq!(
+            problems(&report),
+            [(DelimiterProblemKind::Unmatched, ")", 1, 12), (DelimiterProblemKind::Unmatched, "]", 2, 1)]
+        );
+        assert!(matches!(&report.trees[3], TokenTree::Leaf(token) if token.lexeme == ")"));
+    }
+
+    #[test]
+    fn directive_delimiters_are_leaves() {
+        let report = report("#define OPEN (\nint a[2];\n");
+        assert_eq!(shape(&report.trees), "#define OPEN ( int a [ 2 ] ;");
+        assert!(report.problems.is_empty());
+    }
+}
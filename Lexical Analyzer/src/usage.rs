@@ -0,0 +1,173 @@
+use crate::lexer::{Keyword, Token, TokenType};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+// How often one keyword or operator is used over the files added so far.
+// `per_thousand` is its count per 1000 tokens of all of them, and `files`
+// how many use it at least once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRow {
+    pub construct: String,
+    pub category: &'static str,
+    pub count: usize,
+    pub per_thousand: f64,
+    pub files: usize,
+}
+
+// Keyword and operator counts merged over many files, for `--usage-report`.
+// Tokens counted for the rates leave out comments and EOF.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UsageReport {
+    pub files: usize,
+    pub tokens: usize,
+    counts: HashMap<(&'static str, String), (usize, usize)>,
+}
+
+impl UsageReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds one file's tokens.
+    pub fn add(&mut self, tokens: &[Token]) {
+        self.files += 1;
+        let mut seen = HashSet::new();
+        for token in tokens.iter().filter(|t| !matches!(t.token_type, TokenType::Comment | TokenType::EOF)) {
+            self.tokens += 1;
+            let Some(key) = construct(token) else {
+                continue;
+            };
+            let entry = self.counts.entry(key.clone()).or_insert((0, 0));
+            entry.0 += 1;
+            if seen.insert(key) {
+                entry.1 += 1;
+            }
+        }
+    }
+
+    // Every construct used, most used first, then by name.
+    pub fn rows(&self) -> Vec<UsageRow> {
+        let mut rows: Vec<UsageRow> = self
+            .counts
+            .iter()
+            .map(|((category, construct), (count, files))| UsageRow {
+                construct: construct.clone(),
+                category,
+                count: *count,
+                per_thousand: (*count as f64 * 10000.0 / self.tokens as f64).round() / 10.0,
+                files: *files,
+            })
+            .collect();
+        rows.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.construct.cmp(&b.construct)));
+        rows
+    }
+}
+
+// A keyword or operator as it is taught: by its spelling in the keyword and
+// operator tables, so `and` under `--word-operators` counts as `&&`. Other
+// directives keep their own names.
+fn construct(token: &Token) -> Option<(&'static str, String)> {
+    let table = match token.token_type {
+        TokenType::Keyword(Keyword::Directive) => return Some(("keyword", token.lexeme.to_string())),
+        TokenType::Keyword(_) => TokenType::keywords(),
+        TokenType::Operator(_) => TokenType::operators(),
+        _ => return None,
+    };
+    let spelling = table.iter().find(|(_, token_type)| *token_type == token.token_type).map_or(token.lexeme.as_str(), |(spelling, _)| spelling);
+    Some((token.token_type.category(), spelling.to_string()))
+}
+
+pub fn usage_table(report: &UsageReport) -> String {
+    let mut out = String::from("=== CONSTRUCT USAGE ===\n");
+    writeln!(out, "{} files, {} tokens", report.files, report.tokens).unwrap();
+    writeln!(out, "{:<12} {:<10} {:>8} {:>12} {:>7}", "Construct", "Category", "Count", "Per 1000", "Files").unwrap();
+    writeln!(out, "{}", "-".repeat(53)).unwrap();
+    for row in report.rows() {
+        writeln!(
+            out,
+            "{:<12} {:<10} {:>8} {:>12.1} {:>7}",
+            row.construct, row.category, row.count, row.per_thousand, row.files
+        )
+        .unwrap();
+    }
+    out
+}
+
+// The rows as CSV with a header, a field holding a comma or quote quoted.
+pub fn usage_csv(report: &UsageReport) -> String {
+    let field = |text: &str| {
+        if text.contains([',', '"']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text.to_string()
+        }
+    };
+    let mut out = String::from("construct,category,count,per_1000_tokens,files\n");
+    for row in report.rows() {
+        writeln!(out, "{},{},{},{:.1},{}", field(&row.construct), row.category, row.count, row.per_thousand, row.files).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerOptions};
+
+    // 17 and 21 tokens, the comment not among them.
+    const FIXTURES: [&str; 2] = ["int x = 1;\nif (x > 0) { x = x + 1; }\n", "int y = 2; // note\nwhile (y > 0) { y = y - 1; }\n"];
+
+    fn report(sources: &[&str], options: LexerOptions) -> UsageReport {
+        let mut report = UsageReport::new();
+        for source in sources {
+            let mut lexer = Lexer::new(source.to_string());
+            lexer.set_options(options.clone());
+            lexer.tokenize().unwrap();
+            report.add(lexer.get_tokens());
+        }
+        report
+    }
+
+    fn two_files() -> UsageReport {
+        report(&FIXTURES, LexerOptions { keep_comments: true, ..LexerOptions::default() })
+    }
+
+    #[test]
+    fn counts_merge_across_files() {
+        let report = two_files();
+        assert_eq!((report.files, report.tokens), (2, 38));
+        let rows = report.rows();
+        let rows: Vec<_> = rows.iter().map(|r| (r.construct.as_str(), r.category, r.count, r.per_thousand, r.files)).collect();
+        assert_eq!(
+            rows,
+            [
+                ("=", "operator", 4, 105.3, 2),
+                (">", "operator", 2, 52.6, 2),
+                ("int", "keyword", 2, 52.6, 2),
+                ("+", "operator", 1, 26.3, 1),
+                ("-", "operator", 1, 26.3, 1),
+                ("if", "keyword", 1, 26.3, 1),
+                ("while", "keyword", 1, 26.3, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn table_and_csv_list_the_same_rows() {
+        let report = two_files();
+        let table = usage_table(&report);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[..2], ["=== CONSTRUCT USAGE ===", "2 files, 38 tokens"]);
+        assert_eq!(lines[4], "=            operator          4        105.3       2");
+        assert_eq!(lines.len(), 4 + 7);
+        let csv = usage_csv(&report);
+        assert!(csv.starts_with("construct,category,count,per_1000_tokens,files\n=,operator,4,105.3,2\n>,operator,2,52.6,2\n"));
+        assert!(csv.ends_with("while,keyword,1,26.3,1\n"));
+    }
+
+    #[test]
+    fn word_operators_count_as_their_symbols() {
+        let report = report(&["x = a and not b;\n#pragma once\n"], LexerOptions { word_operators: true, ..LexerOptions::default() });
+        assert_eq!(usage_csv(&report), "construct,category,count,per_1000_tokens,files\n!,operator,1,111.1,1\n#pragma,keyword,1,111.1,1\n&&,operator,1,111.1,1\n=,operator,1,111.1,1\n");
+    }
+}
@@ -0,0 +1,123 @@
+// Where source text lands on a terminal, for renderers that line a caret up
+// with a character or wrap a line to a width: tabs reach the next tab stop,
+// and every other character takes the cells `unicode-width` gives it, from
+// Unicode's East Asian Width property, so wide characters and emoji take two
+// and combining marks and other zero-width characters none.
+
+use unicode_width::UnicodeWidthChar;
+
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+// Cells `ch` takes anywhere but a tab: 0 for combining marks and control
+// characters, 2 for wide ones, 1 otherwise.
+pub fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+// Cells counted from the start of a line, with tab stops every `tab_width`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualColumns {
+    pub tab_width: usize,
+}
+
+impl Default for VisualColumns {
+    fn default() -> Self {
+        VisualColumns { tab_width: DEFAULT_TAB_WIDTH }
+    }
+}
+
+impl VisualColumns {
+    pub fn new(tab_width: usize) -> Self {
+        VisualColumns { tab_width: tab_width.max(1) }
+    }
+
+    // Cells `ch` takes when it starts `cell` cells into its line.
+    pub fn width_at(&self, ch: char, cell: usize) -> usize {
+        if ch == '\t' {
+            self.tab_width - cell % self.tab_width
+        } else {
+            char_width(ch)
+        }
+    }
+
+    // Cells `text` takes from the start of a line.
+    pub fn width(&self, text: &str) -> usize {
+        text.chars().fold(0, |cell, ch| cell + self.width_at(ch, cell))
+    }
+
+    // `text`, starting a line, with each tab replaced by the spaces that
+    // reach its tab stop, so it renders the same after a gutter or prefix.
+    pub fn expand_tabs(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut cell = 0;
+        for ch in text.chars() {
+            let width = self.width_at(ch, cell);
+            if ch == '\t' {
+                out.extend(std::iter::repeat_n(' ', width));
+            } else {
+                out.push(ch);
+            }
+            cell += width;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characters_take_their_cells() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('\u{7}'), 0);
+        assert_eq!(char_width('é'), 1);
+        assert_eq!(char_width('\u{301}'), 0);
+        assert_eq!(char_width('\u{200B}'), 0);
+        assert_eq!(char_width('你'), 2);
+        assert_eq!(char_width('한'), 2);
+        assert_eq!(char_width('Ａ'), 2);
+        assert_eq!(char_width('🎉'), 2);
+        assert_eq!(char_width('→'), 1);
+    }
+
+    #[test]
+    fn widths_change_at_the_range_boundaries() {
+        let cases = [
+            ('\u{7E}', 1), ('\u{7F}', 0), ('\u{9F}', 0), ('\u{A0}', 1),
+            ('\u{2FF}', 1), ('\u{300}', 0), ('\u{36F}', 0), ('\u{370}', 1),
+            ('\u{10FF}', 1), ('\u{1100}', 2), ('\u{115F}', 2), ('\u{1160}', 0), ('\u{11FF}', 0), ('\u{1200}', 1),
+            ('\u{3040}', 1), ('\u{3041}', 2), ('\u{4E00}', 2), ('\u{9FFF}', 2), ('\u{A4CF}', 1),
+            ('\u{ABFF}', 1), ('\u{AC00}', 2), ('\u{D7A3}', 2), ('\u{D7A4}', 1),
+            ('\u{FF00}', 1), ('\u{FF01}', 2), ('\u{FF60}', 2), ('\u{FF61}', 1),
+            ('\u{1F2FF}', 1), ('\u{1F300}', 2), ('\u{1F64F}', 2), ('\u{1F650}', 1),
+            ('\u{1F90B}', 1), ('\u{1F90C}', 2),
+            ('\u{1FFFF}', 1), ('\u{20000}', 2), ('\u{2FFFD}', 2), ('\u{2FFFE}', 1),
+            ('\u{E00FF}', 0), ('\u{E0100}', 0), ('\u{E01EF}', 0),
+        ];
+        for (ch, width) in cases {
+            assert_eq!(char_width(ch), width, "U+{:04X}", ch as u32);
+        }
+    }
+
+    #[test]
+    fn tabs_reach_the_next_stop() {
+        let four = VisualColumns::new(4);
+        assert_eq!(four.width_at('\t', 0), 4);
+        assert_eq!(four.width_at('\t', 5), 3);
+        assert_eq!(four.width("\ta\tbc\t"), 12);
+        assert_eq!(four.expand_tabs("a\tb\t\tc"), "a   b       c");
+        assert_eq!(four.expand_tabs("你\tx"), "你  x");
+        assert_eq!(VisualColumns::default().expand_tabs("\tx"), "        x");
+        assert_eq!(VisualColumns::new(0), VisualColumns::new(1));
+    }
+
+    #[test]
+    fn lines_count_wide_and_combining_characters() {
+        let visual = VisualColumns::new(8);
+        assert_eq!(visual.width("s = \"你好\";"), 11);
+        assert_eq!(visual.width("\"🎉🎉\""), 6);
+        assert_eq!(visual.width("cafe\u{301}"), 4);
+        assert_eq!(visual.width("\t你\t"), 16);
+    }
+}
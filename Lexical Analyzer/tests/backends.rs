@@ -0,0 +1,134 @@
+// Conformance suite for the scanning backends: the hand-written scanner,
+// and the logos one when built with the `logos` feature, must give exactly
+// the tokens, diagnostics and symbols of the regex pattern table, which is
+// the reference.
+
+use mcpp_lexer::generate::generate_program;
+use mcpp_lexer::lexer::{build_symbol_table, Backend, Lexer, LexerOptions, BACKEND_NAMES};
+use std::fs;
+
+// Inputs where the two approaches are most likely to part ways: maximal
+// munch between operators, keyword prefixes, literal edge cases, comments
+// around the end of the file and invalid characters.
+const EDGE_CASES: &[&str] = &[
+    "",
+    "\n\n",
+    "int main() { return 0; }",
+    "a+++b; a---b; a<=b>=c==d!=e&&f||!g; x&y; p%q;",
+    "intx int_ whilefor for1 _if if_ returnx auto autos bool booly",
+    "truex true false falsey",
+    "1 12.5 3.0e10 4e5 7.e2 .5 12..3 0x1F 007",
+    "'a' '\\n' '\\'' '\\\\' \"\" \"a\\\"b\" \"tab\\there\" \"\\q\"",
+    "\"line one\\\nline two\"",
+    "R\"(raw \"text\")\" R\"xy(a)\"b)xy\"",
+    "f(1, ...); void g(int a, ...);",
+    "[[nodiscard]] int h(); [[deprecated(\"x\")]] int k;",
+    "#include <iostream>\n#define N 10\n  #ifdef N\n#endif\n",
+    "x = 1; // trailing\n/* block\n   comment */ y = 2;",
+    "a / b; a /= b; /",
+    "a /* never closed",
+    "int x = 1 @ 2; int y = 3 $ 4;",
+    "string s = \"unterminated\nint z;",
+    "goto end; end: return; class A { public: int a; private: int b; };",
+    "int *p = new int; delete p; typedef int T; using U = float;",
+    "café = 1; naïve = 2;",
+    "x\t=\t1;\r\ny = 2;\r\n",
+];
+
+fn run(source: &str, backend: Backend, options: &LexerOptions) -> Lexer {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.set_options(options.clone());
+    lexer.set_backend(backend);
+    let _ = lexer.tokenize();
+    lexer
+}
+
+fn option_sets() -> Vec<(&'static str, LexerOptions)> {
+    vec![
+        ("default", LexerOptions::default()),
+        (
+            "comments and recovery",
+            LexerOptions {
+                keep_comments: true,
+                error_recovery: true,
+                ..LexerOptions::default()
+            },
+        ),
+        (
+            "unicode and word operators",
+            LexerOptions {
+                unicode_identifiers: true,
+                word_operators: true,
+                concat_strings: true,
+                error_recovery: true,
+                ..LexerOptions::default()
+            },
+        ),
+    ]
+}
+
+// Every backend but the reference.
+fn other_backends() -> impl Iterator<Item = Backend> {
+    BACKEND_NAMES[1..].iter().map(|name| Backend::from_name(name).unwrap())
+}
+
+fn assert_backends_agree(name: &str, source: &str) {
+    for (options_name, options) in option_sets() {
+        let reference = run(source, Backend::Regex, &options);
+        for backend in other_backends() {
+            let other = run(source, backend, &options);
+            let context = format!("{} on {} with {} options", name, backend.name(), options_name);
+            assert_eq!(other.get_tokens(), reference.get_tokens(), "tokens differ for {}", context);
+            assert_eq!(other.get_diagnostics(), reference.get_diagnostics(), "diagnostics differ for {}", context);
+            assert_eq!(
+                other.get_symbol_table().get_symbols(),
+                reference.get_symbol_table().get_symbols(),
+                "symbols differ for {}",
+                context
+            );
+        }
+    }
+}
+
+#[test]
+fn examples_lex_identically() {
+    let mut count = 0;
+    for entry in fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "mcpp") {
+            assert_backends_agree(&path.display().to_string(), &fs::read_to_string(&path).unwrap());
+            count += 1;
+        }
+    }
+    assert!(count >= 4);
+}
+
+#[test]
+fn edge_cases_lex_identically() {
+    for (index, source) in EDGE_CASES.iter().enumerate() {
+        assert_backends_agree(&format!("edge case {} {:?}", index, source), source);
+    }
+}
+
+#[test]
+fn generated_programs_lex_identically() {
+    for seed in [1, 2, 3, 42, 1234] {
+        assert_backends_agree(&format!("generated program, seed {}", seed), &generate_program(seed, 3000));
+    }
+}
+
+#[test]
+fn symbol_tables_build_over_any_backend() {
+    let source = "int add(int a, int b) { int c = a + b; return c; }\n";
+    let mut regex = Lexer::new(source.to_string());
+    let (regex_tokens, regex_table) = build_symbol_table(&mut regex).unwrap();
+    for backend in other_backends() {
+        let mut other = Lexer::new(source.to_string());
+        other.set_backend(backend);
+        let (tokens, table) = build_symbol_table(&mut other).unwrap();
+        assert_eq!(tokens, regex_tokens, "{:?}", backend);
+        assert_eq!(table.get_symbols(), regex_table.get_symbols(), "{:?}", backend);
+    }
+    let names: Vec<&str> = regex_table.get_symbols().iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["add", "a", "b", "c", "a", "b", "c"]);
+}
@@ -0,0 +1,762 @@
+// Runs the mcpp-lexer binary the way a user or CI job would, checking its
+// output files, transcripts and exit codes.
+
+use mcpp_lexer::cache::content_hash;
+use mcpp_lexer::document::parse_document;
+use mcpp_lexer::lexer::TokenType;
+use mcpp_lexer::nesting::Nesting;
+use mcpp_lexer::token_tree::TokenTree;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+// An empty directory for one test, removed when it ends.
+struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("mcpp-cli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch { dir }
+    }
+
+    fn write(&self, name: &str, contents: &str) -> &Self {
+        fs::write(self.dir.join(name), contents).unwrap();
+        self
+    }
+
+    fn write_bytes(&self, name: &str, contents: &[u8]) -> &Self {
+        fs::write(self.dir.join(name), contents).unwrap();
+        self
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.dir.join(name)).unwrap()
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.dir.join(name).exists()
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.run_with_input(args, "")
+    }
+
+    fn run_with_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mcpp-lexer"))
+            .args(args)
+            .current_dir(&self.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn step_mode_follows_scripted_input() {
+    let scratch = Scratch::new("step");
+    scratch.write("s.mcpp", "int x = 1;\n");
+    // Next, print the symbols, step once more, then quit
+    let output = scratch.run_with_input(&["--step", "s.mcpp"], "\ns\n\nq\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "=== MCPP Lexical Analyzer ===\n\
+         Input file: s.mcpp\n\
+         \n\
+         <Int, int, 1, 1>\n    now at line 1, column 4\n\
+         <Identifier, x, 1, 5>\n    now at line 1, column 6\n    + symbol x (variable, int, global)\n\
+         \n\
+         === SYMBOL TABLE ===\n\
+         Name            Type         Data Type    Scope      Line    \n\
+         ----------------------------------------------------------------------\n\
+         x               variable     int          global     1       \n\
+         ----------------------------------------------------------------------\n\
+         Total symbols: 1\n\
+         <Assign, =, 1, 7>\n    now at line 1, column 8\n"
+    );
+    assert!(!scratch.exists("s_tokens.json"));
+}
+
+#[test]
+fn step_mode_runs_to_completion_at_end_of_input() {
+    let scratch = Scratch::new("step-eof");
+    scratch.write("s.mcpp", "int x = 1;\n");
+    let output = scratch.run(&["--step", "s.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    let transcript = stdout(&output);
+    assert_eq!(transcript.matches("now at line").count(), 6);
+    assert!(transcript.ends_with("=== Lexical Analysis Complete ===\nTotal tokens: 6\n"));
+}
+
+#[test]
+fn second_run_reads_the_cache() {
+    let scratch = Scratch::new("cache");
+    scratch.write("a.mcpp", "int main() {\n    int x = 1;\n    return x;\n}\n");
+    let first = scratch.run(&["--cache-dir", "cache", "a.mcpp"]);
+    assert_eq!(first.status.code(), Some(0));
+    assert!(stdout(&first).contains("Total tokens: 15\n"));
+    let tokens = scratch.read("a_tokens.json");
+
+    let second = scratch.run(&["--cache-dir", "cache", "a.mcpp"]);
+    assert_eq!(second.status.code(), Some(0));
+    assert!(stdout(&second).contains("Total tokens: 15 (cached)\n"));
+    assert_eq!(scratch.read("a_tokens.json"), tokens);
+
+    let bypassed = scratch.run(&["--cache-dir", "cache", "--no-cache", "a.mcpp"]);
+    assert!(!stdout(&bypassed).contains("(cached)"));
+
+    // A damaged cache means a full lex, with the same output
+    scratch.write("cache/manifest.json", "{not json");
+    let recovered = scratch.run(&["--cache-dir", "cache", "a.mcpp"]);
+    assert_eq!(recovered.status.code(), Some(0));
+    assert!(!stdout(&recovered).contains("(cached)"));
+    assert_eq!(scratch.read("a_tokens.json"), tokens);
+    assert!(stdout(&scratch.run(&["--cache-dir", "cache", "a.mcpp"])).contains("(cached)"));
+}
+
+#[test]
+fn warnings_fail_the_run_only_when_denied() {
+    let scratch = Scratch::new("deny");
+    // No final newline: W0011
+    scratch.write("w.mcpp", "int x = 1;");
+    let output = scratch.run(&["w.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr(&output).contains("Lexical Warning: File does not end with a newline at line 1, column 11"));
+    assert_eq!(scratch.run(&["--deny-warnings", "w.mcpp"]).status.code(), Some(1));
+
+    let allowed = scratch.run(&["--deny-warnings", "--allow", "W0011", "w.mcpp"]);
+    assert_eq!(allowed.status.code(), Some(0));
+    assert!(!stderr(&allowed).contains("W0011") && !stderr(&allowed).contains("Warning"));
+    assert_eq!(scratch.run(&["--deny-warnings", "--warn-level", "error", "w.mcpp"]).status.code(), Some(0));
+
+    // Trailing whitespace, W0012, is allowed on its own
+    scratch.write("t.mcpp", "int x = 1;  \n");
+    assert_eq!(scratch.run(&["--deny-warnings", "t.mcpp"]).status.code(), Some(1));
+    assert!(stderr(&scratch.run(&["t.mcpp"])).contains("Trailing whitespace at end of line at line 1, column 11"));
+    assert_eq!(scratch.run(&["--deny-warnings", "--allow", "W0012", "t.mcpp"]).status.code(), Some(0));
+}
+
+#[test]
+fn long_names_keep_the_symbol_table_aligned() {
+    let scratch = Scratch::new("symbols");
+    let long = "forty_character_identifier_for_the_table";
+    assert_eq!(long.len(), 40);
+    scratch.write("l.mcpp", &format!("int {} = 1;\nint main() {{\n    int b = {};\n    return 0;\n}}\n", long, long));
+    let output = scratch.run(&["--symbols-sort", "name", "l.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    let transcript = stdout(&output);
+    let table = &transcript[transcript.find("=== SYMBOL TABLE ===").unwrap()..transcript.find("JSON output").unwrap()];
+    assert_eq!(
+        table,
+        "=== SYMBOL TABLE ===\n\
+         Name                                     Type         Data Type    Scope      Line    \n\
+         ---------------------------------------------------------------------------------------\n\
+         b                                        variable     int          main       3       \n\
+         forty_character_identifier_for_the_table variable     int          global     1       \n\
+         forty_character_identifier_for_the_table variable     unknown      main       3       \n\
+         main                                     function     int          global     2       \n\
+         ---------------------------------------------------------------------------------------\n\
+         Total symbols: 4\n\n"
+    );
+
+    let filtered = stdout(&scratch.run(&["--symbols-filter", "scope=global", "--symbols-filter", "unused", "l.mcpp"]));
+    assert!(filtered.contains("\nmain            function     int          global     2       \n"));
+    assert!(filtered.contains("Total symbols: 1 (of 4)\n"));
+    assert_eq!(scratch.run(&["--symbols-filter", "kind=x", "l.mcpp"]).status.code(), Some(2));
+}
+
+#[test]
+fn shadowing_warnings_can_be_allowed() {
+    let scratch = Scratch::new("shadow");
+    scratch.write("s.mcpp", "int main() {\n    int i = 1;\n    { int i = 2; }\n    return i;\n}\n");
+    let output = scratch.run(&["s.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr(&output).contains("'i' shadows a variable declared at line 2 (scope main) at line 3, column 11"));
+    assert_eq!(scratch.run(&["--deny-warnings", "s.mcpp"]).status.code(), Some(1));
+    let allowed = scratch.run(&["--deny-warnings", "--allow", "W0007", "s.mcpp"]);
+    assert_eq!(allowed.status.code(), Some(0));
+    assert!(!stderr(&allowed).contains("shadows"));
+}
+
+#[test]
+fn time_reports_token_and_byte_counts() {
+    let scratch = Scratch::new("time");
+    scratch.write("t.mcpp", "int x = 1;\n");
+    let output = scratch.run(&["--time", "t.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    let report = stderr(&output);
+    let line = report.lines().find(|l| l.starts_with("Lexed ")).unwrap();
+    assert!(line.starts_with("Lexed 6 tokens (11 bytes) in "), "{}", line);
+    assert!(line.contains(" ms: ") && line.ends_with(" MB/s"), "{}", line);
+}
+
+#[test]
+fn failed_runs_still_write_a_partial_document() {
+    let scratch = Scratch::new("partial");
+    let mut lines: Vec<String> = (1..=100).map(|i| format!("int v{} = {};", i, i)).collect();
+    lines[49] = "int v50 = 5 @ 0;".to_string();
+    scratch.write("e.mcpp", &(lines.join("\n") + "\n"));
+
+    let output = scratch.run(&["e.mcpp"]);
+    assert_eq!(output.status.code(), Some(1));
+    let document = parse_document(scratch.read("e_tokens.json").as_bytes()).unwrap();
+    assert!(!document.success);
+    // Five tokens for each of the 49 good lines, then `int v50 = 5`
+    assert_eq!(document.tokens.len(), 49 * 5 + 4);
+    assert_eq!(document.tokens.last().unwrap().line, 50);
+    assert_eq!(document.diagnostics.len(), 1);
+    assert_eq!((document.diagnostics[0].code.as_str(), document.diagnostics[0].span.column), ("E0001", 13));
+
+    // With recovery the document goes on to EOF with an Error token
+    assert_eq!(scratch.run(&["--error-recovery", "e.mcpp"]).status.code(), Some(1));
+    let document = parse_document(scratch.read("e_tokens.json").as_bytes()).unwrap();
+    assert!(!document.success);
+    let errors: Vec<_> = document.tokens.iter().filter(|t| t.token_type == TokenType::Error).collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].lexeme.as_str(), errors[0].line), ("@", 50));
+    assert_eq!(document.tokens.last().unwrap().token_type, TokenType::EOF);
+    assert_eq!(document.tokens.last().unwrap().line, 101);
+}
+
+#[test]
+fn batch_json_holds_every_input() {
+    let scratch = Scratch::new("batch");
+    scratch.write("a.mcpp", "int a = 1;\n").write("b.mcpp", "int b = 2 @ 3;\n").write("c.mcpp", "int c;\n");
+    let output = scratch.run(&["--format", "batch-json", "-o", "out.json", "a.mcpp", "b.mcpp", "c.mcpp"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("Batch JSON output for 3 files saved to: out.json\n"));
+    assert!(!scratch.exists("a_tokens.json"));
+    let document: serde_json::Value = serde_json::from_str(&scratch.read("out.json")).unwrap();
+    let files = document["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+    for (entry, name) in files.iter().zip(["a.mcpp", "b.mcpp", "c.mcpp"]) {
+        assert_eq!(entry["filename"], name);
+        assert!(parse_document(entry.to_string().as_bytes()).is_ok());
+    }
+    assert_eq!(files[1]["success"], false);
+    assert_eq!(document["summary"]["files"], 3);
+    assert_eq!(document["summary"]["succeeded"], 2);
+}
+
+#[test]
+fn batch_json_goes_past_an_unreadable_input() {
+    let scratch = Scratch::new("batch-missing");
+    scratch.write("a.mcpp", "int a = 1;\n").write("c.mcpp", "int c;\n");
+    let output = scratch.run(&["--format", "batch-json", "-o", "out.json", "--manifest", "run.json", "a.mcpp", "b.mcpp", "c.mcpp"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Error reading file 'b.mcpp'"));
+    assert!(stdout(&output).contains("Batch JSON output for 2 files saved to: out.json\n"));
+    let document: serde_json::Value = serde_json::from_str(&scratch.read("out.json")).unwrap();
+    let names: Vec<&str> = document["files"].as_array().unwrap().iter().map(|entry| entry["filename"].as_str().unwrap()).collect();
+    assert_eq!(names, ["a.mcpp", "c.mcpp"]);
+    assert_eq!(document["summary"]["succeeded"], 2);
+
+    let run = manifest(&scratch, "run.json");
+    assert_eq!(run["exit_code"].as_i64(), Some(2));
+    let files = run["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[0]["success"].as_bool(), Some(true));
+    assert!(files[1]["content_hash"].is_null());
+    assert!(files[1]["failure"].is_string());
+    assert_eq!(files[2]["success"].as_bool(), Some(true));
+    assert_eq!(run["outputs"], serde_json::json!(["out.json"]));
+}
+
+#[test]
+fn sarif_log_covers_the_inputs_around_an_unreadable_one() {
+    let scratch = Scratch::new("sarif-missing");
+    scratch.write("a.mcpp", "int a = @;\n").write("c.mcpp", "int c = 1;\n");
+    let output = scratch.run(&["--diagnostics-format", "sarif", "--diagnostics-output", "out.sarif", "--manifest", "run.json", "a.mcpp", "b.mcpp", "c.mcpp"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).contains("SARIF diagnostics saved to: out.sarif\n"));
+    let log: serde_json::Value = serde_json::from_str(&scratch.read("out.sarif")).unwrap();
+    let run = &log["runs"][0];
+    let uris: Vec<&str> = run["artifacts"].as_array().unwrap().iter().map(|artifact| artifact["location"]["uri"].as_str().unwrap()).collect();
+    assert_eq!(uris, ["a.mcpp", "c.mcpp"]);
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["index"], 0);
+
+    let run = manifest(&scratch, "run.json");
+    assert_eq!(run["exit_code"].as_i64(), Some(2));
+    let files: Vec<(&str, Option<bool>)> = run["files"].as_array().unwrap().iter().map(|f| (f["path"].as_str().unwrap(), f["success"].as_bool())).collect();
+    assert_eq!(files, [("a.mcpp", Some(false)), ("b.mcpp", Some(false)), ("c.mcpp", Some(true))]);
+    assert!(run["outputs"].as_array().unwrap().contains(&serde_json::json!("out.sarif")));
+}
+
+#[test]
+fn legacy_comparison_exit_codes() {
+    let scratch = Scratch::new("legacy");
+    scratch.write("l.mcpp", "int x;\n");
+    scratch.write("ok.txt", "INT int 1 1\nIDENTIFIER x 1 5\nSEMICOLON ; 1 6\n");
+    scratch.write("off.txt", "INT int 1 1\nWIDGET x 1 5\nSEMICOLON ; 1 6\n");
+    scratch.write("bad.txt", "INT int 1\n");
+
+    let agree = scratch.run(&["--compare-legacy", "ok.txt", "l.mcpp"]);
+    assert_eq!(agree.status.code(), Some(0));
+    assert!(stdout(&agree).contains("3 tokens agree, 0 disagree with ok.txt\n"));
+
+    // Differences exit with 3, like --check
+    let differ = scratch.run(&["--compare-legacy", "off.txt", "l.mcpp"]);
+    assert_eq!(differ.status.code(), Some(3));
+    assert!(stdout(&differ).contains("line 1, column 5: unknown legacy type 'WIDGET' for 'x'; add it to data/legacy_types.txt\n"));
+    assert!(stdout(&differ).contains("2 tokens agree, 1 disagree with off.txt\n"));
+
+    for broken in ["bad.txt", "missing.txt"] {
+        let output = scratch.run(&["--compare-legacy", broken, "l.mcpp"]);
+        assert_eq!(output.status.code(), Some(2), "{}", broken);
+        assert!(stderr(&output).starts_with(&format!("{}: ", broken)), "{}", stderr(&output));
+    }
+    assert!(!scratch.exists("l_tokens.json"));
+}
+
+#[test]
+fn token_printing_is_capped_but_json_is_not() {
+    let scratch = Scratch::new("cap");
+    // 5 tokens a line, 2,500 in all with EOF
+    scratch.write("big.mcpp", &(1..=500).map(|i| format!("int v{} = {};\n", i, i)).collect::<String>());
+    let output = scratch.run(&["big.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    let transcript = stdout(&output);
+    assert!(transcript.contains("<Semicolon, ;, 400, 15>\n... 501 more tokens, use --all to print everything\n"), "{}", transcript);
+    assert!(!transcript.contains("<Int, int, 401, 1>"));
+    assert_eq!(parse_document(scratch.read("big_tokens.json").as_bytes()).unwrap().tokens.len(), 2501);
+    assert!(stdout(&scratch.run(&["--all", "big.mcpp"])).contains("<EOF, EOF, 501, 1>\n"));
+
+    let ranged = stdout(&scratch.run(&["--lines", "10-11", "big.mcpp"]));
+    let stream = &ranged[ranged.find("=== TOKEN STREAM ===\n").unwrap()..];
+    let printed: Vec<&str> = stream.lines().skip(1).take_while(|l| l.starts_with('<')).collect();
+    assert_eq!(printed.len(), 10);
+    assert_eq!((printed[0], printed[9]), ("<Int, int, 10, 1>", "<Semicolon, ;, 11, 13>"));
+    assert!(!stream.contains("more tokens"));
+
+    let tail = stdout(&scratch.run(&["--tail", "2", "big.mcpp"]));
+    assert!(tail.contains("=== TOKEN STREAM ===\n<Semicolon, ;, 500, 15>\n<EOF, EOF, 501, 1>\n"), "{}", tail);
+    assert_eq!(scratch.run(&["--head", "1", "--tail", "1", "big.mcpp"]).status.code(), Some(2));
+    assert_eq!(scratch.run(&["--lines", "5-2", "big.mcpp"]).status.code(), Some(2));
+}
+
+#[test]
+fn reformatted_source_passes_check_only_ignoring_positions() {
+    let scratch = Scratch::new("check");
+    scratch.write("a.mcpp", "int main() {\n    int x = 1;\n    return x;\n}\n");
+    assert_eq!(scratch.run(&["a.mcpp"]).status.code(), Some(0));
+    scratch.write("expected.json", &scratch.read("a_tokens.json"));
+    scratch.write("a.mcpp", "int main()\n{\n  int x = 1; return x;\n}\n");
+
+    let exact = scratch.run(&["--check", "expected.json", "a.mcpp"]);
+    assert_eq!(exact.status.code(), Some(3));
+    let report = stdout(&exact);
+    assert!(report.contains("=== CHECK ===\nPolicy: exact\nMismatch at token 4:\n"), "{}", report);
+
+    let loose = scratch.run(&["--check", "expected.json", "--ignore", "positions", "a.mcpp"]);
+    assert_eq!(loose.status.code(), Some(0), "{}", stdout(&loose));
+    assert!(stdout(&loose).contains("Policy: ignoring positions\nCheck passed: 15 tokens match expected.json (ignoring positions)\n"));
+
+    let combined = scratch.run(&["--check", "expected.json", "--ignore", "comments,lexemes", "a.mcpp"]);
+    assert!(stdout(&combined).contains("Policy: ignoring lexemes, comments\n"));
+    assert_eq!(scratch.run(&["--check", "expected.json", "--ignore", "spacing", "a.mcpp"]).status.code(), Some(2));
+}
+
+#[test]
+fn non_ascii_strings_warn_and_can_be_denied() {
+    let scratch = Scratch::new("ascii");
+    scratch.write("s.mcpp", "string s = \"café\";\n");
+    let output = scratch.run(&["--warn", "non-ascii-strings", "s.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr(&output).contains("String literal contains non-ASCII character 'é' (U+00E9) at line 1, column 16"), "{}", stderr(&output));
+    assert_eq!(scratch.run(&["--warn", "non-ascii-strings", "--deny-warnings", "s.mcpp"]).status.code(), Some(1));
+    assert_eq!(scratch.run(&["--deny-warnings", "s.mcpp"]).status.code(), Some(0));
+}
+
+#[test]
+fn project_config_is_discovered_and_overridden() {
+    let scratch = Scratch::new("config");
+    fs::create_dir_all(scratch.dir.join("course/week1")).unwrap();
+    scratch.write(".mcpprc", "max-nesting = 1\nindent-size = 8\n");
+    scratch.write("course/week1/a.mcpp", "int main() { if (x) { return 0; } }\n");
+    let config = fs::canonicalize(scratch.dir.join(".mcpprc")).unwrap();
+
+    let found = scratch.run(&["course/week1/a.mcpp"]);
+    assert_eq!(found.status.code(), Some(0));
+    let warnings = stderr(&found);
+    assert!(warnings.contains(&format!("Warning: {}: Line 2: unknown key 'indent-size' ignored\n", config.display())), "{}", warnings);
+    assert!(warnings.contains("Brace nesting depth 2 exceeds the limit of 1 at line 1, column 21"), "{}", warnings);
+
+    let overridden = stderr(&scratch.run(&["--max-nesting", "2", "course/week1/a.mcpp"]));
+    assert!(overridden.contains("unknown key 'indent-size'") && !overridden.contains("nesting depth"), "{}", overridden);
+
+    let ignored = stderr(&scratch.run(&["--no-config", "course/week1/a.mcpp"]));
+    assert!(!ignored.contains("indent-size") && !ignored.contains("nesting depth"), "{}", ignored);
+
+    scratch.write(".mcpprc", "max-nesting = true\n");
+    let broken = scratch.run(&["course/week1/a.mcpp"]);
+    assert_eq!(broken.status.code(), Some(2));
+    assert!(stderr(&broken).contains("Line 1: 'max-nesting' expects a string or integer"), "{}", stderr(&broken));
+}
+
+fn manifest(scratch: &Scratch, name: &str) -> serde_json::Value {
+    serde_json::from_str(&scratch.read(name)).unwrap()
+}
+
+#[test]
+fn manifest_records_each_exit_status() {
+    let scratch = Scratch::new("manifest");
+    scratch.write("ok.mcpp", "int x = 1;\n");
+    scratch.write("bad.mcpp", "int y = @;\n");
+
+    let success = scratch.run(&["--manifest", "ok.json", "ok.mcpp"]);
+    assert_eq!(success.status.code(), Some(0));
+    let run = manifest(&scratch, "ok.json");
+    assert_eq!((run["schema_version"].as_u64(), run["tool"].as_str(), run["exit_code"].as_i64()), (Some(1), Some("mcpp-lexer"), Some(0)));
+    assert_eq!(run["arguments"], serde_json::json!(["--manifest", "ok.json", "ok.mcpp"]));
+    let file = &run["files"][0];
+    let hash = format!("{:016x}", content_hash(&[b"int x = 1;\n"]));
+    assert_eq!(file["content_hash"].as_str(), Some(hash.as_str()));
+    assert_eq!((file["success"].as_bool(), file["errors"].as_u64()), (Some(true), Some(0)));
+    assert_eq!(file["outputs"], serde_json::json!(["ok_tokens.json"]));
+    assert!(run["elapsed_ms"].as_f64().is_some());
+
+    // Lexical errors: the manifest is still written, and the tokens too
+    let failed = scratch.run(&["--manifest", "bad.json", "ok.mcpp", "bad.mcpp"]);
+    assert_eq!(failed.status.code(), Some(1));
+    let run = manifest(&scratch, "bad.json");
+    assert_eq!(run["exit_code"].as_i64(), Some(1));
+    let counts: Vec<(bool, u64)> = run["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| (f["success"].as_bool().unwrap(), f["errors"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(counts, [(true, 0), (false, 1)]);
+    assert!(scratch.exists("bad_tokens.json"));
+
+    let missing = scratch.run(&["--manifest", "missing.json", "nope.mcpp"]);
+    assert_eq!(missing.status.code(), Some(2));
+    let run = manifest(&scratch, "missing.json");
+    assert_eq!(run["exit_code"].as_i64(), Some(2));
+    assert!(run["files"][0]["content_hash"].is_null());
+    assert_eq!(run["files"][0]["failure"].as_str(), Some("No such file or directory (os error 2)"));
+
+    // A --check mismatch
+    scratch.write("expected.json", &scratch.read("ok_tokens.json"));
+    scratch.write("other.mcpp", "int z = 2;\n");
+    assert_eq!(scratch.run(&["--manifest", "check.json", "--check", "expected.json", "other.mcpp"]).status.code(), Some(3));
+    assert_eq!(manifest(&scratch, "check.json")["exit_code"].as_i64(), Some(3));
+}
+
+#[test]
+fn symbols_by_scope_layout() {
+    let scratch = Scratch::new("scopes");
+    scratch.write(
+        "n.mcpp",
+        "int g = 1;\nint main() {\n    int a = 0;\n    if (a) { int b = 1; } else { int c = 2; }\n    while (a) {\n        { int e = a; }\n    }\n}\n",
+    );
+    let output = stdout(&scratch.run(&["--symbols-by-scope", "n.mcpp"]));
+    let start = output.find("=== SYMBOLS BY SCOPE ===").unwrap();
+    let end = output[start..].find("Total symbols").unwrap() + start;
+    let layout: Vec<&str> = output[start..end].lines().map(str::trim_end).collect();
+    assert_eq!(
+        layout,
+        [
+            "=== SYMBOLS BY SCOPE ===",
+            "global: 2 symbols",
+            "  Name            Type         Data Type    Line",
+            "  --------------------------------------------------",
+            "  g               variable     int          1",
+            "  main            function     int          2",
+            "",
+            "  main (lines 2-8): 3 symbols",
+            "    Name            Type         Data Type    Line",
+            "    --------------------------------------------------",
+            "    a               variable     int          3",
+            "    a               variable     unknown      4",
+            "    a               variable     unknown      5",
+            "",
+            "    block@4 (lines 4-4): 1 symbol",
+            "      Name            Type         Data Type    Line",
+            "      --------------------------------------------------",
+            "      b               variable     int          4",
+            "",
+            "    block@4 (lines 4-4): 1 symbol",
+            "      Name            Type         Data Type    Line",
+            "      --------------------------------------------------",
+            "      c               variable     int          4",
+            "",
+            "    block@5 (lines 5-7): 0 symbols",
+            "",
+            "      block@6 (lines 6-6): 2 symbols",
+            "        Name            Type         Data Type    Line",
+            "        --------------------------------------------------",
+            "        e               variable     int          6",
+            "        a               variable     unknown      6",
+            "",
+        ]
+    );
+    let document = parse_document(scratch.read("n_tokens.json").as_bytes()).unwrap();
+    let scopes: Vec<(String, usize)> = document.symbols_by_scope.unwrap().0.into_iter().map(|g| (g.scope, g.depth)).collect();
+    assert_eq!(scopes.len(), 6);
+    assert_eq!(scopes[3], ("block@4".to_string(), 2));
+}
+
+#[test]
+fn summary_json_covers_every_input_and_a_total() {
+    let scratch = Scratch::new("summary");
+    scratch.write("a.mcpp", "int main() {\n    return 0;\n}\n");
+    scratch.write("b.mcpp", "int x = @;\n");
+    let output = scratch.run(&["--summary-json", "--quiet", "a.mcpp", "b.mcpp"]);
+    assert_eq!(output.status.code(), Some(1));
+    let summary: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(summary["schema_version"], 1);
+    let files = summary["files"].as_array().unwrap();
+    let heads: Vec<(&str, bool, u64)> = files
+        .iter()
+        .map(|f| (f["path"].as_str().unwrap(), f["success"].as_bool().unwrap(), f["tokens"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(heads, [("a.mcpp", true, 9), ("b.mcpp", false, 3)]);
+    assert_eq!(summary["total"]["tokens"], 12);
+    assert_eq!(summary["total"]["diagnostics_by_code"], serde_json::json!({ "E0001": 1 }));
+    assert_eq!(summary["total"]["lines"]["total"], 4);
+    assert!(!scratch.exists("a_tokens.json") && !scratch.exists("b_tokens.json"));
+
+    let compact = stdout(&scratch.run(&["--summary-json", "--compact-json", "a.mcpp"]));
+    assert_eq!(compact.trim_end().lines().count(), 1);
+
+    // Alone, --quiet still writes the token files and reports errors
+    let quiet = scratch.run(&["--quiet", "a.mcpp", "b.mcpp"]);
+    assert_eq!(quiet.status.code(), Some(1));
+    assert_eq!(stdout(&quiet), "");
+    assert!(String::from_utf8_lossy(&quiet.stderr).contains("Invalid character"));
+    assert!(scratch.exists("a_tokens.json") && scratch.exists("b_tokens.json"));
+}
+
+#[test]
+fn quiet_leaves_out_every_banner() {
+    let scratch = Scratch::new("quiet-banners");
+    scratch.write("q.mcpp", "int x = 1;\n");
+    assert_eq!(scratch.run(&["q.mcpp"]).status.code(), Some(0));
+    scratch.write("expected.json", &scratch.read("q_tokens.json"));
+
+    let check = scratch.run(&["--quiet", "--check", "expected.json", "q.mcpp"]);
+    assert_eq!(check.status.code(), Some(0));
+    assert_eq!((stdout(&check), stderr(&check)), (String::new(), String::new()));
+    for format in ["bison-stream", "token-tree"] {
+        let output = scratch.run(&["--quiet", "--format", format, "q.mcpp"]);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stdout(&output), "", "{}", format);
+    }
+
+    // A failed check is still reported
+    scratch.write("q.mcpp", "int y = 1;\n");
+    let mismatch = scratch.run(&["--quiet", "--check", "expected.json", "q.mcpp"]);
+    assert_eq!(mismatch.status.code(), Some(3));
+    assert!(stdout(&mismatch).starts_with("\n=== CHECK ===\n"));
+}
+
+#[test]
+fn binary_inputs_are_refused_and_other_extensions_warned_about() {
+    let scratch = Scratch::new("binary");
+    scratch.write_bytes("blob.o", b"\x7fELF\x02\x01\x01\x00\x00\x00int");
+    scratch.write("gen.txt", "int x = 1;\n");
+
+    let refused = scratch.run(&["blob.o"]);
+    assert_eq!(refused.status.code(), Some(2));
+    assert_eq!(stderr(&refused), "Error reading file 'blob.o': input appears to be binary; use --force to lex it anyway\n");
+    assert!(!scratch.exists("blob_tokens.json"));
+
+    // Lexed anyway: one error, as recovery is off
+    let forced = scratch.run(&["--force", "blob.o"]);
+    assert_eq!(forced.status.code(), Some(1));
+    assert_eq!(stderr(&forced).matches("Lexical Error").count(), 1);
+    assert!(scratch.exists("blob_tokens.json"));
+
+    let text = scratch.run(&["gen.txt"]);
+    assert_eq!(text.status.code(), Some(0));
+    assert_eq!(stderr(&text), "Warning: 'gen.txt' does not have the .mcpp extension; lexing it as MCPP\n");
+    assert!(scratch.exists("gen_tokens.json"));
+}
+
+#[test]
+fn function_filter_restricts_the_stream_and_metrics() {
+    let scratch = Scratch::new("function");
+    scratch.write("two.mcpp", "int add(int a, int b) {\n    return a + b;\n}\nint main() {\n    return add(1, 2);\n}\n");
+    let output = scratch.run(&["--function", "main", "--metrics", "two.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    let text = stdout(&output);
+    let stream: Vec<&str> = text.lines().skip_while(|l| *l != "=== TOKEN STREAM ===").skip(1).take_while(|l| !l.is_empty()).collect();
+    assert_eq!(stream.len(), 14);
+    assert_eq!((stream[0], stream[13]), ("<Int, int, 4, 1>", "<RightBrace, }, 6, 1>"));
+    let metrics: Vec<&str> = text.lines().skip_while(|l| *l != "=== FUNCTION METRICS ===").skip(3).take_while(|l| !l.is_empty()).collect();
+    assert_eq!(metrics.len(), 1);
+    assert!(metrics[0].starts_with("main "));
+    assert!(text.contains("Total tokens: 31\nTokens in main: 14\n"));
+
+    let missing = scratch.run(&["--function", "nope", "two.mcpp"]);
+    assert_eq!(missing.status.code(), Some(0));
+    assert_eq!(stderr(&missing), "Warning: No function named 'nope' in two.mcpp\n");
+    assert!(stdout(&missing).contains("Tokens in nope: 0\n"));
+}
+
+#[test]
+fn token_tree_format_writes_groups_and_warns_about_unmatched_closers() {
+    let scratch = Scratch::new("tree");
+    scratch.write("t.mcpp", "x = (a + b));\n");
+    let output = scratch.run(&["--format", "token-tree", "t.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output), "Lexical Warning: Parenthesis ')' closes nothing at line 1, column 12\n");
+    let trees: Vec<TokenTree> = serde_json::from_str(&scratch.read("t_tree.json")).unwrap();
+    assert_eq!(trees.len(), 5);
+    match &trees[2] {
+        TokenTree::Group { delimiter, children, close_span, .. } => {
+            assert_eq!((*delimiter, children.len(), close_span.map(|s| s.column)), (Nesting::Paren, 3, Some(11)));
+        }
+        other => panic!("expected a group, got {:?}", other),
+    }
+    assert!(matches!(&trees[3], TokenTree::Leaf(token) if token.lexeme == ")"));
+    assert!(!scratch.exists("t_tokens.json"));
+}
+
+#[test]
+fn symbol_checks_exit_by_their_result() {
+    let scratch = Scratch::new("symbols");
+    scratch.write("g.mcpp", "int total = 0;\nint add(int a, int b) {\n    return a + b;\n}\n");
+    scratch.write("all.csv", "name,kind,type,scope\ntotal,variable,int,global\nadd,function,int,global\na,variable,int,add\nb,variable,int,add\n");
+    scratch.write("part.csv", "name,kind,type,scope\ntotal,variable,int,global\nadd,function,int,global\n");
+    scratch.write("wrong.csv", "name,kind,type,scope\ntotal,variable,float,global\nadd,function,int,global\na,variable,int,add\nb,variable,int,add\n");
+
+    let exact = scratch.run(&["--check-symbols", "all.csv", "g.mcpp"]);
+    assert_eq!(exact.status.code(), Some(0));
+    assert!(stdout(&exact).ends_with("Score: 4/4 symbols (100.0%), passed\n"));
+
+    let extras = scratch.run(&["--check-symbols", "part.csv", "g.mcpp"]);
+    assert_eq!(extras.status.code(), Some(3));
+    assert!(stdout(&extras).contains("EXTRA variable int a in add at line 2\n"));
+    let allowed = scratch.run(&["--check-symbols", "part.csv", "--allow-extra-symbols", "g.mcpp"]);
+    assert_eq!(allowed.status.code(), Some(0));
+
+    let wrong = scratch.run(&["--check-symbols", "wrong.csv", "g.mcpp"]);
+    assert_eq!(wrong.status.code(), Some(3));
+    assert!(stdout(&wrong).contains("FAIL variable float total in global: type is int\n"));
+    assert!(stdout(&wrong).ends_with("Score: 3/4 symbols (75.0%), failed\n"));
+
+    let missing = scratch.run(&["--check-symbols", "nope.csv", "g.mcpp"]);
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(!scratch.exists("g_tokens.json"));
+}
+
+#[test]
+fn usage_report_merges_every_input() {
+    let scratch = Scratch::new("usage");
+    scratch.write("a.mcpp", "int x = 1;\nif (x > 0) { x = x + 1; }\n");
+    scratch.write("b.mcpp", "int y = 2; // note\nwhile (y > 0) { y = y - 1; }\n");
+    let output = scratch.run(&["--usage-report", "csv", "a.mcpp", "b.mcpp"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "construct,category,count,per_1000_tokens,files\n\
+         =,operator,4,105.3,2\n\
+         >,operator,2,52.6,2\n\
+         int,keyword,2,52.6,2\n\
+         +,operator,1,26.3,1\n\
+         -,operator,1,26.3,1\n\
+         if,keyword,1,26.3,1\n\
+         while,keyword,1,26.3,1\n"
+    );
+    assert!(!scratch.exists("a_tokens.json"));
+
+    let text = stdout(&scratch.run(&["--usage-report", "text", "a.mcpp", "b.mcpp"]));
+    assert!(text.starts_with("=== CONSTRUCT USAGE ===\n2 files, 38 tokens\n"));
+    let combined = scratch.run(&["--usage-report", "text", "--summary-json", "a.mcpp"]);
+    assert_eq!(combined.status.code(), Some(2));
+}
+
+#[test]
+fn ascii_only_fails_the_run_with_exact_positions() {
+    let scratch = Scratch::new("ascii");
+    scratch.write("u.mcpp", "// café\nint x = 1;\nstring s = \"ü\";\n");
+    assert_eq!(scratch.run(&["--quiet", "u.mcpp"]).status.code(), Some(0));
+
+    let first = scratch.run(&["--quiet", "--ascii-only", "u.mcpp"]);
+    assert_eq!(first.status.code(), Some(1));
+    assert_eq!(
+        stderr(&first),
+        "\nLexical Error: Non-ASCII character 'é' (U+00E9, bytes 0xC3 0xA9) in an ASCII-only source at line 1, column 7\n"
+    );
+    let all = scratch.run(&["--quiet", "--ascii-only", "--error-recovery", "u.mcpp"]);
+    assert_eq!(all.status.code(), Some(1));
+    assert_eq!(stderr(&all).matches("Non-ASCII").count(), 2);
+    assert!(stderr(&all).contains("'ü' (U+00FC, bytes 0xC3 0xBC) in an ASCII-only source at line 3, column 13\n"));
+}
+
+#[test]
+fn rename_writes_the_output_or_refuses() {
+    let scratch = Scratch::new("rename");
+    scratch.write("r.mcpp", "int count = 0; // count\nstring s = \"count\";\nint main() { return count; }\n");
+    scratch.write("f.mcpp", "int count = 0;\nint f() {\n    int total = 1;\n    return count + total;\n}\n");
+    scratch.write("bad.mcpp", "int count = @;\n");
+
+    let clean = scratch.run(&["--rename", "count=total", "-o", "out.mcpp", "r.mcpp"]);
+    assert_eq!(clean.status.code(), Some(0));
+    assert!(stdout(&clean).contains("Identifiers renamed from 'count' to 'total': 2\nRenamed source saved to: out.mcpp\n"));
+    assert_eq!(scratch.read("out.mcpp"), "int total = 0; // count\nstring s = \"count\";\nint main() { return total; }\n");
+
+    let keyword = scratch.run(&["--rename", "count=while", "r.mcpp"]);
+    assert_eq!(keyword.status.code(), Some(2));
+    assert!(stderr(&keyword).contains("'while' is a reserved keyword"));
+    let collision = scratch.run(&["--rename", "count=total", "f.mcpp"]);
+    assert_eq!(collision.status.code(), Some(2));
+    assert!(stderr(&collision).contains("'total' is already declared in f at line 3, where 'count' is used"));
+    assert!(!scratch.exists("r_renamed.mcpp") && !scratch.exists("f_renamed.mcpp"));
+
+    assert_eq!(scratch.run(&["--rename", "count=n", "bad.mcpp"]).status.code(), Some(1));
+    assert_eq!(scratch.run(&["--rename", "count", "r.mcpp"]).status.code(), Some(2));
+}
+
+#[test]
+fn max_complexity_warns_and_can_fail_the_run() {
+    let scratch = Scratch::new("complexity");
+    scratch.write("m.mcpp", "int busy(int a) {\n    if (a > 0 && a < 5) { a = 1; }\n    return a;\n}\n");
+    let warned = scratch.run(&["--quiet", "--max-complexity", "2", "m.mcpp"]);
+    assert_eq!(warned.status.code(), Some(0));
+    assert_eq!(stderr(&warned), "Lexical Warning: Function 'busy' has cyclomatic complexity 3, over the limit of 2 at line 1, column 5\n");
+    assert_eq!(stderr(&scratch.run(&["--quiet", "--max-complexity", "3", "m.mcpp"])), "");
+    assert_eq!(scratch.run(&["--quiet", "--max-complexity", "2", "--deny-warnings", "m.mcpp"]).status.code(), Some(1));
+    assert_eq!(scratch.run(&["--max-complexity", "x", "m.mcpp"]).status.code(), Some(2));
+}
+
+#[test]
+fn excerpts_expand_tabs_to_the_given_width() {
+    let scratch = Scratch::new("tab-width");
+    scratch.write("t.mcpp", "\tstring s = \"你好\"; int a = 1 @ 2;\n");
+    let output = scratch.run(&["--quiet", "--excerpts", "--tab-width", "4", "t.mcpp"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "\nLexical Error: Invalid character '@' at line 1, column 29\n1 |     string s = \"你好\"; int a = 1 @ 2;\n  |                                  ^\n"
+    );
+    assert!(stderr(&scratch.run(&["--quiet", "--excerpts", "t.mcpp"])).contains("\n  |                                      ^\n"));
+    assert_eq!(scratch.run(&["--tab-width", "x", "t.mcpp"]).status.code(), Some(2));
+}
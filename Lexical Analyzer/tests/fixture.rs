@@ -0,0 +1,4 @@
+// A test written by `mcpp-lexer --emit-rust-fixture`, compiled and run just
+// as it would be once pasted here. src/fixture.rs checks that the generator
+// still writes exactly this file.
+include!("golden/quoted_hashes_fixture.rs");
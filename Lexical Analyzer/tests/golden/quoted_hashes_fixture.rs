@@ -0,0 +1,30 @@
+use mcpp_lexer::lexer::{Delimiter, Keyword, Lexer, LiteralKind, Operator, TokenType};
+
+const QUOTED_HASHES_SOURCE: &str = r###"string s = "a\"#b";
+string t = R"("##)";
+"###;
+
+#[test]
+fn quoted_hashes_tokens() {
+    let mut lexer = Lexer::new(QUOTED_HASHES_SOURCE.to_string());
+    let _ = lexer.tokenize();
+    let actual: Vec<(TokenType, &str, usize, usize)> = lexer
+        .get_tokens()
+        .iter()
+        .map(|t| (t.token_type.clone(), t.lexeme.as_str(), t.line, t.column))
+        .collect();
+    let expected: Vec<(TokenType, &str, usize, usize)> = vec![
+        (TokenType::Keyword(Keyword::String), "string", 1, 1),
+        (TokenType::Identifier, "s", 1, 8),
+        (TokenType::Operator(Operator::Assign), "=", 1, 10),
+        (TokenType::Literal(LiteralKind::String), "\"a\\\"#b\"", 1, 12),
+        (TokenType::Delimiter(Delimiter::Semicolon), ";", 1, 19),
+        (TokenType::Keyword(Keyword::String), "string", 2, 1),
+        (TokenType::Identifier, "t", 2, 8),
+        (TokenType::Operator(Operator::Assign), "=", 2, 10),
+        (TokenType::Literal(LiteralKind::String), "R\"(\"##)\"", 2, 12),
+        (TokenType::Delimiter(Delimiter::Semicolon), ";", 2, 20),
+        (TokenType::EOF, "EOF", 3, 1),
+    ];
+    assert_eq!(actual, expected);
+}
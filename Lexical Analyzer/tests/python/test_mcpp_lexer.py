@@ -0,0 +1,35 @@
+# Run with `maturin develop && pytest tests/python` from "Lexical Analyzer".
+import pytest
+
+import mcpp_lexer
+
+
+def test_tokens_are_dicts():
+    tokens = mcpp_lexer.tokenize("int x = 1;\n")
+    assert len(tokens) == 6
+    assert tokens[1] == {"type": "Identifier", "lexeme": "x", "line": 1, "column": 5, "value": None}
+    assert tokens[-1]["type"] == "EOF"
+    assert mcpp_lexer.tokenize("[[nodiscard]] int f();\n")[0]["value"] == "nodiscard"
+
+
+def test_symbols_are_dicts():
+    symbols = mcpp_lexer.symbols("class Box { public: int size = 0; };\n")
+    assert [s["name"] for s in symbols] == ["Box", "size"]
+    assert symbols[1] == {
+        "name": "size",
+        "symbol_type": "variable",
+        "data_type": "int",
+        "scope": "Box",
+        "line": 1,
+        "initialized": True,
+        "access": "public",
+    }
+
+
+def test_lexical_errors_carry_the_first_position():
+    with pytest.raises(mcpp_lexer.LexicalError) as raised:
+        mcpp_lexer.tokenize("int a = 1;\nint b = 2 @ 3 $ 4;\n")
+    error = raised.value
+    assert (error.code, error.line, error.column, error.errors) == ("E0001", 2, 11, 2)
+    assert str(error) == "Invalid character '@' at line 2, column 11 (and 1 more)"
+    assert isinstance(error, Exception)
@@ -0,0 +1,108 @@
+// Conformance suite for the renderers that place source text on a
+// terminal: whatever comes before an error on its line, tabs, wide CJK
+// characters, emoji or combining accents, the excerpt's caret must land
+// under the character in error and the annotated view must show the line
+// exactly as the excerpt does.
+
+use mcpp_lexer::annotate::{annotate, AnnotateOptions};
+use mcpp_lexer::lexer::{Lexer, LexerOptions};
+use mcpp_lexer::position::ColumnUnit;
+use mcpp_lexer::visual::VisualColumns;
+
+// Lines with one invalid character, and the tricky text before it.
+const LINES: &[&str] = &[
+    "\tint a = 1 @ 2;",
+    "\t\tx = 1;\ty = 2; @",
+    "int\tx\t= 1;   @",
+    "string s = \"你好\"; int a = 1 @ 2;",
+    "string s = \"한국어\"\t@",
+    "string s = \"🎉🎉\"; @",
+    "string s = \"cafe\u{301}\"; @",
+    "string s = \"e\u{301}\u{302}\u{303}\"; @",
+    "// 注释\n\t\"你\u{301}\" @",
+];
+
+// Lexes `line` as a whole file, so the only diagnostic is its error.
+fn lexed(line: &str, unit: ColumnUnit) -> Lexer {
+    let mut lexer = Lexer::new(format!("{}\n", line));
+    lexer.set_options(LexerOptions {
+        error_recovery: true,
+        column_unit: unit,
+        ..LexerOptions::default()
+    });
+    let _ = lexer.tokenize();
+    lexer
+}
+
+// The excerpt of the single error, as its source line and underline.
+fn excerpt(lexer: &Lexer, unit: ColumnUnit, tab_width: usize) -> (String, String) {
+    let diagnostics = lexer.get_diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{:?}", lexer.source());
+    let excerpt = diagnostics[0].excerpt(lexer.source(), unit, tab_width).unwrap();
+    let (shown, underline) = excerpt.split_once('\n').unwrap();
+    (shown.to_string(), underline.to_string())
+}
+
+#[test]
+fn carets_land_under_the_character_in_error() {
+    for unit in [ColumnUnit::Bytes, ColumnUnit::Chars, ColumnUnit::Utf16] {
+        for tab_width in [1, 4, 8] {
+            for line in LINES {
+                let lexer = lexed(line, unit);
+                let (shown, underline) = excerpt(&lexer, unit, tab_width);
+                let physical = line.rsplit('\n').next().unwrap();
+                let before = &physical[..physical.find('@').unwrap()];
+                let cells = VisualColumns::new(tab_width).width(before);
+                let context = format!("{:?} in {:?} with tabs of {}", line, unit, tab_width);
+                let gutter = shown.find("| ").unwrap() + 2;
+                assert_eq!(&shown[gutter..], VisualColumns::new(tab_width).expand_tabs(physical), "{}", context);
+                assert_eq!(underline[gutter..], format!("{}^", " ".repeat(cells)), "{}", context);
+            }
+        }
+    }
+}
+
+#[test]
+fn wide_characters_in_error_are_underlined_across_both_cells() {
+    for (line, expected) in [("x = 你;", "    ^~"), ("x = 🎉; y = @;", "    ^~"), ("\t你 = 1;", "        ^~")] {
+        let lexer = lexed(line, ColumnUnit::Chars);
+        let diagnostic = &lexer.get_diagnostics()[0];
+        let excerpt = diagnostic.excerpt(lexer.source(), ColumnUnit::Chars, 8).unwrap();
+        assert_eq!(excerpt.split_once('\n').unwrap().1, format!("  | {}", expected), "{:?}", line);
+    }
+}
+
+#[test]
+fn the_annotated_view_shows_each_line_as_the_excerpt_does() {
+    for tab_width in [2, 4, 8] {
+        for line in LINES {
+            let lexer = lexed(line, ColumnUnit::Chars);
+            let (shown, _) = excerpt(&lexer, ColumnUnit::Chars, tab_width);
+            let options = AnnotateOptions {
+                tab_width,
+                ..AnnotateOptions::default()
+            };
+            let view = annotate(&lexer.tokens_by_line(), &options);
+            assert!(view.lines().any(|l| l == shown), "{:?} with tabs of {}:\n{}\nnot in\n{}", line, tab_width, shown, view);
+        }
+    }
+}
+
+#[test]
+fn wide_lines_wrap_at_the_edge_in_cells() {
+    let line = format!("string s = \"{}\";", "你".repeat(40));
+    let lexer = lexed(&line, ColumnUnit::Chars);
+    let options = AnnotateOptions {
+        width: 40,
+        ..AnnotateOptions::default()
+    };
+    let view = annotate(&lexer.tokens_by_line(), &options);
+    let visual = VisualColumns::default();
+    let source_lines: Vec<&str> = view.lines().take_while(|l| !l.starts_with("  |     ")).collect();
+    assert!(source_lines.len() > 2);
+    for piece in &source_lines {
+        assert!(visual.width(&piece[4..]) <= 36, "{:?}", piece);
+    }
+    let rejoined: String = source_lines.iter().flat_map(|l| l[4..].chars()).filter(|&ch| ch != ' ').collect();
+    assert_eq!(rejoined, line.replace(' ', ""));
+}